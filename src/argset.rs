@@ -28,6 +28,20 @@ pub(crate) fn keep_arg() -> Arg {
         .action(clap::ArgAction::SetTrue)
 }
 
+/// The `--with-deps` option for also moving dependent or depended-upon patches.
+pub(crate) fn with_deps_arg() -> Arg {
+    Arg::new("with-deps")
+        .long("with-deps")
+        .short('D')
+        .help("Also move patches related by dependencies")
+        .long_help(
+            "Also move the patches that the specified patches depend on (or, when \
+             popping, the patches that depend on the specified patches). See \
+             `stg deps` for how dependencies are determined.",
+        )
+        .action(clap::ArgAction::SetTrue)
+}
+
 /// The `--merged` option checking for already-merged patches before pushes.
 pub(crate) fn merged_arg() -> Arg {
     Arg::new("merged")
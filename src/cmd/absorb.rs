@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg absorb` implementation.
+
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Context, Result};
+use bstr::{BString, ByteSlice};
+use clap::ArgMatches;
+use indexmap::IndexMap;
+
+use crate::{
+    argset,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    print_info_message, print_warning_message,
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stupid::{parse_file_diffs, FileDiff, Hunk, Stupid},
+    wrap::Message,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "absorb",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Fold worktree changes into the applied patches they modify")
+        .long_about(
+            "Fold changes from the worktree and index into the applied patches that \
+             most recently modified the changed lines.\n\
+             \n\
+             Each hunk of the difference between the topmost patch and the worktree \
+             is considered separately. A hunk that modifies or removes lines is \
+             absorbed into the applied patch that last modified all of those lines. \
+             A hunk that only adds lines is absorbed into the applied patch that \
+             last modified the lines adjacent to the addition.\n\
+             \n\
+             Hunks are left in the worktree when the lines were last modified by \
+             more than one patch, when the lines were not modified by any applied \
+             patch, or when the hunk belongs to a new or deleted file. A warning is \
+             printed for each hunk that is left in the worktree.\n\
+             \n\
+             All of the absorbed hunks are folded into their patches as a single \
+             operation that may be reverted with 'stg undo'. If any of the patches \
+             above an updated patch can no longer be pushed, nothing is absorbed. \
+             The worktree is not modified, but the index entries of files with \
+             absorbed hunks are reset to match the updated patches.",
+        )
+        .arg(argset::committer_date_is_author_date_arg())
+}
+
+/// Which applied patch, if any, a hunk may be absorbed into.
+#[derive(Debug, PartialEq, Eq)]
+enum Owner<'a> {
+    Patch(&'a PatchName),
+    Unowned,
+    Ambiguous,
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
+    let stupid = repo.stupid();
+
+    repo.check_repository_state()?;
+    stupid.statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+    if stack.applied().is_empty() {
+        return Err(super::Error::NoAppliedPatches.into());
+    }
+
+    stupid.update_index_refresh()?;
+    let head_id = stack.get_branch_head().id;
+    let head_tree_id = stack.get_branch_head().tree_id()?.detach();
+    let files = parse_file_diffs(&stupid.diff_index_zero_context(head_tree_id)?);
+    if files.is_empty() {
+        print_info_message(matches, "no local changes to absorb");
+        return Ok(());
+    }
+
+    let patch_ids: HashMap<gix::ObjectId, &PatchName> = stack
+        .applied()
+        .iter()
+        .map(|pn| (stack.get_patch_commit(pn).id, pn))
+        .collect();
+
+    let mut selections: IndexMap<PatchName, Vec<(usize, usize)>> = IndexMap::new();
+    for (file_index, file) in files.iter().enumerate() {
+        let is_new_or_deleted = file.header.lines().any(|line| {
+            line.starts_with(b"new file mode ") || line.starts_with(b"deleted file mode ")
+        });
+        if file.hunks.is_empty() || is_new_or_deleted {
+            print_warning_message(
+                matches,
+                &format!(
+                    "`{}`: cannot absorb new, deleted, or binary file",
+                    file.path
+                ),
+            );
+            continue;
+        }
+
+        let line_owners: Vec<Option<&PatchName>> = stupid
+            .blame(stack.base().id, head_id, file.path.as_ref())?
+            .iter()
+            .map(|commit_id| patch_ids.get(commit_id).copied())
+            .collect();
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            match find_owner(&line_owners, &hunk.hunk) {
+                Owner::Patch(patchname) => selections
+                    .entry(patchname.clone())
+                    .or_default()
+                    .push((file_index, hunk_index)),
+                Owner::Unowned => print_warning_message(
+                    matches,
+                    &format!(
+                        "`{}`: {} not modified by any applied patch",
+                        file.path,
+                        hunk_label(&hunk.hunk)
+                    ),
+                ),
+                Owner::Ambiguous => print_warning_message(
+                    matches,
+                    &format!(
+                        "`{}`: {} is ambiguous; modified by multiple patches",
+                        file.path,
+                        hunk_label(&hunk.hunk)
+                    ),
+                ),
+            }
+        }
+    }
+
+    if selections.is_empty() {
+        print_info_message(matches, "no changes could be absorbed");
+        return Ok(());
+    }
+
+    // Absorb into the topmost patches first so that each target's hunks are folded
+    // into a stack that already contains the other absorbed changes above it.
+    selections.sort_by_cached_key(|patchname, _| std::cmp::Reverse(stack.index_of(patchname)));
+
+    let mut targets: Vec<(PatchName, gix::ObjectId)> = Vec::with_capacity(selections.len());
+    for (patchname, selection) in &selections {
+        let diff = assemble_zero_context_diff(&files, selection)?;
+        let tree_id = stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(head_tree_id)?;
+            stupid_temp.apply_zero_context_to_index(diff.as_ref())?;
+            stupid_temp.write_tree()
+        })?;
+        targets.push((patchname.clone(), tree_id));
+    }
+
+    let absorbed_paths: Vec<&BString> = {
+        let mut file_indices: Vec<usize> = selections
+            .values()
+            .flatten()
+            .map(|(file_index, _)| *file_index)
+            .collect();
+        file_indices.sort_unstable();
+        file_indices.dedup();
+        file_indices.iter().map(|&i| &files[i].path).collect()
+    };
+
+    let temp_patchname = {
+        let disallow: Vec<&PatchName> = stack.all_patches().collect();
+        PatchName::make("absorb-temp", true, None).uniquify(&[], &disallow)
+    };
+
+    let committer = repo.get_committer()?.to_owned();
+    let committer_date_is_author_date = matches.get_flag("committer-date-is-author-date");
+
+    let stack = stack
+        .setup_transaction()
+        .use_index_and_worktree(false)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let mut absorb = || -> Result<()> {
+                for (patchname, tree_id) in &targets {
+                    let top = trans.top().clone();
+                    let temp_tree_id = stupid.with_temp_index(|stupid_temp| {
+                        stupid_temp.read_tree(top.tree_id()?.detach())?;
+                        if stupid_temp.apply_treediff_to_index(head_tree_id, *tree_id, true)? {
+                            stupid_temp.write_tree()
+                        } else {
+                            Err(anyhow!("cannot absorb changes into `{patchname}`"))
+                        }
+                    })?;
+                    let temp_commit_id = repo.commit_ex(
+                        &committer,
+                        &committer,
+                        &Message::from(format!("Absorb into {patchname}")),
+                        temp_tree_id,
+                        [top.id],
+                    )?;
+                    trans.new_applied(&temp_patchname, temp_commit_id)?;
+
+                    super::refresh::fold_temp_patch(
+                        trans,
+                        patchname,
+                        &temp_patchname,
+                        |trans, tree_id| {
+                            let patch_commit = trans.get_patch_commit(patchname);
+                            let author = patch_commit.author_strict()?;
+                            let mut committer = committer.clone();
+                            if committer_date_is_author_date {
+                                committer.time = author.time;
+                            }
+                            let commit_id = repo.commit_ex(
+                                &author,
+                                &committer,
+                                &patch_commit.message_ex(),
+                                tree_id,
+                                patch_commit.parent_ids().map(|id| id.detach()),
+                            )?;
+                            Ok((None, Some(commit_id)))
+                        },
+                    )
+                    .with_context(|| format!("absorbing into `{patchname}`"))?;
+                }
+                Ok(())
+            };
+            // Any failure, including a halted push, aborts the entire absorb.
+            absorb().map_err(|e| anyhow!("{e:#}"))
+        })
+        .execute("absorb")?;
+
+    stupid.reset_index_paths(stack.get_branch_head().id, absorbed_paths)?;
+
+    for (patchname, selection) in &selections {
+        let count = selection.len();
+        let plural = if count == 1 { "" } else { "s" };
+        print_info_message(
+            matches,
+            &format!("absorbed {count} hunk{plural} into `{patchname}`"),
+        );
+    }
+
+    Ok(())
+}
+
+/// Determine which patch last modified the lines that a hunk touches.
+///
+/// `line_owners` maps each line of the pre-image, indexed by line number minus one, to
+/// the applied patch that last modified it, if any. A hunk that only adds lines is
+/// attributed using the lines immediately before and after the addition, if any.
+fn find_owner<'a>(line_owners: &[Option<&'a PatchName>], hunk: &Hunk) -> Owner<'a> {
+    let lines: Vec<Option<&PatchName>> = if hunk.old_lines == 0 {
+        [hunk.old_start, hunk.old_start + 1]
+            .into_iter()
+            .filter_map(|line_number| {
+                line_number
+                    .checked_sub(1)
+                    .and_then(|index| line_owners.get(index))
+                    .copied()
+            })
+            .collect()
+    } else {
+        let start = hunk.old_start - 1;
+        let end = (start + hunk.old_lines).min(line_owners.len());
+        line_owners[start.min(end)..end].to_vec()
+    };
+    if lines.iter().all(Option::is_none) {
+        return Owner::Unowned;
+    } else if lines.iter().any(Option::is_none) {
+        return Owner::Ambiguous;
+    }
+    let mut owners: Vec<&PatchName> = lines.into_iter().flatten().collect();
+    owners.dedup();
+    match owners.as_slice() {
+        [patchname] => Owner::Patch(patchname),
+        _ => Owner::Ambiguous,
+    }
+}
+
+fn hunk_label(hunk: &Hunk) -> String {
+    if hunk.old_lines == 0 {
+        format!("addition after line {}", hunk.old_start)
+    } else if hunk.old_lines == 1 {
+        format!("line {}", hunk.old_start)
+    } else {
+        format!(
+            "lines {}-{}",
+            hunk.old_start,
+            hunk.old_start + hunk.old_lines - 1
+        )
+    }
+}
+
+/// Build a zero-context diff containing the selected `(file, hunk)` indices.
+///
+/// The post-image line numbers are recomputed to account for only the selected hunks
+/// of each file.
+fn assemble_zero_context_diff(files: &[FileDiff], selection: &[(usize, usize)]) -> Result<BString> {
+    let mut diff = BString::from(Vec::new());
+    for (file_index, file) in files.iter().enumerate() {
+        let mut delta: isize = 0;
+        let mut is_header_written = false;
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            if !selection.contains(&(file_index, hunk_index)) {
+                continue;
+            }
+            if !is_header_written {
+                diff.extend_from_slice(&file.header);
+                is_header_written = true;
+            }
+            let Hunk {
+                old_start,
+                old_lines,
+                new_lines,
+                ..
+            } = hunk.hunk;
+            let new_start = if old_lines == 0 {
+                old_start + 1
+            } else if new_lines == 0 {
+                old_start - 1
+            } else {
+                old_start
+            }
+            .checked_add_signed(delta)
+            .expect("selected hunks are ordered");
+            writeln!(
+                diff,
+                "@@ -{old_start},{old_lines} +{new_start},{new_lines} @@{}",
+                hunk.section
+            )?;
+            for line in &hunk.lines {
+                diff.extend_from_slice(line);
+            }
+            delta += new_lines as isize - old_lines as isize;
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(old_start: usize, old_lines: usize) -> Hunk {
+        Hunk {
+            old_start,
+            old_lines,
+            new_start: 0,
+            new_lines: 1,
+        }
+    }
+
+    #[test]
+    fn hunk_owners() {
+        let p0 = PatchName::make("p0", false, None);
+        let p1 = PatchName::make("p1", false, None);
+        let owners = [None, Some(&p0), Some(&p0), Some(&p1), None];
+
+        assert_eq!(find_owner(&owners, &hunk(2, 2)), Owner::Patch(&p0));
+        assert_eq!(find_owner(&owners, &hunk(3, 2)), Owner::Ambiguous);
+        assert_eq!(find_owner(&owners, &hunk(1, 2)), Owner::Ambiguous);
+        assert_eq!(find_owner(&owners, &hunk(5, 1)), Owner::Unowned);
+
+        // Additions are attributed using the adjacent lines. Additions bordering both
+        // a base line and a patch's line are ambiguous.
+        assert_eq!(find_owner(&owners, &hunk(2, 0)), Owner::Patch(&p0));
+        assert_eq!(find_owner(&owners, &hunk(1, 0)), Owner::Ambiguous);
+        assert_eq!(find_owner(&owners, &hunk(3, 0)), Owner::Ambiguous);
+        assert_eq!(find_owner(&owners, &hunk(4, 0)), Owner::Ambiguous);
+        assert_eq!(find_owner(&owners, &hunk(0, 0)), Owner::Unowned);
+        assert_eq!(find_owner(&owners, &hunk(5, 0)), Owner::Unowned);
+
+        // Additions at the start or end of the file only have one adjacent line.
+        let owners = [Some(&p0), None, Some(&p1)];
+        assert_eq!(find_owner(&owners, &hunk(0, 0)), Owner::Patch(&p0));
+        assert_eq!(find_owner(&owners, &hunk(3, 0)), Owner::Patch(&p1));
+    }
+}
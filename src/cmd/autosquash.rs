@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg autosquash` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    argset,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    operation::{self, Operation},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "autosquash",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    let app = clap::Command::new(STGIT_COMMAND.name)
+        .about("Squash fixup!, squash!, and amend! patches into their targets")
+        .long_about(
+            "Squash patches whose subjects start with \"fixup! \", \"squash! \", or \
+             \"amend! \" into their target patches without moving the stack base.\n\
+             \n\
+             Each such patch is moved directly after the first preceding patch whose \
+             subject or patch name matches the remainder of its subject, as with `stg \
+             rebase --autosquash`. Patches for which no target is found are left in \
+             place. Use `stg new --fixup` to create such patches.\n\
+             \n\
+             Merge conflicts may arise when the squashed patches are pushed back onto \
+             the stack. If this occurs, resolve the conflicts and then continue with \
+             '--continue', or use '--skip' or '--abort'.",
+        )
+        .override_usage(super::make_usage(
+            "stg autosquash",
+            &["[OPTIONS]", "(--continue | --skip | --abort)"],
+        ))
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .short('i')
+                .help("Edit the autosquash instructions in editor")
+                .long_help(
+                    "Edit the rearranged instructions in the editor before they are \
+                     performed, as with `stg rebase --interactive`.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(argset::committer_date_is_author_date_arg())
+        .arg(argset::push_conflicts_arg());
+
+    operation::add_args(app, &["interactive"])
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return super::rebase::resume(matches, "autosquash", action);
+    }
+
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let config = repo.config_snapshot();
+    let stupid = repo.stupid();
+
+    if stack.is_protected(&config) {
+        return Err(anyhow!(
+            "this branch is protected; autosquash is not permitted"
+        ));
+    }
+
+    repo.check_repository_state()?;
+    stack.check_head_top_mismatch()?;
+    stupid.statuses(None)?.check_index_and_worktree_clean()?;
+
+    if !matches.get_flag("interactive") && !super::rebase::has_autosquash_patches(&stack) {
+        crate::print_info_message(matches, "No patches to autosquash");
+        return Ok(());
+    }
+
+    let applied = stack.applied().to_vec();
+    let mut operation = Operation::new("autosquash", &stack)?;
+    operation.committer_date_is_author_date = matches.get_flag("committer-date-is-author-date");
+
+    let stack = stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            trans.pop_patches(|pn| applied.contains(pn))?;
+            Ok(())
+        })
+        .execute("autosquash (pop)")?;
+
+    super::rebase::interactive_pushback(
+        stack,
+        &repo,
+        matches,
+        &applied,
+        argset::resolve_allow_push_conflicts(&config, matches),
+        true,
+        operation,
+    )
+}
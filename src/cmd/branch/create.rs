@@ -9,9 +9,9 @@ use bstr::ByteSlice;
 
 use crate::{
     ext::RepositoryExtended,
-    patch::SingleRevisionSpec,
+    patch::{PatchName, SingleRevisionSpec},
     print_info_message,
-    stack::{InitializationPolicy, Stack, StackAccess},
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
     wrap::{Branch, PartialRefName},
 };
@@ -21,7 +21,10 @@ pub(super) fn command() -> clap::Command {
         .short_flag('c')
         .override_usage(super::super::make_usage(
             "stg branch --create",
-            &["<new-branch> [committish]"],
+            &[
+                "<new-branch> [committish]",
+                "<new-branch> --on <branch:patch>",
+            ],
         ))
         .about("Create and switch to a new branch")
         .long_about(
@@ -32,7 +35,12 @@ pub(super) fn command() -> clap::Command {
              StGit attempts to detect the branch from which the new branch forked, as \
              well as the remote repository of that parent branch such that 'stg pull' \
              will pull from the correct remote branch. A warning will be printed if \
-             the parent branch cannot be determined.",
+             the parent branch cannot be determined.\n\
+             \n\
+             With '--on', the new branch is stacked on a patch of another branch's \
+             stack. The new branch is based on the patch's commit and the parent \
+             branch and patch are recorded such that 'stg restack' can later rebase \
+             the new branch onto the patch's updated commit.",
         )
         .arg(
             clap::Arg::new("new-branch")
@@ -45,6 +53,13 @@ pub(super) fn command() -> clap::Command {
                 .help("Base commit for new branch")
                 .value_parser(clap::value_parser!(SingleRevisionSpec)),
         )
+        .arg(
+            clap::Arg::new("on")
+                .long("on")
+                .help("Stack new branch on a patch of another branch")
+                .value_name("branch:patch")
+                .conflicts_with("committish"),
+        )
 }
 
 pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
@@ -69,12 +84,22 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
     let statuses = stupid.statuses(None)?;
     statuses.check_conflicts()?;
 
+    let parent_patch = matches
+        .get_one::<String>("on")
+        .map(|on| {
+            statuses.check_worktree_clean()?;
+            resolve_parent_patch(repo, on)
+        })
+        .transpose()?;
+
     let maybe_committish = matches.get_one::<SingleRevisionSpec>("committish");
     let maybe_committish_str = matches
         .get_raw("committish")
         .map(|raw_values| raw_values.into_iter().next().unwrap().to_str().unwrap());
 
-    let parent_branch = if let Some(committish_str) = maybe_committish_str {
+    let parent_branch = if let Some((parent_stack, _)) = parent_patch.as_ref() {
+        Some(repo.get_branch(&parent_stack.get_branch_name().parse()?)?)
+    } else if let Some(committish_str) = maybe_committish_str {
         statuses.check_worktree_clean()?;
         if let Some(parent_reference) =
             repo.find_reference(committish_str)
@@ -104,19 +129,27 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
         repo.get_current_branch().ok()
     };
 
-    let (target_commit, target_name) = if let Some(parent_branch) = parent_branch.as_ref() {
-        (
-            Rc::new(parent_branch.get_commit()?),
-            parent_branch.get_branch_name()?,
-        )
-    } else if let Some(committish) = maybe_committish {
-        (
-            committish.resolve(repo, None::<&Stack>)?.commit,
-            maybe_committish_str.unwrap(),
-        )
-    } else {
-        (Rc::new(repo.head_commit()?), "HEAD")
-    };
+    let on_name;
+    let (target_commit, target_name) =
+        if let Some((parent_stack, patchname)) = parent_patch.as_ref() {
+            on_name = format!("{}:{patchname}", parent_stack.get_branch_name());
+            (
+                parent_stack.get_patch_commit(patchname).clone(),
+                on_name.as_str(),
+            )
+        } else if let Some(parent_branch) = parent_branch.as_ref() {
+            (
+                Rc::new(parent_branch.get_commit()?),
+                parent_branch.get_branch_name()?,
+            )
+        } else if let Some(committish) = maybe_committish {
+            (
+                committish.resolve(repo, None::<&Stack>)?.commit,
+                maybe_committish_str.unwrap(),
+            )
+        } else {
+            (Rc::new(repo.head_commit()?), "HEAD")
+        };
 
     repo.edit_reference(gix::refs::transaction::RefEdit {
         change: gix::refs::transaction::Change::Update {
@@ -146,7 +179,15 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
         }
     };
 
-    if let Some(parent_branch) = parent_branch.as_ref() {
+    if let Some((parent_stack, patchname)) = parent_patch.as_ref() {
+        let parent_branchname = parent_stack.get_branch_name().parse::<PartialRefName>()?;
+        super::set_stgit_parent(repo, new_branchname, Some(&parent_branchname))?;
+        stack.set_parent_patch(patchname)?;
+        print_info_message(
+            matches,
+            &format!("Recording `{parent_branchname}:{patchname}` as parent patch"),
+        );
+    } else if let Some(parent_branch) = parent_branch.as_ref() {
         let parent_branchname = parent_branch.get_branch_partial_name().ok();
         super::set_stgit_parent(repo, new_branchname, parent_branchname.as_ref())?;
         if let Some(upstream_name) = set_upstream(parent_branch, &new_branch, repo)? {
@@ -171,6 +212,28 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
     }
 }
 
+/// Resolve the `<parent-branch>:<patch>` argument of `--on`.
+fn resolve_parent_patch<'repo>(
+    repo: &'repo gix::Repository,
+    on: &str,
+) -> Result<(Stack<'repo>, PatchName)> {
+    let (branchname, patchname) = on
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected `<branch>:<patch>`, got `{on}`"))?;
+    let parent_stack = Stack::from_branch_name(
+        repo,
+        &branchname.parse()?,
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let patchname: PatchName = patchname.parse()?;
+    if !parent_stack.has_patch(&patchname) {
+        return Err(anyhow!(
+            "patch `{patchname}` does not exist in branch `{branchname}`"
+        ));
+    }
+    Ok((parent_stack, patchname))
+}
+
 fn set_upstream(
     from_branch: &Branch,
     to_branch: &Branch,
@@ -31,7 +31,8 @@ pub(super) fn command() -> clap::Command {
              configured and the worktree must be clean. The parent branch will be \
              checked-out after the current branch is deleted.\n\
              \n\
-             A protected branch may not be deleted; it must be unprotected first.",
+             A protected branch may not be deleted; it must be unprotected first. A \
+             branch checked out in another worktree may not be deleted.",
         )
         .arg(
             clap::Arg::new("branch-any")
@@ -63,6 +64,13 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
     let config_snapshot = repo.config_snapshot();
     let stupid = repo.stupid();
 
+    if let Some(path) = repo.find_other_worktree(target_branch.get_reference_name())? {
+        return Err(anyhow!(
+            "cannot delete branch `{target_branchname}` checked out in worktree `{}`",
+            path.display()
+        ));
+    }
+
     let switch_to_branch = if Some(&target_branchname) == current_branchname.as_ref() {
         if let Some(parent_branch) = get_stgit_parent(&config_snapshot, &target_branchname) {
             let statuses = stupid.statuses(None)?;
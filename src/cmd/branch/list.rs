@@ -6,12 +6,15 @@ use std::io::Write;
 
 use anyhow::Result;
 use bstr::ByteSlice;
+use serde::Serialize;
 use termcolor::WriteColor;
 
 use crate::{
     ext::RepositoryExtended,
+    format,
+    patch::PatchName,
     stack::{InitializationPolicy, Stack},
-    wrap::Branch,
+    wrap::{Branch, PartialRefName},
 };
 
 pub(super) fn command() -> clap::Command {
@@ -21,10 +24,27 @@ pub(super) fn command() -> clap::Command {
         .about("List branches in this repository")
         .long_about(
             "List each branch in the current repository along with its description, if \
-             any. The current branch is prefixed with '>'. Branches initialized with \
-             StGit stacks are prefixed with 's'. Protected branches are prefixed with \
-             'p'.",
+             any. The current branch is prefixed with '>'. Branches checked out in \
+             another worktree are prefixed with '+'. Branches initialized with StGit \
+             stacks are prefixed with 's'. Protected branches are prefixed with 'p'.\n\
+             \n\
+             Branches stacked on a patch of another branch, as created with 'stg branch \
+             --create <new-branch> --on <branch>:<patch>', are listed indented below \
+             their parent branch along with the name of the patch they are stacked on.",
         )
+        .arg(format::format_arg())
+}
+
+/// Record for `--format` output.
+#[derive(Serialize)]
+struct BranchRecord {
+    name: String,
+    current: bool,
+    stack: bool,
+    protected: bool,
+    description: String,
+    stacked_on: String,
+    worktree: String,
 }
 
 pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
@@ -39,7 +59,6 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
     }
 
     branchnames.sort();
-    let branchname_width = branchnames.iter().map(|name| name.as_ref().len()).max();
 
     let current_branch = repo.get_current_branch().ok();
     let current_branchname = current_branch
@@ -48,10 +67,71 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
 
     let config = repo.config_snapshot();
 
+    let stacks: Vec<Option<Stack>> = branchnames
+        .iter()
+        .map(|branchname| {
+            Stack::from_branch_name(repo, branchname, InitializationPolicy::RequireInitialized).ok()
+        })
+        .collect();
+    let parent_patches: Vec<Option<(String, PatchName)>> = stacks
+        .iter()
+        .map(|stack| {
+            stack
+                .as_ref()
+                .and_then(|stack| stack.get_parent_patch(&config))
+        })
+        .collect();
+    let worktree_branches = repo.get_other_worktree_branches()?;
+    let other_worktree = |branchname: &PartialRefName| {
+        let refname = format!("refs/heads/{branchname}");
+        worktree_branches
+            .iter()
+            .find(|(worktree_refname, _)| worktree_refname.as_bstr() == refname.as_bytes())
+            .map(|(_, path)| path)
+    };
+
+    if let Some(format) = format::get_format(matches) {
+        let records: Vec<BranchRecord> = branchnames
+            .iter()
+            .zip(stacks.iter().zip(parent_patches.iter()))
+            .map(|(branchname, (stack, parent_patch))| BranchRecord {
+                name: branchname.to_string(),
+                current: Some(branchname) == current_branchname.as_ref(),
+                stack: stack.is_some(),
+                protected: stack
+                    .as_ref()
+                    .is_some_and(|stack| stack.is_protected(&config)),
+                description: config
+                    .string_by("branch", Some(branchname.into()), "description")
+                    .unwrap_or_default()
+                    .to_str_lossy()
+                    .into_owned(),
+                stacked_on: parent_patch
+                    .as_ref()
+                    .map(|(parent_branchname, patchname)| {
+                        format!("{parent_branchname}:{patchname}")
+                    })
+                    .unwrap_or_default(),
+                worktree: other_worktree(branchname)
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        return format.write_list(&mut std::io::stdout().lock(), &records);
+    }
+
     let mut stdout = crate::color::get_color_stdout(matches);
     let mut color_spec = termcolor::ColorSpec::new();
 
-    for branchname in &branchnames {
+    let order = tree_order(&branchnames, &parent_patches);
+
+    let branchname_width = order
+        .iter()
+        .map(|(i, depth)| depth * 2 + branchnames[*i].as_ref().len())
+        .max();
+
+    for (i, depth) in order {
+        let branchname = &branchnames[i];
         let is_current = Some(branchname) == current_branchname.as_ref();
 
         if is_current {
@@ -59,13 +139,16 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
             write!(stdout, "> ")?;
             color_spec.clear();
             stdout.set_color(&color_spec)?;
+        } else if other_worktree(branchname).is_some() {
+            stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Cyan)))?;
+            write!(stdout, "+ ")?;
+            color_spec.clear();
+            stdout.set_color(&color_spec)?;
         } else {
             write!(stdout, "  ")?;
         };
 
-        if let Ok(stack) =
-            Stack::from_branch_name(repo, branchname, InitializationPolicy::RequireInitialized)
-        {
+        if let Some(stack) = &stacks[i] {
             color_spec.set_fg(Some(termcolor::Color::Cyan));
             stdout.set_color(&color_spec)?;
             write!(stdout, "s")?;
@@ -88,7 +171,8 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
             stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Green)))?;
         }
         let branchname_width = branchname_width.expect("max is Some when !branchnames.is_empty()");
-        write!(stdout, "{branchname:branchname_width$}")?;
+        let indented_branchname = format!("{:indent$}{branchname}", "", indent = depth * 2);
+        write!(stdout, "{indented_branchname:branchname_width$}")?;
         if is_current {
             color_spec.clear();
             stdout.set_color(&color_spec)?;
@@ -100,6 +184,10 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
         color_spec.clear();
         stdout.set_color(&color_spec)?;
 
+        if let Some((_, patchname)) = &parent_patches[i] {
+            write!(stdout, " (on {patchname})")?;
+        }
+
         let description = config
             .string_by("branch", Some(branchname.into()), "description")
             .unwrap_or_default();
@@ -114,3 +202,45 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
 
     Ok(())
 }
+
+/// Order branches as a tree, with stacked branches below their parent branch.
+///
+/// Returns the index and tree depth of each branch, in display order.
+fn tree_order(
+    branchnames: &[PartialRefName],
+    parent_patches: &[Option<(String, PatchName)>],
+) -> Vec<(usize, usize)> {
+    let parent_index = |i: usize| {
+        parent_patches[i]
+            .as_ref()
+            .and_then(|(parent_branchname, _)| {
+                branchnames
+                    .iter()
+                    .position(|name| name.as_ref() == parent_branchname)
+            })
+    };
+
+    let mut order: Vec<(usize, usize)> = Vec::with_capacity(branchnames.len());
+    let mut pending: Vec<(usize, usize)> = Vec::new();
+    // Branches stacked on each other in a cycle have no root; they are started from
+    // their first branch once all other branches are ordered.
+    while let Some(root) = (0..branchnames.len())
+        .filter(|i| !order.iter().any(|(j, _)| j == i))
+        .find(|i| parent_index(*i).is_none())
+        .or_else(|| (0..branchnames.len()).find(|i| !order.iter().any(|(j, _)| j == i)))
+    {
+        pending.push((root, 0));
+        while let Some((i, depth)) = pending.pop() {
+            if order.iter().any(|(j, _)| *j == i) {
+                continue;
+            }
+            order.push((i, depth));
+            for child in (0..branchnames.len()).rev() {
+                if parent_index(child) == Some(i) {
+                    pending.push((child, depth + 1));
+                }
+            }
+        }
+    }
+    order
+}
@@ -11,6 +11,7 @@ mod list;
 mod protect;
 mod rename;
 mod unprotect;
+mod worktree;
 
 use anyhow::Result;
 use bstr::ByteSlice;
@@ -52,6 +53,7 @@ fn make() -> clap::Command {
                 "[--merge] <branch>",
                 "{--list,-l}",
                 "{--create,-c} <new-branch> [committish]",
+                "{--create,-c} <new-branch> --on <branch:patch>",
                 "{--clone,-C} [new-branch]",
                 "{--rename,-r} [old-name] <new-name>",
                 "{--protect,-p} [branch]",
@@ -59,6 +61,7 @@ fn make() -> clap::Command {
                 "{--delete,-D} [--force] [branch]",
                 "--cleanup [--force] [branch]",
                 "{--describe,-d} <description> [branch]",
+                "--worktree <path> <branch>",
             ],
         ))
         .subcommand(self::list::command())
@@ -70,6 +73,7 @@ fn make() -> clap::Command {
         .subcommand(self::delete::command())
         .subcommand(self::cleanup::command())
         .subcommand(self::describe::command())
+        .subcommand(self::worktree::command())
         .arg(
             clap::Arg::new("merge")
                 .long("merge")
@@ -98,6 +102,7 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
             "--delete" => self::delete::dispatch(&repo, submatches),
             "--cleanup" => self::cleanup::dispatch(&repo, submatches),
             "--describe" => self::describe::dispatch(&repo, submatches),
+            "--worktree" => self::worktree::dispatch(&repo, submatches),
             s => panic!("unhandled branch subcommand {s}"),
         }
     } else if let Some(target_branch_loc) = matches.get_one::<BranchLocator>("branch-any") {
@@ -109,5 +109,45 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
         stupid.branch_move(Some(old_branchname.as_ref()), new_branchname.as_ref())?;
     }
     super::set_stgit_parent(repo, new_branchname, parent_branchname.as_ref())?;
+    follow_renamed_parent_branch(repo, old_branchname, new_branchname)?;
     Ok(())
 }
+
+/// Update the parent branch of branches whose parent branch was renamed.
+fn follow_renamed_parent_branch(
+    repo: &gix::Repository,
+    old_branchname: &PartialRefName,
+    new_branchname: &PartialRefName,
+) -> Result<()> {
+    let mut local_config_file = repo.local_config_file().context("opening local config")?;
+    let mut subsections: Vec<String> = Vec::new();
+    if let Some(sections) = local_config_file.sections_by_name("branch") {
+        for section in sections {
+            if let Some(subsection) = section
+                .header()
+                .subsection_name()
+                .and_then(|name| name.to_str().ok())
+                .filter(|name| name.ends_with(".stgit"))
+            {
+                if section.value("parentbranch").as_deref()
+                    == Some(old_branchname.as_ref().as_bytes().as_bstr())
+                {
+                    subsections.push(subsection.to_string());
+                }
+            }
+        }
+    }
+    if subsections.is_empty() {
+        return Ok(());
+    }
+    for subsection in subsections {
+        local_config_file.set_raw_value_by(
+            "branch",
+            Some(subsection.as_str().into()),
+            "parentbranch",
+            new_branchname.as_ref(),
+        )?;
+    }
+    repo.write_local_config(local_config_file)
+        .context("writing local config")
+}
@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg branch --worktree` implementation.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    branchloc::BranchLocator,
+    ext::RepositoryExtended,
+    stack::{InitializationPolicy, Stack, StackAccess},
+    stupid::Stupid,
+};
+
+pub(super) fn command() -> clap::Command {
+    clap::Command::new("--worktree")
+        .override_usage(super::super::make_usage(
+            "stg branch --worktree",
+            &["<path> <branch>"],
+        ))
+        .about("Create a linked worktree for a branch's stack")
+        .long_about(
+            "Create a linked worktree at <path> with <branch> checked out, such that \
+             the branch's stack may be worked on in that worktree.\n\
+             \n\
+             The branch must be StGit-enabled and must not be checked out in the \
+             current worktree or any other worktree. Operations that would move the \
+             head of a branch checked out in another worktree, such as pushing or \
+             popping patches, are refused outside of that worktree. Operations that \
+             leave the applied patches unchanged, e.g. 'stg new --noapply' or 'stg \
+             edit' of an unapplied patch, remain possible from any worktree.",
+        )
+        .arg(
+            clap::Arg::new("path")
+                .help("Path of the new worktree")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::Arg::new("branch-any")
+                .help("Branch to check out in the new worktree")
+                .value_name("branch")
+                .required(true)
+                .value_parser(clap::value_parser!(BranchLocator)),
+        )
+}
+
+pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
+    let path = matches
+        .get_one::<PathBuf>("path")
+        .expect("required argument");
+    let branch_loc = matches
+        .get_one::<BranchLocator>("branch-any")
+        .expect("required argument");
+    let stack = Stack::from_branch_locator(
+        repo,
+        Some(branch_loc),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let branchname = stack.get_branch_name();
+
+    if repo
+        .get_current_branch()
+        .is_ok_and(|branch| branch.get_reference_name() == stack.get_branch_refname())
+    {
+        return Err(anyhow!(
+            "branch `{branchname}` is checked out in the current worktree"
+        ));
+    }
+    if let Some(other_path) = repo.find_other_worktree(stack.get_branch_refname())? {
+        return Err(anyhow!(
+            "branch `{branchname}` is already checked out in worktree `{}`",
+            other_path.display()
+        ));
+    }
+
+    repo.stupid().worktree_add(path, branchname)
+}
@@ -639,6 +639,8 @@ _stg ()
         --color)
             ((i++))
             ;;
+        --dry-run)
+            ;;
         -C)
             __C_args[C_args_count++]=-C
             ((i++))
@@ -663,7 +665,7 @@ _stg ()
 
         case "$cur" in
         --*)
-            mapfile -t COMPREPLY < <(compgen -W "'--version ' --color= '--help " -- "$cur")
+            mapfile -t COMPREPLY < <(compgen -W "'--version ' --color= '--dry-run ' '--help " -- "$cur")
             ;;
         *)
             mapfile -t COMPREPLY < <(compgen -S ' ' -W "$(__stg completion list commands-and-aliases)" -- "$cur")
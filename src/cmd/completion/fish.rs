@@ -76,7 +76,7 @@ end
 function __fish_stg_needs_command
     set -l cmd (commandline -opc)
     set -e cmd[1]
-    argparse -s C=+ color= h/help version -- $cmd 2>/dev/null
+    argparse -s C=+ color= dry-run h/help version -- $cmd 2>/dev/null
     or return 0
     set -q _flag_version; and return 1
     if set -q argv[1]
@@ -195,6 +195,7 @@ end
 complete -c stg -n __fish_stg_needs_command -xa '(stg completion list commands-and-aliases --style=fish)'
 complete -c stg -n __fish_stg_needs_command -s C -xa '(__fish_complete_directories)' -d 'Run as if started in this directory'
 complete -c stg -n __fish_stg_needs_command -l color -a 'auto always ansi never' -d 'When to colorize output'
+complete -c stg -n __fish_stg_needs_command -l dry-run -d 'Show what the command would do without changing anything'
 complete -c stg -n __fish_stg_needs_command -l version -d 'Print version information'
 complete -c stg -n __fish_stg_needs_command -s h -l help -d 'Print help information'
 
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg deps` implementation.
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    ext::RepositoryExtended,
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, PatchDependencies, Stack, StackAccess, StackStateAccess},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "deps",
+    category: super::CommandCategory::StackInspection,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Show patch dependencies")
+        .long_about(
+            "Show the patches that a patch depends on.\n\
+             \n\
+             A patch depends on another patch lower in the stack when the lines it \
+             modifies overlap or are adjacent to lines modified by the other patch. \
+             Such a patch cannot be pushed without the patches it depends on also \
+             being applied beneath it. Dependencies are determined for applied and \
+             unapplied patches according to their order in the stack.\n\
+             \n\
+             The dependencies of the topmost patch are shown by default.\n\
+             \n\
+             The `--with-deps` option of `stg float`, `stg sink`, `stg push`, and \
+             `stg pop` uses these dependencies to also move the patches that a \
+             patch needs (or that need it).",
+        )
+        .arg(
+            Arg::new("patch")
+                .help("Patch to show dependencies of")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("transitive")
+                .long("transitive")
+                .short('t')
+                .help("Show indirect dependencies as well")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("reverse")
+                .long("reverse")
+                .short('r')
+                .help("Show patches depending on the patch")
+                .long_help(
+                    "Show the patches that depend on the patch instead of the patches \
+                     the patch depends on.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help("Check that the patch applies with only its dependencies")
+                .long_help(
+                    "Check that the patch, along with its transitive dependencies, \
+                     applies cleanly to the stack base. The check is performed in a \
+                     temporary index; the index and working tree are not touched.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["transitive", "reverse"]),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
+
+    let patchname: PatchName = if let Some(locator) = matches.get_one::<PatchLocator>("patch") {
+        locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::Visible)?
+    } else if let Some(patchname) = stack.applied().last() {
+        patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let deps = PatchDependencies::compute(&repo, &stack)?;
+
+    if matches.get_flag("check") {
+        let closure = deps.with_dependencies([&patchname]);
+        return if let Some(failed_patchname) =
+            PatchDependencies::check_applies(&repo, &stack, stack.base(), &closure)?
+        {
+            Err(anyhow!(
+                "`{failed_patchname}` does not apply cleanly to the stack base \
+                 with only the dependencies of `{patchname}`"
+            ))
+        } else {
+            Ok(())
+        };
+    }
+
+    let reverse_flag = matches.get_flag("reverse");
+    let patchnames: Vec<PatchName> = if matches.get_flag("transitive") {
+        let mut closure = if reverse_flag {
+            deps.with_dependents([&patchname])
+        } else {
+            deps.with_dependencies([&patchname])
+        };
+        closure.retain(|pn| pn != &patchname);
+        closure
+    } else if reverse_flag {
+        deps.dependents(&patchname).into_iter().cloned().collect()
+    } else {
+        deps.dependencies(&patchname).to_vec()
+    };
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for patchname in patchnames {
+        writeln!(stdout, "{patchname}")?;
+    }
+
+    Ok(())
+}
@@ -7,9 +7,11 @@ use std::{io::Write, rc::Rc};
 use anyhow::Result;
 use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
+use serde::Serialize;
 
 use crate::{
     ext::{CommitExtended, RepositoryExtended},
+    format,
     patch::SingleRevisionSpec,
     stack::Stack,
     stupid::Stupid,
@@ -55,6 +57,14 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .conflicts_with("stat"),
         )
+        .arg(format::format_arg().conflicts_with_all(["stat", "bare"]))
+}
+
+/// Record for `--format` output.
+#[derive(Serialize)]
+struct FileRecord {
+    status: String,
+    path: String,
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -69,6 +79,20 @@ fn run(matches: &ArgMatches) -> Result<()> {
         Rc::new(repo.head_commit()?)
     };
     let parent = commit.get_parent_commit()?;
+
+    if let Some(format) = format::get_format(matches) {
+        let records: Vec<FileRecord> = repo
+            .stupid()
+            .diff_tree_name_status(parent.tree_id()?.detach(), commit.tree_id()?.detach())?
+            .into_iter()
+            .map(|(status, path)| FileRecord {
+                status: status.to_str_lossy().into_owned(),
+                path: path.to_str_lossy().into_owned(),
+            })
+            .collect();
+        return format.write_list(&mut std::io::stdout().lock(), &records);
+    }
+
     let mut output = repo.stupid().diff_tree_files_status(
         parent.tree_id()?.detach(),
         commit.tree_id()?.detach(),
@@ -15,7 +15,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{InitializationPolicy, PatchDependencies, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -36,7 +36,10 @@ fn make() -> clap::Command {
              to be floated may currently be either applied or unapplied. The necessary \
              pop and push operations will be performed to float the named patches. \
              Patches not specified will remain applied or unapplied as they were prior \
-             to the float operation.",
+             to the float operation.\n\
+             \n\
+             With '--with-deps', the patches that the floated patches depend on are \
+             floated along with them. See `stg deps`.",
         )
         .override_usage(super::make_usage(
             "stg float",
@@ -67,6 +70,7 @@ fn make() -> clap::Command {
                 .value_hint(clap::ValueHint::FilePath)
                 .value_parser(clap::value_parser!(PathBuf)),
         )
+        .arg(argset::with_deps_arg())
         .arg(argset::keep_arg())
         .arg(argset::committer_date_is_author_date_arg())
 }
@@ -98,6 +102,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
         return Err(anyhow!("no patches to float"));
     }
 
+    let patches = if matches.get_flag("with-deps") {
+        PatchDependencies::compute(&repo, &stack)?.with_dependencies(&patches)
+    } else {
+        patches
+    };
+
     if !keep_flag && (!noapply_flag || patches.iter().any(|pn| stack.is_applied(pn))) {
         statuses.check_index_and_worktree_clean()?;
     }
@@ -9,6 +9,7 @@ use crate::{
     argset,
     color::get_color_stdout,
     ext::RepositoryExtended,
+    operation::{self, Operation},
     patch::{LocationConstraint, PatchLocator, PatchName},
     stack::{InitializationPolicy, Stack, StackStateAccess},
     stupid::Stupid,
@@ -22,8 +23,22 @@ pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
 };
 
 fn make() -> clap::Command {
-    clap::Command::new(STGIT_COMMAND.name)
+    let app = clap::Command::new(STGIT_COMMAND.name)
         .about("Go to patch by pushing or popping as necessary")
+        .long_about(
+            "Go to patch by pushing or popping as necessary.\n\
+             \n\
+             If pushing a patch results in merge conflicts, the conflicts are \
+             written to the work tree and the command halts. Once the conflicts \
+             are resolved, use '--continue' to refresh the resolution into the \
+             conflicting patch and push the remaining patches. Alternatively, use \
+             '--skip' to leave the conflicting patch unapplied or '--abort' to undo \
+             the entire operation.",
+        )
+        .override_usage(super::make_usage(
+            "stg goto",
+            &["[OPTIONS] <patch>", "(--continue | --skip | --abort)"],
+        ))
         .arg(argset::keep_arg())
         .arg(argset::merged_arg())
         .arg(argset::committer_date_is_author_date_arg())
@@ -31,13 +46,19 @@ fn make() -> clap::Command {
         .arg(
             Arg::new("patch")
                 .help("Patch to go to")
-                .required(true)
+                .required_unless_present("resume")
                 .allow_hyphen_values(true)
                 .value_parser(clap::value_parser!(PatchLocator)),
-        )
+        );
+
+    operation::add_args(app, &["patch"])
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return operation::resume(matches, "goto", action, operation::push_remaining);
+    }
+
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
     let stupid = repo.stupid();
@@ -62,7 +83,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .resolve_name(&stack)?
         .constrain(&stack, LocationConstraint::Visible)?;
 
-    stack
+    let mut operation = Operation::new("goto", &stack)?;
+    operation.check_merged = merged_flag;
+    operation.committer_date_is_author_date = committer_date_is_author_date;
+    let mut to_apply: Vec<PatchName> = Vec::new();
+
+    let result = stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .allow_push_conflicts(allow_push_conflicts)
@@ -81,12 +107,13 @@ fn run(matches: &ArgMatches) -> Result<()> {
                     .position(|pn| pn == &patchname)
                     .expect("already determined patch exists and not hidden or applied");
 
-                let to_apply: Vec<PatchName> = trans.unapplied()[0..=pos].to_vec();
+                to_apply = trans.unapplied()[0..=pos].to_vec();
                 trans.push_patches(&to_apply, merged_flag)?;
                 Ok(())
             }
         })
-        .execute("goto")?;
+        .execute("goto");
+    operation.finish(&repo, &to_apply, result)?;
 
     Ok(())
 }
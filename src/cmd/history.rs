@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg history` implementation.
+
+use std::{io::Write, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use bstr::{BString, ByteSlice};
+use clap::{Arg, ArgMatches};
+use termcolor::WriteColor;
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess},
+    stupid::{normalize_for_interdiff, Stupid},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "history",
+    category: super::CommandCategory::PatchInspection,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Show the revisions of a patch")
+        .long_about(
+            "List each distinct commit a patch has had over its lifetime.\n\
+             \n\
+             Every operation that modifies a patch, e.g. 'stg refresh' or 'stg edit', \
+             produces a new commit for the patch. The stack log records each of these \
+             commits. The revisions are numbered starting from 1 for the oldest \
+             revision and are listed along with the time and the stack log message of \
+             the operation that produced them. Patch renames are followed.\n\
+             \n\
+             Use '--interdiff N..M' to show how the patch changed between revisions N \
+             and M. The interdiff is a diff of the two revisions' diffs, ignoring \
+             differences in blob ids and hunk line numbers.\n\
+             \n\
+             Only revisions recorded in the stack log are available. Clearing the \
+             stack log with 'stg log --clear' discards older revisions.",
+        )
+        .arg(
+            Arg::new("patch")
+                .help("Patch to show history of (default: topmost patch)")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(argset::branch_arg())
+        .arg(
+            Arg::new("interdiff")
+                .long("interdiff")
+                .short('i')
+                .help("Show the interdiff between revisions <N> and <M>")
+                .value_name("N..M")
+                .value_parser(parse_revision_range),
+        )
+        .arg(argset::diff_opts_arg())
+}
+
+/// A distinct commit of a patch as recorded in the stack log.
+pub(super) struct PatchRevision<'repo> {
+    /// The patch commit.
+    pub(super) commit: Rc<gix::Commit<'repo>>,
+
+    /// The oldest stack state commit where the patch has this commit, i.e. the state
+    /// of the operation that produced this revision.
+    pub(super) state_commit: Rc<gix::Commit<'repo>>,
+}
+
+fn parse_revision_range(s: &str) -> Result<(usize, usize)> {
+    let (first, second) = s
+        .split_once("..")
+        .ok_or_else(|| anyhow!("'{s}' is not a revision range of the form N..M"))?;
+    let parse_revision = |n: &str| match n.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(anyhow!("'{n}' is not a valid revision number")),
+    };
+    Ok((parse_revision(first)?, parse_revision(second)?))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+
+    let patchname = if let Some(locator) = matches.get_one::<PatchLocator>("patch") {
+        locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::All)?
+    } else if let Some(patchname) = stack.applied().last() {
+        patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let revisions = patch_revisions(&stack, &patchname)?;
+
+    if let Some((first, second)) = matches.get_one::<(usize, usize)>("interdiff").copied() {
+        let get_revision = |n: usize| {
+            revisions.get(n - 1).ok_or_else(|| {
+                anyhow!(
+                    "patch `{patchname}` has {} revision{}, not {n}",
+                    revisions.len(),
+                    if revisions.len() == 1 { "" } else { "s" }
+                )
+            })
+        };
+        let first_revision = get_revision(first)?;
+        let second_revision = get_revision(second)?;
+        let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+        let interdiff = repo.stupid().diff_no_index(
+            (
+                &format!("{patchname}.v{first}"),
+                &patch_content(&repo, &first_revision.commit, &diff_opts)?,
+            ),
+            (
+                &format!("{patchname}.v{second}"),
+                &patch_content(&repo, &second_revision.commit, &diff_opts)?,
+            ),
+            crate::color::use_color(matches),
+        )?;
+        std::io::stdout().write_all(&interdiff)?;
+        return Ok(());
+    }
+
+    let mut stdout = get_color_stdout(matches);
+    let mut color_spec = termcolor::ColorSpec::new();
+    let number_width = revisions.len().to_string().len();
+    for (i, revision) in revisions.iter().enumerate() {
+        let commit_id = revision.commit.id();
+        let time = revision
+            .state_commit
+            .time()?
+            .format(gix::date::time::format::GIT_RFC2822);
+        let message = revision.state_commit.message_raw()?;
+        let summary = message.lines().next().unwrap_or_default();
+        write!(stdout, "{:>number_width$}   ", i + 1)?;
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+        write!(stdout, "{}", commit_id.shorten_or_id())?;
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Blue)))?;
+        write!(stdout, "   {time}")?;
+        stdout.set_color(color_spec.set_fg(None))?;
+        writeln!(stdout, "   {}", summary.to_str_lossy())?;
+    }
+
+    Ok(())
+}
+
+/// Find the distinct commits of a patch recorded in the stack log, oldest first.
+///
+/// The stack state log is walked from the current state back to the oldest available
+/// state. Renames are followed by matching the patch's commit id in states where the
+/// patch's name is not present.
+pub(super) fn patch_revisions<'repo>(
+    stack: &Stack<'repo>,
+    patchname: &PatchName,
+) -> Result<Vec<PatchRevision<'repo>>> {
+    // Each revision is paired with the depth of its producing state in the log.
+    let mut revisions: Vec<(PatchRevision<'repo>, usize)> = Vec::new();
+    let mut patchname = patchname.clone();
+    let mut last_commit_id: Option<gix::ObjectId> = None;
+    let mut state_commit = Rc::new(
+        stack
+            .repo
+            .find_reference(stack.get_stack_refname())?
+            .peel_to_commit()?,
+    );
+
+    for depth in 0.. {
+        let state = StackState::from_commit(stack.repo, &state_commit)?;
+        let patch_commit = if state.has_patch(&patchname) {
+            Some(state.get_patch(&patchname).commit.clone())
+        } else if let Some(last_commit_id) = last_commit_id {
+            // The patch may have been renamed by the operation recorded in the
+            // previous (newer) state.
+            state
+                .all_patches()
+                .find(|pn| state.get_patch(pn).commit.id == last_commit_id)
+                .map(|pn| {
+                    patchname = pn.clone();
+                    state.get_patch(pn).commit.clone()
+                })
+        } else {
+            None
+        };
+
+        let Some(patch_commit) = patch_commit else {
+            break;
+        };
+        last_commit_id = Some(patch_commit.id);
+
+        if let Some((revision, revision_depth)) = revisions
+            .iter_mut()
+            .find(|(rev, _)| rev.commit.id == patch_commit.id)
+        {
+            revision.state_commit = state_commit.clone();
+            *revision_depth = depth;
+        } else {
+            revisions.push((
+                PatchRevision {
+                    commit: patch_commit,
+                    state_commit: state_commit.clone(),
+                },
+                depth,
+            ));
+        }
+
+        if let Some(prev) = state.prev {
+            state_commit = prev;
+        } else {
+            break;
+        }
+    }
+
+    // A revision that reappears, e.g. due to undo, is ordered by the oldest state
+    // that produced it.
+    revisions.sort_by_key(|(_, depth)| std::cmp::Reverse(*depth));
+    Ok(revisions.into_iter().map(|(rev, _)| rev).collect())
+}
+
+/// Render a patch commit's message and normalized diff for use in an interdiff.
+pub(super) fn patch_content(
+    repo: &gix::Repository,
+    commit: &gix::Commit<'_>,
+    diff_opts: &[String],
+) -> Result<BString> {
+    let parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+    let diff = repo.stupid().diff_tree_patch(
+        parent_tree_id,
+        commit.tree_id()?.detach(),
+        None::<Vec<String>>,
+        false,
+        diff_opts,
+    )?;
+    let mut content = BString::from(commit.message_raw()?.to_vec());
+    if !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content.push(b'\n');
+    content.extend_from_slice(&normalize_for_interdiff(&diff));
+    Ok(content)
+}
@@ -4,11 +4,13 @@
 
 use anyhow::Result;
 use clap::{Arg, ArgMatches};
+use serde::Serialize;
 
 use crate::{
     argset,
     branchloc::BranchLocator,
     ext::RepositoryExtended,
+    format,
     patch::SingleRevisionSpec,
     stack::{InitializationPolicy, Stack, StackAccess},
 };
@@ -40,6 +42,14 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(SingleRevisionSpec))
                 .help("StGit revision"),
         )
+        .arg(format::format_arg())
+}
+
+/// Record for `--format` output.
+#[derive(Serialize)]
+struct IdRecord {
+    id: String,
+    kind: String,
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -50,11 +60,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
         InitializationPolicy::AllowUninitialized,
     )?;
 
-    let oid = matches
+    let (oid, kind) = matches
         .get_one::<SingleRevisionSpec>("stgit-revision")
-        .map(|spec| spec.resolve_object(&repo, &stack).map(|object| object.id))
+        .map(|spec| {
+            spec.resolve_object(&repo, &stack)
+                .map(|object| (object.id, object.kind))
+        })
         .transpose()?
-        .unwrap_or_else(|| stack.get_branch_head().id);
+        .unwrap_or_else(|| (stack.get_branch_head().id, gix::object::Kind::Commit));
+
+    if let Some(format) = format::get_format(matches) {
+        let record = IdRecord {
+            id: oid.to_string(),
+            kind: kind.to_string(),
+        };
+        return format.write_one(&mut std::io::stdout().lock(), &record);
+    }
 
     println!("{oid}");
     Ok(())
@@ -14,7 +14,7 @@ use clap::{Arg, ArgGroup};
 use crate::{
     color::get_color_stdout,
     ext::{RepositoryExtended, TimeExtended},
-    patch::{patchedit, PatchName},
+    patch::{autoid::AutoId, patchedit, PatchName},
     print_info_message,
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
@@ -153,6 +153,18 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(PathBuf))
                 .value_hint(clap::ValueHint::DirPath),
         )
+        .arg(
+            Arg::new("auto")
+                .long("auto")
+                .help("Auto generate patch ids for the imported patches")
+                .long_help(
+                    "Auto generate patch ids for the imported patches. Patch ids are \
+                     generated according to the `stgit.autoid.*` configuration \
+                     variables and are unique amongst all branches' stacks.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["name", "stripname"]),
+        )
         .arg(
             Arg::new("stripname")
                 .long("stripname")
@@ -574,7 +586,9 @@ fn create_patch<'repo>(
     let ignore_flag = matches.get_flag("ignore");
     let replace_flag = matches.get_flag("replace");
 
-    let patchname = if !ignore_flag && !replace_flag {
+    let patchname = if matches.get_flag("auto") {
+        AutoId::new(&stack, None)?.next()?
+    } else if !ignore_flag && !replace_flag {
         let disallow_patchnames: Vec<&PatchName> = stack.all_patches().collect();
         patchname.uniquify(&[], &disallow_patchnames)
     } else if ignore_flag && stack.applied().contains(&patchname) {
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg label` implementation.
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+use indexmap::IndexSet;
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{patchrange, PatchName, PatchRange, RangeConstraint},
+    stack::{meta, InitializationPolicy, Stack, StackStateAccess},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "label",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Manage patch labels")
+        .long_about(
+            "Add labels to or remove labels from patches.\n\
+             \n\
+             Labels allow patches to be grouped by topic, e.g. \"perf\" or \
+             \"needs-review\". A patch may have any number of labels. Labels may \
+             contain letters, digits, '-', '_', and '/'.\n\
+             \n\
+             Commands accepting patch ranges may select all patches with a given \
+             label using the `@label:<label>` syntax. For example, `stg push \
+             @label:perf` pushes all unapplied patches labeled \"perf\".\n\
+             \n\
+             Labels are stored in the \"labels\" patch metadata key; see `stg meta`.",
+        )
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("add")
+                .about("Add label to patches")
+                .arg(label_arg())
+                .arg(patchranges_arg()),
+        )
+        .subcommand(
+            clap::Command::new("remove")
+                .about("Remove label from patches")
+                .arg(label_arg())
+                .arg(patchranges_arg()),
+        )
+        .subcommand(
+            clap::Command::new("list")
+                .about("List labels")
+                .long_about(
+                    "List labels in use in the stack. When patches are specified, only \
+                     the labels of those patches are listed.",
+                )
+                .arg(patchranges_arg().help("Patches to list labels of")),
+        )
+        .arg(argset::branch_arg().global(true))
+}
+
+fn label_arg() -> Arg {
+    Arg::new("label")
+        .help("Label name")
+        .required(true)
+        .value_parser(meta::parse_label)
+}
+
+fn patchranges_arg() -> Arg {
+    Arg::new("patchranges")
+        .help("Patches to label (default: topmost patch)")
+        .value_name("patch")
+        .num_args(1..)
+        .allow_hyphen_values(true)
+        .value_parser(clap::value_parser!(PatchRange))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let (subcommand, sub_matches) = matches.subcommand().expect("subcommand is required");
+    let stack = Stack::from_branch_locator(
+        &repo,
+        sub_matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::AllowUninitialized,
+    )?;
+
+    let patchnames: Option<Vec<PatchName>> = sub_matches
+        .get_many::<PatchRange>("patchranges")
+        .map(|range_specs| patchrange::resolve_names(&stack, range_specs, RangeConstraint::All))
+        .transpose()?;
+
+    if subcommand == "list" {
+        let labels: IndexSet<&str> = if let Some(patchnames) = patchnames.as_ref() {
+            patchnames
+                .iter()
+                .flat_map(|pn| stack.get_patch(pn).meta.labels())
+                .collect()
+        } else {
+            stack
+                .all_patches()
+                .flat_map(|pn| stack.get_patch(pn).meta.labels())
+                .collect()
+        };
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        for label in labels {
+            writeln!(stdout, "{label}")?;
+        }
+        return Ok(());
+    }
+
+    let patchnames = if let Some(patchnames) = patchnames {
+        patchnames
+    } else if let Some(patchname) = stack.applied().last() {
+        vec![patchname.clone()]
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let label = sub_matches.get_one::<String>("label").expect("required");
+    let is_add = subcommand == "add";
+
+    let mut updates = Vec::with_capacity(patchnames.len());
+    for patchname in patchnames {
+        let mut patch_meta = stack.get_patch(&patchname).meta.clone();
+        let changed = if is_add {
+            patch_meta.add_label(label)
+        } else {
+            patch_meta.remove_label(label)
+        };
+        if changed {
+            updates.push((patchname, patch_meta));
+        }
+    }
+
+    if updates.is_empty() {
+        return if is_add {
+            Ok(())
+        } else {
+            Err(anyhow!("no patches with label `{label}`"))
+        };
+    }
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            for (patchname, patch_meta) in updates {
+                trans.update_patch_meta(&patchname, patch_meta)?;
+            }
+            Ok(())
+        })
+        .execute(&format!("label {subcommand} {label}"))?;
+
+    Ok(())
+}
@@ -2,15 +2,25 @@
 
 //! `stg log` implementation.
 
+use std::io::Write;
+
 use anyhow::{anyhow, Result};
-use clap::{Arg, ArgMatches};
+use bstr::ByteSlice;
+use clap::{Arg, ArgGroup, ArgMatches};
+use serde::Serialize;
+use termcolor::WriteColor;
 
 use crate::{
     argset,
     branchloc::BranchLocator,
-    ext::RepositoryExtended,
-    patch::{patchrange, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackAccess},
+    color::get_color_stdout,
+    ext::{RepositoryExtended, TimeExtended},
+    format::{self, SignatureRecord},
+    patch::{patchrange, PatchName, PatchRange, RangeConstraint},
+    stack::{
+        statediff::{compare_states, StateChange},
+        InitializationPolicy, LogRetention, Stack, StackAccess, StackState,
+    },
     stupid::Stupid,
 };
 
@@ -32,13 +42,36 @@ fn make() -> clap::Command {
              through historical stack states. The 'stg reset' command may be used to \
              reset the stack directly to a historic state.\n\
              \n\
+             With '--diff', each log entry is summarized by comparing its stack state \
+             with the previous state: the patches that were added, deleted, renamed, \
+             reordered, or moved between the applied, unapplied, and hidden groups, and \
+             the patches that have a new commit. Each new commit is classified by \
+             whether the patch's content, message, or author changed, or whether the \
+             patch was rebased onto a new parent. For patches modified in place, a \
+             diffstat of the change is shown. Since 'stg undo' restores the previous \
+             state, this summary shows what an undo would revert. Use '--diff --full' \
+             to instead show the raw diffs of the stack state trees.\n\
+             \n\
              The '--clear' option may be used to delete the stack's change history. \
              Undo and redo are unavailable on a stack without change history. Clearing \
-             the stack state history cannot be undone.",
+             the stack state history cannot be undone.\n\
+             \n\
+             The '--prune' option deletes only the older part of the stack's change \
+             history, retaining either the newest '--keep' entries or the entries \
+             recorded since the '--older-than' date. The retained entries are rewritten \
+             such that the oldest one no longer has a previous state. Patch commits \
+             referenced only by the deleted entries are then subject to garbage \
+             collection. Pruning cannot be undone. The 'stgit.log.maxEntries' \
+             configuration variable may be set to prune the history automatically \
+             whenever the stack is modified.",
         )
         .override_usage(super::make_usage(
             "stg log",
-            &["[OPTIONS] [--] [patch]...", "--clear"],
+            &[
+                "[OPTIONS] [--] [patch]...",
+                "--clear",
+                "--prune (--keep <n> | --older-than <date>)",
+            ],
         ))
         .arg(
             Arg::new("patchranges-all")
@@ -53,7 +86,7 @@ fn make() -> clap::Command {
             Arg::new("diff")
                 .long("diff")
                 .short('d')
-                .help("Show stack state diffs")
+                .help("Show a summary of changes to the stack in each entry")
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(
@@ -85,8 +118,65 @@ fn make() -> clap::Command {
                 .help("Clear the stack history")
                 // .exclusive(true),
                 .action(clap::ArgAction::SetTrue)
-                .conflicts_with_all(["patchranges-all", "diff", "number", "full", "graphical"]),
+                .conflicts_with_all([
+                    "patchranges-all",
+                    "diff",
+                    "number",
+                    "full",
+                    "graphical",
+                    "format",
+                ]),
+        )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .help("Delete older entries from the stack history")
+                .action(clap::ArgAction::SetTrue)
+                .requires("retention")
+                .conflicts_with_all([
+                    "patchranges-all",
+                    "diff",
+                    "number",
+                    "full",
+                    "graphical",
+                    "format",
+                    "clear",
+                ]),
         )
+        .arg(
+            Arg::new("keep")
+                .long("keep")
+                .help("With --prune, keep the newest <n> entries")
+                .value_name("n")
+                .value_parser(parse_keep)
+                .requires("prune"),
+        )
+        .arg(
+            Arg::new("older-than")
+                .long("older-than")
+                .help("With --prune, delete entries older than <date>")
+                .value_name("date")
+                .requires("prune"),
+        )
+        .group(ArgGroup::new("retention").args(["keep", "older-than"]))
+        .arg(format::format_arg().conflicts_with_all(["diff", "full", "graphical"]))
+}
+
+/// Parse the number of log entries to keep, which must be at least one.
+fn parse_keep(s: &str) -> Result<usize> {
+    match argset::parse_usize(s)? {
+        0 => Err(anyhow!("at least one entry must be kept")),
+        n => Ok(n),
+    }
+}
+
+/// Record for `--format` output.
+#[derive(Serialize)]
+struct LogRecord {
+    id: String,
+    author: SignatureRecord,
+    subject: String,
+    message: String,
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -99,18 +189,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     if matches.get_flag("clear") {
         stack.clear_state_log("clear log")
+    } else if matches.get_flag("prune") {
+        let retention = if let Some(keep) = matches.get_one::<usize>("keep") {
+            LogRetention::Count(*keep)
+        } else {
+            let date = matches
+                .get_one::<String>("older-than")
+                .expect("--prune requires --keep or --older-than");
+            LogRetention::Since(gix::date::Time::parse_time(date)?.seconds)
+        };
+        stack.prune_state_log(retention, "prune log")?;
+        Ok(())
     } else {
-        let pathspecs: Option<Vec<String>> =
-            if let Some(range_specs) = matches.get_many::<PatchRange>("patchranges-all") {
-                Some(
-                    patchrange::resolve_names(&stack, range_specs, RangeConstraint::All)?
-                        .iter()
-                        .map(|pn| format!("patches/{pn}"))
-                        .collect(),
-                )
-            } else {
-                None
-            };
+        let patchnames: Option<Vec<PatchName>> = matches
+            .get_many::<PatchRange>("patchranges-all")
+            .map(|range_specs| patchrange::resolve_names(&stack, range_specs, RangeConstraint::All))
+            .transpose()?;
 
         let simplified_parent_id = stack
             .repo
@@ -121,6 +215,32 @@ fn run(matches: &ArgMatches) -> Result<()> {
             .ok_or_else(|| anyhow!("`{}` does not have any parents", stack.get_stack_refname()))?
             .detach();
 
+        if let Some(format) = format::get_format(matches) {
+            let records = log_records(
+                &repo,
+                simplified_parent_id,
+                patchnames.as_deref(),
+                matches.get_one::<usize>("number").copied(),
+            )?;
+            return format.write_list(&mut std::io::stdout().lock(), &records);
+        }
+
+        if matches.get_flag("diff") && !matches.get_flag("full") {
+            return show_state_diffs(
+                &repo,
+                simplified_parent_id,
+                patchnames.as_deref(),
+                matches.get_one::<usize>("number").copied(),
+                &mut get_color_stdout(matches),
+            );
+        }
+
+        let pathspecs: Option<Vec<String>> = patchnames.map(|patchnames| {
+            patchnames
+                .iter()
+                .map(|pn| format!("patches/{pn}"))
+                .collect()
+        });
         let stupid = repo.stupid();
 
         if matches.get_flag("graphical") {
@@ -138,3 +258,185 @@ fn run(matches: &ArgMatches) -> Result<()> {
         }
     }
 }
+
+/// Collect stack log entry commits, newest first, by walking the simplified stack log.
+///
+/// Each entry commit is paired with its parent entry, if any. When patch names are
+/// provided, only entries that changed at least one of those patches'
+/// `patches/<name>` metadata blobs are included.
+fn log_entries<'repo>(
+    repo: &'repo gix::Repository,
+    simplified_id: gix::ObjectId,
+    patchnames: Option<&[PatchName]>,
+    num_commits: Option<usize>,
+) -> Result<Vec<(gix::Commit<'repo>, Option<gix::Commit<'repo>>)>> {
+    let patch_blob_id = |tree: &gix::Tree<'_>, patchname: &PatchName| -> Result<_> {
+        Ok(tree
+            .lookup_entry_by_path(format!("patches/{patchname}"))?
+            .map(|entry| entry.object_id()))
+    };
+
+    let mut entries = Vec::new();
+    let mut next_id = Some(simplified_id);
+    while let Some(commit_id) = next_id {
+        if num_commits.is_some_and(|n| entries.len() >= n) {
+            break;
+        }
+        let commit = repo.find_commit(commit_id)?;
+        let parent = commit
+            .parent_ids()
+            .next()
+            .map(|id| repo.find_commit(id))
+            .transpose()?;
+        next_id = parent.as_ref().map(|parent| parent.id);
+
+        if let Some(patchnames) = patchnames {
+            let tree = commit.tree()?;
+            let parent_tree = parent.as_ref().map(|parent| parent.tree()).transpose()?;
+            let mut changed = false;
+            for patchname in patchnames {
+                let parent_blob_id = parent_tree
+                    .as_ref()
+                    .map(|parent_tree| patch_blob_id(parent_tree, patchname))
+                    .transpose()?
+                    .flatten();
+                if patch_blob_id(&tree, patchname)? != parent_blob_id {
+                    changed = true;
+                    break;
+                }
+            }
+            if !changed {
+                continue;
+            }
+        }
+
+        entries.push((commit, parent));
+    }
+    Ok(entries)
+}
+
+/// Collect stack log records, newest first.
+fn log_records(
+    repo: &gix::Repository,
+    simplified_id: gix::ObjectId,
+    patchnames: Option<&[PatchName]>,
+    num_commits: Option<usize>,
+) -> Result<Vec<LogRecord>> {
+    let mut records = Vec::new();
+    for (commit, _) in log_entries(repo, simplified_id, patchnames, num_commits)? {
+        let commit_ref = commit.decode()?;
+        let message = commit_ref.message.to_str_lossy().trim_end().to_string();
+        records.push(LogRecord {
+            id: commit.id.to_string(),
+            author: SignatureRecord::new(commit_ref.author()),
+            subject: message.lines().next().unwrap_or_default().to_string(),
+            message,
+        });
+    }
+    Ok(records)
+}
+
+/// Show each stack log entry with a summary of how it changed the stack state.
+///
+/// When patch names are provided, only changes involving those patches are shown.
+fn show_state_diffs(
+    repo: &gix::Repository,
+    simplified_id: gix::ObjectId,
+    patchnames: Option<&[PatchName]>,
+    num_commits: Option<usize>,
+    stdout: &mut termcolor::StandardStream,
+) -> Result<()> {
+    let mut color_spec = termcolor::ColorSpec::new();
+    let stupid = repo.stupid();
+    let entries = log_entries(repo, simplified_id, patchnames, num_commits)?;
+    for (i, (commit, parent)) in entries.iter().enumerate() {
+        let state = StackState::from_commit(repo, commit)?;
+        let prev_state = parent
+            .as_ref()
+            .map(|parent| StackState::from_commit(repo, parent))
+            .transpose()?;
+        let changes = compare_states(repo, prev_state.as_ref(), &state)?;
+
+        if i > 0 {
+            writeln!(stdout)?;
+        }
+        let time = commit
+            .author()?
+            .time
+            .format(gix::date::time::format::GIT_RFC2822);
+        let message = commit.message_raw()?;
+        let summary = message.lines().next().unwrap_or_default();
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+        write!(stdout, "{}", commit.id().shorten_or_id())?;
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Blue)))?;
+        write!(stdout, "   {time}")?;
+        stdout.set_color(color_spec.set_fg(None))?;
+        writeln!(stdout, "   {}", summary.to_str_lossy())?;
+
+        for change in changes.iter().filter(|change| {
+            patchnames.map_or(true, |patchnames| {
+                patchnames.iter().any(|pn| change.involves(pn))
+            })
+        }) {
+            let (label, color) = match change {
+                StateChange::Base { .. } => ("base", None),
+                StateChange::Added { .. } => ("added", Some(termcolor::Color::Green)),
+                StateChange::Deleted { .. } => ("deleted", Some(termcolor::Color::Red)),
+                StateChange::Renamed { .. } => ("renamed", None),
+                StateChange::Moved { .. } => ("moved", None),
+                StateChange::Reordered { .. } => ("reordered", None),
+                StateChange::Modified { .. } => ("modified", Some(termcolor::Color::Cyan)),
+            };
+            write!(stdout, "    ")?;
+            stdout.set_color(color_spec.set_fg(color))?;
+            write!(stdout, "{label:<9}")?;
+            stdout.set_color(color_spec.set_fg(None))?;
+            match change {
+                StateChange::Base { old, new } => {
+                    let old = repo.find_commit(*old)?;
+                    let new = repo.find_commit(*new)?;
+                    writeln!(
+                        stdout,
+                        " {} -> {}",
+                        old.id().shorten_or_id(),
+                        new.id().shorten_or_id()
+                    )?;
+                }
+                StateChange::Added { patchname, group }
+                | StateChange::Deleted { patchname, group } => {
+                    writeln!(stdout, " {patchname} ({group})")?;
+                }
+                StateChange::Renamed { old, new } => writeln!(stdout, " {old} -> {new}")?,
+                StateChange::Moved {
+                    patchname,
+                    from,
+                    to,
+                } => writeln!(stdout, " {patchname} ({from} -> {to})")?,
+                StateChange::Reordered { group, patchnames } => {
+                    write!(stdout, " {group}:")?;
+                    for pn in patchnames {
+                        write!(stdout, " {pn}")?;
+                    }
+                    writeln!(stdout)?;
+                }
+                StateChange::Modified {
+                    patchname,
+                    old_commit,
+                    new_commit,
+                    kinds,
+                } => {
+                    write!(stdout, " {patchname} ({})", kinds.names().join(", "))?;
+                    if kinds.content && !kinds.rebased {
+                        let stat = stupid.diff_tree_shortstat(
+                            old_commit.tree_id()?.detach(),
+                            new_commit.tree_id()?.detach(),
+                        )?;
+                        write!(stdout, ": {stat}")?;
+                    }
+                    writeln!(stdout)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
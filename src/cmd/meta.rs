@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg meta` implementation.
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{meta, InitializationPolicy, Stack, StackStateAccess},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "meta",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Manage patch metadata")
+        .long_about(
+            "Get, set, unset, or list metadata associated with a patch.\n\
+             \n\
+             Patch metadata is a set of key/value pairs recorded in the stack state \
+             alongside each patch. Because the metadata is part of the stack state, \
+             changes to it are recorded in the stack log and may be reverted with \
+             `stg undo`. Metadata follows a patch when it is renamed or picked, and \
+             the metadata of squashed patches is combined. Note that stacks with \
+             patch metadata cannot be read by versions of StGit without patch \
+             metadata support.\n\
+             \n\
+             Keys must start with a letter and may contain letters, digits, '-', \
+             '_', and '.'. The following keys have conventional meanings:\n\
+             \n\
+             note          - Free-form notes about the patch\n\
+             labels        - Whitespace-separated list of labels\n\
+             upstream      - URL of the patch's upstream review\n\
+             sent-version  - Version number of the patch when last sent (a positive \
+             integer)\n\
+             scope         - Newline-separated paths the patch may change; see `stg \
+             scope`\n\
+             \n\
+             The topmost patch is used unless a patch is specified with '--patch'.",
+        )
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("get")
+                .about("Print the value of a metadata key")
+                .arg(
+                    Arg::new("key")
+                        .help("Metadata key")
+                        .required(true)
+                        .value_parser(meta::parse_key),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("set")
+                .about("Set the value of a metadata key")
+                .arg(
+                    Arg::new("key")
+                        .help("Metadata key")
+                        .required(true)
+                        .value_parser(meta::parse_key),
+                )
+                .arg(
+                    Arg::new("value")
+                        .help("Value to set")
+                        .required(true)
+                        .allow_hyphen_values(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("unset")
+                .about("Remove metadata keys")
+                .arg(
+                    Arg::new("key")
+                        .help("Metadata keys to remove")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(meta::parse_key),
+                ),
+        )
+        .subcommand(clap::Command::new("list").about("List all metadata of a patch"))
+        .arg(argset::branch_arg().global(true).display_order(998))
+        .arg(
+            Arg::new("patch")
+                .long("patch")
+                .short('p')
+                .help("Use <patch> instead of the topmost patch")
+                .global(true)
+                .display_order(999)
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let (subcommand, sub_matches) = matches.subcommand().expect("subcommand is required");
+    let stack = Stack::from_branch_locator(
+        &repo,
+        sub_matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::AllowUninitialized,
+    )?;
+
+    let patchname: PatchName = if let Some(locator) = sub_matches.get_one::<PatchLocator>("patch") {
+        locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::All)?
+    } else if let Some(patchname) = stack.applied().last() {
+        patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let mut patch_meta = stack.get_patch(&patchname).meta.clone();
+
+    let reflog_msg = match subcommand {
+        "get" => {
+            let key = sub_matches.get_one::<String>("key").expect("required");
+            let value = patch_meta
+                .get(key)
+                .ok_or_else(|| anyhow!("patch `{patchname}` has no `{key}` metadata"))?;
+            writeln!(std::io::stdout(), "{value}")?;
+            return Ok(());
+        }
+        "list" => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            for (key, value) in patch_meta.iter() {
+                writeln!(stdout, "{key}={value}")?;
+            }
+            return Ok(());
+        }
+        "set" => {
+            let key = sub_matches.get_one::<String>("key").expect("required");
+            let value = sub_matches.get_one::<String>("value").expect("required");
+            meta::validate(key, value)?;
+            patch_meta.set(key, value);
+            format!("meta set {key} {patchname}")
+        }
+        "unset" => {
+            let keys: Vec<&String> = sub_matches
+                .get_many::<String>("key")
+                .expect("required")
+                .collect();
+            for key in &keys {
+                if !patch_meta.unset(key) {
+                    return Err(anyhow!("patch `{patchname}` has no `{key}` metadata"));
+                }
+            }
+            let keys = keys
+                .iter()
+                .map(|key| key.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("meta unset {keys} {patchname}")
+        }
+        _ => panic!("valid subcommand is required"),
+    };
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| trans.update_patch_meta(&patchname, patch_meta))
+        .execute(&reflog_msg)?;
+
+    Ok(())
+}
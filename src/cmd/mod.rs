@@ -7,11 +7,14 @@
 
 use clap::builder::StyledStr;
 
+pub(crate) mod absorb;
+pub(crate) mod autosquash;
 pub(crate) mod branch;
 pub(crate) mod clean;
 pub(crate) mod commit;
 pub(crate) mod completion;
 pub(crate) mod delete;
+pub(crate) mod deps;
 pub(crate) mod diff;
 pub(crate) mod edit;
 pub(crate) mod email;
@@ -21,10 +24,14 @@ pub(crate) mod float;
 pub(crate) mod fold;
 pub(crate) mod goto;
 pub(crate) mod hide;
+pub(crate) mod history;
 pub(crate) mod id;
 pub(crate) mod import;
 pub(crate) mod init;
+pub(crate) mod label;
 pub(crate) mod log;
+pub(crate) mod meta;
+pub(crate) mod r#move;
 pub(crate) mod name;
 pub(crate) mod new;
 pub(crate) mod next;
@@ -34,19 +41,24 @@ pub(crate) mod pop;
 pub(crate) mod prev;
 pub(crate) mod pull;
 pub(crate) mod push;
+pub(crate) mod range_diff;
 pub(crate) mod rebase;
 pub(crate) mod redo;
 pub(crate) mod refresh;
 pub(crate) mod rename;
 pub(crate) mod repair;
 pub(crate) mod reset;
+pub(crate) mod restack;
+pub(crate) mod scope;
 pub(crate) mod series;
 pub(crate) mod show;
 pub(crate) mod sink;
 pub(crate) mod spill;
+pub(crate) mod split;
 pub(crate) mod squash;
 pub(crate) mod sync;
 pub(crate) mod top;
+pub(crate) mod tui;
 pub(crate) mod uncommit;
 pub(crate) mod undo;
 pub(crate) mod unhide;
@@ -84,11 +96,14 @@ pub(crate) struct StGitCommand {
 /// This is used in [`crate::main`] for command line argument parsing and eventual
 /// dispatch of a subcommand.
 pub(crate) const STGIT_COMMANDS: &[StGitCommand] = &[
+    absorb::STGIT_COMMAND,
+    autosquash::STGIT_COMMAND,
     branch::STGIT_COMMAND,
     clean::STGIT_COMMAND,
     commit::STGIT_COMMAND,
     completion::STGIT_COMMAND,
     delete::STGIT_COMMAND,
+    deps::STGIT_COMMAND,
     diff::STGIT_COMMAND,
     edit::STGIT_COMMAND,
     email::STGIT_COMMAND,
@@ -98,10 +113,14 @@ pub(crate) const STGIT_COMMANDS: &[StGitCommand] = &[
     fold::STGIT_COMMAND,
     goto::STGIT_COMMAND,
     hide::STGIT_COMMAND,
+    history::STGIT_COMMAND,
     id::STGIT_COMMAND,
     import::STGIT_COMMAND,
     init::STGIT_COMMAND,
+    label::STGIT_COMMAND,
     log::STGIT_COMMAND,
+    meta::STGIT_COMMAND,
+    r#move::STGIT_COMMAND,
     name::STGIT_COMMAND,
     new::STGIT_COMMAND,
     next::STGIT_COMMAND,
@@ -111,25 +130,40 @@ pub(crate) const STGIT_COMMANDS: &[StGitCommand] = &[
     prev::STGIT_COMMAND,
     pull::STGIT_COMMAND,
     push::STGIT_COMMAND,
+    range_diff::STGIT_COMMAND,
     rebase::STGIT_COMMAND,
     redo::STGIT_COMMAND,
     refresh::STGIT_COMMAND,
     rename::STGIT_COMMAND,
     repair::STGIT_COMMAND,
     reset::STGIT_COMMAND,
+    restack::STGIT_COMMAND,
+    scope::STGIT_COMMAND,
     series::STGIT_COMMAND,
     show::STGIT_COMMAND,
     sink::STGIT_COMMAND,
     spill::STGIT_COMMAND,
+    split::STGIT_COMMAND,
     squash::STGIT_COMMAND,
     sync::STGIT_COMMAND,
     top::STGIT_COMMAND,
+    tui::STGIT_COMMAND,
     uncommit::STGIT_COMMAND,
     undo::STGIT_COMMAND,
     unhide::STGIT_COMMAND,
     version::STGIT_COMMAND,
 ];
 
+/// Names of the builtin commands that support `stg --dry-run`.
+///
+/// These commands only modify the stack via stack transactions, which are discarded
+/// for dry runs.
+pub(crate) const DRY_RUN_COMMANDS: &[&str] = &[
+    "clean", "commit", "delete", "edit", "float", "goto", "hide", "move", "new", "pop", "push",
+    "rebase", "redo", "rename", "reset", "restack", "sink", "squash", "sync", "uncommit", "undo",
+    "unhide",
+];
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum Error {
     #[error("{0}")]
@@ -0,0 +1,362 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg move` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{patchrange, PatchLocator, PatchName, PatchRange, RangeConstraint},
+    stack::{
+        InitializationPolicy, PatchState, Stack, StackAccess, StackStateAccess, TransactionError,
+    },
+    stupid::Stupid,
+    wrap::Message,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "move",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Move patches to another branch's stack")
+        .long_about(
+            "Move the specified patches from the current stack, or the stack of the \
+             branch given with '--branch', to the stack of the '--to-branch' branch.\n\
+             \n\
+             The moved patches keep their names, commits, and metadata, and remain in \
+             the same relative order. They are deleted from the source stack and \
+             inserted into the target stack, with both stacks recording the change in \
+             their stack logs such that it may be undone with 'stg undo' on either \
+             branch.\n\
+             \n\
+             If the target branch is checked out, the moved patches are pushed onto \
+             the top of its stack. Otherwise they are inserted as unapplied patches at \
+             the top of the target stack. The '--above' or '--below' options may be \
+             used to instead place the patches next to a patch of the target stack. \
+             Moved hidden patches remain hidden.\n\
+             \n\
+             Applied patches of the source stack that are above the moved patches are \
+             pushed back once the moved patches are deleted. The move is refused if \
+             any of these patches would not apply cleanly. The target stack is \
+             modified before the source stack, so should deleting the patches from \
+             the source stack still fail, the patches remain in both stacks. Use \
+             'stg undo' on either branch to recover.",
+        )
+        .override_usage(super::make_usage(
+            "stg move",
+            &["[OPTIONS] <patch>... --to-branch <branch> [--above <target> | --below <target>]"],
+        ))
+        .arg(
+            Arg::new("patchranges")
+                .help("Patches to move")
+                .value_name("patch")
+                .num_args(1..)
+                .required(true)
+                .value_parser(clap::value_parser!(PatchRange)),
+        )
+        .arg(
+            Arg::new("to-branch")
+                .long("to-branch")
+                .help("Move patches to the stack of <branch>")
+                .value_name("branch")
+                .required(true)
+                .value_parser(clap::value_parser!(BranchLocator)),
+        )
+        .arg(
+            Arg::new("target-above")
+                .long("above")
+                .short('T')
+                .help("Place patches above <target> patch of the target stack")
+                .value_name("target")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("target-below")
+                .long("below")
+                .short('t')
+                .help("Place patches below <target> patch of the target stack")
+                .value_name("target")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator))
+                .conflicts_with("target-above"),
+        )
+        .arg(argset::branch_arg().help("Move patches from the stack of <branch>"))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let source = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let target = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("to-branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let source_branchname = source.get_branch_name().to_string();
+    let target_branchname = target.get_branch_name().to_string();
+
+    if source.get_branch_refname() == target.get_branch_refname() {
+        return Err(anyhow!(
+            "cannot move patches within branch `{source_branchname}`; \
+             use `stg sink` or `stg float` to reorder patches"
+        ));
+    }
+
+    let current_refname = repo
+        .get_current_branch()
+        .ok()
+        .map(|branch| branch.get_reference_name().to_owned());
+    let is_current = |stack: &Stack| {
+        current_refname
+            .as_ref()
+            .is_some_and(|refname| refname.as_ref() == stack.get_branch_refname())
+    };
+    let source_is_current = is_current(&source);
+    let target_is_current = is_current(&target);
+
+    let range_specs = matches
+        .get_many::<PatchRange>("patchranges")
+        .expect("required argument");
+    let patchnames = patchrange::resolve_names(&source, range_specs, RangeConstraint::All)?;
+    let patchnames: Vec<PatchName> = source
+        .all_patches()
+        .filter(|pn| patchnames.contains(pn))
+        .cloned()
+        .collect();
+
+    for pn in &patchnames {
+        if let Some(colliding) = target.collides(pn) {
+            return Err(anyhow!(
+                "patch `{pn}` collides with existing patch `{colliding}` \
+                 in branch `{target_branchname}`"
+            ));
+        }
+    }
+
+    let is_above = matches.contains_id("target-above");
+    let opt_target: Option<PatchName> = matches
+        .get_one::<PatchLocator>("target-above")
+        .or_else(|| matches.get_one::<PatchLocator>("target-below"))
+        .map(|loc| loc.resolve_name(&target))
+        .transpose()
+        .map_err(|e| anyhow!("target: {e}"))?;
+
+    if let Some(target_patch) = &opt_target {
+        if target.is_hidden(target_patch) {
+            return Err(anyhow!(
+                "cannot move patches next to hidden patch `{target_patch}`"
+            ));
+        }
+        if target.is_applied(target_patch) && !target_is_current {
+            return Err(anyhow!(
+                "cannot move patches next to applied patch `{target_patch}` \
+                 since branch `{target_branchname}` is not checked out"
+            ));
+        }
+    }
+
+    if source_is_current || target_is_current {
+        repo.check_repository_state()?;
+        let statuses = repo.stupid().statuses(None)?;
+        statuses.check_conflicts()?;
+        statuses.check_index_and_worktree_clean()?;
+    }
+    source.check_head_top_mismatch()?;
+    target.check_head_top_mismatch()?;
+    if patchnames.iter().any(|pn| source.is_applied(pn)) {
+        // Fail before the patches are inserted into the target stack.
+        source.check_other_worktree()?;
+        check_source_repush(&source, &patchnames)?;
+    }
+
+    let moved: Vec<(PatchName, PatchState, bool)> = patchnames
+        .iter()
+        .map(|pn| {
+            (
+                pn.clone(),
+                source.get_patch(pn).clone(),
+                source.is_hidden(pn),
+            )
+        })
+        .collect();
+    let visible: Vec<PatchName> = moved
+        .iter()
+        .filter(|(_, _, is_hidden)| !is_hidden)
+        .map(|(pn, _, _)| pn.clone())
+        .collect();
+
+    // Insert the patches into the target stack first such that the patches are not
+    // lost if the insertion fails. A halted transaction, e.g. due to push conflicts,
+    // still records the inserted patches, so the source patches are deleted anyway.
+    let target_result = target
+        .setup_transaction()
+        .use_index_and_worktree(target_is_current)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let insert_pos = if let Some(target_patch) = opt_target
+                .as_ref()
+                .filter(|target_patch| trans.is_unapplied(target_patch))
+            {
+                trans
+                    .unapplied()
+                    .iter()
+                    .position(|pn| pn == target_patch)
+                    .expect("target patch is unapplied")
+                    + usize::from(is_above)
+            } else if target_is_current {
+                0
+            } else {
+                trans.unapplied().len()
+            };
+
+            let visible_moved = moved.iter().filter(|(_, _, is_hidden)| !is_hidden);
+            for (i, (pn, patch, _)) in visible_moved.enumerate() {
+                trans.new_unapplied(pn, patch.commit.id, insert_pos + i)?;
+                trans.update_patch_meta(pn, patch.meta.clone())?;
+            }
+            for (pn, patch, _) in moved.iter().filter(|(_, _, is_hidden)| *is_hidden) {
+                trans.new_unapplied(pn, patch.commit.id, 0)?;
+                trans.update_patch_meta(pn, patch.meta.clone())?;
+                trans.hide_patches(std::slice::from_ref(pn))?;
+            }
+
+            if !target_is_current {
+                return Ok(());
+            }
+            match opt_target.as_ref() {
+                Some(target_patch) if trans.is_applied(target_patch) => {
+                    let mut applied = trans.applied().to_vec();
+                    let target_pos = applied
+                        .iter()
+                        .position(|pn| pn == target_patch)
+                        .expect("target patch is applied")
+                        + usize::from(is_above);
+                    applied.splice(target_pos..target_pos, visible.iter().cloned());
+                    let unapplied: Vec<PatchName> = trans
+                        .unapplied()
+                        .iter()
+                        .filter(|pn| !visible.contains(pn))
+                        .cloned()
+                        .collect();
+                    trans.reorder_patches(Some(&applied), Some(&unapplied), None)
+                }
+                Some(_) => Ok(()),
+                None => trans.push_patches(&visible, false),
+            }
+        })
+        .execute(&format!("move from {source_branchname}"));
+
+    match target_result {
+        Err(e)
+            if !matches!(
+                e.downcast_ref::<TransactionError>(),
+                Some(TransactionError::TransactionHalt { .. })
+            ) =>
+        {
+            return Err(e);
+        }
+        _ => {}
+    }
+
+    source
+        .setup_transaction()
+        .use_index_and_worktree(source_is_current)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let to_push = trans.delete_patches(|pn| patchnames.contains(pn))?;
+            trans.push_patches(&to_push, false)?;
+            Ok(())
+        })
+        .execute(&format!("move to {target_branchname}"))?;
+
+    target_result.map(|_| ())
+}
+
+/// Check that the source stack's patches above the moved patches may be pushed back
+/// once the moved patches are deleted.
+///
+/// The patches are merged in-memory such that a conflict is detected before either stack
+/// is modified.
+fn check_source_repush(source: &Stack, patchnames: &[PatchName]) -> Result<()> {
+    let applied = source.applied();
+    let Some(first_pos) = applied.iter().position(|pn| patchnames.contains(pn)) else {
+        return Ok(());
+    };
+    let repo = source.repo;
+    let mut top_tree_id = if first_pos == 0 {
+        source.base().tree_id()?.detach()
+    } else {
+        source
+            .get_patch_commit(&applied[first_pos - 1])
+            .tree_id()?
+            .detach()
+    };
+    repo.stupid().with_temp_index(|stupid_temp| {
+        for pn in applied[first_pos..]
+            .iter()
+            .filter(|pn| !patchnames.contains(pn))
+        {
+            let commit = source.get_patch_commit(pn);
+            let tree_id = commit.tree_id()?.detach();
+            let old_parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+            top_tree_id = if old_parent_tree_id == top_tree_id || tree_id == top_tree_id {
+                tree_id
+            } else if old_parent_tree_id == tree_id {
+                top_tree_id
+            } else {
+                stupid_temp.read_tree(top_tree_id)?;
+                if stupid_temp.apply_treediff_to_index(old_parent_tree_id, tree_id, true)? {
+                    stupid_temp.write_tree()?
+                } else if let Some(merged_tree_id) =
+                    repo.merge_trees_clean(old_parent_tree_id, top_tree_id, tree_id)?
+                {
+                    merged_tree_id
+                } else if let Some(merged_tree_id) =
+                    merge_tree_commits(source, old_parent_tree_id, top_tree_id, tree_id)?
+                {
+                    merged_tree_id
+                } else {
+                    return Err(anyhow!(
+                        "patch `{pn}` does not apply cleanly once the moved patches are \
+                         deleted from branch `{}`",
+                        source.get_branch_name()
+                    ));
+                }
+            };
+        }
+        Ok(())
+    })
+}
+
+/// Merge trees with `git merge-tree`, which merges as pushing a patch would.
+///
+/// Since `git merge-tree` merges commits, temporary commits are made for our and their
+/// trees with a commit of the base tree as their parent.
+fn merge_tree_commits(
+    stack: &Stack,
+    base_tree_id: gix::ObjectId,
+    our_tree_id: gix::ObjectId,
+    their_tree_id: gix::ObjectId,
+) -> Result<Option<gix::ObjectId>> {
+    let repo = stack.repo;
+    let author = repo.get_author()?;
+    let committer = repo.get_committer()?;
+    let message = Message::from("stg move check");
+    let base_id = repo.commit_ex(author, committer, &message, base_tree_id, [])?;
+    let our_id = repo.commit_ex(author, committer, &message, our_tree_id, [base_id])?;
+    let their_id = repo.commit_ex(author, committer, &message, their_tree_id, [base_id])?;
+    repo.stupid().merge_tree(our_id, their_id)
+}
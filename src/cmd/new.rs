@@ -5,13 +5,14 @@
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
 use clap::{Arg, ArgGroup, ArgMatches};
 
 use super::refresh;
 use crate::{
     color::get_color_stdout,
     ext::{RepositoryExtended, SignatureExtended},
-    patch::{patchedit, PatchName},
+    patch::{patchedit, LocationConstraint, PatchLocator, PatchName},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
@@ -94,8 +95,28 @@ fn make() -> clap::Command {
             Arg::new("auto")
                 .long("auto")
                 .help("Auto generate patch id for the new patch")
+                .long_help(
+                    "Auto generate patch id for the new patch. The generated id is \
+                     governed by the `stgit.autoid.*` configuration variables and is \
+                     unique amongst the patches of all branches' stacks.",
+                )
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("fixup")
+                .long("fixup")
+                .help("Create a fixup patch for <patch>")
+                .long_help(
+                    "Create a patch whose message is \"fixup! \" followed by the subject \
+                     of <patch>. Such patches are squashed into <patch> by `stg \
+                     autosquash` and `stg rebase --autosquash`. The editor is not \
+                     launched unless '--edit' is given.",
+                )
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator))
+                .conflicts_with_all(["message", "file", "save-template"]),
+        )
         .next_help_heading("Refresh Options")
         .arg(
             Arg::new("refresh")
@@ -180,7 +201,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     let patchname = if let Some(patchname) = matches
         .get_one::<PatchName>("patchname")
         .or_else(|| matches.get_one::<PatchName>("name"))
-        .or_else(|| auto_patch_id.as_ref())
+        .or(auto_patch_id.as_ref())
         .cloned()
     {
         if let Some(colliding_patchname) = stack.collides(&patchname) {
@@ -192,27 +213,49 @@ fn run(matches: &ArgMatches) -> Result<()> {
         Ok(None)
     }?;
 
+    let fixup_message = if let Some(locator) = matches.get_one::<PatchLocator>("fixup") {
+        let target_patchname = locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::All)?;
+        let target_commit = stack.get_patch_commit(&target_patchname);
+        let subject = target_commit
+            .message()
+            .map(|message_ref| message_ref.title.to_str_lossy().trim().to_string())
+            .unwrap_or_default();
+        let subject = if subject.is_empty() {
+            target_patchname.to_string()
+        } else {
+            subject
+        };
+        Some(format!("fixup! {subject}\n"))
+    } else {
+        None
+    };
+
     let is_refreshing = matches.get_flag("refresh") || matches.contains_id("pathspecs");
 
     let tree_id = if is_refreshing {
-        refresh::assemble_refresh_tree(&stack, matches, None)?
+        refresh::assemble_refresh_tree(&stack, matches, None, None)?.0
     } else {
         stack.get_branch_head().tree_id()?.detach()
     };
 
     let parent_id = stack.get_branch_head().id;
 
-    let (patchname, commit_id) = match patchedit::EditBuilder::default()
+    let mut edit_builder = patchedit::EditBuilder::default()
         .allow_autosign(true)
         .allow_diff_edit(false)
-        .allow_implicit_edit(true)
+        .allow_implicit_edit(fixup_message.is_none())
         .allow_template_save(!is_refreshing)
         .original_patchname(patchname.as_ref())
         .default_author(repo.get_author()?.override_author(matches))
         .override_tree_id(tree_id)
-        .override_parent_id(parent_id)
-        .edit(&stack, &repo, matches)?
-    {
+        .override_parent_id(parent_id);
+    if let Some(message) = fixup_message {
+        edit_builder = edit_builder.default_message(message);
+    }
+
+    let (patchname, commit_id) = match edit_builder.edit(&stack, &repo, matches)? {
         patchedit::EditOutcome::TemplateSaved(_) => return Ok(()),
         patchedit::EditOutcome::Edited {
             new_patchname,
@@ -17,10 +17,12 @@ use crate::{
     branchloc::BranchLocator,
     color::get_color_stdout,
     ext::{CommitExtended, RepositoryExtended},
+    operation::{self, Operation},
     patch::{
-        revspec, PatchName, RangeConstraint, RangeRevisionSpec, SingleRevisionSpec, StGitRevision,
+        autoid::AutoId, revspec, PatchName, RangeConstraint, RangeRevisionSpec, SingleRevisionSpec,
+        StGitRevision,
     },
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stack::{InitializationPolicy, PatchMeta, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -32,7 +34,7 @@ pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
 };
 
 fn make() -> clap::Command {
-    clap::Command::new(STGIT_COMMAND.name)
+    let app = clap::Command::new(STGIT_COMMAND.name)
         .about("Import a patch from another branch or a commit object")
         .long_about(
             "Import one or more patches from another branch or commit object into the \
@@ -47,7 +49,12 @@ fn make() -> clap::Command {
              option is a format string as may be supplied to the '--pretty' option of \
              'git show'. The default is \"format:%B%n(imported from commit %H)\", \
              which appends the commit hash of the picked commit to the patch's commit \
-             message.",
+             message.\n\
+             \n\
+             If pushing a picked patch results in merge conflicts, resolve the \
+             conflicts and use '--continue' to push the remaining picked patches, \
+             '--skip' to leave the conflicting patch unapplied, or '--abort' to \
+             undo the pick.",
         )
         .override_usage(super::make_usage(
             "stg pick",
@@ -56,13 +63,14 @@ fn make() -> clap::Command {
                 "[OPTIONS] [--name NAME] [--parent COMMITTISH] <source>",
                 "[OPTIONS] --fold [--file PATH]... <source>...",
                 "[OPTIONS] --update <source>...",
+                "(--continue | --skip | --abort)",
             ],
         ))
         .arg(
             Arg::new("stgit-revision")
                 .help("Patch name or committish to import")
                 .value_name("source")
-                .required(true)
+                .required_unless_present("resume")
                 .num_args(1..)
                 .value_parser(clap::value_parser!(RangeRevisionSpec)),
         )
@@ -106,6 +114,18 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(PatchName))
                 .conflicts_with_all(["fold", "update"]),
         )
+        .arg(
+            Arg::new("auto")
+                .long("auto")
+                .help("Auto generate patch ids for the picked patches")
+                .long_help(
+                    "Auto generate patch ids for the picked patches. Patch ids are \
+                     generated according to the `stgit.autoid.*` configuration \
+                     variables and are unique amongst all branches' stacks.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["name", "fold", "update"]),
+        )
         .arg(
             Arg::new("parent")
                 .long("parent")
@@ -138,10 +158,16 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::Append)
                 .value_name("path")
                 .requires("fold"),
-        )
+        );
+
+    operation::add_args(app, &["stgit-revision"])
 }
 
 fn run(matches: &clap::ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return operation::resume(matches, "pick", action, operation::push_remaining);
+    }
+
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::AutoInitialize)?;
     let ref_stack = Stack::from_branch_locator(
@@ -191,7 +217,7 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
                     .map(|rev| rev.commit)
             })
             .transpose()?;
-        pick_picks(stack, matches, opt_parent, &picks)
+        pick_picks(stack, &ref_stack, matches, opt_parent, &picks)
     }
 }
 
@@ -251,6 +277,7 @@ fn fold_picks(stack: &Stack, matches: &clap::ArgMatches, picks: &[StGitRevision]
 
 fn pick_picks(
     stack: Stack,
+    ref_stack: &Stack,
     matches: &clap::ArgMatches,
     opt_parent: Option<Rc<gix::Commit>>,
     picks: &[StGitRevision],
@@ -258,13 +285,31 @@ fn pick_picks(
     let stupid = stack.repo.stupid();
     let config = stack.repo.config_snapshot();
     let patchname_len_limit = PatchName::get_length_limit(&config);
-    let mut new_patches: Vec<(PatchName, gix::ObjectId)> = Vec::with_capacity(picks.len());
+    let mut new_patches: Vec<(PatchName, gix::ObjectId, PatchMeta)> =
+        Vec::with_capacity(picks.len());
+    let mut disallow: Vec<PatchName> = stack.all_patches().cloned().collect();
+    let mut autoid = if matches.get_flag("auto") {
+        Some(AutoId::new(&stack, None)?)
+    } else {
+        None
+    };
 
     for StGitRevision { patchname, commit } in picks {
         let commit_ref = commit.decode()?;
-        let mut disallow: Vec<&PatchName> = stack.all_patches().collect();
-
-        let patchname = if let Some(name) = matches.get_one::<PatchName>("name") {
+        // Metadata follows patches picked from the reference stack, but not reverts.
+        let meta = match patchname {
+            Some(patchname)
+                if !matches.get_flag("revert")
+                    && ref_stack.has_patch(patchname)
+                    && ref_stack.get_patch_commit_id(patchname) == commit.id =>
+            {
+                ref_stack.get_patch(patchname).meta.clone()
+            }
+            _ => PatchMeta::default(),
+        };
+        let patchname = if let Some(autoid) = autoid.as_mut() {
+            autoid.next()?
+        } else if let Some(name) = matches.get_one::<PatchName>("name") {
             name.clone()
         } else if let Some(patchname) = patchname {
             if matches.get_flag("revert") {
@@ -338,25 +383,32 @@ fn pick_picks(
             top.tree_id()?.detach(),
             [bottom.id],
         )?;
-        new_patches.push((patchname, new_commit_id));
-        disallow.push(&new_patches[new_patches.len() - 1].0);
+        disallow.push(patchname.clone());
+        new_patches.push((patchname, new_commit_id, meta));
     }
 
-    stack
+    let to_push: Vec<PatchName> = new_patches
+        .iter()
+        .map(|(patchname, _, _)| patchname.clone())
+        .collect();
+    let operation = Operation::new("pick", &stack)?;
+    let repo = stack.repo;
+
+    let result = stack
         .setup_transaction()
         .with_output_stream(get_color_stdout(matches))
         .use_index_and_worktree(true)
         .transact(|trans| {
-            let mut to_push = Vec::new();
-            for (i, (patchname, commit_id)) in new_patches.iter().enumerate() {
+            for (i, (patchname, commit_id, meta)) in new_patches.iter().enumerate() {
                 trans.new_unapplied(patchname, *commit_id, i)?;
-                to_push.push(patchname);
+                trans.update_patch_meta(patchname, meta.clone())?;
             }
             if !matches.get_flag("noapply") {
                 trans.push_patches(&to_push, false)?;
             }
             Ok(())
         })
-        .execute("pick")?;
+        .execute("pick");
+    operation.finish(repo, &to_push, result)?;
     Ok(())
 }
@@ -12,7 +12,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{InitializationPolicy, PatchDependencies, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -35,7 +35,11 @@ fn make() -> clap::Command {
              performed such that only the patches specified on the command line \
              are unapplied at the end of the operation. It is possible for some \
              of these intermediate push operations to fail due to conflicts if \
-             patches are popped out of last-pushed first-popped order.",
+             patches are popped out of last-pushed first-popped order.\n\
+             \n\
+             With '--with-deps', applied patches that depend on the popped patches \
+             are also popped, avoiding conflicts when the remaining patches are \
+             pushed back. See `stg deps`.",
         )
         .override_usage(super::make_usage(
             "stg pop",
@@ -85,6 +89,7 @@ fn make() -> clap::Command {
                 .help("Keep patches' modifications in index and worktree after popping")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(argset::with_deps_arg())
         .arg(argset::keep_arg())
 }
 
@@ -143,6 +148,14 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     assert!(!patches.is_empty());
 
+    if matches.get_flag("with-deps") {
+        patches = PatchDependencies::compute(&repo, &stack)?
+            .with_dependents(&patches)
+            .into_iter()
+            .filter(|pn| stack.is_applied(pn))
+            .collect();
+    }
+
     let keep_flag = matches.get_flag("keep");
     let spill_flag = matches.get_flag("spill");
     repo.check_repository_state()?;
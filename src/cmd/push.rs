@@ -9,8 +9,9 @@ use crate::{
     argset,
     color::get_color_stdout,
     ext::RepositoryExtended,
+    operation::{self, Operation},
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{InitializationPolicy, PatchDependencies, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -22,7 +23,7 @@ pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
 };
 
 fn make() -> clap::Command {
-    clap::Command::new(STGIT_COMMAND.name)
+    let app = clap::Command::new(STGIT_COMMAND.name)
         .about("Push (apply) one or more unapplied patches")
         .long_about(
             "Push one or more unapplied patches from the series onto the stack.\n\
@@ -34,7 +35,10 @@ fn make() -> clap::Command {
              while pushing a patch, the conflicts are written to the work tree \
              and the push command halts. Conflicts may then be resolved using \
              the normal Git methods, or alternatively the push may be undone \
-             using 'stg undo'.",
+             using 'stg undo'.\n\
+             \n\
+             With '--with-deps', any unapplied patches that the pushed patches depend \
+             on are pushed before them. See `stg deps`.",
         )
         .override_usage(super::make_usage(
             "stg push",
@@ -42,6 +46,7 @@ fn make() -> clap::Command {
                 "[OPTIONS] [patch]...",
                 "[OPTIONS] -n <number>",
                 "[OPTIONS] --all",
+                "(--continue | --skip | --abort)",
             ],
         ))
         .arg(
@@ -110,13 +115,29 @@ fn make() -> clap::Command {
                 )
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(argset::with_deps_arg())
         .arg(argset::keep_arg())
         .arg(argset::merged_arg())
         .arg(argset::committer_date_is_author_date_arg())
-        .arg(argset::push_conflicts_arg())
+        .arg(argset::push_conflicts_arg());
+
+    operation::add_args(
+        app,
+        &[
+            "patchranges-unapplied",
+            "all",
+            "number",
+            "noapply",
+            "set-tree",
+        ],
+    )
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return operation::resume(matches, "push", action, operation::push_remaining);
+    }
+
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
     let stupid = repo.stupid();
@@ -169,6 +190,14 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     assert!(!patches.is_empty());
 
+    if matches.get_flag("with-deps") {
+        patches = PatchDependencies::compute(&repo, &stack)?
+            .with_dependencies(&patches)
+            .into_iter()
+            .filter(|pn| stack.is_unapplied(pn))
+            .collect();
+    }
+
     let reverse_flag = matches.get_flag("reverse");
     let noapply_flag = matches.get_flag("noapply");
     let settree_flag = matches.get_flag("set-tree");
@@ -187,11 +216,15 @@ fn run(matches: &ArgMatches) -> Result<()> {
         patches.reverse();
     }
 
-    stack
+    let mut operation = Operation::new("push", &stack)?;
+    operation.check_merged = merged_flag;
+    operation.committer_date_is_author_date = matches.get_flag("committer-date-is-author-date");
+
+    let result = stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .allow_push_conflicts(allow_push_conflicts)
-        .committer_date_is_author_date(matches.get_flag("committer-date-is-author-date"))
+        .committer_date_is_author_date(operation.committer_date_is_author_date)
         .with_output_stream(get_color_stdout(matches))
         .transact(|trans| {
             if settree_flag {
@@ -214,7 +247,8 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 trans.push_patches(&patches, merged_flag)
             }
         })
-        .execute("push")?;
+        .execute("push");
+    operation.finish(&repo, &patches, result)?;
 
     Ok(())
 }
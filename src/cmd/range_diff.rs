@@ -0,0 +1,426 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg range-diff` implementation.
+
+use std::{collections::BTreeSet, io::Write, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use bstr::{BStr, BString, ByteSlice};
+use clap::{Arg, ArgMatches};
+use termcolor::WriteColor;
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{revspec, PatchName, RangeConstraint, RangeRevisionSpec},
+    stack::{InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "range-diff",
+    category: super::CommandCategory::PatchInspection,
+    make,
+    run,
+};
+
+/// Minimum similarity for pairing patches with different names.
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Compare two versions of a patch series")
+        .long_about(
+            "Compare the patches of two versions of a stack, showing how each patch \
+             changed along with any new, dropped, and reordered patches.\n\
+             \n\
+             Each version may be specified as a stack state from the stack log, e.g. \
+             using a commit id from the output of 'stg log'. The patches of a stack \
+             state are its applied and unapplied patches. A version may also be \
+             specified as a patch range, e.g. 'p1..p4' or '<branch>:..', or as any \
+             StGit revision, e.g. '{base}' or '<branch>:<patch>'. A revision that is \
+             not a stack state selects the commits between it and its merge base with \
+             the current stack's base. When <new> is not specified, the current state \
+             of the stack is used.\n\
+             \n\
+             Patches are paired by name. Remaining patches are paired by the \
+             similarity of their messages and diffs, allowing renamed patches to be \
+             followed.\n\
+             \n\
+             Each pairing is listed in the order of the new version using the \
+             following notation:\n\
+             \n\
+             = - the patch is unchanged\n\
+             ! - the patch changed; an interdiff follows\n\
+             < - the patch was dropped\n\
+             > - the patch is new",
+        )
+        .arg(
+            Arg::new("old")
+                .help("Old version of the series")
+                .value_name("old")
+                .required(true)
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(RangeRevisionSpec)),
+        )
+        .arg(
+            Arg::new("new")
+                .help("New version of the series (default: current stack state)")
+                .value_name("new")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(RangeRevisionSpec)),
+        )
+        .arg(argset::branch_arg())
+        .arg(
+            Arg::new("no-patch")
+                .long("no-patch")
+                .short('s')
+                .help("Only list the patch pairings, without interdiffs")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(argset::diff_opts_arg())
+}
+
+/// A patch of one version of a series.
+struct SeriesPatch<'repo> {
+    name: PatchName,
+    commit: Rc<gix::Commit<'repo>>,
+    content: BString,
+}
+
+/// How a patch of the old series relates to a patch of the new series.
+enum Pairing {
+    Matched { old: usize, new: usize },
+    Dropped(usize),
+    New(usize),
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+
+    let old_series = resolve_series(
+        &repo,
+        &stack,
+        matches
+            .get_one::<RangeRevisionSpec>("old")
+            .expect("required"),
+        &diff_opts,
+    )?;
+    let new_series = if let Some(spec) = matches.get_one::<RangeRevisionSpec>("new") {
+        resolve_series(&repo, &stack, spec, &diff_opts)?
+    } else {
+        stack
+            .applied_and_unapplied()
+            .map(|pn| series_patch(&repo, pn.clone(), stack.get_patch_commit(pn), &diff_opts))
+            .collect::<Result<_>>()?
+    };
+
+    let pairings = pair_patches(&old_series, &new_series);
+    let reordered = find_reordered(&pairings);
+
+    let use_color = crate::color::use_color(matches);
+    let show_patch = !matches.get_flag("no-patch");
+    let stupid = repo.stupid();
+    let old_width = old_series.len().to_string().len();
+    let new_width = new_series.len().to_string().len();
+    let mut stdout = get_color_stdout(matches);
+    let mut color_spec = termcolor::ColorSpec::new();
+
+    for pairing in &pairings {
+        let (old_i, new_i) = match pairing {
+            Pairing::Matched { old, new } => (Some(*old), Some(*new)),
+            Pairing::Dropped(old) => (Some(*old), None),
+            Pairing::New(new) => (None, Some(*new)),
+        };
+        let old = old_i.map(|i| &old_series[i]);
+        let new = new_i.map(|i| &new_series[i]);
+        let (sigil, color) = match (old, new) {
+            (Some(old), Some(new)) if old.content == new.content => ('=', None),
+            (Some(_), Some(_)) => ('!', Some(termcolor::Color::Yellow)),
+            (Some(_), None) => ('<', Some(termcolor::Color::Red)),
+            (None, _) => ('>', Some(termcolor::Color::Green)),
+        };
+
+        // Patches missing from one side use dashes in place of the index and id.
+        let id_len = old
+            .or(new)
+            .unwrap()
+            .commit
+            .id()
+            .shorten_or_id()
+            .to_string()
+            .len();
+        let side = |i: Option<usize>, patch: Option<&SeriesPatch<'_>>, width: usize| {
+            if let (Some(i), Some(patch)) = (i, patch) {
+                format!("{:>width$}:  {}", i + 1, patch.commit.id().shorten_or_id())
+            } else {
+                format!("{:>width$}:  {}", "-", "-".repeat(id_len))
+            }
+        };
+        stdout.set_color(color_spec.set_fg(color))?;
+        write!(
+            stdout,
+            "{} {sigil} {}",
+            side(old_i, old, old_width),
+            side(new_i, new, new_width)
+        )?;
+        stdout.set_color(color_spec.set_fg(None))?;
+
+        match (old, new) {
+            (Some(old), Some(new)) if old.name != new.name => {
+                write!(stdout, " {} -> {}", old.name, new.name)?;
+            }
+            (_, Some(patch)) | (Some(patch), None) => write!(stdout, " {}", patch.name)?,
+            (None, None) => unreachable!(),
+        }
+        if old.is_some() && new_i.is_some_and(|i| reordered.contains(&i)) {
+            write!(stdout, " (reordered)")?;
+        }
+        writeln!(stdout)?;
+
+        if let (true, '!', Some(old), Some(new)) = (show_patch, sigil, old, new) {
+            let interdiff = stupid.diff_no_index(
+                (old.name.as_ref(), &old.content),
+                (new.name.as_ref(), &new.content),
+                use_color,
+            )?;
+            write_indented_hunks(&mut stdout, interdiff.as_bstr())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve a specification of a version of a series into its patches.
+fn resolve_series<'repo>(
+    repo: &'repo gix::Repository,
+    stack: &'repo Stack<'repo>,
+    spec: &RangeRevisionSpec,
+    diff_opts: &[String],
+) -> Result<Vec<SeriesPatch<'repo>>> {
+    let RangeRevisionSpec::Single(single_spec) = spec else {
+        return revspec::resolve(repo, Some(stack), [spec], RangeConstraint::All)?
+            .into_iter()
+            .map(|rev| {
+                let name = rev
+                    .patchname
+                    .expect("patch range revisions have patch names");
+                series_patch(repo, name, &rev.commit, diff_opts)
+            })
+            .collect();
+    };
+
+    let commit = single_spec.resolve(repo, Some(stack))?.commit;
+
+    if commit.tree()?.find_entry("stack.json").is_some() {
+        let state = StackState::from_commit(repo, &commit)?;
+        return state
+            .applied_and_unapplied()
+            .map(|pn| series_patch(repo, pn.clone(), state.get_patch_commit(pn), diff_opts))
+            .collect();
+    }
+
+    let merge_base = repo
+        .stupid()
+        .merge_bases(stack.base().id, commit.id)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            anyhow!(
+                "`{single_spec}` has no common ancestor with the base of `{}`",
+                stack.get_branch_name()
+            )
+        })?;
+    let mut commit_ids = repo
+        .stupid()
+        .rev_list(merge_base, commit.id, None::<Vec<String>>)?;
+    commit_ids.reverse();
+    commit_ids
+        .into_iter()
+        .map(|commit_id| {
+            let commit = repo.find_commit(commit_id)?;
+            let name = stack
+                .all_patches()
+                .find(|pn| stack.get_patch_commit_id(pn) == commit_id)
+                .cloned()
+                .unwrap_or_else(|| {
+                    PatchName::make(
+                        &commit.message_ex().decode().unwrap_or_default(),
+                        true,
+                        None,
+                    )
+                });
+            series_patch(repo, name, &Rc::new(commit), diff_opts)
+        })
+        .collect()
+}
+
+fn series_patch<'repo>(
+    repo: &'repo gix::Repository,
+    name: PatchName,
+    commit: &Rc<gix::Commit<'repo>>,
+    diff_opts: &[String],
+) -> Result<SeriesPatch<'repo>> {
+    Ok(SeriesPatch {
+        name,
+        commit: commit.clone(),
+        content: super::history::patch_content(repo, commit, diff_opts)?,
+    })
+}
+
+/// Pair the patches of the old and new series.
+///
+/// Patches are first paired by name. The remaining patches are then paired greedily by
+/// decreasing similarity. The returned pairings follow the order of the new series,
+/// with each dropped patch following its nearest preceding old patch.
+fn pair_patches(old_series: &[SeriesPatch<'_>], new_series: &[SeriesPatch<'_>]) -> Vec<Pairing> {
+    let mut old_for_new: Vec<Option<usize>> = new_series
+        .iter()
+        .map(|new| old_series.iter().position(|old| old.name == new.name))
+        .collect();
+
+    let unpaired_old: Vec<usize> = (0..old_series.len())
+        .filter(|i| !old_for_new.contains(&Some(*i)))
+        .collect();
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for (new_i, _) in old_for_new
+        .iter()
+        .enumerate()
+        .filter(|(_, old)| old.is_none())
+    {
+        for &old_i in &unpaired_old {
+            let score = similarity(&old_series[old_i].content, &new_series[new_i].content);
+            if score >= SIMILARITY_THRESHOLD {
+                candidates.push((score, old_i, new_i));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (_, old_i, new_i) in candidates {
+        if old_for_new[new_i].is_none() && !old_for_new.contains(&Some(old_i)) {
+            old_for_new[new_i] = Some(old_i);
+        }
+    }
+
+    let mut dropped: Vec<usize> = (0..old_series.len())
+        .filter(|i| !old_for_new.contains(&Some(*i)))
+        .rev()
+        .collect();
+    let mut pairings = Vec::with_capacity(old_series.len() + new_series.len());
+    for (new_i, old_i) in old_for_new.into_iter().enumerate() {
+        if let Some(old_i) = old_i {
+            while dropped.last().is_some_and(|dropped_i| *dropped_i < old_i) {
+                pairings.push(Pairing::Dropped(dropped.pop().unwrap()));
+            }
+            pairings.push(Pairing::Matched {
+                old: old_i,
+                new: new_i,
+            });
+        } else {
+            pairings.push(Pairing::New(new_i));
+        }
+    }
+    pairings.extend(dropped.into_iter().rev().map(Pairing::Dropped));
+    pairings
+}
+
+/// Find the new-series indices of matched patches that changed relative order.
+///
+/// The patches whose old positions form the longest increasing subsequence are
+/// considered to be in place; all other matched patches were reordered.
+fn find_reordered(pairings: &[Pairing]) -> BTreeSet<usize> {
+    let matched: Vec<(usize, usize)> = pairings
+        .iter()
+        .filter_map(|pairing| match pairing {
+            Pairing::Matched { old, new } => Some((*old, *new)),
+            _ => None,
+        })
+        .collect();
+
+    // lengths[i] is the length of the longest increasing subsequence ending at i.
+    let mut lengths = vec![1usize; matched.len()];
+    let mut prev: Vec<Option<usize>> = vec![None; matched.len()];
+    for i in 0..matched.len() {
+        for j in 0..i {
+            if matched[j].0 < matched[i].0 && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                prev[i] = Some(j);
+            }
+        }
+    }
+
+    let mut in_place = BTreeSet::new();
+    let mut current = (0..matched.len()).max_by_key(|&i| lengths[i]);
+    while let Some(i) = current {
+        in_place.insert(i);
+        current = prev[i];
+    }
+
+    (0..matched.len())
+        .filter(|i| !in_place.contains(i))
+        .map(|i| matched[i].1)
+        .collect()
+}
+
+/// Similarity of two patches' contents as the Jaccard index of their lines.
+///
+/// Diff file headers and hunk headers are ignored.
+fn similarity(a: &[u8], b: &[u8]) -> f64 {
+    fn content_lines(content: &[u8]) -> BTreeSet<&[u8]> {
+        content
+            .lines()
+            .filter(|line| {
+                !line.trim().is_empty()
+                    && !line.starts_with(b"diff --git ")
+                    && !line.starts_with(b"--- ")
+                    && !line.starts_with(b"+++ ")
+                    && !line.starts_with(b"@@")
+            })
+            .collect()
+    }
+
+    let a = content_lines(a);
+    let b = content_lines(b);
+    let union = a.union(&b).count();
+    if union == 0 {
+        0.0
+    } else {
+        a.intersection(&b).count() as f64 / union as f64
+    }
+}
+
+/// Write the hunks of a diff, omitting the file header, indented by four spaces.
+fn write_indented_hunks(stdout: &mut impl Write, diff: &BStr) -> Result<()> {
+    let mut in_header = true;
+    for line in diff.lines_with_terminator() {
+        if in_header && line.find(b"@@ -").is_none() {
+            continue;
+        }
+        in_header = false;
+        stdout.write_all(b"    ")?;
+        stdout.write_all(line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_similarity() {
+        let a = b"subject\n\ndiff --git a/f b/f\n--- a/f\n+++ b/f\n@@\n+1\n+2\n+3\n";
+        let b = b"subject\n\ndiff --git a/g b/g\n--- a/g\n+++ b/g\n@@\n+1\n+2\n+4\n";
+        assert_eq!(similarity(a, a), 1.0);
+        assert_eq!(similarity(a, b), 0.6);
+        assert_eq!(similarity(b"", b""), 0.0);
+    }
+}
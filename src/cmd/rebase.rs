@@ -4,7 +4,7 @@
 
 use std::{fmt::Write, str::FromStr};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
 
@@ -12,9 +12,10 @@ use crate::{
     argset,
     color::get_color_stdout,
     ext::RepositoryExtended,
+    operation::{self, Operation, ResumeAction, Step},
     patch::{patchedit, PatchName, SingleRevisionSpec},
     print_info_message,
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stack::{is_dry_run, InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -26,7 +27,7 @@ pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
 };
 
 fn make() -> clap::Command {
-    clap::Command::new(STGIT_COMMAND.name)
+    let app = clap::Command::new(STGIT_COMMAND.name)
         .about("Move the stack base to another point in history")
         .long_about(
             "Pop all patches from the current stack, move the stack base to the given \
@@ -34,18 +35,25 @@ fn make() -> clap::Command {
             \n\
             Merge conflicts may arise when patches are being pushed-back onto the \
             stack. If this occurs, resolve the conflicts and then continue the rebase \
-            with the following sequence:\n\
-            \n    \
-            stg add --update\n    \
-            stg refresh\n    \
-            stg goto top-patch\n\
+            with '--continue'. Changes to tracked files are refreshed into the \
+            conflicting patch before the remaining patches are pushed.\n\
             \n\
-            Or to skip the conflicting patch:\n\
-            \n    \
-            stg undo --hard\n    \
-            stg push next-patch..top-patch\n\
-            ",
+            Alternatively, use '--skip' to leave the conflicting patch unapplied and \
+            push the remaining patches, or '--abort' to restore the stack and its \
+            base to their state from before the rebase.\n\
+            \n\
+            With '--interactive', the instructions edited in the editor may also \
+            run shell commands with \"exec\" or stop the rebase with \"break\" \
+            after pushing the preceding patches. A stopped rebase, including one \
+            stopped by a failing \"exec\" command, is resumed with '--continue', \
+            which refreshes any changes to tracked files into the topmost patch. \
+            Commands such as 'stg push' and 'stg goto' are refused while the rebase \
+            is stopped.",
         )
+        .override_usage(super::make_usage(
+            "stg rebase",
+            &["[OPTIONS] [committish]", "(--continue | --skip | --abort)"],
+        ))
         .arg(
             Arg::new("committish")
                 .help("New base commit for the stack")
@@ -58,6 +66,26 @@ fn make() -> clap::Command {
                 .help("Interactively manipulate patches in editor")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("autosquash")
+                .long("autosquash")
+                .help("Squash fixup!, squash!, and amend! patches into their targets")
+                .long_help(
+                    "Automatically squash patches whose subjects start with \"fixup! \", \
+                     \"squash! \", or \"amend! \" into their target patches when pushing \
+                     back patches. Each such patch is moved directly after the first \
+                     preceding patch whose subject or patch name matches the remainder \
+                     of its subject. \"fixup!\" patches are squashed while keeping the \
+                     target's message, \"amend!\" patches replace the target's message \
+                     with their own message without its subject line, and \"squash!\" \
+                     patches are squashed with the combined message being edited.\n\
+                     \n\
+                     With '--interactive', the rearranged instructions are presented in \
+                     the editor. See also `stg autosquash` and `stg new --fixup`.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("nopush"),
+        )
         .arg(
             Arg::new("nopush")
                 .long("nopush")
@@ -87,10 +115,16 @@ fn make() -> clap::Command {
                 )
                 .action(clap::ArgAction::SetTrue),
         )
-        .arg(argset::push_conflicts_arg())
+        .arg(argset::push_conflicts_arg());
+
+    operation::add_args(app, &["committish", "interactive", "autosquash", "nopush"])
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return resume(matches, "rebase", action);
+    }
+
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
     let config = repo.config_snapshot();
@@ -99,6 +133,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     let allow_push_conflicts = argset::resolve_allow_push_conflicts(&config, matches);
     let committer_date_is_author_date = matches.get_flag("committer-date-is-author-date");
     let interactive = matches.get_flag("interactive");
+    let autosquash = matches.get_flag("autosquash");
 
     let target_commit = if let Some(target_rev_spec) =
         matches.get_one::<SingleRevisionSpec>("committish")
@@ -110,7 +145,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     {
         let id = repo.rev_parse_single(remote_ref.as_bstr())?;
         id.object()?.into_commit().into()
-    } else if interactive {
+    } else if interactive || autosquash {
         stack.base().clone()
     } else {
         print_info_message(
@@ -127,7 +162,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         ));
     };
 
-    if !interactive && target_commit.id == stack.base().id {
+    if !interactive && !autosquash && target_commit.id == stack.base().id {
         print_info_message(
             matches,
             &format!(
@@ -177,6 +212,15 @@ fn run(matches: &ArgMatches) -> Result<()> {
             .unwrap_or(false)
     };
 
+    if is_dry_run() {
+        if !autostash {
+            clean_result?;
+        }
+        return dry_run(stack, matches, target_commit, allow_push_conflicts);
+    }
+
+    let mut operation = Operation::new("rebase", &stack)?;
+
     let using_stash = if autostash && clean_result.is_err() {
         stupid.stash_push()?;
         true
@@ -187,6 +231,9 @@ fn run(matches: &ArgMatches) -> Result<()> {
     };
 
     let applied = stack.applied().to_vec();
+    operation.check_merged = matches.get_flag("merged");
+    operation.committer_date_is_author_date = committer_date_is_author_date;
+    operation.autostash = using_stash;
 
     stack
         .setup_transaction()
@@ -224,27 +271,27 @@ fn run(matches: &ArgMatches) -> Result<()> {
         stack.log_external_mods(Some("rebase"))?
     };
 
-    if matches.get_flag("interactive") {
-        interactive_pushback(
+    if interactive || autosquash {
+        return interactive_pushback(
             stack,
             &repo,
-            &config,
             matches,
             &applied,
             allow_push_conflicts,
-            committer_date_is_author_date,
-        )?;
+            autosquash,
+            operation,
+        );
     } else if !matches.get_flag("nopush") {
         stack.check_head_top_mismatch()?;
-        let check_merged = matches.get_flag("merged");
-        stack
+        let result = stack
             .setup_transaction()
             .use_index_and_worktree(true)
             .allow_push_conflicts(allow_push_conflicts)
             .committer_date_is_author_date(committer_date_is_author_date)
             .with_output_stream(get_color_stdout(matches))
-            .transact(|trans| trans.push_patches(&applied, check_merged))
-            .execute("rebase (reapply)")?;
+            .transact(|trans| trans.push_patches(&applied, operation.check_merged))
+            .execute("rebase (reapply)");
+        operation.finish(&repo, &applied, result)?;
     }
 
     if using_stash {
@@ -258,6 +305,69 @@ fn run(matches: &ArgMatches) -> Result<()> {
     }
 }
 
+/// Resume the halted or stopped rebase-like `command`, performing its remaining steps.
+pub(super) fn resume(matches: &ArgMatches, command: &str, action: ResumeAction) -> Result<()> {
+    operation::resume_with_steps(
+        matches,
+        command,
+        action,
+        operation::push_remaining,
+        |stack, operation| {
+            let allow_push_conflicts =
+                argset::resolve_allow_push_conflicts(&stack.repo.config_snapshot(), matches);
+            run_steps(stack, matches, allow_push_conflicts, operation)
+        },
+    )
+}
+
+/// Report the outcome of rebasing without changing the stack, index, or worktree.
+///
+/// Instead of running the rebase command, the stack is moved onto the target commit
+/// within a single stack transaction.
+fn dry_run(
+    stack: Stack,
+    matches: &ArgMatches,
+    target_commit: std::rc::Rc<gix::Commit>,
+    allow_push_conflicts: bool,
+) -> Result<()> {
+    if matches.get_flag("interactive") {
+        return Err(anyhow!(
+            "`stg rebase --interactive` does not support `--dry-run`"
+        ));
+    } else if matches.get_flag("autosquash") {
+        return Err(anyhow!(
+            "`stg rebase --autosquash` does not support `--dry-run`"
+        ));
+    }
+    print_info_message(
+        matches,
+        &format!(
+            "Rebasing to {}",
+            formatted_target_id_and_ref(stack.repo, std::rc::Rc::clone(&target_commit))
+        ),
+    );
+    let applied = stack.applied().to_vec();
+    let target_id = target_commit.id;
+    let push = !matches.get_flag("nopush");
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .allow_push_conflicts(allow_push_conflicts)
+        .committer_date_is_author_date(matches.get_flag("committer-date-is-author-date"))
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            trans.pop_patches(|pn| applied.contains(pn))?;
+            let target_commit = trans.repo().find_commit(target_id)?;
+            trans.rebase_onto(std::rc::Rc::new(target_commit));
+            if push {
+                trans.push_patches(&applied, matches.get_flag("merged"))?;
+            }
+            Ok(())
+        })
+        .execute("rebase")?;
+    Ok(())
+}
+
 fn formatted_target_id_and_ref(
     repo: &gix::Repository,
     target_commit: std::rc::Rc<gix::Commit>,
@@ -325,59 +435,104 @@ const INTERACTIVE_HELP_LINES: &str = "\
 #
 #   k, keep <patch> = do not modify this patch
 #   e, edit <patch> = interactively edit this patch
+#   r, reword <patch> = edit this patch's message, but not its diff
 #   s, squash <patch> = squash patch into the previous patch
 #   f, fixup <patch> = like \"squash\", but discard this patch's commit message
 #   h, hide <patch> = hide patch
 #   d, delete <patch> = delete patch
+#   u, unapply <patch> = keep this patch unapplied, as if below the APPLY_LINE
+#   rename <patch> <new-name> = rename patch
+#   x, exec <command> = run shell command after pushing the preceding patches
+#   b, break = stop after pushing the preceding patches; resume with --continue
 #
 # These lines can be reordered; they are executed from top to bottom.
 #
-# Patches above the APPLY_LINE are applied; other patches are kept unapplied.
+# Patches above the APPLY_LINE are applied; other patches are kept unapplied, in
+# the order given.
 ";
 
 #[derive(Debug, Clone)]
 struct Instruction {
-    patchname: PatchName,
     action: Action,
     apply: bool,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Action {
-    Keep,
-    Edit,
-    Squash,
-    Fixup,
-    Hide,
-    Delete,
+    Keep(PatchName),
+    Edit(PatchName),
+    Reword(PatchName),
+    Squash(PatchName),
+    Fixup(PatchName),
+    Hide(PatchName),
+    Delete(PatchName),
+    Unapply(PatchName),
+    Rename(PatchName, PatchName),
+    Exec(String),
+    Break,
 }
 
-fn interactive_pushback(
+impl Action {
+    /// Get the name of the patch the action applies to, if any.
+    fn patchname(&self) -> Option<&PatchName> {
+        match self {
+            Action::Keep(patchname)
+            | Action::Edit(patchname)
+            | Action::Reword(patchname)
+            | Action::Squash(patchname)
+            | Action::Fixup(patchname)
+            | Action::Hide(patchname)
+            | Action::Delete(patchname)
+            | Action::Unapply(patchname)
+            | Action::Rename(patchname, _) => Some(patchname),
+            Action::Exec(_) | Action::Break => None,
+        }
+    }
+
+    /// Determine whether this action is the same kind of action as `other`.
+    fn is_same_kind(&self, other: &Action) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// Push back the popped patches as directed by instructions.
+///
+/// The instructions are initially to keep all patches, keeping previously applied
+/// patches applied, rearranged by [`autosquash_instructions()`] when `autosquash` is
+/// enabled. With `--interactive`, the instructions are then edited by the user.
+pub(super) fn interactive_pushback(
     stack: Stack,
     repo: &gix::Repository,
-    config: &gix::config::Snapshot,
     matches: &ArgMatches,
     previously_applied: &[PatchName],
     allow_push_conflicts: bool,
-    committer_date_is_author_date: bool,
+    autosquash: bool,
+    operation: Operation,
 ) -> Result<()> {
+    let committer_date_is_author_date = operation.committer_date_is_author_date;
     let mut stack = stack;
+    let mut operation = operation;
 
     if stack.all_patches().next().is_none() {
-        return Ok(());
+        return run_steps(stack, matches, allow_push_conflicts, operation);
     }
 
-    let filename = ".stgit-rebase-interactive.txt";
-    std::fs::write(
-        filename,
-        make_instructions_template(&stack, previously_applied),
-    )?;
+    let mut instructions = initial_instructions(&stack, previously_applied);
 
-    let buf = patchedit::call_editor(filename, config)?;
-    let buf = buf
-        .to_str()
-        .map_err(|_| anyhow!("`{filename}` is not valid UTF-8"))?;
-    let mut instructions = parse_instructions(buf)?;
+    if autosquash {
+        instructions = autosquash_instructions(&stack, instructions);
+    }
+
+    if matches.get_flag("interactive") {
+        let filename = ".stgit-rebase-interactive.txt";
+        std::fs::write(filename, make_instructions_template(&stack, &instructions))?;
+
+        let buf = patchedit::call_editor(filename, &repo.config_snapshot())?;
+        let buf = buf
+            .to_str()
+            .map_err(|_| anyhow!("`{filename}` is not valid UTF-8"))?;
+        instructions = parse_instructions(buf)?;
+    }
 
     validate_instructions(&stack, &instructions)?;
 
@@ -385,26 +540,32 @@ fn interactive_pushback(
 
     while index < instructions.len() {
         let instruction = instructions[index].clone();
-        let patchname = &instruction.patchname;
 
-        match instruction.action {
-            Action::Keep => {
+        match &instruction.action {
+            Action::Keep(_) | Action::Exec(_) | Action::Break => {
                 index += 1;
             }
 
-            Action::Delete => {
+            Action::Unapply(patchname) => {
+                instructions[index] = Instruction {
+                    action: Action::Keep(patchname.clone()),
+                    apply: false,
+                };
+                index += 1;
+            }
+
+            Action::Delete(_) => {
                 // Find contiguous delete instructions in order to delete in batches.
                 let mut delete_instructions: Vec<Instruction> = Vec::new();
-                while index < instructions.len() && instructions[index].action == Action::Delete {
+                while index < instructions.len()
+                    && matches!(instructions[index].action, Action::Delete(_))
+                {
                     delete_instructions.push(instructions.remove(index));
                 }
                 assert!(!delete_instructions.is_empty());
-                assert!(delete_instructions
-                    .iter()
-                    .all(|inst| inst.action == Action::Delete));
                 let to_delete: Vec<&PatchName> = delete_instructions
                     .iter()
-                    .map(|inst| &inst.patchname)
+                    .filter_map(|inst| inst.action.patchname())
                     .collect();
                 stack = stack
                     .setup_transaction()
@@ -417,18 +578,17 @@ fn interactive_pushback(
                     .execute("delete")?;
             }
 
-            Action::Hide => {
+            Action::Hide(_) => {
                 let mut hide_instructions: Vec<Instruction> = Vec::new();
-                while index < instructions.len() && instructions[index].action == Action::Hide {
+                while index < instructions.len()
+                    && matches!(instructions[index].action, Action::Hide(_))
+                {
                     hide_instructions.push(instructions.remove(index));
                 }
                 assert!(!hide_instructions.is_empty());
-                assert!(hide_instructions
-                    .iter()
-                    .all(|inst| inst.action == Action::Hide));
                 let to_hide: Vec<PatchName> = hide_instructions
                     .iter()
-                    .map(|inst| inst.patchname.clone())
+                    .filter_map(|inst| inst.action.patchname().cloned())
                     .collect();
                 stack = stack
                     .setup_transaction()
@@ -437,16 +597,37 @@ fn interactive_pushback(
                     .execute("hide")?;
             }
 
-            Action::Edit => {
+            Action::Rename(patchname, new_patchname) => {
+                if patchname != new_patchname {
+                    stack = stack
+                        .setup_transaction()
+                        .with_output_stream(get_color_stdout(matches))
+                        .transact(|trans| trans.rename_patch(patchname, new_patchname))
+                        .execute(&format!("rename {patchname} {new_patchname}"))?;
+                }
+                instructions[index] = Instruction {
+                    action: Action::Keep(new_patchname.clone()),
+                    apply: instruction.apply,
+                };
+                index += 1;
+            }
+
+            Action::Edit(patchname) | Action::Reword(patchname) => {
+                let allow_diff_edit = matches!(instruction.action, Action::Edit(_));
                 let dummy_edit_command = clap::Command::new("dummy-edit");
                 let dummy_edit_command = patchedit::add_args(dummy_edit_command, false, false);
+                let edit_args: &[&str] = if allow_diff_edit {
+                    &["dummy-edit", "--edit", "--diff"]
+                } else {
+                    &["dummy-edit", "--edit"]
+                };
                 let edit_matches = dummy_edit_command
-                    .try_get_matches_from(["dummy-edit", "--edit", "--diff"])
+                    .try_get_matches_from(edit_args)
                     .expect("dummy command has valid arguments");
                 match patchedit::EditBuilder::default()
                     .original_patchname(Some(patchname))
                     .existing_patch_commit(stack.get_patch_commit(patchname))
-                    .allow_diff_edit(true)
+                    .allow_diff_edit(allow_diff_edit)
                     .edit(&stack, repo, &edit_matches)?
                 {
                     patchedit::EditOutcome::TemplateSaved(_) => panic!("template save not enabled"),
@@ -476,8 +657,9 @@ fn interactive_pushback(
                         }
 
                         instructions[index] = Instruction {
-                            action: Action::Keep,
-                            patchname: new_patchname.unwrap_or_else(|| patchname.clone()),
+                            action: Action::Keep(
+                                new_patchname.unwrap_or_else(|| patchname.clone()),
+                            ),
                             apply: instruction.apply,
                         };
 
@@ -486,46 +668,66 @@ fn interactive_pushback(
                 }
             }
 
-            Action::Squash | Action::Fixup => {
+            Action::Squash(patchname) | Action::Fixup(patchname) => {
                 let action_str = match instruction.action {
-                    Action::Squash => "squash",
-                    Action::Fixup => "fixup",
+                    Action::Squash(_) => "squash",
+                    Action::Fixup(_) => "fixup",
                     _ => panic!("only squash and fixup expected"),
                 };
 
-                let squash_patchnames: Vec<PatchName> = if index > 0 {
-                    let mut patchnames: Vec<PatchName> =
-                        vec![instructions[index - 1].patchname.clone()];
-                    while index < instructions.len()
-                        && instructions[index].action == instruction.action
-                    {
-                        let Instruction { patchname, .. } = instructions.remove(index);
-                        patchnames.push(patchname);
-                    }
-                    patchnames
-                } else {
-                    return Err(anyhow!(
-                        "cannot {action_str} `{patchname}`: no preceding patch"
-                    ));
-                };
+                let preceding_patchname = index
+                    .checked_sub(1)
+                    .and_then(|prev_index| instructions[prev_index].action.patchname())
+                    .cloned();
+                let squash_patchnames: Vec<PatchName> =
+                    if let Some(preceding_patchname) = preceding_patchname {
+                        let mut patchnames: Vec<PatchName> = vec![preceding_patchname];
+                        while index < instructions.len()
+                            && instructions[index].action.is_same_kind(&instruction.action)
+                        {
+                            let Instruction { action, .. } = instructions.remove(index);
+                            patchnames.push(action.patchname().expect("squash has patch").clone());
+                        }
+                        patchnames
+                    } else {
+                        return Err(anyhow!(
+                            "cannot {action_str} `{patchname}`: no preceding patch"
+                        ));
+                    };
 
                 let target_patchname = &squash_patchnames[0];
 
                 let dummy_squash_command = clap::Command::new("dummy-squash");
                 let dummy_squash_command = patchedit::add_args(dummy_squash_command, true, false);
                 let squash_matches = match instruction.action {
-                    Action::Squash => {
+                    Action::Squash(_) => {
                         dummy_squash_command.try_get_matches_from(["dummy-squash", "--edit"])
                     }
-                    Action::Fixup => {
-                        let commit = stack.get_patch_commit(target_patchname);
-                        let message = commit.message_raw()?.to_str().map_err(|_| {
-                            anyhow!("fixup target patch `{target_patchname}` has non-UTF-8 message")
-                        })?;
+                    Action::Fixup(_) => {
+                        // The message of an `amend!` patch replaces the target's message.
+                        let amend_message = squash_patchnames[1..]
+                            .iter()
+                            .rev()
+                            .find_map(|pn| amend_message(stack.get_patch_commit(pn)));
+                        let message = if let Some(amend_message) = amend_message {
+                            amend_message
+                        } else {
+                            let commit = stack.get_patch_commit(target_patchname);
+                            commit
+                                .message_raw()?
+                                .to_str()
+                                .map_err(|_| {
+                                    anyhow!(
+                                        "fixup target patch `{target_patchname}` has non-UTF-8 \
+                                         message"
+                                    )
+                                })?
+                                .to_string()
+                        };
                         dummy_squash_command.try_get_matches_from([
                             "dummy-squash",
                             "--message",
-                            message,
+                            &message,
                         ])
                     }
                     _ => panic!("only squash and fixup expected"),
@@ -544,8 +746,7 @@ fn interactive_pushback(
                             false,
                         )?;
                         instructions[index - 1] = Instruction {
-                            action: Action::Keep,
-                            patchname: new_patchname,
+                            action: Action::Keep(new_patchname),
                             apply: instruction.apply,
                         };
                         Ok(())
@@ -555,49 +756,160 @@ fn interactive_pushback(
         }
     }
 
-    let to_push: Vec<PatchName> = instructions
-        .iter()
-        .filter_map(|inst| {
-            if inst.apply {
-                Some(inst.patchname.clone())
-            } else {
-                None
+    // Only keep, exec, and break instructions remain.
+    let mut steps: Vec<Step> = Vec::new();
+    let mut ordered: Vec<PatchName> = Vec::new();
+    for instruction in instructions {
+        match (instruction.action, instruction.apply) {
+            (Action::Keep(patchname), apply) => {
+                if apply {
+                    steps.push(Step::Push(patchname.clone()));
+                }
+                ordered.push(patchname);
             }
-        })
+            (Action::Exec(command), _) => steps.push(Step::Exec(command)),
+            (Action::Break, _) => steps.push(Step::Break),
+            (action, _) => panic!("unexpected remaining action {action:?}"),
+        }
+    }
+
+    // Patches that are to remain unapplied take on the order of the instructions.
+    let mut unapplied: Vec<PatchName> = ordered
+        .into_iter()
+        .filter(|pn| stack.is_unapplied(pn))
         .collect();
-    let check_merged = matches.get_flag("merged");
+    for patchname in stack.unapplied() {
+        if !unapplied.contains(patchname) {
+            unapplied.push(patchname.clone());
+        }
+    }
+    if unapplied != stack.unapplied() {
+        stack = stack
+            .setup_transaction()
+            .with_output_stream(get_color_stdout(matches))
+            .transact(|trans| trans.reorder_patches(None, Some(&unapplied), None))
+            .execute("rebase (reorder)")?;
+    }
 
-    stack.check_head_top_mismatch()?;
-    stack
-        .setup_transaction()
-        .use_index_and_worktree(true)
-        .allow_push_conflicts(allow_push_conflicts)
-        .committer_date_is_author_date(committer_date_is_author_date)
-        .with_output_stream(get_color_stdout(matches))
-        .transact(|trans| trans.push_patches(&to_push, check_merged))
-        .execute("rebase (reapply)")?;
+    operation.steps = steps;
+    run_steps(stack, matches, allow_push_conflicts, operation)
+}
+
+/// Perform the operation's steps, pushing patches, running commands, and stopping as
+/// directed.
+///
+/// If a push results in merge conflicts, a command fails, or a break step is reached,
+/// the operation is recorded along with the steps that remain so that it may be resumed
+/// with `--continue`. Otherwise, any autostash is popped once all steps are performed.
+pub(super) fn run_steps(
+    stack: Stack,
+    matches: &ArgMatches,
+    allow_push_conflicts: bool,
+    operation: Operation,
+) -> Result<()> {
+    let repo = stack.repo;
+    let command = operation.command.clone();
+    let mut stack = stack;
+    let mut operation = operation;
+    let steps = std::mem::take(&mut operation.steps);
+    let mut index: usize = 0;
+
+    while index < steps.len() {
+        match &steps[index] {
+            Step::Push(_) => {
+                let to_push: Vec<PatchName> = steps[index..]
+                    .iter()
+                    .map_while(|step| match step {
+                        Step::Push(patchname) => Some(patchname.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                index += to_push.len();
+                operation.steps = steps[index..].to_vec();
+
+                stack.check_head_top_mismatch()?;
+                let result = stack
+                    .setup_transaction()
+                    .use_index_and_worktree(true)
+                    .allow_push_conflicts(allow_push_conflicts)
+                    .committer_date_is_author_date(operation.committer_date_is_author_date)
+                    .with_output_stream(get_color_stdout(matches))
+                    .transact(|trans| trans.push_patches(&to_push, operation.check_merged))
+                    .execute(&format!("{command} (reapply)"));
+                stack = operation.clone().finish(repo, &to_push, result)?;
+            }
+
+            Step::Exec(shell_command) => {
+                index += 1;
+                operation.steps = steps[index..].to_vec();
+                print_info_message(matches, &format!("Executing `{shell_command}`"));
+                let mut exec_command = std::process::Command::new("sh");
+                exec_command.arg("-c").arg(shell_command);
+                if let Some(work_dir) = repo.workdir() {
+                    exec_command.current_dir(work_dir);
+                }
+                let status = exec_command
+                    .status()
+                    .with_context(|| format!("running `{shell_command}`"))?;
+                if !status.success() {
+                    operation.stop(&stack)?;
+                    return Err(anyhow!(
+                        "`{shell_command}` failed; fix the problem then run \
+                         `stg {command} --continue`, or abort with `stg {command} --abort`"
+                    ));
+                }
+            }
+
+            Step::Break => {
+                index += 1;
+                operation.steps = steps[index..].to_vec();
+                let location = if let Some(patchname) = stack.applied().last() {
+                    format!("`{patchname}`")
+                } else {
+                    "the stack base".to_string()
+                };
+                operation.stop(&stack)?;
+                print_info_message(
+                    matches,
+                    &format!(
+                        "Stopped at {location}; run `stg {command} --continue` to resume \
+                         or `stg {command} --abort` to abort"
+                    ),
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    if operation.autostash && !repo.stupid().stash_pop()? {
+        return Err(
+            super::Error::CausedConflicts("stash pop resulted in conflicts".to_string()).into(),
+        );
+    }
 
     Ok(())
 }
 
-fn make_instructions_template(stack: &Stack, previously_applied: &[PatchName]) -> String {
+fn make_instructions_template(stack: &Stack, instructions: &[Instruction]) -> String {
     let name_width = stack.all_patches().map(PatchName::len).max().unwrap();
     let mut template = String::with_capacity(4096);
     let mut found_apply_boundary = false;
-    for patchname in stack.all_patches() {
-        if !found_apply_boundary && !previously_applied.contains(patchname) {
+    for instruction in instructions {
+        let patchname = instruction
+            .action
+            .patchname()
+            .expect("template instructions are for patches");
+        if !found_apply_boundary && !instruction.apply {
             writeln!(template, "{INTERACTIVE_APPLY_LINE}").unwrap();
             found_apply_boundary = true;
         }
-        let commit = stack.get_patch_commit(patchname);
-        let subject = commit
-            .message()
-            .map(|message_ref| message_ref.title.to_str_lossy())
-            .unwrap_or_default()
-            .replace(['\r', '\n'], " ")
-            .trim()
-            .to_owned();
-        writeln!(template, "keep {patchname:name_width$} # {subject}").unwrap();
+        let action_str = match instruction.action {
+            Action::Squash(_) => "squash",
+            Action::Fixup(_) => "fixup",
+            _ => "keep",
+        };
+        let subject = patch_subject(stack, patchname);
+        writeln!(template, "{action_str} {patchname:name_width$} # {subject}").unwrap();
     }
     if !found_apply_boundary {
         writeln!(template, "{INTERACTIVE_APPLY_LINE}").unwrap();
@@ -606,6 +918,114 @@ fn make_instructions_template(stack: &Stack, previously_applied: &[PatchName]) -
     template
 }
 
+/// Get the patch's subject, i.e. the first line of its message.
+fn patch_subject(stack: &Stack, patchname: &PatchName) -> String {
+    stack
+        .get_patch_commit(patchname)
+        .message()
+        .map(|message_ref| message_ref.title.to_str_lossy())
+        .unwrap_or_default()
+        .replace(['\r', '\n'], " ")
+        .trim()
+        .to_owned()
+}
+
+/// Make instructions to keep all patches, applying the previously applied patches.
+fn initial_instructions(stack: &Stack, previously_applied: &[PatchName]) -> Vec<Instruction> {
+    stack
+        .all_patches()
+        .map(|patchname| Instruction {
+            action: Action::Keep(patchname.clone()),
+            apply: previously_applied.contains(patchname),
+        })
+        .collect()
+}
+
+/// Determine whether autosquashing would squash any of the stack's patches.
+pub(super) fn has_autosquash_patches(stack: &Stack) -> bool {
+    autosquash_instructions(stack, initial_instructions(stack, stack.applied()))
+        .iter()
+        .any(|instruction| !matches!(instruction.action, Action::Keep(_)))
+}
+
+/// Subject prefixes of patches to be autosquashed.
+const AUTOSQUASH_PREFIXES: [&str; 3] = ["fixup! ", "squash! ", "amend! "];
+
+/// Rearrange instructions to squash `fixup!`, `squash!`, and `amend!` patches.
+///
+/// Each patch whose subject starts with one of the [`AUTOSQUASH_PREFIXES`] is moved
+/// directly after its target patch, i.e. the first preceding patch whose subject or
+/// patch name matches the remainder of the subject, and after any other patches already
+/// moved after the target. Such patches are fixed up into the target, except for
+/// `squash!` patches which are squashed. Patches whose target cannot be found are kept
+/// in place.
+fn autosquash_instructions(stack: &Stack, instructions: Vec<Instruction>) -> Vec<Instruction> {
+    // Each group is a target instruction followed by the instructions squashed into it.
+    let mut groups: Vec<Vec<Instruction>> = Vec::new();
+
+    for instruction in instructions {
+        let target_group = match &instruction.action {
+            Action::Keep(patchname) if !stack.is_hidden(patchname) => {
+                let subject = patch_subject(stack, patchname);
+                let mut target = subject.as_str();
+                while let Some(rest) = AUTOSQUASH_PREFIXES
+                    .iter()
+                    .find_map(|prefix| target.strip_prefix(prefix))
+                {
+                    target = rest.trim_start();
+                }
+                if target.len() == subject.len() {
+                    None
+                } else {
+                    let squash = subject.starts_with("squash! ");
+                    groups
+                        .iter_mut()
+                        .find(|group| {
+                            let target_patchname = group[0]
+                                .action
+                                .patchname()
+                                .expect("autosquash instructions are for patches");
+                            !stack.is_hidden(target_patchname)
+                                && (patch_subject(stack, target_patchname) == target
+                                    || AsRef::<str>::as_ref(target_patchname) == target)
+                        })
+                        .map(|group| (group, patchname.clone(), squash))
+                }
+            }
+            _ => None,
+        };
+
+        if let Some((group, patchname, squash)) = target_group {
+            let apply = group[0].apply;
+            group.push(Instruction {
+                action: if squash {
+                    Action::Squash(patchname)
+                } else {
+                    Action::Fixup(patchname)
+                },
+                apply,
+            });
+        } else {
+            groups.push(vec![instruction]);
+        }
+    }
+
+    groups.into_iter().flatten().collect()
+}
+
+/// Get the message with which an `amend!` patch replaces its target's message.
+///
+/// This is the patch's message without its `amend!` subject line.
+fn amend_message(commit: &gix::Commit) -> Option<String> {
+    let message = commit.message_raw().ok()?.to_str().ok()?;
+    let (subject, body) = message.split_once('\n')?;
+    if subject.starts_with("amend! ") && !body.trim().is_empty() {
+        Some(body.trim_start_matches('\n').to_string())
+    } else {
+        None
+    }
+}
+
 fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
     let mut instructions = Vec::new();
     let mut apply = true;
@@ -632,43 +1052,102 @@ fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
             continue;
         }
 
-        if let Some((action_str, patchname_str)) =
-            instruction_str.split_once(|c: char| c.is_ascii_whitespace())
-        {
-            let action = match action_str {
-                "k" | "keep" => Action::Keep,
-                "e" | "edit" => Action::Edit,
-                "s" | "squash" => Action::Squash,
-                "f" | "fix" | "fixup" => Action::Fixup,
-                "h" | "hide" => Action::Hide,
-                "d" | "delete" => Action::Delete,
-                _ => return Err(anyhow!("unknown instruction action `{action_str}`")),
-            };
-
-            let patchname = PatchName::from_str(patchname_str)?;
-
-            instructions.push(Instruction {
-                patchname,
-                action,
-                apply,
+        let (action_str, args_str) = instruction_str
+            .split_once(|c: char| c.is_ascii_whitespace())
+            .map_or((instruction_str, ""), |(action_str, args_str)| {
+                (action_str, args_str.trim())
             });
-        } else {
-            return Err(anyhow!("bad instruction line: `{line}`"));
-        }
+
+        let action = match action_str {
+            "x" | "exec" => {
+                // The command is the remainder of the line, including any `#`.
+                let command = line[action_str.len()..].trim();
+                if command.is_empty() {
+                    return Err(anyhow!("bad instruction line: `{line}`"));
+                }
+                Action::Exec(command.to_string())
+            }
+            "b" | "break" => {
+                if !args_str.is_empty() {
+                    return Err(anyhow!("bad instruction line: `{line}`"));
+                }
+                Action::Break
+            }
+            "rename" => {
+                let Some((patchname_str, new_patchname_str)) =
+                    args_str.split_once(|c: char| c.is_ascii_whitespace())
+                else {
+                    return Err(anyhow!("bad instruction line: `{line}`"));
+                };
+                Action::Rename(
+                    PatchName::from_str(patchname_str)?,
+                    PatchName::from_str(new_patchname_str.trim())?,
+                )
+            }
+            "k" | "keep" | "e" | "edit" | "r" | "reword" | "s" | "squash" | "f" | "fix"
+            | "fixup" | "h" | "hide" | "d" | "delete" | "u" | "unapply" => {
+                if args_str.is_empty() {
+                    return Err(anyhow!("bad instruction line: `{line}`"));
+                }
+                let patchname = PatchName::from_str(args_str)?;
+                match action_str {
+                    "k" | "keep" => Action::Keep(patchname),
+                    "e" | "edit" => Action::Edit(patchname),
+                    "r" | "reword" => Action::Reword(patchname),
+                    "s" | "squash" => Action::Squash(patchname),
+                    "f" | "fix" | "fixup" => Action::Fixup(patchname),
+                    "h" | "hide" => Action::Hide(patchname),
+                    "d" | "delete" => Action::Delete(patchname),
+                    _ => Action::Unapply(patchname),
+                }
+            }
+            _ => return Err(anyhow!("unknown instruction action `{action_str}`")),
+        };
+
+        instructions.push(Instruction { action, apply });
     }
     Ok(instructions)
 }
 
 fn validate_instructions(stack: &Stack, instructions: &[Instruction]) -> Result<()> {
     let mut seen_patchnames: Vec<&PatchName> = Vec::new();
+    let mut new_patchnames: Vec<&PatchName> = Vec::new();
     for instruction in instructions {
-        let patchname = &instruction.patchname;
-        if !stack.has_patch(patchname) {
-            return Err(anyhow!("unknown patch name `{patchname}`"));
-        } else if seen_patchnames.contains(&patchname) {
-            return Err(anyhow!("duplicated patch name `{patchname}`"));
-        } else {
-            seen_patchnames.push(patchname);
+        match &instruction.action {
+            Action::Exec(_) | Action::Break if !instruction.apply => {
+                let action_str = if matches!(instruction.action, Action::Break) {
+                    "break"
+                } else {
+                    "exec"
+                };
+                return Err(anyhow!(
+                    "`{action_str}` instructions must be above the APPLY_LINE"
+                ));
+            }
+            Action::Rename(patchname, new_patchname) if patchname != new_patchname => {
+                if new_patchnames.iter().any(|pn| pn.collides(new_patchname))
+                    || stack
+                        .all_patches()
+                        .any(|pn| pn != patchname && pn.collides(new_patchname))
+                {
+                    return Err(anyhow!(
+                        "cannot rename `{patchname}` to `{new_patchname}`: \
+                         patch name already in use"
+                    ));
+                }
+                new_patchnames.push(new_patchname);
+            }
+            _ => {}
+        }
+
+        if let Some(patchname) = instruction.action.patchname() {
+            if !stack.has_patch(patchname) {
+                return Err(anyhow!("unknown patch name `{patchname}`"));
+            } else if seen_patchnames.contains(&patchname) {
+                return Err(anyhow!("duplicated patch name `{patchname}`"));
+            } else {
+                seen_patchnames.push(patchname);
+            }
         }
     }
     Ok(())
@@ -0,0 +1,569 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Policy-driven checking of the changes incorporated by `stg refresh`.
+//!
+//! The changes a refresh would make to a patch are determined by comparing the patch's
+//! current diff with the diff it would have after the refresh. The differences are
+//! classified and the `stgit.refresh.check` policy decides whether classifications
+//! not listed in `stgit.refresh.checkallow` are warned about, rejected, or confirmed
+//! interactively.
+
+use std::{fmt::Display, io::Write, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use bstr::{BStr, ByteSlice};
+use clap::{Arg, ArgMatches};
+use is_terminal::IsTerminal;
+use serde::Serialize;
+use termcolor::WriteColor;
+
+use crate::{
+    color::{get_color_stderr, get_color_stdout},
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    stack::{Stack, StackStateAccess},
+    stupid::{normalize_for_interdiff, FileHunks, Stupid},
+};
+
+/// What to do when the changes of a refresh are not allowed by `stgit.refresh.checkallow`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Policy {
+    Warn,
+    Deny,
+    Prompt,
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Policy::Warn),
+            "deny" => Ok(Policy::Deny),
+            "prompt" => Ok(Policy::Prompt),
+            _ => Err(anyhow!("unsupported refresh check policy `{s}`")),
+        }
+    }
+}
+
+impl Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Warn => "warn",
+            Policy::Deny => "deny",
+            Policy::Prompt => "prompt",
+        }
+        .fmt(f)
+    }
+}
+
+/// Classification of the changes a refresh makes to a patch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Class {
+    /// Only lines are added, and only to files already changed by the patch.
+    PureAddition,
+
+    /// Files are added that exist neither in the patch nor below it.
+    NewFiles,
+
+    /// Existing files are changed that the patch did not previously change.
+    OutsidePatch,
+
+    /// Lines added by the patch are removed or replaced.
+    RemovesPatchLines,
+
+    /// Files changed by the patch are changed in other ways, e.g. removing lines that
+    /// the patch did not add, binary changes, or mode changes.
+    ModifiesPatch,
+
+    /// The changes do not merge cleanly with the patch.
+    Conflicting,
+}
+
+impl FromStr for Class {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pure-addition" => Ok(Class::PureAddition),
+            "new-files" => Ok(Class::NewFiles),
+            "outside-patch" => Ok(Class::OutsidePatch),
+            "removes-patch-lines" => Ok(Class::RemovesPatchLines),
+            "modifies-patch" => Ok(Class::ModifiesPatch),
+            "conflicting" => Ok(Class::Conflicting),
+            _ => Err(anyhow!("unknown refresh check classification `{s}`")),
+        }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Class::PureAddition => "pure-addition",
+            Class::NewFiles => "new-files",
+            Class::OutsidePatch => "outside-patch",
+            Class::RemovesPatchLines => "removes-patch-lines",
+            Class::ModifiesPatch => "modifies-patch",
+            Class::Conflicting => "conflicting",
+        }
+        .fmt(f)
+    }
+}
+
+/// How a changed file relates to the patch being refreshed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Scope {
+    /// The file is already changed by the patch.
+    Patch,
+
+    /// The file does not exist below the patch and is not added by the patch.
+    New,
+
+    /// The file exists below the patch, but is not changed by the patch.
+    Outside,
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Patch => "patch",
+            Scope::New => "new",
+            Scope::Outside => "outside",
+        }
+        .fmt(f)
+    }
+}
+
+/// Changes to a single file by a refresh.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(super) struct FileReport {
+    pub path: String,
+    pub scope: Scope,
+    pub added: usize,
+    pub removed: usize,
+    pub removed_patch_lines: usize,
+    pub binary: bool,
+}
+
+/// Outcome of checking a refresh, as written by `--check-report`.
+#[derive(Serialize)]
+struct Report {
+    patch: String,
+    commit: String,
+    policy: Policy,
+    classification: Vec<Class>,
+    allowed: Vec<Class>,
+    accepted: bool,
+    files: Vec<FileReport>,
+    interdiff: String,
+}
+
+/// Arguments for `stg refresh` controlling the refresh check.
+pub(super) fn args() -> [Arg; 3] {
+    [
+        Arg::new("checked")
+            .long("checked")
+            .help("Check changes against the refresh check policy")
+            .long_help(
+                "Check the changes being incorporated into the patch before \
+                 refreshing. The interdiff between the patch's current diff and its \
+                 refreshed diff is classified. Changes with classifications not \
+                 allowed by `stgit.refresh.checkallow` are handled according to \
+                 <policy>: \"warn\" shows the interdiff and refreshes anyway, \"deny\" \
+                 shows the interdiff and aborts the refresh, and \"prompt\" shows the \
+                 interdiff and asks for confirmation.\n\
+                 \n\
+                 When <policy> is not provided, `stgit.refresh.check` is used, \
+                 defaulting to \"prompt\". Setting `stgit.refresh.check` enables the \
+                 check without this option.",
+            )
+            .num_args(0..=1)
+            .require_equals(true)
+            .value_name("policy")
+            .value_parser(["warn", "deny", "prompt"]),
+        Arg::new("no-checked")
+            .long("no-checked")
+            .help("Do not check changes, regardless of `stgit.refresh.check`")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with_all(["checked", "check-report"]),
+        Arg::new("check-report")
+            .long("check-report")
+            .help("Write JSON report of the refresh check to <file>")
+            .long_help(
+                "Write a JSON report of the refresh check to <file>, or to stdout if \
+                 <file> is \"-\". The report includes the classification of the \
+                 changes, per-file statistics, the interdiff, and whether the refresh \
+                 was accepted. Implies '--checked'.",
+            )
+            .num_args(1)
+            .value_name("file")
+            .value_hint(clap::ValueHint::FilePath)
+            .value_parser(clap::value_parser!(PathBuf)),
+    ]
+}
+
+/// Check the changes that refreshing `patchname` with the temp commit would make.
+///
+/// An error is returned if the refresh is rejected, either by policy or by the user.
+pub(super) fn check_refresh(
+    stack: &Stack,
+    patchname: &PatchName,
+    temp_commit_id: gix::ObjectId,
+    matches: &ArgMatches,
+) -> Result<()> {
+    if matches.get_flag("no-checked") {
+        return Ok(());
+    }
+
+    let repo = stack.repo;
+    let config = repo.config_snapshot();
+    let config_policy = config
+        .string("stgit.refresh.check")
+        .map(|s| Policy::from_str(&s.to_str_lossy()))
+        .transpose()?;
+    let report_path = matches.get_one::<PathBuf>("check-report");
+    if !matches.contains_id("checked") && config_policy.is_none() && report_path.is_none() {
+        return Ok(());
+    }
+    let policy = if let Some(policy) = matches.get_one::<String>("checked") {
+        Policy::from_str(policy)?
+    } else {
+        config_policy.unwrap_or(Policy::Prompt)
+    };
+    let allowed = config
+        .string("stgit.refresh.checkallow")
+        .map_or_else(|| Ok(vec![Class::PureAddition]), |s| parse_classes(&s))?;
+
+    let stupid = repo.stupid();
+    let patch_commit = stack.get_patch_commit(patchname);
+    let patch_tree_id = patch_commit.tree_id()?.detach();
+    let parent_tree_id = patch_commit.get_parent_commit()?.tree_id()?.detach();
+    let temp_commit = repo.find_commit(temp_commit_id)?;
+    let temp_tree_id = temp_commit.tree_id()?.detach();
+    let temp_parent_tree_id = temp_commit.get_parent_commit()?.tree_id()?.detach();
+
+    // The refreshed patch's tree is the temp patch's changes applied to the patch.
+    let refreshed_tree_id =
+        repo.merge_trees_clean(temp_parent_tree_id, patch_tree_id, temp_tree_id)?;
+
+    let patch_hunks = stupid.diff_tree_hunks(parent_tree_id, patch_tree_id)?;
+    let parent_tree = repo.find_tree(parent_tree_id)?;
+    let exists_in_parent = |path: &BStr| {
+        parent_tree
+            .lookup_entry_by_path(gix::path::from_bstr(path))
+            .map(|entry| entry.is_some())
+    };
+
+    let (classification, files, interdiff) = if let Some(refreshed_tree_id) = refreshed_tree_id {
+        let refresh_hunks = stupid.diff_tree_hunks(patch_tree_id, refreshed_tree_id)?;
+        let files = file_reports(&patch_hunks, &refresh_hunks, true, exists_in_parent)?;
+        let patch_diff = |tree_id| {
+            stupid
+                .diff_tree_patch(
+                    parent_tree_id,
+                    tree_id,
+                    None::<Vec<String>>,
+                    false,
+                    ["--no-ext-diff"],
+                )
+                .map(|diff| normalize_for_interdiff(&diff))
+        };
+        let interdiff = stupid.diff_no_index(
+            (&format!("{patchname}.old"), &patch_diff(patch_tree_id)?),
+            (&format!("{patchname}.new"), &patch_diff(refreshed_tree_id)?),
+            false,
+        )?;
+        (classify(&files), files, interdiff)
+    } else {
+        // Without a refreshed tree, the refresh's own changes are reported. Their line
+        // numbers do not correspond to the patch, so patch lines cannot be identified.
+        let refresh_hunks = stupid.diff_tree_hunks(temp_parent_tree_id, temp_tree_id)?;
+        let files = file_reports(&patch_hunks, &refresh_hunks, false, exists_in_parent)?;
+        let mut classification = classify(&files);
+        classification.retain(|class| *class != Class::PureAddition);
+        classification.push(Class::Conflicting);
+        let diff = stupid.diff_tree_patch(
+            temp_parent_tree_id,
+            temp_tree_id,
+            None::<Vec<String>>,
+            false,
+            ["--no-ext-diff"],
+        )?;
+        (classification, files, diff)
+    };
+
+    let accepted_by_rules = classification.iter().all(|class| allowed.contains(class));
+    let report_to_stdout = report_path.is_some_and(|path| path.as_os_str() == "-");
+
+    let accepted = if accepted_by_rules {
+        true
+    } else {
+        let mut out = if report_to_stdout {
+            get_color_stderr(matches)
+        } else {
+            get_color_stdout(matches)
+        };
+        write_summary(
+            &mut out,
+            patchname,
+            &classification,
+            &files,
+            interdiff.as_bstr(),
+        )?;
+        match policy {
+            Policy::Warn => true,
+            Policy::Deny => false,
+            Policy::Prompt => {
+                if !std::io::stdin().is_terminal() {
+                    return Err(anyhow!(
+                        "cannot prompt to confirm refresh without a terminal; \
+                         use `--checked=warn` or `--checked=deny`"
+                    ));
+                }
+                crate::nl_extensions::inquire_confirm("Refresh patch?")?
+            }
+        }
+    };
+
+    if let Some(path) = report_path {
+        let report = Report {
+            patch: patchname.to_string(),
+            commit: patch_commit.id.to_string(),
+            policy,
+            classification: classification.clone(),
+            allowed,
+            accepted,
+            files,
+            interdiff: interdiff.to_str_lossy().to_string(),
+        };
+        if report_to_stdout {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+        } else {
+            let mut data = serde_json::to_vec_pretty(&report)?;
+            data.push(b'\n');
+            std::fs::write(path, data)?;
+        }
+    }
+
+    if accepted {
+        Ok(())
+    } else if policy == Policy::Deny {
+        Err(anyhow!(
+            "refresh of `{patchname}` denied by refresh check: {}",
+            join_classes(&classification)
+        ))
+    } else {
+        Err(anyhow!("refresh operation aborted"))
+    }
+}
+
+/// Parse a comma or whitespace separated list of classifications.
+fn parse_classes(s: &BStr) -> Result<Vec<Class>> {
+    s.to_str_lossy()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(Class::from_str)
+        .collect()
+}
+
+fn join_classes(classes: &[Class]) -> String {
+    classes
+        .iter()
+        .map(Class::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Determine the per-file changes of a refresh.
+///
+/// The `patch_hunks` are the patch's current diff and `refresh_hunks` are the changes
+/// made by the refresh. When `refresh_on_patch` is true, the refresh hunks' old line
+/// numbers refer to the patch's tree, which allows identifying removed lines that were
+/// added by the patch.
+fn file_reports(
+    patch_hunks: &[FileHunks],
+    refresh_hunks: &[FileHunks],
+    refresh_on_patch: bool,
+    exists_in_parent: impl Fn(&BStr) -> Result<bool, gix::object::find::existing::Error>,
+) -> Result<Vec<FileReport>> {
+    let mut files = Vec::with_capacity(refresh_hunks.len());
+    for file in refresh_hunks {
+        let patch_file = patch_hunks.iter().find(|pf| pf.path == file.path);
+        let scope = if patch_file.is_some() {
+            Scope::Patch
+        } else if exists_in_parent(file.path.as_bstr())? {
+            Scope::Outside
+        } else {
+            Scope::New
+        };
+        let removed_patch_lines = match patch_file {
+            Some(patch_file) if refresh_on_patch => file
+                .hunks
+                .iter()
+                .filter(|hunk| hunk.old_lines > 0)
+                .map(|hunk| {
+                    let removed = hunk.old_start..hunk.old_start + hunk.old_lines;
+                    patch_file
+                        .hunks
+                        .iter()
+                        .filter(|ph| ph.new_lines > 0)
+                        .map(|ph| {
+                            let added = ph.new_start..ph.new_start + ph.new_lines;
+                            removed
+                                .end
+                                .min(added.end)
+                                .saturating_sub(removed.start.max(added.start))
+                        })
+                        .sum::<usize>()
+                })
+                .sum(),
+            _ => 0,
+        };
+        files.push(FileReport {
+            path: file.path.to_str_lossy().to_string(),
+            scope,
+            added: file.hunks.iter().map(|hunk| hunk.new_lines).sum(),
+            removed: file.hunks.iter().map(|hunk| hunk.old_lines).sum(),
+            removed_patch_lines,
+            binary: file.binary,
+        });
+    }
+    Ok(files)
+}
+
+/// Classify the changes of a refresh from its per-file changes.
+///
+/// No classification is returned when there are no changes.
+fn classify(files: &[FileReport]) -> Vec<Class> {
+    let mut classes = Vec::new();
+    for file in files {
+        let class = match file.scope {
+            Scope::New => Class::NewFiles,
+            Scope::Outside => Class::OutsidePatch,
+            Scope::Patch if file.removed_patch_lines > 0 => Class::RemovesPatchLines,
+            Scope::Patch
+                if file.binary || file.removed > 0 || (file.added == 0 && file.removed == 0) =>
+            {
+                Class::ModifiesPatch
+            }
+            Scope::Patch => continue,
+        };
+        if !classes.contains(&class) {
+            classes.push(class);
+        }
+        if file.scope == Scope::Patch
+            && file.removed > file.removed_patch_lines
+            && !classes.contains(&Class::ModifiesPatch)
+        {
+            classes.push(Class::ModifiesPatch);
+        }
+    }
+    if classes.is_empty() && !files.is_empty() {
+        classes.push(Class::PureAddition);
+    }
+    classes.sort();
+    classes
+}
+
+fn write_summary(
+    out: &mut termcolor::StandardStream,
+    patchname: &PatchName,
+    classification: &[Class],
+    files: &[FileReport],
+    interdiff: &BStr,
+) -> Result<()> {
+    writeln!(out, ":: Checking intentions for patch: {patchname}")?;
+    writeln!(out, ":> Classification: {}", join_classes(classification))?;
+    writeln!(out, ":> Files:")?;
+    for file in files {
+        write!(out, "\t{:<7} +{} -{}", file.scope, file.added, file.removed)?;
+        if file.removed_patch_lines > 0 {
+            write!(out, " ({} patch lines removed)", file.removed_patch_lines)?;
+        }
+        writeln!(out, " {}", file.path)?;
+    }
+    writeln!(out, ":> Interdiff:")?;
+    let use_color = out.supports_color();
+    for line in interdiff.lines() {
+        out.write_all(b"\t")?;
+        if use_color {
+            let color = match line.first() {
+                Some(b'+') if !line.starts_with(b"+++") => Some(termcolor::Color::Green),
+                Some(b'-') if !line.starts_with(b"---") => Some(termcolor::Color::Red),
+                Some(b'@') => Some(termcolor::Color::Cyan),
+                _ => None,
+            };
+            out.set_color(termcolor::ColorSpec::new().set_fg(color))?;
+            out.write_all(line)?;
+            out.reset()?;
+        } else {
+            out.write_all(line)?;
+        }
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stupid::Hunk;
+
+    fn hunks(path: &str, ranges: &[(usize, usize, usize, usize)]) -> FileHunks {
+        FileHunks {
+            path: path.into(),
+            binary: false,
+            hunks: ranges
+                .iter()
+                .map(|&(old_start, old_lines, new_start, new_lines)| Hunk {
+                    old_start,
+                    old_lines,
+                    new_start,
+                    new_lines,
+                })
+                .collect(),
+        }
+    }
+
+    fn reports(patch: &[FileHunks], refresh: &[FileHunks]) -> Vec<FileReport> {
+        file_reports(patch, refresh, true, |path| Ok(path != "new.txt")).unwrap()
+    }
+
+    #[test]
+    fn classification() {
+        // The patch adds lines 3-4 of a.txt.
+        let patch = [hunks("a.txt", &[(2, 0, 3, 2)])];
+
+        let files = reports(&patch, &[hunks("a.txt", &[(4, 0, 5, 1)])]);
+        assert_eq!(classify(&files), vec![Class::PureAddition]);
+
+        let files = reports(&patch, &[hunks("a.txt", &[(4, 2, 3, 0)])]);
+        assert_eq!(files[0].removed_patch_lines, 1);
+        assert_eq!(
+            classify(&files),
+            vec![Class::RemovesPatchLines, Class::ModifiesPatch]
+        );
+
+        let files = reports(&patch, &[hunks("a.txt", &[(1, 1, 1, 1)])]);
+        assert_eq!(classify(&files), vec![Class::ModifiesPatch]);
+
+        let files = reports(
+            &patch,
+            &[
+                hunks("a.txt", &[(4, 0, 5, 1)]),
+                hunks("b.txt", &[(1, 0, 2, 1)]),
+                hunks("new.txt", &[(0, 0, 1, 1)]),
+            ],
+        );
+        assert_eq!(files[1].scope, Scope::Outside);
+        assert_eq!(files[2].scope, Scope::New);
+        assert_eq!(classify(&files), vec![Class::NewFiles, Class::OutsidePatch]);
+
+        assert!(classify(&reports(&patch, &[])).is_empty());
+    }
+}
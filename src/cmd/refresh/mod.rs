@@ -2,12 +2,15 @@
 
 //! `stg refresh` implementation.
 
+mod check;
+
 use std::{
     path::{Path, PathBuf},
-    rc::Rc,
+    str::FromStr,
 };
 
 use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
 use clap::{Arg, ArgGroup, ArgMatches, ValueHint};
 use indexmap::IndexSet;
 
@@ -17,7 +20,10 @@ use crate::{
     ext::{CommitExtended, RepositoryExtended, SignatureExtended},
     hook::run_pre_commit_hook,
     patch::{patchedit, LocationConstraint, PatchLocator, PatchName},
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stack::{
+        InitializationPolicy, PatchMeta, PatchState, Stack, StackAccess, StackStateAccess,
+        StackTransaction,
+    },
     stupid::{Status, StatusOptions, Statuses, Stupid, StupidContext},
     wrap::Message,
 };
@@ -73,6 +79,11 @@ fn make() -> clap::Command {
                 .long("update")
                 .short('u')
                 .help("Only update the current patch files")
+                .long_help(
+                    "Only update the files already changed by the patch. If the patch \
+                     has a recorded file scope, the files in the scope are updated \
+                     instead; see `stg scope`.",
+                )
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(
@@ -100,11 +111,30 @@ fn make() -> clap::Command {
                 )
                 .action(clap::ArgAction::SetTrue),
         )
+        .args(check::args())
         .arg(
-            Arg::new("checked")
-                .long("checked")
-                .help("Interactively validate intentions")
-                .action(clap::ArgAction::SetTrue),
+            Arg::new("scope")
+                .long("scope")
+                .help("Guard against changes outside the patch's file scope")
+                .long_help(
+                    "Guard against incorporating changes to files outside the patch's \
+                     recorded file scope; see `stg scope`. With \"refuse\", the \
+                     refresh is aborted if there are changes to files outside the \
+                     scope. With \"split\", changes to files outside the scope are \
+                     moved to a new patch on top of the stack. The default is taken \
+                     from `stgit.refresh.scope`. Patches without a recorded scope are \
+                     not guarded.",
+                )
+                .value_name("mode")
+                .value_parser(clap::value_parser!(ScopeMode))
+                .conflicts_with("update"),
+        )
+        .arg(
+            Arg::new("no-scope")
+                .long("no-scope")
+                .help("Do not guard against changes outside the patch's file scope")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("scope"),
         )
         .arg(argset::push_conflicts_arg())
         .arg(
@@ -152,6 +182,25 @@ fn make() -> clap::Command {
     patchedit::add_args(app, true, false)
 }
 
+/// How refresh handles changes to files outside a patch's recorded file scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ScopeMode {
+    Refuse,
+    Split,
+}
+
+impl FromStr for ScopeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(ScopeMode::Refuse),
+            "split" => Ok(ScopeMode::Split),
+            _ => Err(anyhow!("unsupported refresh scope mode `{s}`")),
+        }
+    }
+}
+
 fn run(matches: &ArgMatches) -> Result<()> {
     if matches.get_flag("spill") {
         return Err(anyhow!(
@@ -176,10 +225,26 @@ fn run(matches: &ArgMatches) -> Result<()> {
         return Err(super::Error::NoAppliedPatches.into());
     };
 
-    let tree_id = assemble_refresh_tree(
+    let scope_mode = if matches.get_flag("no-scope") || matches.get_flag("update") {
+        None
+    } else if let Some(mode) = matches.get_one::<ScopeMode>("scope") {
+        Some(*mode)
+    } else {
+        config
+            .string("stgit.refresh.scope")
+            .map(|s| ScopeMode::from_str(&s.to_str_lossy()))
+            .transpose()?
+    };
+    let scope_guard = scope_mode.and_then(|mode| {
+        let patch_meta = &stack.get_patch(&patchname).meta;
+        patch_meta.scope().is_some().then_some((patch_meta, mode))
+    });
+
+    let (tree_id, out_of_scope_paths) = assemble_refresh_tree(
         &stack,
         matches,
         matches.get_flag("update").then_some(&patchname),
+        scope_guard,
     )?;
 
     let mut log_msg = "refresh ".to_string();
@@ -201,14 +266,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         PatchName::make("refresh-temp", true, len_limit).uniquify(&allow, &disallow)
     };
 
-    if matches.get_flag("checked") {
-        crate::nl_extensions::validate_refresh_intentions(
-            &repo,
-            &stack,
-            &patchname,
-            Rc::new(stack.repo.find_commit(temp_commit_id)?),
-        )?;
-    }
+    check::check_refresh(&stack, &patchname, temp_commit_id, matches)?;
 
     let stack = stack
         .setup_transaction()
@@ -219,64 +277,41 @@ fn run(matches: &ArgMatches) -> Result<()> {
         ))?;
 
     let mut absorb_success = false;
-    stack
+    let stack = stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .with_output_stream(get_color_stdout(matches))
         .allow_push_conflicts(allow_push_conflicts)
         .transact(|trans| {
-            if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname) {
+            if trans.applied().contains(&patchname) {
                 // Absorb temp patch into already applied patch
-                let to_pop = trans.applied()[pos + 1..].to_vec();
-                if to_pop.len() > 1 {
-                    let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
-                    assert!(
-                        popped_extra.is_empty(),
-                        "only requested patches should be popped"
-                    );
-                    trans.push_patches(&[&temp_patchname], false)?;
-                }
-
-                let temp_commit = trans.get_patch_commit(&temp_patchname);
-
-                let mut to_pop = to_pop;
-                let top_name = to_pop.pop();
-                assert_eq!(top_name.as_ref(), Some(&temp_patchname));
-
-                let (new_patchname, new_commit_id) = match patchedit::EditBuilder::default()
-                    .original_patchname(Some(&patchname))
-                    .existing_patch_commit(trans.get_patch_commit(&patchname))
-                    .override_tree_id(temp_commit.tree_id()?.detach())
-                    .allow_diff_edit(false)
-                    .allow_implicit_edit(false)
-                    .allow_template_save(false)
-                    .edit(trans, &repo, matches)?
-                {
-                    patchedit::EditOutcome::Edited {
-                        new_patchname,
-                        new_commit_id,
-                    } => (new_patchname, new_commit_id),
-                    patchedit::EditOutcome::TemplateSaved(_) => {
-                        panic!("not allowed for refresh")
-                    }
-                };
-
-                trans.delete_patches(|pn| pn == &temp_patchname)?;
-                assert_eq!(Some(&patchname), trans.applied().last());
-                if let Some(commit_id) = new_commit_id {
-                    trans.update_patch(&patchname, commit_id)?;
-                }
-                if let Some(new_patchname) = new_patchname {
-                    trans.rename_patch(&patchname, &new_patchname)?;
-                } else {
+                let new_patchname =
+                    fold_temp_patch(trans, &patchname, &temp_patchname, |trans, tree_id| {
+                        match patchedit::EditBuilder::default()
+                            .original_patchname(Some(&patchname))
+                            .existing_patch_commit(trans.get_patch_commit(&patchname))
+                            .override_tree_id(tree_id)
+                            .allow_diff_edit(false)
+                            .allow_implicit_edit(false)
+                            .allow_template_save(false)
+                            .edit(trans, &repo, matches)?
+                        {
+                            patchedit::EditOutcome::Edited {
+                                new_patchname,
+                                new_commit_id,
+                            } => Ok((new_patchname, new_commit_id)),
+                            patchedit::EditOutcome::TemplateSaved(_) => {
+                                panic!("not allowed for refresh")
+                            }
+                        }
+                    })?;
+                if new_patchname.is_none() {
                     log_msg.push_str(patchname.as_ref());
                 }
                 if let Some(annotation) = opt_annotate {
                     log_msg.push_str("\n\n");
                     log_msg.push_str(annotation);
                 }
-
-                trans.push_patches(&to_pop, false)?;
                 absorb_success = true;
             } else {
                 // Absorb temp patch into unapplied patch
@@ -341,6 +376,10 @@ fn run(matches: &ArgMatches) -> Result<()> {
         })
         .execute(&log_msg)?;
 
+    if !out_of_scope_paths.is_empty() {
+        split_out_of_scope(stack, &patchname, &out_of_scope_paths, matches)?;
+    }
+
     if !absorb_success {
         println!(
             "The new changes did not apply cleanly to {}. \
@@ -352,13 +391,78 @@ fn run(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Fold an applied temporary patch into an applied patch below it.
+///
+/// The patches above `patchname`, including the temporary patch, are popped and the
+/// temporary patch is pushed directly onto `patchname`. The resulting tree is passed to
+/// `edit`, which determines the patch's new name and commit, if any. The temporary
+/// patch is then deleted, the patch is updated, and the popped patches are pushed back.
+///
+/// Returns the patch's new name if it was renamed.
+pub(super) fn fold_temp_patch<'repo, F>(
+    trans: &mut StackTransaction<'repo>,
+    patchname: &PatchName,
+    temp_patchname: &PatchName,
+    edit: F,
+) -> Result<Option<PatchName>>
+where
+    F: FnOnce(
+        &StackTransaction<'repo>,
+        gix::ObjectId,
+    ) -> Result<(Option<PatchName>, Option<gix::ObjectId>)>,
+{
+    let pos = trans
+        .applied()
+        .iter()
+        .position(|pn| pn == patchname)
+        .expect("patch to fold into is applied");
+    let to_pop = trans.applied()[pos + 1..].to_vec();
+    assert_eq!(to_pop.last(), Some(temp_patchname));
+    if to_pop.len() > 1 {
+        let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+        assert!(
+            popped_extra.is_empty(),
+            "only requested patches should be popped"
+        );
+        trans.push_patches(&[temp_patchname], false)?;
+    }
+
+    let mut to_pop = to_pop;
+    to_pop.pop();
+
+    let temp_tree_id = trans.get_patch_commit(temp_patchname).tree_id()?.detach();
+    let (new_patchname, new_commit_id) = edit(trans, temp_tree_id)?;
+
+    trans.delete_patches(|pn| pn == temp_patchname)?;
+    assert_eq!(Some(patchname), trans.applied().last());
+    if let Some(commit_id) = new_commit_id {
+        trans.update_patch(patchname, commit_id)?;
+    }
+    if let Some(new_patchname) = new_patchname.as_ref() {
+        trans.rename_patch(patchname, new_patchname)?;
+    }
+
+    trans.push_patches(&to_pop, false)?;
+    Ok(new_patchname)
+}
+
 fn determine_refresh_paths(
     stupid: &StupidContext,
     statuses: &Statuses,
-    patch_commit: Option<&Rc<gix::Commit>>,
+    patch: Option<&PatchState>,
     force: bool,
 ) -> Result<IndexSet<PathBuf>> {
-    let refresh_paths: IndexSet<&Path> = if let Some(patch_commit) = patch_commit {
+    let refresh_paths: IndexSet<&Path> = if let Some(patch_meta) = patch
+        .map(|patch| &patch.meta)
+        .filter(|patch_meta| patch_meta.scope().is_some())
+    {
+        // Restrict update to the paths in the patch's recorded scope.
+        statuses
+            .iter()
+            .map(|entry| entry.path())
+            .filter(|path| path.to_str().is_some_and(|path| patch_meta.in_scope(path)))
+            .collect()
+    } else if let Some(patch_commit) = patch.map(|patch| &patch.commit) {
         // Restrict update to the paths that were already part of the patch.
         let parent_tree_id = patch_commit.get_parent_commit()?.tree_id()?.detach();
         let diff_files =
@@ -438,22 +542,45 @@ fn write_tree(
     }
 }
 
+/// Assemble the tree to be incorporated into a patch by a refresh.
+///
+/// When `scope_guard` is provided, changes to files outside of the patch's recorded
+/// file scope are either refused or, when splitting, left out of the tree and returned
+/// such that they may be incorporated into another patch.
 pub(crate) fn assemble_refresh_tree(
     stack: &Stack,
     matches: &ArgMatches,
     limit_to_patchname: Option<&PatchName>,
-) -> Result<gix::ObjectId> {
+    scope_guard: Option<(&PatchMeta, ScopeMode)>,
+) -> Result<(gix::ObjectId, IndexSet<PathBuf>)> {
     let stupid = stack.repo.stupid();
     let opt_pathspecs = matches.get_many::<PathBuf>("pathspecs");
-    let is_path_limiting = limit_to_patchname.is_some() || opt_pathspecs.is_some();
+    let mut is_path_limiting = limit_to_patchname.is_some() || opt_pathspecs.is_some();
+    let mut out_of_scope_paths = IndexSet::new();
     let statuses;
 
     let refresh_paths = if matches.get_flag("index") {
         // When refreshing from the index, no path limiting may be used.
         assert!(!is_path_limiting);
+        if let Some((patch_meta, mode)) = scope_guard {
+            let head_tree_id = stack.get_branch_head().tree_id()?.detach();
+            let index_tree_id = stupid.write_tree()?;
+            let diff_files = stupid.diff_tree_files(head_tree_id, index_tree_id)?;
+            let paths: IndexSet<PathBuf> = diff_files
+                .iter()
+                .filter(|path| !path.to_str().is_some_and(|path| patch_meta.in_scope(path)))
+                .map(Path::to_path_buf)
+                .collect();
+            if !paths.is_empty() {
+                if mode == ScopeMode::Split {
+                    return Err(anyhow!("`--scope=split` cannot be used with `--index`"));
+                }
+                return Err(out_of_scope_error(&paths));
+            }
+        }
         IndexSet::new()
     } else {
-        let maybe_patch_commit = limit_to_patchname.map(|pn| stack.get_patch_commit(pn));
+        let maybe_patch = limit_to_patchname.map(|pn| stack.get_patch(pn));
         let submodules_flag = matches.get_flag("submodules");
         let nosubmodules_flag = matches.get_flag("no-submodules");
         let use_submodules = if !submodules_flag && !nosubmodules_flag {
@@ -472,12 +599,24 @@ pub(crate) fn assemble_refresh_tree(
         }
         statuses = stupid.statuses(Some(&status_opts))?;
 
-        determine_refresh_paths(
-            &stupid,
-            &statuses,
-            maybe_patch_commit,
-            matches.get_flag("force"),
-        )?
+        let refresh_paths =
+            determine_refresh_paths(&stupid, &statuses, maybe_patch, matches.get_flag("force"))?;
+
+        if let Some((patch_meta, mode)) = scope_guard {
+            let (in_scope, out_of_scope): (IndexSet<PathBuf>, IndexSet<PathBuf>) = refresh_paths
+                .into_iter()
+                .partition(|path| path.to_str().is_some_and(|path| patch_meta.in_scope(path)));
+            if !out_of_scope.is_empty() {
+                if mode == ScopeMode::Refuse {
+                    return Err(out_of_scope_error(&out_of_scope));
+                }
+                is_path_limiting = true;
+                out_of_scope_paths = out_of_scope;
+            }
+            in_scope
+        } else {
+            refresh_paths
+        }
     };
 
     let tree_id = write_tree(stack, &refresh_paths, is_path_limiting)?;
@@ -492,5 +631,47 @@ pub(crate) fn assemble_refresh_tree(
         write_tree(stack, &refresh_paths, is_path_limiting)?
     };
 
-    Ok(tree_id)
+    Ok((tree_id, out_of_scope_paths))
+}
+
+fn out_of_scope_error(paths: &IndexSet<PathBuf>) -> anyhow::Error {
+    let paths = paths
+        .iter()
+        .map(|path| format!("`{}`", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    anyhow!(
+        "refusing to refresh files outside the patch's scope: {paths}; \
+         use `stg scope add` to extend the scope or `--scope=split`"
+    )
+}
+
+/// Create a new patch on top of the stack from changes outside a patch's scope.
+fn split_out_of_scope(
+    stack: Stack,
+    patchname: &PatchName,
+    paths: &IndexSet<PathBuf>,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let repo = stack.repo;
+    let tree_id = write_tree(&stack, paths, true)?;
+    let commit_id = repo.commit_ex(
+        repo.get_author()?,
+        repo.get_committer()?,
+        &Message::from(format!("Changes outside the scope of {patchname}")),
+        tree_id,
+        [stack.get_branch_head().id],
+    )?;
+    let split_patchname = {
+        let disallow: Vec<&PatchName> = stack.all_patches().collect();
+        PatchName::make(&format!("{patchname}-unscoped"), true, None).uniquify(&[], &disallow)
+    };
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| trans.new_applied(&split_patchname, commit_id))
+        .execute(&format!(
+            "refresh {split_patchname} (split from {patchname})"
+        ))?;
+    Ok(())
 }
@@ -247,7 +247,7 @@ fn run_repair_reset(matches: &clap::ArgMatches) -> Result<()> {
     if stack.get_branch_head().id == stack.head().id {
         print_info_message(
             matches,
-            &format!("git head already matching stack state, doing nothing"),
+            "git head already matching stack state, doing nothing",
         );
         return Ok(());
     }
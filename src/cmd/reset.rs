@@ -9,7 +9,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackState},
+    stack::{is_dry_run, InitializationPolicy, Stack, StackState},
     stupid::Stupid,
 };
 
@@ -89,6 +89,11 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
             .execute("reset")?;
         Ok(())
     } else if matches.get_flag("hard") {
+        if is_dry_run() {
+            return Err(anyhow!(
+                "`stg reset --hard` without a committish does not support `--dry-run`"
+            ));
+        }
         let head_tree_id = repo.head_commit()?.tree_id()?.detach();
         repo.stupid().read_tree_checkout_hard(head_tree_id)
     } else {
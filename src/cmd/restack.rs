@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg restack` implementation.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    print_info_message,
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stupid::Stupid,
+    wrap::{Branch, PartialRefName},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "restack",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Rebase stacked branches onto their parent patches")
+        .long_about(
+            "Rebase the current branch onto the current commit of the patch it is \
+             stacked on, then do the same for each branch stacked on the current \
+             branch, directly or indirectly.\n\
+             \n\
+             A stacked branch is created with 'stg branch --create <new-branch> --on \
+             <branch>:<patch>'. When the parent patch is modified, e.g. with 'stg \
+             refresh', the stacked branch remains based on the patch's old commit \
+             until it is restacked. Restacking pops the stacked branch's applied \
+             patches, moves its base to the parent patch's new commit, and pushes the \
+             patches back.\n\
+             \n\
+             Branches that are not checked out are restacked without using the \
+             worktree. A patch that does not push cleanly onto such a branch stops \
+             the restack and remains unapplied.",
+        )
+        .override_usage(super::make_usage("stg restack", &["[OPTIONS]"]))
+        .arg(argset::branch_arg().help("Restack starting from <branch>"))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let config = repo.config_snapshot();
+    let start = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+
+    // Map each branch to the branches stacked on its patches.
+    let mut stacked_branches: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for local_branch in repo.references()?.local_branches()?.filter_map(Result::ok) {
+        let local_branch = Branch::wrap(local_branch);
+        let Ok(branchname) = local_branch.get_branch_partial_name() else {
+            continue;
+        };
+        let Ok(stack) =
+            Stack::from_branch_name(&repo, &branchname, InitializationPolicy::RequireInitialized)
+        else {
+            continue;
+        };
+        if let Some((parent_branchname, _)) = stack.get_parent_patch(&config) {
+            stacked_branches
+                .entry(parent_branchname)
+                .or_default()
+                .push(branchname.to_string());
+        }
+    }
+
+    let start_branchname = start.get_branch_name().to_string();
+    let is_stacked = start.get_parent_patch(&config).is_some();
+
+    let mut to_restack: Vec<String> = Vec::new();
+    let mut queue = VecDeque::from([start_branchname.clone()]);
+    while let Some(branchname) = queue.pop_front() {
+        if to_restack.contains(&branchname) {
+            continue;
+        }
+        if let Some(children) = stacked_branches.get(&branchname) {
+            queue.extend(children.iter().cloned());
+        }
+        to_restack.push(branchname);
+    }
+
+    if !is_stacked && to_restack.len() == 1 {
+        print_info_message(
+            matches,
+            &format!("Branch `{start_branchname}` has no stacked branches to restack"),
+        );
+        return Ok(());
+    }
+
+    let current_branchname = repo
+        .get_current_branch()
+        .ok()
+        .and_then(|branch| branch.get_branch_partial_name().ok());
+
+    for branchname in &to_restack {
+        let branchname: PartialRefName = branchname.parse()?;
+        let is_current = current_branchname.as_ref() == Some(&branchname);
+        restack(&repo, matches, &branchname, is_current)?;
+    }
+
+    Ok(())
+}
+
+/// Rebase a stacked branch onto its parent patch's current commit.
+fn restack(
+    repo: &gix::Repository,
+    matches: &ArgMatches,
+    branchname: &PartialRefName,
+    is_current: bool,
+) -> Result<()> {
+    let config = repo.config_snapshot();
+    let stack =
+        Stack::from_branch_name(repo, branchname, InitializationPolicy::RequireInitialized)?;
+    let Some((parent_branchname, parent_patchname)) = stack.get_parent_patch(&config) else {
+        return Ok(());
+    };
+    let parent_stack = Stack::from_branch_name(
+        repo,
+        &parent_branchname.parse()?,
+        InitializationPolicy::RequireInitialized,
+    )?;
+    if !parent_stack.has_patch(&parent_patchname) {
+        return Err(anyhow!(
+            "parent patch `{parent_patchname}` of branch `{branchname}` \
+             does not exist in branch `{parent_branchname}`"
+        ));
+    }
+    let new_base_id = parent_stack.get_patch_commit_id(&parent_patchname);
+
+    if new_base_id == stack.base().id {
+        print_info_message(matches, &format!("Branch `{branchname}` is up to date"));
+        return Ok(());
+    }
+
+    if stack.is_protected(&config) {
+        return Err(anyhow!(
+            "branch `{branchname}` is protected; restack is not permitted"
+        ));
+    }
+
+    stack.check_head_top_mismatch()?;
+    if is_current {
+        repo.check_repository_state()?;
+        let statuses = repo.stupid().statuses(None)?;
+        statuses.check_conflicts()?;
+        statuses.check_index_and_worktree_clean()?;
+    }
+
+    print_info_message(
+        matches,
+        &format!("Restacking `{branchname}` onto `{parent_branchname}:{parent_patchname}`"),
+    );
+
+    let applied = stack.applied().to_vec();
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(is_current)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            trans.pop_patches(|_| true)?;
+            let new_base = trans.repo().find_commit(new_base_id)?;
+            trans.rebase_onto(Rc::new(new_base));
+            trans.push_patches(&applied, false)
+        })
+        .execute("restack")?;
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg scope` implementation.
+
+use std::{
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Arg, ArgMatches, ValueHint};
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "scope",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Manage patch file scopes")
+        .long_about(
+            "Record, list, or modify the file scope of a patch.\n\
+             \n\
+             A patch's file scope is the set of files the patch is expected to \
+             change. Patches do not have a scope until one is recorded, which is \
+             initially the set of files changed by the patch. Directories may be \
+             added to the scope, in which case all files within the directory are in \
+             scope.\n\
+             \n\
+             When refreshing a patch with a scope, `stg refresh --scope=refuse` \
+             refuses to incorporate changes to files outside the scope and `stg \
+             refresh --scope=split` moves such changes to a new patch. The '--update' \
+             option of `stg refresh` is limited to the files in the scope.\n\
+             \n\
+             The scope is stored in the \"scope\" patch metadata key; see `stg meta`. \
+             The topmost patch is used unless a patch is specified with '--patch'.",
+        )
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("record")
+                .about("Record the files changed by the patch as its scope")
+                .long_about(
+                    "Record the files changed by the patch as its scope, replacing \
+                     any previously recorded scope.",
+                ),
+        )
+        .subcommand(
+            clap::Command::new("add")
+                .about("Add files to the patch's scope")
+                .long_about(
+                    "Add files or directories to the patch's scope. If the patch does \
+                     not have a scope, the files changed by the patch are also added.",
+                )
+                .arg(paths_arg()),
+        )
+        .subcommand(
+            clap::Command::new("remove")
+                .about("Remove files from the patch's scope")
+                .arg(paths_arg()),
+        )
+        .subcommand(clap::Command::new("list").about("List the files in the patch's scope"))
+        .subcommand(clap::Command::new("clear").about("Remove the patch's scope"))
+        .arg(argset::branch_arg().global(true).display_order(998))
+        .arg(
+            Arg::new("patch")
+                .long("patch")
+                .short('p')
+                .help("Use <patch> instead of the topmost patch")
+                .global(true)
+                .display_order(999)
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+}
+
+fn paths_arg() -> Arg {
+    Arg::new("paths")
+        .help("Files or directories")
+        .value_name("path")
+        .required(true)
+        .num_args(1..)
+        .value_hint(ValueHint::AnyPath)
+        .value_parser(clap::value_parser!(PathBuf))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let (subcommand, sub_matches) = matches.subcommand().expect("subcommand is required");
+    let stack = Stack::from_branch_locator(
+        &repo,
+        sub_matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::AllowUninitialized,
+    )?;
+
+    let patchname: PatchName = if let Some(locator) = sub_matches.get_one::<PatchLocator>("patch") {
+        locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::All)?
+    } else if let Some(patchname) = stack.applied().last() {
+        patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let mut patch_meta = stack.get_patch(&patchname).meta.clone();
+    let mut scope: Vec<String> = patch_meta
+        .scope()
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+
+    match subcommand {
+        "list" => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            for path in &scope {
+                writeln!(stdout, "{path}")?;
+            }
+            return Ok(());
+        }
+        "record" => {
+            scope = patch_paths(&stack, &patchname)?;
+            if scope.is_empty() {
+                return Err(anyhow!(
+                    "patch `{patchname}` does not change any files; use `stg scope add`"
+                ));
+            }
+        }
+        "add" => {
+            if patch_meta.scope().is_none() {
+                scope = patch_paths(&stack, &patchname)?;
+            }
+            for path in sub_matches.get_many::<PathBuf>("paths").expect("required") {
+                scope.push(to_scope_path(&repo, path)?);
+            }
+        }
+        "remove" => {
+            for path in sub_matches.get_many::<PathBuf>("paths").expect("required") {
+                let path = to_scope_path(&repo, path)?;
+                let len_before = scope.len();
+                scope.retain(|scope_path| scope_path != &path);
+                if scope.len() == len_before {
+                    return Err(anyhow!("`{path}` is not in the scope of `{patchname}`"));
+                }
+            }
+        }
+        "clear" => {
+            if scope.is_empty() {
+                return Err(anyhow!("patch `{patchname}` has no scope"));
+            }
+            scope.clear();
+        }
+        _ => panic!("valid subcommand is required"),
+    }
+
+    patch_meta.set_scope(scope.iter().map(String::as_str));
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| trans.update_patch_meta(&patchname, patch_meta))
+        .execute(&format!("scope {subcommand} {patchname}"))?;
+
+    Ok(())
+}
+
+/// Get the worktree-relative paths of the files changed by a patch.
+fn patch_paths(stack: &Stack, patchname: &PatchName) -> Result<Vec<String>> {
+    let patch_commit = stack.get_patch_commit(patchname);
+    let parent_tree_id = patch_commit.get_parent_commit()?.tree_id()?.detach();
+    let diff_files = stack
+        .repo
+        .stupid()
+        .diff_tree_files(parent_tree_id, patch_commit.tree_id()?.detach())?;
+    diff_files
+        .iter()
+        .map(|path| {
+            path.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("non-UTF-8 path `{}`", path.display()))
+        })
+        .collect()
+}
+
+/// Convert a path relative to the current directory to a path relative to the root of
+/// the worktree.
+fn to_scope_path(repo: &gix::Repository, path: &Path) -> Result<String> {
+    let work_dir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("cannot resolve paths without a worktree"))?;
+    let path = if path.is_absolute() {
+        path.strip_prefix(work_dir)
+            .map_err(|_| anyhow!("`{}` is outside the worktree", path.display()))?
+            .to_path_buf()
+    } else {
+        let prefix = repo.prefix().context("determining Git prefix")?;
+        prefix.map_or_else(|| path.to_path_buf(), |prefix| prefix.join(path))
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if components.pop().is_none() {
+                    return Err(anyhow!("`{}` is outside the worktree", path.display()));
+                }
+            }
+            Component::Normal(name) => components.push(
+                name.to_str()
+                    .ok_or_else(|| anyhow!("non-UTF-8 path `{}`", path.display()))?,
+            ),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow!("`{}` is outside the worktree", path.display()));
+            }
+        }
+    }
+
+    if components.is_empty() {
+        Err(anyhow!("the worktree root may not be used as a scope path"))
+    } else {
+        Ok(components.join("/"))
+    }
+}
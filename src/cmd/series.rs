@@ -13,6 +13,7 @@ use crate::{
     argset,
     branchloc::BranchLocator,
     ext::{CommitExtended, RepositoryExtended},
+    format::{self, PatchRecord},
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
 };
@@ -134,7 +135,14 @@ fn make() -> clap::Command {
                 .short('c')
                 .help("Display the number of selected patches and exit")
                 .action(clap::ArgAction::SetTrue)
-                .conflicts_with_all(["description", "author", "empty", "show-branch", "no-prefix"]),
+                .conflicts_with_all([
+                    "description",
+                    "author",
+                    "empty",
+                    "show-branch",
+                    "no-prefix",
+                    "format",
+                ]),
         )
         .arg(
             Arg::new("commit-id")
@@ -289,6 +297,7 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .overrides_with("show-branch"),
         )
+        .arg(format::format_arg())
 }
 
 #[derive(Clone)]
@@ -365,11 +374,27 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     if let Some(range_specs) = matches.get_many::<PatchRange>("patchranges-all") {
         let top_patchname = stack.applied().last();
-        for patchname in patchrange::resolve_names_contiguous(
-            &stack,
-            range_specs,
-            RangeConstraint::AllWithAppliedBoundary,
-        )? {
+        let range_specs: Vec<&PatchRange> = range_specs.collect();
+        // Labeled patches need not be contiguous, so they are shown in stack order.
+        let patchnames = if range_specs
+            .iter()
+            .any(|range| matches!(range, PatchRange::Label(_)))
+        {
+            let mut patchnames = patchrange::resolve_names(
+                &stack,
+                range_specs,
+                RangeConstraint::AllWithAppliedBoundary,
+            )?;
+            patchnames.sort_by_key(|pn| stack.index_of(pn));
+            patchnames
+        } else {
+            patchrange::resolve_names_contiguous(
+                &stack,
+                range_specs,
+                RangeConstraint::AllWithAppliedBoundary,
+            )?
+        };
+        for patchname in patchnames {
             let commit_id = stack.get_patch_commit_id(&patchname);
             let sigil = if Some(&patchname) == top_patchname {
                 '>'
@@ -525,19 +550,21 @@ fn run(matches: &ArgMatches) -> Result<()> {
     let indices_flag = matches.get_flag("indices");
     let offsets_flag = matches.get_flag("offsets");
 
-    let index_width = (indices_flag && !patches.is_empty())
-        .then(|| patches.last().unwrap().index.to_string().len())
-        .unwrap_or_default();
+    let index_width = if indices_flag && !patches.is_empty() {
+        patches.last().unwrap().index.to_string().len()
+    } else {
+        0
+    };
 
-    let offset_width = (offsets_flag && !patches.is_empty())
-        .then(|| {
-            [patches.first().unwrap(), patches.last().unwrap()]
-                .iter()
-                .map(|entry| format!("{:+}", entry.offset_from_top).len())
-                .max()
-                .unwrap()
-        })
-        .unwrap_or_default();
+    let offset_width = if offsets_flag && !patches.is_empty() {
+        [patches.first().unwrap(), patches.last().unwrap()]
+            .iter()
+            .map(|entry| format!("{:+}", entry.offset_from_top).len())
+            .max()
+            .unwrap()
+    } else {
+        0
+    };
 
     let mut stdout = crate::color::get_color_stdout(matches);
     let mut color_spec = termcolor::ColorSpec::new();
@@ -546,6 +573,14 @@ fn run(matches: &ArgMatches) -> Result<()> {
         patches.reverse();
     }
 
+    if let Some(format) = format::get_format(matches) {
+        let records = patches
+            .iter()
+            .map(|Entry { patchname, .. }| PatchRecord::new(&stack, patchname))
+            .collect::<Result<Vec<_>>>()?;
+        return format.write_list(&mut std::io::stdout().lock(), &records);
+    }
+
     for Entry {
         patchname,
         commit_id,
@@ -10,7 +10,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, LocationConstraint, PatchLocator, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{InitializationPolicy, PatchDependencies, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -41,6 +41,10 @@ fn make() -> clap::Command {
              of the stack where they less likely to be impacted by the push of another \
              patch, and from where they can be more easily committed or pushed to \
              another repository.\n\
+             \n\
+             With '--with-deps', the patches that the sunk patches depend on are sunk \
+             along with them such that no patch is placed beneath a patch it depends \
+             on. See `stg deps`.\n\
              ",
         )
         .arg(
@@ -94,6 +98,7 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(PatchLocator))
                 .conflicts_with("target-below"),
         )
+        .arg(argset::with_deps_arg())
         .arg(argset::keep_arg())
         .arg(argset::committer_date_is_author_date_arg())
 }
@@ -143,6 +148,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
             return Err(super::Error::NoAppliedPatches.into());
         };
 
+    let patches = if matches.get_flag("with-deps") {
+        PatchDependencies::compute(&repo, &stack)?.with_dependencies(&patches)
+    } else {
+        patches
+    };
+
     if let Some(target_patch) = &opt_target {
         if patches.contains(target_patch) {
             return Err(anyhow!(
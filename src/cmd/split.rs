@@ -0,0 +1,770 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg split` implementation.
+
+use std::{io::Write, ops::RangeInclusive};
+
+use anyhow::{anyhow, Context, Result};
+use bstr::{BString, ByteSlice};
+use clap::{Arg, ArgMatches};
+use is_terminal::IsTerminal;
+use termcolor::WriteColor;
+
+use crate::{
+    argset,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::{parse_file_diffs, FileDiff, Hunk, HunkDiff, Stupid},
+    wrap::Message,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "split",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Split a patch into multiple patches")
+        .long_about(
+            "Split a patch into two or more patches.\n\
+             \n\
+             Changes from the patch are selected for a new patch which is placed \
+             immediately below the patch being split. The patch being split retains \
+             its name and message and keeps the remaining changes.\n\
+             \n\
+             Without any of '--hunks', '--path', or '--lines', the hunks of the patch \
+             are shown and the changes for each new patch are selected interactively, \
+             followed by a prompt for the new patch's message. This repeats, creating \
+             a new patch each time, until no hunks are selected.\n\
+             \n\
+             With '--hunks', '--path', or '--lines', the selected changes are split \
+             into a single new patch. The options may be combined and repeated. Use \
+             '--list' to see the patch's numbered hunks.\n\
+             \n\
+             The patch being split need not be the topmost patch and may be \
+             unapplied. The index and worktree are not modified. The split is \
+             performed as a single operation that may be reverted with 'stg undo'.",
+        )
+        .arg(
+            Arg::new("patch")
+                .help("Patch to split (default: topmost patch)")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("hunks")
+                .long("hunks")
+                .short('H')
+                .help("Select hunks by number, e.g. '1,3-4'")
+                .value_name("list")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_hunk_list),
+        )
+        .arg(
+            Arg::new("path")
+                .long("path")
+                .short('p')
+                .help("Select all changes to <path>")
+                .long_help(
+                    "Select all changes to files at <path>. The path is relative to \
+                     the top of the repository and may be a directory.",
+                )
+                .value_name("path")
+                .action(clap::ArgAction::Append)
+                .value_hint(clap::ValueHint::FilePath),
+        )
+        .arg(
+            Arg::new("lines")
+                .long("lines")
+                .short('L')
+                .help("Select changed lines <start>-<end> of <path>")
+                .long_help(
+                    "Select changed lines of <path> in the range <start>-<end>, \
+                     inclusive. Added lines are selected by their line number in the \
+                     patched file and removed lines by their line number in the \
+                     original file.",
+                )
+                .value_name("path:start-end")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_line_range),
+        )
+        .arg(
+            Arg::new("list")
+                .long("list")
+                .short('l')
+                .help("List the patch's numbered hunks and exit")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["hunks", "path", "lines", "name", "message"]),
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .short('n')
+                .help("Use <name> for the first new patch")
+                .value_name("name")
+                .value_parser(clap::value_parser!(PatchName)),
+        )
+        .arg(
+            Arg::new("message")
+                .long("message")
+                .short('m')
+                .help("Use <message> for the new patch")
+                .value_name("message")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
+        .arg(argset::committer_date_is_author_date_arg())
+}
+
+/// Changed lines of a file selected with `--lines`.
+#[derive(Clone, Debug)]
+struct LineRange {
+    path: BString,
+    range: RangeInclusive<usize>,
+}
+
+fn parse_hunk_list(s: &str) -> Result<Vec<RangeInclusive<usize>>> {
+    let parse_number = |n: &str| match n.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(anyhow!("'{n}' is not a valid hunk number")),
+    };
+    s.split(',')
+        .map(|part| {
+            if let Some((first, last)) = part.split_once('-') {
+                Ok(parse_number(first)?..=parse_number(last)?)
+            } else {
+                let n = parse_number(part)?;
+                Ok(n..=n)
+            }
+        })
+        .collect()
+}
+
+fn parse_line_range(s: &str) -> Result<LineRange> {
+    let (path, range) = s
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("'{s}' is not of the form <path>:<start>-<end>"))?;
+    let parse_number = |n: &str| {
+        n.parse::<usize>()
+            .map_err(|_| anyhow!("'{n}' is not a valid line number"))
+    };
+    let range = if let Some((start, end)) = range.split_once('-') {
+        parse_number(start)?..=parse_number(end)?
+    } else {
+        let n = parse_number(range)?;
+        n..=n
+    };
+    Ok(LineRange {
+        path: path.into(),
+        range,
+    })
+}
+
+/// The selectable units of a diff: each hunk, or the whole file for files without
+/// hunks, e.g. binary files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Unit {
+    file: usize,
+    hunk: Option<usize>,
+}
+
+/// How much of a unit is selected.
+#[derive(Clone, Debug)]
+enum Pick {
+    All,
+    Lines(Vec<RangeInclusive<usize>>),
+}
+
+fn units(files: &[FileDiff]) -> Vec<Unit> {
+    files
+        .iter()
+        .enumerate()
+        .flat_map(|(file, file_diff)| {
+            if file_diff.hunks.is_empty() {
+                vec![Unit { file, hunk: None }]
+            } else {
+                (0..file_diff.hunks.len())
+                    .map(|hunk| Unit {
+                        file,
+                        hunk: Some(hunk),
+                    })
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
+    let stupid = repo.stupid();
+
+    let patchname = if let Some(locator) = matches.get_one::<PatchLocator>("patch") {
+        locator
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::Visible)?
+    } else if let Some(patchname) = stack.applied().last() {
+        patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let patch_commit = stack.get_patch_commit(&patchname).clone();
+    let parent_tree_id = patch_commit.get_parent_commit()?.tree_id()?.detach();
+    let patch_tree_id = patch_commit.tree_id()?.detach();
+
+    if matches.get_flag("list") {
+        let files = diff_files(&repo, parent_tree_id, patch_tree_id)?;
+        let mut stdout = get_color_stdout(matches);
+        for (i, unit) in units(&files).iter().enumerate() {
+            writeln!(stdout, "{:>3}  {}", i + 1, unit_label(&files, *unit))?;
+        }
+        return Ok(());
+    }
+
+    repo.check_repository_state()?;
+    let statuses = stupid.statuses(None)?;
+    statuses.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let is_non_interactive =
+        matches.contains_id("hunks") || matches.contains_id("path") || matches.contains_id("lines");
+    let is_terminal = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    if !is_non_interactive && !is_terminal {
+        return Err(anyhow!(
+            "select changes with `--hunks`, `--path`, or `--lines` when not running \
+             in a terminal"
+        ));
+    }
+
+    let config = repo.config_snapshot();
+    let name_len_limit = PatchName::get_length_limit(&config);
+    let mut taken_patchnames: Vec<PatchName> = stack.all_patches().cloned().collect();
+    if let Some(name) = matches.get_one::<PatchName>("name") {
+        if let Some(colliding) = taken_patchnames.iter().find(|pn| pn.collides(name)) {
+            return Err(anyhow!("patch name `{colliding}` already taken"));
+        }
+    }
+
+    // Each new patch is represented by its name, message, and tree.
+    let mut new_patches: Vec<(PatchName, String, gix::ObjectId)> = Vec::new();
+    let mut tree_id = parent_tree_id;
+
+    loop {
+        let files = diff_files(&repo, tree_id, patch_tree_id)?;
+        let units = units(&files);
+
+        let picks = if is_non_interactive {
+            select_from_args(matches, &files, &units)?
+        } else if units.len() < 2 {
+            if new_patches.is_empty() {
+                return Err(anyhow!(
+                    "patch `{patchname}` has only one hunk; use `--lines` to split it"
+                ));
+            }
+            break;
+        } else if let Some(picks) = select_interactively(matches, &files, &units)? {
+            picks
+        } else {
+            break;
+        };
+
+        let diff = assemble_diff(&files, &units, &picks)?;
+        if diff.is_empty() {
+            return Err(anyhow!("selection does not match any changes"));
+        }
+        let new_tree_id = stupid
+            .with_temp_index(|stupid_temp| {
+                stupid_temp.read_tree(tree_id)?;
+                stupid_temp.apply_to_index(diff.as_bstr())?;
+                stupid_temp.write_tree()
+            })
+            .context("applying selected changes")?;
+        if new_tree_id == patch_tree_id {
+            return Err(anyhow!(
+                "selection includes all changes of `{patchname}`; nothing would remain"
+            ));
+        }
+
+        let message = if let Some(message) = matches.get_one::<String>("message") {
+            message.clone()
+        } else if is_terminal {
+            prompt_message(new_patches.len() + 1)?
+        } else {
+            return Err(anyhow!(
+                "a message is required for the new patch; use `--message`"
+            ));
+        };
+
+        let new_patchname = match matches.get_one::<PatchName>("name") {
+            Some(name) if new_patches.is_empty() => name.clone(),
+            _ => PatchName::make(&message, true, name_len_limit)
+                .uniquify(&[], &taken_patchnames.iter().collect::<Vec<_>>()),
+        };
+        taken_patchnames.push(new_patchname.clone());
+        new_patches.push((new_patchname, message, new_tree_id));
+        tree_id = new_tree_id;
+
+        if is_non_interactive {
+            break;
+        }
+    }
+
+    if new_patches.is_empty() {
+        return Err(anyhow!("no changes selected"));
+    }
+
+    let author = patch_commit.author_strict()?;
+    let default_committer = repo.get_committer()?;
+    let committer = if matches.get_flag("committer-date-is-author-date") {
+        let mut committer = default_committer.to_owned();
+        committer.time = author.time;
+        committer
+    } else {
+        default_committer.to_owned()
+    };
+
+    let mut parent_id = patch_commit.get_parent_commit()?.id;
+    let mut new_commits: Vec<(PatchName, gix::ObjectId)> = Vec::new();
+    for (new_patchname, message, tree_id) in new_patches {
+        let message = if message.ends_with('\n') {
+            message
+        } else {
+            message + "\n"
+        };
+        let commit_id = repo.commit_ex(
+            &author,
+            &committer,
+            &Message::from(message),
+            tree_id,
+            [parent_id],
+        )?;
+        new_commits.push((new_patchname, commit_id));
+        parent_id = commit_id;
+    }
+    let remainder_commit_id = repo.commit_ex(
+        &author,
+        &committer,
+        &patch_commit.message_ex(),
+        patch_tree_id,
+        [parent_id],
+    )?;
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let mut to_push: Vec<PatchName> = Vec::new();
+            let insert_pos =
+                if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname) {
+                    let to_pop = trans.applied()[pos..].to_vec();
+                    let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+                    assert!(popped_extra.is_empty());
+                    to_push.extend(new_commits.iter().map(|(pn, _)| pn.clone()));
+                    to_push.extend(to_pop);
+                    0
+                } else {
+                    trans
+                        .unapplied()
+                        .iter()
+                        .position(|pn| pn == &patchname)
+                        .expect("patch is visible")
+                };
+            trans.update_patch(&patchname, remainder_commit_id)?;
+            for (i, (new_patchname, commit_id)) in new_commits.iter().enumerate() {
+                trans.new_unapplied(new_patchname, *commit_id, insert_pos + i)?;
+            }
+            trans.push_patches(&to_push, false)
+        })
+        .execute(&format!("split {patchname}"))?;
+
+    Ok(())
+}
+
+fn diff_files(
+    repo: &gix::Repository,
+    tree1: gix::ObjectId,
+    tree2: gix::ObjectId,
+) -> Result<Vec<FileDiff>> {
+    let diff = repo.stupid().diff_tree_patch(
+        tree1,
+        tree2,
+        None::<Vec<String>>,
+        false,
+        ["--binary", "--no-renames", "--no-ext-diff"],
+    )?;
+    Ok(parse_file_diffs(&diff))
+}
+
+fn unit_label(files: &[FileDiff], unit: Unit) -> String {
+    let file = &files[unit.file];
+    if let Some(hunk) = unit.hunk {
+        let hunk_diff = &file.hunks[hunk];
+        let Hunk {
+            old_start,
+            old_lines,
+            new_start,
+            new_lines,
+        } = hunk_diff.hunk;
+        let added = hunk_diff
+            .lines
+            .iter()
+            .filter(|l| l.starts_with(b"+"))
+            .count();
+        let removed = hunk_diff
+            .lines
+            .iter()
+            .filter(|l| l.starts_with(b"-"))
+            .count();
+        format!(
+            "{} @@ -{old_start},{old_lines} +{new_start},{new_lines} @@{} (+{added} -{removed})",
+            file.path.to_str_lossy(),
+            hunk_diff.section.to_str_lossy(),
+        )
+    } else {
+        format!("{} (no hunks)", file.path.to_str_lossy())
+    }
+}
+
+/// Determine the selected units from the `--hunks`, `--path`, and `--lines` options.
+fn select_from_args(
+    matches: &ArgMatches,
+    files: &[FileDiff],
+    units: &[Unit],
+) -> Result<Vec<Option<Pick>>> {
+    let mut picks: Vec<Option<Pick>> = vec![None; units.len()];
+
+    if let Some(lists) = matches.get_many::<Vec<RangeInclusive<usize>>>("hunks") {
+        for range in lists.flatten() {
+            for n in range.clone() {
+                let pick = picks.get_mut(n - 1).ok_or_else(|| {
+                    anyhow!(
+                        "hunk {n} does not exist; the patch has {} hunks",
+                        units.len()
+                    )
+                })?;
+                *pick = Some(Pick::All);
+            }
+        }
+    }
+
+    if let Some(paths) = matches.get_many::<String>("path") {
+        for path in paths {
+            let path = path.trim_end_matches('/').as_bytes();
+            let mut found = false;
+            for (unit, pick) in units.iter().zip(picks.iter_mut()) {
+                let file_path = files[unit.file].path.as_bytes();
+                if file_path == path
+                    || (file_path.starts_with(path) && file_path.get(path.len()) == Some(&b'/'))
+                {
+                    *pick = Some(Pick::All);
+                    found = true;
+                }
+            }
+            if !found {
+                return Err(anyhow!(
+                    "path `{}` is not changed by the patch",
+                    path.to_str_lossy()
+                ));
+            }
+        }
+    }
+
+    if let Some(line_ranges) = matches.get_many::<LineRange>("lines") {
+        for LineRange { path, range } in line_ranges {
+            let mut found = false;
+            for (unit, pick) in units.iter().zip(picks.iter_mut()) {
+                if &files[unit.file].path != path {
+                    continue;
+                }
+                if unit.hunk.is_none() {
+                    return Err(anyhow!("cannot select lines of `{path}`"));
+                }
+                found = true;
+                match pick {
+                    Some(Pick::All) => {}
+                    Some(Pick::Lines(ranges)) => ranges.push(range.clone()),
+                    None => *pick = Some(Pick::Lines(vec![range.clone()])),
+                }
+            }
+            if !found {
+                return Err(anyhow!("path `{path}` is not changed by the patch"));
+            }
+        }
+    }
+
+    Ok(picks)
+}
+
+/// Show the hunks and prompt for the ones to include in the next new patch.
+///
+/// Returns `None` if no hunks were selected.
+fn select_interactively(
+    matches: &ArgMatches,
+    files: &[FileDiff],
+    units: &[Unit],
+) -> Result<Option<Vec<Option<Pick>>>> {
+    let mut stdout = get_color_stdout(matches);
+    let mut color_spec = termcolor::ColorSpec::new();
+    for (i, unit) in units.iter().enumerate() {
+        stdout.set_color(
+            color_spec
+                .set_fg(Some(termcolor::Color::Yellow))
+                .set_bold(true),
+        )?;
+        writeln!(stdout, "[{}] {}", i + 1, unit_label(files, *unit))?;
+        color_spec.clear();
+        stdout.reset()?;
+        let Some(hunk) = unit.hunk else {
+            continue;
+        };
+        for line in &files[unit.file].hunks[hunk].lines {
+            let color = match line.first() {
+                Some(b'+') => Some(termcolor::Color::Green),
+                Some(b'-') => Some(termcolor::Color::Red),
+                _ => None,
+            };
+            stdout.set_color(color_spec.set_fg(color))?;
+            stdout.write_all(line)?;
+            stdout.reset()?;
+        }
+    }
+    stdout.flush()?;
+
+    let labels: Vec<String> = units
+        .iter()
+        .enumerate()
+        .map(|(i, unit)| format!("[{}] {}", i + 1, unit_label(files, *unit)))
+        .collect();
+    let num_units = labels.len();
+    let selected =
+        inquire::MultiSelect::new("Select hunks for the new patch (none to finish)", labels)
+            .with_render_config(crate::nl_extensions::inquire_default_render_config())
+            .with_validator(
+                move |selected: &[inquire::list_option::ListOption<&String>]| {
+                    if selected.len() == num_units {
+                        Ok(inquire::validator::Validation::Invalid(
+                            "at least one hunk must remain in the patch".into(),
+                        ))
+                    } else {
+                        Ok(inquire::validator::Validation::Valid)
+                    }
+                },
+            )
+            .raw_prompt()?;
+
+    if selected.is_empty() {
+        return Ok(None);
+    }
+    let mut picks = vec![None; units.len()];
+    for option in selected {
+        picks[option.index] = Some(Pick::All);
+    }
+    Ok(Some(picks))
+}
+
+fn prompt_message(number: usize) -> Result<String> {
+    Ok(
+        inquire::Text::new(&format!("Message for new patch #{number}"))
+            .with_render_config(crate::nl_extensions::inquire_default_render_config())
+            .with_validator(inquire::validator::ValueRequiredValidator::default())
+            .prompt()?,
+    )
+}
+
+/// Build a diff containing only the selected changes.
+fn assemble_diff(files: &[FileDiff], units: &[Unit], picks: &[Option<Pick>]) -> Result<BString> {
+    let mut diff = BString::from(Vec::new());
+    for (file_index, file) in files.iter().enumerate() {
+        let file_picks: Vec<(Unit, &Pick)> = units
+            .iter()
+            .zip(picks)
+            .filter(|(unit, _)| unit.file == file_index)
+            .filter_map(|(unit, pick)| pick.as_ref().map(|pick| (*unit, pick)))
+            .collect();
+        if file_picks.is_empty() {
+            continue;
+        }
+
+        let mut hunks: Vec<HunkDiff> = Vec::new();
+        let mut is_complete =
+            file_picks.len() == units.iter().filter(|u| u.file == file_index).count();
+        for (unit, pick) in file_picks {
+            let Some(hunk) = unit.hunk else {
+                continue;
+            };
+            let hunk = &file.hunks[hunk];
+            match pick {
+                Pick::All => hunks.push(hunk.clone()),
+                Pick::Lines(ranges) => {
+                    if let Some(filtered) = filter_hunk(hunk, ranges) {
+                        is_complete &= filtered.lines == hunk.lines;
+                        hunks.push(filtered);
+                    } else {
+                        is_complete = false;
+                    }
+                }
+            }
+        }
+        if hunks.is_empty() && !file.hunks.is_empty() {
+            continue;
+        }
+
+        if is_complete {
+            diff.extend_from_slice(&file.header);
+        } else {
+            // A deleted file is only deleted once all of its lines are removed.
+            for line in file.header.lines_with_terminator() {
+                if line.starts_with(b"deleted file mode ") || line.starts_with(b"index ") {
+                    continue;
+                } else if line.starts_with(b"+++ /dev/null") {
+                    diff.extend_from_slice(b"+++ b/");
+                    diff.extend_from_slice(&file.path);
+                    diff.push(b'\n');
+                } else {
+                    diff.extend_from_slice(line);
+                }
+            }
+        }
+        for hunk in hunks {
+            let Hunk {
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+            } = hunk.hunk;
+            writeln!(
+                diff,
+                "@@ -{old_start},{old_lines} +{new_start},{new_lines} @@{}",
+                hunk.section
+            )?;
+            for line in hunk.lines {
+                diff.extend_from_slice(&line);
+            }
+        }
+    }
+    Ok(diff)
+}
+
+/// Reduce a hunk to the changed lines in the given line ranges.
+///
+/// Unselected removed lines become context lines and unselected added lines are
+/// dropped. Returns `None` if no changed lines are selected.
+fn filter_hunk(hunk: &HunkDiff, ranges: &[RangeInclusive<usize>]) -> Option<HunkDiff> {
+    let is_selected = |n: usize| ranges.iter().any(|range| range.contains(&n));
+    let mut old_number = hunk.hunk.old_start;
+    let mut new_number = hunk.hunk.new_start;
+    let mut lines: Vec<BString> = Vec::with_capacity(hunk.lines.len());
+    let mut is_changed = false;
+    let mut is_last_kept = true;
+
+    for line in &hunk.lines {
+        match line.first() {
+            Some(b'-') => {
+                if is_selected(old_number) {
+                    lines.push(line.clone());
+                    is_changed = true;
+                } else {
+                    let mut context = BString::from(" ");
+                    context.extend_from_slice(&line[1..]);
+                    lines.push(context);
+                }
+                old_number += 1;
+                is_last_kept = true;
+            }
+            Some(b'+') => {
+                is_last_kept = is_selected(new_number);
+                if is_last_kept {
+                    lines.push(line.clone());
+                    is_changed = true;
+                }
+                new_number += 1;
+            }
+            Some(b'\\') => {
+                if is_last_kept {
+                    lines.push(line.clone());
+                }
+            }
+            _ => {
+                lines.push(line.clone());
+                old_number += 1;
+                new_number += 1;
+                is_last_kept = true;
+            }
+        }
+    }
+
+    if !is_changed {
+        return None;
+    }
+
+    let count = |kinds: &[u8]| {
+        lines
+            .iter()
+            .filter(|line| line.first().is_some_and(|c| kinds.contains(c)))
+            .count()
+    };
+    Some(HunkDiff {
+        hunk: Hunk {
+            old_start: hunk.hunk.old_start,
+            old_lines: count(b" -"),
+            new_start: hunk.hunk.new_start,
+            new_lines: count(b" +"),
+        },
+        section: hunk.section.clone(),
+        lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunk_filtering() {
+        let hunk = HunkDiff {
+            hunk: Hunk {
+                old_start: 10,
+                old_lines: 3,
+                new_start: 10,
+                new_lines: 3,
+            },
+            section: BString::from(""),
+            lines: [" a\n", "-b\n", "-c\n", "+B\n", "+C\n", " d\n"]
+                .into_iter()
+                .map(BString::from)
+                .collect(),
+        };
+
+        let filtered = filter_hunk(&hunk, &[11..=11]).unwrap();
+        assert_eq!(filtered.lines, vec![" a\n", "-b\n", " c\n", "+B\n", " d\n"]);
+        assert_eq!(
+            filtered.hunk,
+            Hunk {
+                old_start: 10,
+                old_lines: 4,
+                new_start: 10,
+                new_lines: 4,
+            }
+        );
+
+        assert!(filter_hunk(&hunk, &[1..=5, 20..=30]).is_none());
+        assert_eq!(filter_hunk(&hunk, &[1..=20]).unwrap().lines, hunk.lines);
+    }
+
+    #[test]
+    fn hunk_list_parsing() {
+        assert_eq!(parse_hunk_list("1,3-4").unwrap(), vec![1..=1, 3..=4]);
+        assert!(parse_hunk_list("0").is_err());
+        assert!(parse_hunk_list("a-2").is_err());
+        let range = parse_line_range("dir/file:2-5").unwrap();
+        assert_eq!(range.path, "dir/file");
+        assert_eq!(range.range, 2..=5);
+        assert!(parse_line_range("file").is_err());
+    }
+}
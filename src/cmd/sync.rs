@@ -17,8 +17,11 @@ use crate::{
     branchloc::BranchLocator,
     color::get_color_stdout,
     ext::{CommitExtended, RepositoryExtended},
+    operation::{self, Operation, SyncSource},
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess, StackTransaction},
+    stack::{
+        is_dry_run, InitializationPolicy, Stack, StackAccess, StackStateAccess, StackTransaction,
+    },
     stupid::Stupid,
 };
 
@@ -30,18 +33,27 @@ pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
 };
 
 fn make() -> clap::Command {
-    clap::Command::new(STGIT_COMMAND.name)
+    let app = clap::Command::new(STGIT_COMMAND.name)
         .about("Synchronize patches with a branch or a series")
         .long_about(
             "For each of the specified patches, perform a three-way merge with the \
              same patch in the specified branch or series. The command can be used for \
              keeping patches on several branches in sync. Note that the operation may \
              fail for some patches because of conflicts. The patches in the series \
-             must apply cleanly.",
+             must apply cleanly.\n\
+             \n\
+             If synchronizing a patch results in merge conflicts, resolve the \
+             conflicts and use '--continue' to refresh the resolution into the patch \
+             and synchronize the remaining patches. Alternatively, use '--skip' to \
+             leave the conflicting patch unapplied or '--abort' to undo the entire \
+             operation.",
         )
         .override_usage(super::make_usage(
             "stg sync",
-            &["<--ref-branch=BRANCH|--series=SERIES> [<patch>...|--all]"],
+            &[
+                "<--ref-branch=BRANCH|--series=SERIES> [<patch>...|--all]",
+                "(--continue | --skip | --abort)",
+            ],
         ))
         .arg(
             Arg::new("patchranges")
@@ -69,7 +81,8 @@ fn make() -> clap::Command {
                 .short('B')
                 .help("Synchronize patches with <branch>")
                 .value_name("branch")
-                .value_parser(clap::value_parser!(BranchLocator)),
+                .value_parser(clap::value_parser!(BranchLocator))
+                .required_unless_present_any(["series", "resume"]),
         )
         .arg(
             Arg::new("series")
@@ -80,15 +93,66 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(PathBuf))
                 .value_hint(clap::ValueHint::FilePath),
         )
-        .group(
-            ArgGroup::new("target")
-                .args(["ref-branch", "series"])
-                .required(true),
-        )
-        .arg(argset::committer_date_is_author_date_arg())
+        .group(ArgGroup::new("target").args(["ref-branch", "series"]))
+        .arg(argset::committer_date_is_author_date_arg());
+
+    operation::add_args(app, &["patchranges", "all", "ref-branch", "series"])
+}
+
+/// Where patches are synchronized from.
+enum Reference<'a, 'repo> {
+    Branch(&'a Stack<'repo>),
+    Series(&'a Path),
 }
 
 fn run(matches: &clap::ArgMatches) -> Result<()> {
+    if let Some(action) = operation::get_resume_action(matches) {
+        return operation::resume(matches, "sync", action, |trans, operation, remaining| {
+            let committer_date_is_author_date = operation.committer_date_is_author_date;
+            let conflict_patch = operation.conflict.as_ref().map(|conflict| &conflict.patch);
+            let source = operation
+                .sync
+                .as_mut()
+                .expect("sync operation has a sync source");
+            let ref_stack = source
+                .ref_branch
+                .as_ref()
+                .map(|name| {
+                    Stack::from_branch_locator(
+                        trans.repo(),
+                        Some(&BranchLocator::from_str(name)?),
+                        InitializationPolicy::AllowUninitialized,
+                    )
+                })
+                .transpose()?;
+            let reference = if let Some(ref_stack) = ref_stack.as_ref() {
+                Reference::Branch(ref_stack)
+            } else if let Some(series_path) = source.series.as_ref() {
+                Reference::Series(series_path.parent().unwrap_or_else(|| Path::new(".")))
+            } else {
+                panic!("sync source must have either ref_branch or series");
+            };
+
+            // A conflicting patch that was pushed, but not yet synchronized, is
+            // synchronized before the remaining patches are pushed. When skipped, the
+            // patch is no longer applied.
+            if let Some(pn) = source.unsynced.take() {
+                if conflict_patch == Some(&pn) && trans.applied().last() == Some(&pn) {
+                    sync_patch(trans, &reference, &pn, committer_date_is_author_date)?;
+                }
+            }
+
+            push_and_sync(
+                trans,
+                &reference,
+                remaining,
+                &source.patches,
+                committer_date_is_author_date,
+                &mut source.unsynced,
+            )
+        });
+    }
+
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
     let stupid = repo.stupid();
@@ -169,14 +233,20 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
         return Err(anyhow!("no common patches to synchronize"));
     };
 
+    let mut operation = Operation::new("sync", &stack)?;
     let mut stack = stack;
     let mut pushed: Vec<PatchName> = Vec::new();
     let mut popped: Vec<PatchName> = Vec::new();
     let unapplied: Vec<PatchName> = stack.unapplied().to_vec();
+    let mut dry_run_pop: Vec<PatchName> = Vec::new();
 
     if let Some(pos) = stack.applied().iter().position(|pn| pn == first_patch) {
         let to_pop: Vec<_> = stack.applied()[pos + 1..].to_vec();
-        if !to_pop.is_empty() {
+        if is_dry_run() {
+            // The patches are popped in the same transaction as the sync for dry runs
+            // since the stack is not updated by executing a dry run transaction.
+            dry_run_pop = to_pop.clone();
+        } else if !to_pop.is_empty() {
             stack = stack
                 .setup_transaction()
                 .use_index_and_worktree(true)
@@ -195,70 +265,167 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
 
     popped.extend(patches.iter().filter(|&pn| unapplied.contains(pn)).cloned());
 
-    stack
+    let reference = if let Some(ref_stack) = ref_stack.as_ref() {
+        Reference::Branch(ref_stack)
+    } else if let Some(series_dir) = series_dir {
+        Reference::Series(series_dir)
+    } else {
+        panic!("must have either ref_branch or series_dir");
+    };
+
+    let targets: Vec<PatchName> = pushed.into_iter().chain(popped).collect();
+    operation.committer_date_is_author_date = matches.get_flag("committer-date-is-author-date");
+    operation.sync = Some(SyncSource {
+        ref_branch: ref_stack
+            .as_ref()
+            .map(|ref_stack| ref_stack.get_branch_name().to_string()),
+        series: matches
+            .get_one::<PathBuf>("series")
+            .map(std::fs::canonicalize)
+            .transpose()?,
+        patches: sync_patches.clone(),
+        unsynced: None,
+    });
+    let mut unsynced = None;
+
+    let result = stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .with_output_stream(get_color_stdout(matches))
         .transact(|trans| {
-            for pn in pushed.iter().chain(popped.iter()) {
-                let parent_id = trans.top().id;
-
-                if popped.contains(pn) {
-                    trans.push_patches(&[pn], false)?;
-                }
+            if !dry_run_pop.is_empty() {
+                let popped_extra = trans.pop_patches(|pn| dry_run_pop.contains(pn))?;
+                assert!(popped_extra.is_empty());
+            }
+            push_and_sync(
+                trans,
+                &reference,
+                &targets,
+                &sync_patches,
+                operation.committer_date_is_author_date,
+                &mut unsynced,
+            )
+        })
+        .execute("sync");
+    if let Some(source) = operation.sync.as_mut() {
+        source.unsynced = unsynced;
+    }
+    operation.finish(&repo, &targets, result)?;
 
-                if !sync_patches.contains(pn) {
-                    continue;
-                }
+    Ok(())
+}
 
-                let commit = trans.get_patch_commit(pn);
-
-                let maybe_tree_id = if let Some(ref_stack) = ref_stack.as_ref() {
-                    branch_merge_patch(ref_stack, trans, pn, commit)?
-                } else if let Some(series_dir) = series_dir {
-                    series_merge_patch(series_dir, trans, pn, commit)?
-                } else {
-                    panic!("must have either ref_branch or series_dir");
-                };
-
-                if let Some(tree_id) = maybe_tree_id {
-                    let author = commit.author_strict()?;
-                    let default_committer = trans.repo().get_committer()?;
-                    let committer = if matches.get_flag("committer-date-is-author-date") {
-                        let mut committer = default_committer.to_owned();
-                        committer.time = author.time;
-                        committer
-                    } else {
-                        default_committer.to_owned()
-                    };
-                    let commit_id = trans.repo().commit_ex(
-                        &author,
-                        &committer,
-                        &commit.message_ex(),
-                        tree_id,
-                        [parent_id],
-                    )?;
-                    trans.update_patch(pn, commit_id)?;
+/// Push each of `patches` that is not already applied and merge the changes from the
+/// reference into those patches that are to be synchronized.
+///
+/// Should pushing a patch that is to be synchronized result in conflicts, the patch is
+/// recorded in `unsynced` such that it may be synchronized once the conflicts are
+/// resolved.
+fn push_and_sync(
+    trans: &mut StackTransaction,
+    reference: &Reference,
+    patches: &[PatchName],
+    to_sync: &[PatchName],
+    committer_date_is_author_date: bool,
+    unsynced: &mut Option<PatchName>,
+) -> Result<()> {
+    for pn in patches {
+        if !trans.applied().contains(pn) {
+            if let Err(e) = trans.push_patches(&[pn], false) {
+                if to_sync.contains(pn) {
+                    *unsynced = Some(pn.clone());
                 }
+                return Err(e);
             }
-            Ok(())
-        })
-        .execute("sync")?;
+        }
+
+        if to_sync.contains(pn) {
+            sync_patch(trans, reference, pn, committer_date_is_author_date)?;
+        }
+    }
+    Ok(())
+}
 
+/// Merge the changes from the reference into the applied patch.
+fn sync_patch(
+    trans: &mut StackTransaction,
+    reference: &Reference,
+    pn: &PatchName,
+    committer_date_is_author_date: bool,
+) -> Result<()> {
+    let commit = trans.get_patch_commit(pn);
+    let parent_id = commit.get_parent_commit()?.id;
+
+    let merged = match reference {
+        Reference::Branch(ref_stack) => branch_merge_patch(ref_stack, trans, pn, commit)?,
+        Reference::Series(series_dir) => series_merge_patch(series_dir, trans, pn, commit)?,
+    };
+
+    let maybe_tree_id = match merged {
+        Merged::Tree(maybe_tree_id) => maybe_tree_id,
+        Merged::Conflicts => {
+            return trans.halt_with_conflicts(format!("merge conflicts syncing `{pn}`"));
+        }
+    };
+
+    if let Some(tree_id) = maybe_tree_id {
+        let author = commit.author_strict()?;
+        let default_committer = trans.repo().get_committer()?;
+        let committer = if committer_date_is_author_date {
+            let mut committer = default_committer.to_owned();
+            committer.time = author.time;
+            committer
+        } else {
+            default_committer.to_owned()
+        };
+        let commit_id = trans.repo().commit_ex(
+            &author,
+            &committer,
+            &commit.message_ex(),
+            tree_id,
+            [parent_id],
+        )?;
+        trans.update_patch(pn, commit_id)?;
+    }
     Ok(())
 }
 
+/// Outcome of merging a patch with its reference.
+enum Merged {
+    /// The merge succeeded, possibly with a new tree for the patch.
+    Tree(Option<gix::ObjectId>),
+
+    /// The merge resulted in conflicts, which are left in the index and worktree.
+    Conflicts,
+}
+
 fn branch_merge_patch(
     ref_stack: &Stack,
     trans: &StackTransaction,
     patchname: &PatchName,
     commit: &gix::Commit,
-) -> Result<Option<gix::ObjectId>> {
+) -> Result<Merged> {
     let commit_ref = commit.decode()?;
     let ref_commit = ref_stack.get_patch_commit(patchname);
     let ref_commit_ref = ref_commit.decode()?;
     let ref_parent = ref_commit.get_parent_commit()?;
     let ref_parent_ref = ref_parent.decode()?;
+
+    // Dry runs merge in-memory instead of in the worktree.
+    if trans.is_dry_run() {
+        return Ok(
+            match trans.repo().merge_trees_clean(
+                ref_parent_ref.tree(),
+                commit_ref.tree(),
+                ref_commit_ref.tree(),
+            )? {
+                Some(tree_id) if tree_id != commit_ref.tree() => Merged::Tree(Some(tree_id)),
+                Some(_) => Merged::Tree(None),
+                None => Merged::Conflicts,
+            },
+        );
+    }
+
     let stupid = trans.repo().stupid();
     stupid.read_tree_checkout(
         trans.get_branch_head().tree_id()?.detach(),
@@ -270,17 +437,14 @@ fn branch_merge_patch(
         commit_ref.tree(),
         ref_commit_ref.tree(),
     )? {
-        return Err(super::Error::CausedConflicts(format!(
-            "merge conflicts syncing `{patchname}`"
-        ))
-        .into());
+        return Ok(Merged::Conflicts);
     }
 
     if stupid.diff_index_quiet(commit_ref.tree())? {
         let tree_id = stupid.write_tree()?;
-        Ok(Some(tree_id))
+        Ok(Merged::Tree(Some(tree_id)))
     } else {
-        Ok(None)
+        Ok(Merged::Tree(None))
     }
 }
 
@@ -289,7 +453,7 @@ fn series_merge_patch(
     trans: &StackTransaction,
     patchname: &PatchName,
     commit: &gix::Commit,
-) -> Result<Option<gix::ObjectId>> {
+) -> Result<Merged> {
     let patch_filename: &str = patchname.as_ref();
     let patch_path = series_dir.join(patch_filename);
     let diff: BString = std::fs::read(&patch_path)
@@ -303,6 +467,31 @@ fn series_merge_patch(
 
     let trans_head_tree_id = trans.get_branch_head().tree_id()?.detach();
 
+    // Dry runs apply the patch to a temporary index and merge in-memory instead of in
+    // the worktree.
+    if trans.is_dry_run() {
+        let tree_id = stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(parent_commit_ref.tree())?;
+            stupid_temp
+                .apply_to_index(diff.as_ref())
+                .with_context(|| format!("applying {patchname} from series"))?;
+            stupid_temp.write_tree()
+        })?;
+        return Ok(
+            match trans.repo().merge_trees_clean(
+                parent_commit_ref.tree(),
+                trans_head_tree_id,
+                tree_id,
+            )? {
+                Some(merged_tree_id) if merged_tree_id != commit.tree_id()?.detach() => {
+                    Merged::Tree(Some(tree_id))
+                }
+                Some(_) => Merged::Tree(None),
+                None => Merged::Conflicts,
+            },
+        );
+    }
+
     stupid.update_index_refresh()?;
     stupid.read_tree_checkout(trans_head_tree_id, parent_commit_ref.tree())?;
     stupid
@@ -328,15 +517,12 @@ fn series_merge_patch(
 
     stupid.read_tree_checkout(tree_id, trans_head_tree_id)?;
     if !stupid.merge_recursive(parent_commit_ref.tree(), trans_head_tree_id, tree_id)? {
-        return Err(super::Error::CausedConflicts(format!(
-            "merge conflicts syncing `{patchname}`"
-        ))
-        .into());
+        return Ok(Merged::Conflicts);
     }
 
     if stupid.diff_index_quiet(commit.tree_id()?.detach())? {
-        Ok(Some(tree_id))
+        Ok(Merged::Tree(Some(tree_id)))
     } else {
-        Ok(None)
+        Ok(Merged::Tree(None))
     }
 }
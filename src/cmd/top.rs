@@ -11,6 +11,7 @@ use crate::{
     argset,
     branchloc::BranchLocator,
     ext::RepositoryExtended,
+    format::{self, PatchRecord},
     stack::{InitializationPolicy, Stack, StackStateAccess},
 };
 
@@ -31,6 +32,7 @@ fn make() -> clap::Command {
              message will be printed if no patches are applied.",
         )
         .arg(argset::branch_arg())
+        .arg(format::format_arg())
 }
 
 fn run(matches: &clap::ArgMatches) -> Result<()> {
@@ -42,6 +44,10 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
     )?;
 
     if let Some(patchname) = stack.applied().last() {
+        if let Some(format) = format::get_format(matches) {
+            let record = PatchRecord::new(&stack, patchname)?;
+            return format.write_one(&mut std::io::stdout().lock(), &record);
+        }
         let mut stdout = crate::color::get_color_stdout(matches);
         let mut color_spec = termcolor::ColorSpec::new();
         color_spec.set_bold(true);
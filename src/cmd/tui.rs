@@ -0,0 +1,713 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg tui` implementation.
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
+use clap::ArgMatches;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{self, Attribute, Color},
+    terminal,
+};
+use is_terminal::IsTerminal;
+
+use crate::{
+    argset,
+    ext::{CommitExtended, RepositoryExtended},
+    format::PatchStatus,
+    patch::PatchName,
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "tui",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Browse and manipulate the stack in a terminal UI")
+        .long_about(
+            "Show the stack in a full-screen terminal user interface.\n\
+             \n\
+             The left pane lists the applied, unapplied, and hidden patches. The right \
+             pane shows the message and diff of the selected patch.\n\
+             \n\
+             Stack operations are performed with the following keys:\n\
+             \n  \
+               p        push the selected patches\n  \
+               o        pop the selected patches\n  \
+               g, Enter goto the selected patch\n  \
+               f        float the selected patches\n  \
+               s        sink the selected patches\n  \
+               S        squash the marked patches\n  \
+               e        edit the selected patch\n  \
+               d        delete the selected patches\n  \
+               u        undo the last operation\n  \
+               Ctrl-r   redo the last undone operation\n\
+             \n\
+             Operations apply to the marked patches, if any, or else to the patch under \
+             the cursor. Use Space to mark and unmark patches. Each operation is \
+             performed by the corresponding StGit command, e.g. 'stg float', and is \
+             thus recorded in the stack log and may be undone immediately with 'u'.\n\
+             \n\
+             Move the cursor with j/k or the arrow keys, scroll the patch with \
+             PageDown/PageUp or Ctrl-d/Ctrl-u, and quit with q or Esc.",
+        )
+        .arg(argset::diff_opts_arg())
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(anyhow!("`stg tui` must be run in a terminal"));
+    }
+
+    let repo = gix::Repository::open()?;
+    let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+    let color = matches
+        .get_one::<String>("color")
+        .cloned()
+        .unwrap_or_else(|| "auto".to_string());
+    let mut app = App::new(&repo, diff_opts, color)?;
+    let mut screen = Screen::enter()?;
+
+    loop {
+        app.draw(&mut screen.stdout)?;
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                if let Some(action) = app.handle_key(key) {
+                    match action {
+                        Input::Quit => break,
+                        Input::Action(action) => {
+                            screen.suspend()?;
+                            let result = app.perform(action);
+                            screen.resume()?;
+                            app.finish(result)?;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The terminal in raw mode with the alternate screen active.
+///
+/// The terminal is restored when dropped, including when unwinding due to an error.
+struct Screen {
+    stdout: std::io::Stdout,
+    active: bool,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let mut screen = Self {
+            stdout: std::io::stdout(),
+            active: false,
+        };
+        screen.resume()?;
+        Ok(screen)
+    }
+
+    /// Temporarily restore the terminal, e.g. so that an editor may be run.
+    fn suspend(&mut self) -> Result<()> {
+        if self.active {
+            self.active = false;
+            crossterm::execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+            terminal::disable_raw_mode()?;
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        if !self.active {
+            terminal::enable_raw_mode()?;
+            crossterm::execute!(self.stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+            self.active = true;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+enum Input {
+    Quit,
+    Action(Action),
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Push,
+    Pop,
+    Goto,
+    Float,
+    Sink,
+    Squash,
+    Edit,
+    Delete,
+    Undo,
+    Redo,
+}
+
+impl Action {
+    fn command(self) -> &'static super::StGitCommand {
+        match self {
+            Action::Push => &super::push::STGIT_COMMAND,
+            Action::Pop => &super::pop::STGIT_COMMAND,
+            Action::Goto => &super::goto::STGIT_COMMAND,
+            Action::Float => &super::float::STGIT_COMMAND,
+            Action::Sink => &super::sink::STGIT_COMMAND,
+            Action::Squash => &super::squash::STGIT_COMMAND,
+            Action::Edit => &super::edit::STGIT_COMMAND,
+            Action::Delete => &super::delete::STGIT_COMMAND,
+            Action::Undo => &super::undo::STGIT_COMMAND,
+            Action::Redo => &super::redo::STGIT_COMMAND,
+        }
+    }
+}
+
+/// A line of the patch detail pane.
+struct DetailLine {
+    text: String,
+    color: Option<Color>,
+    bold: bool,
+}
+
+/// A row of the patch list pane.
+enum ListRow {
+    Header(PatchStatus, usize),
+    Patch(usize),
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+struct App<'repo> {
+    repo: &'repo gix::Repository,
+    diff_opts: Vec<String>,
+    color: String,
+    stack: Stack<'repo>,
+    patches: Vec<(PatchName, PatchStatus)>,
+    marked: Vec<PatchName>,
+    selected: usize,
+    list_offset: usize,
+    detail: Vec<DetailLine>,
+    detail_id: Option<gix::ObjectId>,
+    detail_offset: usize,
+    status: Option<Status>,
+}
+
+impl<'repo> App<'repo> {
+    fn new(repo: &'repo gix::Repository, diff_opts: Vec<String>, color: String) -> Result<Self> {
+        let mut app = Self {
+            repo,
+            diff_opts,
+            color,
+            stack: Stack::current(repo, InitializationPolicy::AllowUninitialized)?,
+            patches: Vec::new(),
+            marked: Vec::new(),
+            selected: 0,
+            list_offset: 0,
+            detail: Vec::new(),
+            detail_id: None,
+            detail_offset: 0,
+            status: None,
+        };
+        app.reload(None)?;
+        if let Some(pos) = app
+            .stack
+            .applied()
+            .last()
+            .and_then(|top| app.patches.iter().position(|(pn, _)| pn == top))
+        {
+            app.select(pos)?;
+        }
+        Ok(app)
+    }
+
+    /// Reload the stack, keeping the cursor on the given patch if it still exists.
+    fn reload(&mut self, keep: Option<PatchName>) -> Result<()> {
+        self.stack = Stack::current(self.repo, InitializationPolicy::AllowUninitialized)?;
+        self.patches = self
+            .stack
+            .applied()
+            .iter()
+            .map(|pn| (pn.clone(), PatchStatus::Applied))
+            .chain(
+                self.stack
+                    .unapplied()
+                    .iter()
+                    .map(|pn| (pn.clone(), PatchStatus::Unapplied)),
+            )
+            .chain(
+                self.stack
+                    .hidden()
+                    .iter()
+                    .map(|pn| (pn.clone(), PatchStatus::Hidden)),
+            )
+            .collect();
+        self.marked.retain(|pn| self.stack.has_patch(pn));
+        let selected = keep
+            .and_then(|keep| self.patches.iter().position(|(pn, _)| pn == &keep))
+            .unwrap_or(self.selected);
+        self.detail_id = None;
+        self.select(selected)
+    }
+
+    /// Move the cursor to the patch at the given position and update the detail pane.
+    fn select(&mut self, pos: usize) -> Result<()> {
+        self.selected = pos.min(self.patches.len().saturating_sub(1));
+        let Some((patchname, _)) = self.patches.get(self.selected) else {
+            self.detail.clear();
+            self.detail_id = None;
+            return Ok(());
+        };
+        let commit = self.stack.get_patch_commit(patchname);
+        if self.detail_id != Some(commit.id) {
+            self.detail = patch_detail(self.repo, commit, &self.diff_opts)?;
+            self.detail_id = Some(commit.id);
+            self.detail_offset = 0;
+        }
+        Ok(())
+    }
+
+    fn selected_patchname(&self) -> Option<&PatchName> {
+        self.patches.get(self.selected).map(|(pn, _)| pn)
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Input> {
+        self.status = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let page = usize::from(terminal::size().map_or(24, |(_, rows)| rows)) / 2;
+        let result = match key.code {
+            KeyCode::Char('c') if ctrl => return Some(Input::Quit),
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Input::Quit),
+            KeyCode::Char('r') if ctrl => return Some(Input::Action(Action::Redo)),
+            KeyCode::Char('d') if ctrl => {
+                self.scroll_detail(page as isize);
+                Ok(())
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.scroll_detail(-(page as isize));
+                Ok(())
+            }
+            KeyCode::PageDown => {
+                self.scroll_detail(page as isize);
+                Ok(())
+            }
+            KeyCode::PageUp => {
+                self.scroll_detail(-(page as isize));
+                Ok(())
+            }
+            KeyCode::Char('j') | KeyCode::Down => self.select(self.selected + 1),
+            KeyCode::Char('k') | KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(self.patches.len().saturating_sub(1)),
+            KeyCode::Char(' ') => {
+                if let Some(patchname) = self.selected_patchname().cloned() {
+                    if let Some(pos) = self.marked.iter().position(|pn| pn == &patchname) {
+                        self.marked.remove(pos);
+                    } else {
+                        self.marked.push(patchname);
+                    }
+                }
+                self.select(self.selected + 1)
+            }
+            KeyCode::Char('p') => return Some(Input::Action(Action::Push)),
+            KeyCode::Char('o') => return Some(Input::Action(Action::Pop)),
+            KeyCode::Char('g') | KeyCode::Enter => return Some(Input::Action(Action::Goto)),
+            KeyCode::Char('f') => return Some(Input::Action(Action::Float)),
+            KeyCode::Char('s') => return Some(Input::Action(Action::Sink)),
+            KeyCode::Char('S') => return Some(Input::Action(Action::Squash)),
+            KeyCode::Char('e') => return Some(Input::Action(Action::Edit)),
+            KeyCode::Char('d') => return Some(Input::Action(Action::Delete)),
+            KeyCode::Char('u') => return Some(Input::Action(Action::Undo)),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            self.status = Some(Status::Error(format!("{e:#}")));
+        }
+        None
+    }
+
+    fn scroll_detail(&mut self, delta: isize) {
+        self.detail_offset = self
+            .detail_offset
+            .saturating_add_signed(delta)
+            .min(self.detail.len().saturating_sub(1));
+    }
+
+    /// The patches targeted by an action: the marked patches in stack order, or else
+    /// the patch under the cursor.
+    fn target_patchnames(&self) -> Vec<PatchName> {
+        if self.marked.is_empty() {
+            self.selected_patchname().into_iter().cloned().collect()
+        } else {
+            self.patches
+                .iter()
+                .map(|(pn, _)| pn)
+                .filter(|pn| self.marked.contains(pn))
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// Run the StGit command corresponding to the action.
+    ///
+    /// The terminal must be suspended so that the command's output and any editor it
+    /// runs appear on the normal screen.
+    fn perform(&self, action: Action) -> Result<()> {
+        let mut args: Vec<String> = vec![];
+        match action {
+            Action::Undo | Action::Redo => {}
+            Action::Goto | Action::Edit => {
+                let patchname = self
+                    .selected_patchname()
+                    .ok_or_else(|| anyhow!("no patch selected"))?;
+                args.push(patchname.to_string());
+            }
+            Action::Squash => {
+                if self.marked.len() < 2 {
+                    return Err(anyhow!("mark at least two patches to squash"));
+                }
+                args.extend(self.target_patchnames().iter().map(PatchName::to_string));
+            }
+            Action::Push | Action::Pop | Action::Float | Action::Sink | Action::Delete => {
+                let patchnames = self.target_patchnames();
+                if patchnames.is_empty() {
+                    return Err(anyhow!("no patch selected"));
+                }
+                args.extend(patchnames.iter().map(PatchName::to_string));
+            }
+        }
+
+        let command = action.command();
+        let matches = (command.make)()
+            .arg(crate::color::get_color_arg())
+            .try_get_matches_from(
+                [command.name, "--color", &self.color]
+                    .into_iter()
+                    .map(String::from)
+                    .chain(args),
+            )?;
+        (command.run)(&matches)
+    }
+
+    /// Update the application state after an action completes.
+    ///
+    /// On success, the status line shows the message of the new stack log entry.
+    fn finish(&mut self, result: Result<()>) -> Result<()> {
+        let keep = self.selected_patchname().cloned();
+        if result.is_ok() {
+            self.marked.clear();
+        }
+        self.reload(keep)?;
+        self.status = Some(match result {
+            Ok(()) => Status::Info(self.last_log_message()?),
+            Err(e) => Status::Error(format!("{e:#}")),
+        });
+        Ok(())
+    }
+
+    /// The message of the most recent stack log entry.
+    fn last_log_message(&self) -> Result<String> {
+        let state_commit = self
+            .repo
+            .find_reference(self.stack.get_stack_refname())?
+            .peel_to_commit()?;
+        let message = state_commit.message_raw()?;
+        Ok(message
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_str_lossy()
+            .into_owned())
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let width = usize::from(width);
+        let height = usize::from(height);
+        let body_height = height.saturating_sub(2);
+
+        let rows = self.list_rows();
+        let name_width = self
+            .patches
+            .iter()
+            .map(|(pn, _)| pn.to_string().chars().count())
+            .max()
+            .unwrap_or(0);
+        let list_width = (name_width + 4).clamp(20, (width / 3).max(20)).min(width);
+        let detail_width = width.saturating_sub(list_width + 1);
+
+        if let Some(selected_row) = rows
+            .iter()
+            .position(|row| matches!(row, ListRow::Patch(i) if *i == self.selected))
+        {
+            if selected_row < self.list_offset {
+                self.list_offset = selected_row.saturating_sub(1);
+            } else if selected_row >= self.list_offset + body_height {
+                self.list_offset = selected_row + 1 - body_height;
+            }
+        }
+
+        let title = format!(
+            " {}: {} applied, {} unapplied, {} hidden",
+            self.stack.get_branch_name(),
+            self.stack.applied().len(),
+            self.stack.unapplied().len(),
+            self.stack.hidden().len(),
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            style::SetAttribute(Attribute::Reverse),
+            style::Print(fit(&title, width)),
+            style::SetAttribute(Attribute::Reset),
+        )?;
+
+        for line in 0..body_height {
+            queue!(out, cursor::MoveTo(0, (line + 1) as u16))?;
+            match rows.get(self.list_offset + line) {
+                Some(ListRow::Header(status, count)) => {
+                    let label = match status {
+                        PatchStatus::Applied => "Applied",
+                        PatchStatus::Unapplied => "Unapplied",
+                        PatchStatus::Hidden => "Hidden",
+                    };
+                    queue!(
+                        out,
+                        style::SetForegroundColor(Color::Cyan),
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print(fit(&format!("{label} ({count})"), list_width)),
+                    )?;
+                }
+                Some(ListRow::Patch(i)) => {
+                    let (patchname, status) = &self.patches[*i];
+                    let is_top = self.stack.applied().last() == Some(patchname);
+                    let marker = if self.marked.contains(patchname) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    let sign = match status {
+                        PatchStatus::Applied if is_top => '>',
+                        PatchStatus::Applied => '+',
+                        PatchStatus::Unapplied => '-',
+                        PatchStatus::Hidden => '!',
+                    };
+                    if *i == self.selected {
+                        queue!(out, style::SetAttribute(Attribute::Reverse))?;
+                    }
+                    if is_top {
+                        queue!(out, style::SetAttribute(Attribute::Bold))?;
+                    }
+                    if *status == PatchStatus::Hidden {
+                        queue!(out, style::SetForegroundColor(Color::DarkGrey))?;
+                    }
+                    queue!(
+                        out,
+                        style::Print(fit(&format!("{marker}{sign} {patchname}"), list_width)),
+                    )?;
+                }
+                None => {
+                    queue!(out, style::Print(fit("", list_width)))?;
+                }
+            }
+            queue!(
+                out,
+                style::SetAttribute(Attribute::Reset),
+                style::ResetColor,
+                style::Print('│'),
+            )?;
+
+            if let Some(detail_line) = self.detail.get(self.detail_offset + line) {
+                if let Some(color) = detail_line.color {
+                    queue!(out, style::SetForegroundColor(color))?;
+                }
+                if detail_line.bold {
+                    queue!(out, style::SetAttribute(Attribute::Bold))?;
+                }
+                queue!(
+                    out,
+                    style::Print(fit(&detail_line.text, detail_width)),
+                    style::SetAttribute(Attribute::Reset),
+                    style::ResetColor,
+                )?;
+            } else {
+                queue!(out, style::Print(fit("", detail_width)))?;
+            }
+        }
+
+        let status_line = match &self.status {
+            Some(Status::Error(message)) => {
+                queue!(out, style::SetForegroundColor(Color::Red))?;
+                format!("error: {}", message.lines().next().unwrap_or_default())
+            }
+            Some(Status::Info(message)) => message.clone(),
+            None => "j/k:move  space:mark  p:push  o:pop  g:goto  f:float  s:sink  \
+                     S:squash  e:edit  d:delete  u:undo  ^r:redo  q:quit"
+                .to_string(),
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            style::Print(fit(&status_line, width)),
+            style::ResetColor,
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// The rows of the patch list pane: a header for each non-empty section followed
+    /// by the section's patches. The applied and unapplied headers are always shown.
+    fn list_rows(&self) -> Vec<ListRow> {
+        let mut rows = Vec::new();
+        for status in [
+            PatchStatus::Applied,
+            PatchStatus::Unapplied,
+            PatchStatus::Hidden,
+        ] {
+            let indices: Vec<usize> = self
+                .patches
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, s))| *s == status)
+                .map(|(i, _)| i)
+                .collect();
+            if indices.is_empty() && status == PatchStatus::Hidden {
+                continue;
+            }
+            rows.push(ListRow::Header(status, indices.len()));
+            rows.extend(indices.into_iter().map(ListRow::Patch));
+        }
+        rows
+    }
+}
+
+/// Render a patch's header, message, and diff for the detail pane.
+fn patch_detail(
+    repo: &gix::Repository,
+    commit: &gix::Commit<'_>,
+    diff_opts: &[String],
+) -> Result<Vec<DetailLine>> {
+    let commit_ref = commit.decode()?;
+    let author = commit_ref.author();
+    let mut lines = vec![
+        DetailLine {
+            text: format!("commit {}", commit.id),
+            color: Some(Color::Yellow),
+            bold: false,
+        },
+        DetailLine {
+            text: format!(
+                "Author: {} <{}>",
+                author.name.to_str_lossy(),
+                author.email.to_str_lossy()
+            ),
+            color: None,
+            bold: false,
+        },
+        DetailLine {
+            text: format!(
+                "Date:   {}",
+                author.time.format(gix::date::time::format::GIT_RFC2822)
+            ),
+            color: None,
+            bold: false,
+        },
+        DetailLine {
+            text: String::new(),
+            color: None,
+            bold: false,
+        },
+    ];
+
+    let message = commit.message_ex().decode()?.into_owned();
+    lines.extend(message.trim_end().lines().map(|line| DetailLine {
+        text: expand_tabs(&format!("    {line}")),
+        color: None,
+        bold: false,
+    }));
+    lines.push(DetailLine {
+        text: String::new(),
+        color: None,
+        bold: false,
+    });
+
+    let parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+    let diff = repo.stupid().diff_tree_patch(
+        parent_tree_id,
+        commit.tree_id()?.detach(),
+        None::<Vec<String>>,
+        false,
+        diff_opts,
+    )?;
+    lines.extend(diff.lines().map(|line| {
+        let (color, bold) = if line.starts_with(b"diff ")
+            || line.starts_with(b"index ")
+            || line.starts_with(b"--- ")
+            || line.starts_with(b"+++ ")
+        {
+            (None, true)
+        } else if line.starts_with(b"@@") {
+            (Some(Color::Cyan), false)
+        } else if line.starts_with(b"+") {
+            (Some(Color::Green), false)
+        } else if line.starts_with(b"-") {
+            (Some(Color::Red), false)
+        } else {
+            (None, false)
+        };
+        DetailLine {
+            text: expand_tabs(&line.to_str_lossy()),
+            color,
+            bold,
+        }
+    }));
+
+    Ok(lines)
+}
+
+/// Replace tabs with spaces and drop other control characters.
+fn expand_tabs(s: &str) -> String {
+    let mut expanded = String::with_capacity(s.len());
+    let mut column = 0;
+    for c in s.chars() {
+        if c == '\t' {
+            let spaces = 8 - column % 8;
+            expanded.extend(std::iter::repeat(' ').take(spaces));
+            column += spaces;
+        } else if !c.is_control() {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// Truncate or pad a string to exactly `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    let mut fitted: String = s.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat(' ').take(width - len));
+    fitted
+}
@@ -11,7 +11,7 @@ use crate::{
     argset,
     color::get_color_stdout,
     ext::{CommitExtended, RepositoryExtended},
-    patch::PatchName,
+    patch::{autoid::AutoId, PatchName},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
@@ -63,6 +63,18 @@ fn make() -> clap::Command {
                 .num_args(1..)
                 .value_parser(clap::value_parser!(PatchName)),
         )
+        .arg(
+            Arg::new("auto")
+                .long("auto")
+                .help("Auto generate patch ids for the uncommitted commits")
+                .long_help(
+                    "Auto generate patch ids for the uncommitted commits. Patch ids are \
+                     generated according to the `stgit.autoid.*` configuration \
+                     variables and are unique amongst all branches' stacks.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("patchname"),
+        )
         .arg(
             Arg::new("number")
                 .long("number")
@@ -116,7 +128,10 @@ fn run(matches: &ArgMatches) -> Result<()> {
         let exclusive = if bases.contains(&target_commit.id) {
             matches.get_flag("exclusive")
         } else {
-            target_commit = repo.find_commit(bases[0])?;
+            let base = bases.first().ok_or_else(|| {
+                anyhow!("`{committish}` has no common ancestor with the stack base")
+            })?;
+            target_commit = repo.find_commit(*base)?;
             true
         };
 
@@ -143,7 +158,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
             }
         }
 
-        let patchnames = make_patchnames(&stack, &commits, patchname_len_limit);
+        let patchnames = make_patchnames(
+            &stack,
+            &commits,
+            patchname_len_limit,
+            matches.get_flag("auto"),
+        )?;
         (commits, patchnames)
     } else {
         let mut commits = Vec::new();
@@ -170,7 +190,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 check_patchnames(&stack, &patchnames)?;
                 patchnames
             } else {
-                make_patchnames(&stack, &commits, patchname_len_limit)
+                make_patchnames(
+                    &stack,
+                    &commits,
+                    patchname_len_limit,
+                    matches.get_flag("auto"),
+                )?
             }
         } else if let Some(user_patchnames) = matches.get_many::<PatchName>("patchname") {
             let patchnames = user_patchnames.cloned().collect::<Vec<_>>();
@@ -184,7 +209,12 @@ fn run(matches: &ArgMatches) -> Result<()> {
         } else {
             check_commit(&next_commit)?;
             commits.push(next_commit);
-            make_patchnames(&stack, &commits, patchname_len_limit)
+            make_patchnames(
+                &stack,
+                &commits,
+                patchname_len_limit,
+                matches.get_flag("auto"),
+            )?
         };
         (commits, patchnames)
     };
@@ -225,21 +255,31 @@ fn make_patchnames(
     stack: &Stack,
     commits: &[Rc<gix::Commit<'_>>],
     patchname_len_limit: Option<usize>,
-) -> Vec<PatchName> {
+    auto: bool,
+) -> Result<Vec<PatchName>> {
     let mut patchnames = Vec::with_capacity(commits.len());
     let mut taken_names: Vec<_> = stack.all_patches().cloned().collect();
+    let mut autoid = if auto {
+        Some(AutoId::new(stack, None)?)
+    } else {
+        None
+    };
     for commit in commits.iter().rev() {
-        let patchname = PatchName::make(
-            &commit.message_ex().decode().unwrap_or_default(),
-            true,
-            patchname_len_limit,
-        )
-        .uniquify(&[], &taken_names);
+        let patchname = if let Some(autoid) = autoid.as_mut() {
+            autoid.next()?
+        } else {
+            PatchName::make(
+                &commit.message_ex().decode().unwrap_or_default(),
+                true,
+                patchname_len_limit,
+            )
+            .uniquify(&[], &taken_names)
+        };
         taken_names.push(patchname.clone());
         patchnames.push(patchname);
     }
     patchnames.reverse();
-    patchnames
+    Ok(patchnames)
 }
 
 fn check_patchnames(stack: &Stack, patchnames: &[PatchName]) -> Result<()> {
@@ -6,12 +6,16 @@ use std::rc::Rc;
 
 use anyhow::{anyhow, Result};
 use bstr::{BStr, ByteSlice};
-use clap::Arg;
+use clap::{builder::ValueHint, Arg};
 
 use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
-    stack::{InitializationPolicy, Stack, StackAccess, StackState},
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{
+        statediff::ModifiedKinds, InitializationPolicy, Stack, StackAccess, StackState,
+        StackStateAccess,
+    },
 };
 
 pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
@@ -26,8 +30,23 @@ fn make() -> clap::Command {
         .about("Undo the last command")
         .long_about(
             "Reset the patch stack to the state before the last operation. \
-             Consecutive undos will go back to yet older stack states.",
+             Consecutive undos will go back to yet older stack states.\n\
+             \n\
+             With '--patch', only the named patches are reverted. Each patch's commit \
+             is restored from the most recent stack log entry where the patch's diff, \
+             message, or author differed from its current commit, or from the entry \
+             given with '--to'. All \
+             other patches and the order of the stack are left as-is, although patches \
+             applied above a restored patch are pushed again. Such a selective undo is \
+             itself recorded in the stack log and may be undone with 'stg undo'.",
         )
+        .override_usage(super::make_usage(
+            "stg undo",
+            &[
+                "[OPTIONS]",
+                "[OPTIONS] --patch <patch>... [--to <log-entry>]",
+            ],
+        ))
         .arg(
             Arg::new("number")
                 .long("number")
@@ -46,6 +65,32 @@ fn make() -> clap::Command {
                         })
                 }),
         )
+        .arg(
+            Arg::new("patch")
+                .long("patch")
+                .short('p')
+                .help("Only revert the commit of <patch>")
+                .value_name("patch")
+                .value_hint(ValueHint::Other)
+                .action(clap::ArgAction::Append)
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator))
+                .conflicts_with("number"),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .help("Restore patch commits from stack log entry <log-entry>")
+                .long_help(
+                    "Restore the commits of the patches given with '--patch' from the \
+                     stack log entry <log-entry> instead of from the most recent entry \
+                     where each patch differed. The log entry is specified with a commit \
+                     id as shown by 'stg log'.",
+                )
+                .value_name("log-entry")
+                .value_hint(ValueHint::Other)
+                .requires("patch"),
+        )
         .arg(
             Arg::new("hard")
                 .long("hard")
@@ -57,6 +102,31 @@ fn make() -> clap::Command {
 fn run(matches: &clap::ArgMatches) -> Result<()> {
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    if let Some(locators) = matches.get_many::<PatchLocator>("patch") {
+        let mut patchnames = Vec::new();
+        for locator in locators {
+            patchnames.push(
+                locator
+                    .resolve_name(&stack)?
+                    .constrain(&stack, LocationConstraint::All)?,
+            );
+        }
+        let to_state_id = matches
+            .get_one::<String>("to")
+            .map(|log_entry| -> Result<_> {
+                Ok(repo
+                    .rev_parse_single(log_entry.as_str())
+                    .map_err(|_| anyhow!("invalid log entry `{log_entry}`"))?
+                    .object()?
+                    .peel_tags_to_end()?
+                    .try_into_commit()
+                    .map_err(|_| anyhow!("log entry `{log_entry}` is not a commit"))?
+                    .id)
+            })
+            .transpose()?;
+        return undo_patches(stack, matches, &patchnames, to_state_id);
+    }
+
     let undo_steps = matches.get_one::<isize>("number").copied().unwrap_or(1);
 
     stack
@@ -74,6 +144,100 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Revert the commits of only the given patches.
+///
+/// When `to_state_id` is provided, each patch's commit is restored from that stack
+/// state. Otherwise each patch's commit is restored from the most recent stack state
+/// where it differed from its current commit.
+fn undo_patches(
+    stack: Stack<'_>,
+    matches: &clap::ArgMatches,
+    patchnames: &[PatchName],
+    to_state_id: Option<gix::ObjectId>,
+) -> Result<()> {
+    let message = patchnames
+        .iter()
+        .fold("undo --patch".to_string(), |msg, pn| format!("{msg} {pn}"));
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .discard_changes(matches.get_flag("hard"))
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let to_state = to_state_id
+                .map(|state_id| -> Result<_> {
+                    let commit = trans.repo().find_commit(state_id)?;
+                    let state = StackState::from_commit(trans.repo(), &commit).map_err(|_| {
+                        anyhow!("`{}` is not a stack log entry", commit.id().shorten_or_id())
+                    })?;
+                    Ok((commit.id().shorten_or_id().to_string(), state))
+                })
+                .transpose()?;
+
+            let mut undo_states = Vec::with_capacity(patchnames.len());
+            for pn in patchnames {
+                if let Some((entry, state)) = to_state.as_ref() {
+                    if !state.has_patch(pn) {
+                        return Err(anyhow!(
+                            "patch `{pn}` does not exist in log entry `{entry}`"
+                        ));
+                    }
+                } else {
+                    undo_states.push(find_patch_undo_state(trans.stack(), pn)?);
+                }
+            }
+
+            if let Some((_, state)) = to_state.as_ref() {
+                trans.reset_to_state_partially(state, patchnames)
+            } else {
+                for (pn, state) in patchnames.iter().zip(undo_states.iter()) {
+                    trans.reset_to_state_partially(state, &[pn])?;
+                }
+                Ok(())
+            }
+        })
+        .execute(&message)?;
+
+    Ok(())
+}
+
+/// Find the most recent stack state where the patch's commit differs from its current
+/// commit.
+///
+/// States where the patch was only rebased, i.e. where its commit has a different
+/// parent, but the same diff, message, and author, are passed over.
+fn find_patch_undo_state<'repo>(
+    stack: &Stack<'repo>,
+    patchname: &PatchName,
+) -> Result<StackState<'repo>> {
+    let current_commit = stack.get_patch_commit(patchname);
+    let state_commit = stack
+        .repo
+        .find_reference(stack.get_stack_refname())?
+        .peel_to_commit()?;
+    let mut state = StackState::from_commit(stack.repo, &state_commit)?;
+    loop {
+        if !state.has_patch(patchname) {
+            return Err(anyhow!(
+                "patch `{patchname}` has no earlier commit in the stack log"
+            ));
+        }
+        let commit = &state.get_patch(patchname).commit;
+        if commit.id != current_commit.id
+            && ModifiedKinds::compare(stack.repo, commit, current_commit)?.is_patch_changed()
+        {
+            return Ok(state);
+        }
+        let Some(prev) = state.prev else {
+            return Err(anyhow!(
+                "patch `{patchname}` has no earlier commit in the stack log"
+            ));
+        };
+        state = StackState::from_commit(stack.repo, &prev)?;
+    }
+}
+
 pub(super) fn find_undo_state<'repo>(
     stack: &Stack<'repo>,
     undo_steps: isize,
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
-use std::borrow::Cow;
+use std::{borrow::Cow, path::PathBuf};
 
 use anyhow::{anyhow, Result};
 use bstr::BStr;
@@ -40,6 +40,23 @@ pub(crate) trait RepositoryExtended {
     /// Returns an error if the head is detached or unborn.
     fn get_current_branch(&self) -> Result<Branch<'_>>;
 
+    /// Get the branches checked out in worktrees other than the current worktree.
+    ///
+    /// Both the main worktree and any linked worktrees are considered. Each branch's
+    /// full reference name is paired with the path of the worktree it is checked out in.
+    fn get_other_worktree_branches(&self) -> Result<Vec<(gix::refs::FullName, PathBuf)>>;
+
+    /// Get the path of another worktree in which the given branch is checked out.
+    fn find_other_worktree(
+        &self,
+        branch_refname: &gix::refs::FullNameRef,
+    ) -> Result<Option<PathBuf>> {
+        Ok(self
+            .get_other_worktree_branches()?
+            .into_iter()
+            .find_map(|(refname, path)| (refname.as_ref() == branch_refname).then_some(path)))
+    }
+
     /// Get repository-local config file which can be used to change local
     /// configuration.
     fn local_config_file(&self) -> Result<gix::config::File<'static>>;
@@ -78,6 +95,18 @@ pub(crate) trait RepositoryExtended {
 
     /// [`gix::Repository::rev_parse_single()`] with StGit-specific error mapping.
     fn rev_parse_single_ex(&self, spec: &str) -> Result<gix::Id<'_>>;
+
+    /// Perform an in-memory three-way merge of trees.
+    ///
+    /// The id of the merged tree is returned if the merge is clean, or `None` if there
+    /// are any conflicts that git would consider unresolved. Only objects are written;
+    /// the index and worktree are never touched.
+    fn merge_trees_clean(
+        &self,
+        base: gix::ObjectId,
+        ours: gix::ObjectId,
+        theirs: gix::ObjectId,
+    ) -> Result<Option<gix::ObjectId>>;
 }
 
 /// Options for creating a git commit object.
@@ -164,6 +193,48 @@ impl RepositoryExtended for gix::Repository {
         }
     }
 
+    fn get_other_worktree_branches(&self) -> Result<Vec<(gix::refs::FullName, PathBuf)>> {
+        let is_same_dir =
+            |a: &std::path::Path, b: &std::path::Path| match (a.canonicalize(), b.canonicalize()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => a == b,
+            };
+        let is_linked = matches!(
+            self.kind(),
+            gix::repository::Kind::WorkTree { is_linked: true }
+        );
+        let main_repo;
+        let main_repo = if is_linked {
+            main_repo = self.main_repo()?;
+            &main_repo
+        } else {
+            self
+        };
+
+        let mut branches = Vec::new();
+        if is_linked {
+            if let (Some(workdir), Some(head_name)) = (main_repo.workdir(), main_repo.head_name()?)
+            {
+                branches.push((head_name, workdir.to_owned()));
+            }
+        }
+        for proxy in main_repo.worktrees()? {
+            if is_same_dir(proxy.git_dir(), self.git_dir()) {
+                continue;
+            }
+            let Ok(base) = proxy.base() else {
+                continue;
+            };
+            if let Some(head_name) = proxy
+                .into_repo_with_possibly_inaccessible_worktree()?
+                .head_name()?
+            {
+                branches.push((head_name, base));
+            }
+        }
+        Ok(branches)
+    }
+
     fn local_config_file(&self) -> Result<gix::config::File<'static>> {
         let source = gix::config::Source::Local;
 
@@ -266,6 +337,35 @@ impl RepositoryExtended for gix::Repository {
         }
     }
 
+    fn merge_trees_clean(
+        &self,
+        base: gix::ObjectId,
+        ours: gix::ObjectId,
+        theirs: gix::ObjectId,
+    ) -> Result<Option<gix::ObjectId>> {
+        use gix::merge::tree::TreatAsUnresolved;
+
+        let options = self
+            .tree_merge_options()?
+            .with_fail_on_conflict(Some(TreatAsUnresolved::git()));
+        let mut outcome = self.merge_trees(base, ours, theirs, Default::default(), options)?;
+        if outcome.has_unresolved_conflicts(TreatAsUnresolved::git()) {
+            return Ok(None);
+        }
+
+        // Unlike git, gitoxide cleanly merges changes to adjacent lines. Content merges
+        // are therefore only accepted when the changes from each side are well
+        // separated, otherwise the merge is left to git.
+        for conflict in &outcome.conflicts {
+            if conflict.content_merge().is_some() && !content_changes_are_separated(self, conflict)?
+            {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(outcome.tree.write()?.detach()))
+    }
+
     fn rev_parse_single_ex(&self, spec: &str) -> Result<gix::Id<'_>> {
         use gix::{
             refs::file::find::existing::Error as FindError,
@@ -331,3 +431,66 @@ impl RepositoryExtended for gix::Repository {
             })
     }
 }
+
+/// Minimum number of unchanged lines required between changes from either side of a
+/// content merge for the in-memory merge result to be used.
+///
+/// Git only requires the changes to not touch, but this margin accounts for hunk
+/// boundaries that may differ between git's and gitoxide's diff implementations.
+const CONTENT_MERGE_MARGIN: u32 = 3;
+
+/// Determine whether both sides of a content merge changed well-separated lines.
+fn content_changes_are_separated(
+    repo: &gix::Repository,
+    conflict: &gix::merge::tree::Conflict,
+) -> Result<bool> {
+    use gix::diff::{
+        blob::{diff, intern::InternedInput, sources::byte_lines_with_terminator, Algorithm},
+        tree_with_rewrites::Change,
+    };
+
+    fn base_and_side(change: &Change) -> Option<(gix::ObjectId, gix::ObjectId)> {
+        match change {
+            Change::Modification {
+                previous_id, id, ..
+            } => Some((*previous_id, *id)),
+            Change::Rewrite { source_id, id, .. } => Some((*source_id, *id)),
+            Change::Addition { .. } | Change::Deletion { .. } => None,
+        }
+    }
+
+    let (ours, theirs) = conflict.changes_in_resolution();
+    let (Some((base_id, ours_id)), Some((theirs_base_id, theirs_id))) =
+        (base_and_side(ours), base_and_side(theirs))
+    else {
+        return Ok(false);
+    };
+    if base_id != theirs_base_id {
+        return Ok(false);
+    }
+
+    let base_blob = repo.find_blob(base_id)?;
+    let changed_ranges = |side_id: gix::ObjectId| -> Result<Vec<std::ops::Range<u32>>> {
+        let side_blob = repo.find_blob(side_id)?;
+        let input = InternedInput::new(
+            byte_lines_with_terminator(&base_blob.data),
+            byte_lines_with_terminator(&side_blob.data),
+        );
+        let mut ranges = Vec::new();
+        diff(
+            Algorithm::Myers,
+            &input,
+            |before: std::ops::Range<u32>, _after| ranges.push(before),
+        );
+        Ok(ranges)
+    };
+    let ours_ranges = changed_ranges(ours_id)?;
+    let theirs_ranges = changed_ranges(theirs_id)?;
+
+    Ok(ours_ranges.iter().all(|ours_range| {
+        theirs_ranges.iter().all(|theirs_range| {
+            ours_range.end + CONTENT_MERGE_MARGIN <= theirs_range.start
+                || theirs_range.end + CONTENT_MERGE_MARGIN <= ours_range.start
+        })
+    }))
+}
@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Machine-readable output for the `--format` option.
+//!
+//! Commands supporting `--format` produce records that are serialized either as JSON
+//! or by expanding a user-provided template. Template placeholders are the names of
+//! the record's JSON fields, e.g. `{name}`, with nested fields separated by `.`, e.g.
+//! `{author.email}`. Literal braces are written as `{{` and `}}`.
+
+use std::{io::Write, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
+use clap::{Arg, ArgMatches};
+use serde::Serialize;
+
+use crate::{
+    ext::CommitExtended,
+    patch::PatchName,
+    stack::{StackAccess, StackStateAccess},
+};
+
+/// The `--format` option for machine-readable output.
+pub(crate) fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .help("Output format: \"json\" or a template")
+        .long_help(
+            "Output in a stable, machine-readable format.\n\
+             \n\
+             With `--format=json`, records are output as JSON. With any other value, \
+             the value is used as a template that is expanded and output on its own \
+             line for each record. Template placeholders name the fields of the JSON \
+             records, e.g. `{name}` or `{author.email}`. Use `{{` and `}}` for literal \
+             braces.",
+        )
+        .value_name("format")
+        .value_parser(clap::value_parser!(OutputFormat))
+}
+
+/// Get the output format from the `--format` option, if present.
+pub(crate) fn get_format(matches: &ArgMatches) -> Option<&OutputFormat> {
+    matches.get_one::<OutputFormat>("format")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    Json,
+    Template(Vec<Segment>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Segment {
+    Literal(String),
+    Field(String),
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "json" {
+            return Ok(Self::Json);
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '.' => {
+                                field.push(c);
+                            }
+                            _ => return Err(anyhow!("invalid placeholder in format `{s}`")),
+                        }
+                    }
+                    if field.is_empty() {
+                        return Err(anyhow!("empty placeholder in format `{s}`"));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                }
+                '}' => return Err(anyhow!("unmatched `}}` in format `{s}`")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self::Template(segments))
+    }
+}
+
+impl OutputFormat {
+    /// Write a list of records, i.e. a JSON array or one template line per record.
+    pub(crate) fn write_list<T: Serialize>(
+        &self,
+        out: &mut impl Write,
+        records: &[T],
+    ) -> Result<()> {
+        match self {
+            Self::Json => {
+                serde_json::to_writer_pretty(&mut *out, records)?;
+                writeln!(out)?;
+            }
+            Self::Template(_) => {
+                for record in records {
+                    self.write_one(out, record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a single record, i.e. a JSON object or a single template line.
+    pub(crate) fn write_one<T: Serialize>(&self, out: &mut impl Write, record: &T) -> Result<()> {
+        match self {
+            Self::Json => {
+                serde_json::to_writer_pretty(&mut *out, record)?;
+                writeln!(out)?;
+            }
+            Self::Template(segments) => {
+                let value = serde_json::to_value(record)?;
+                let mut line = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Literal(s) => line.push_str(s),
+                        Segment::Field(field) => {
+                            let field_value = field
+                                .split('.')
+                                .try_fold(&value, |value, key| value.get(key))
+                                .ok_or_else(|| anyhow!("unknown format field `{field}`"))?;
+                            match field_value {
+                                serde_json::Value::Null => {}
+                                serde_json::Value::String(s) => line.push_str(s),
+                                other => line.push_str(&other.to_string()),
+                            }
+                        }
+                    }
+                }
+                writeln!(out, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Identity and timestamp from a commit's author or committer signature.
+#[derive(Serialize)]
+pub(crate) struct SignatureRecord {
+    name: String,
+    email: String,
+    date: String,
+}
+
+impl SignatureRecord {
+    pub(crate) fn new(signature: gix::actor::SignatureRef<'_>) -> Self {
+        Self {
+            name: signature.name.to_str_lossy().into_owned(),
+            email: signature.email.to_str_lossy().into_owned(),
+            date: signature
+                .time
+                .format(gix::date::time::format::ISO8601_STRICT),
+        }
+    }
+}
+
+/// State of a patch in a stack.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PatchStatus {
+    Applied,
+    Unapplied,
+    Hidden,
+}
+
+/// The stable record describing a patch for `--format` output.
+#[derive(Serialize)]
+pub(crate) struct PatchRecord {
+    name: String,
+    branch: String,
+    state: PatchStatus,
+    top: bool,
+    index: usize,
+    offset: isize,
+    id: String,
+    author: SignatureRecord,
+    committer: SignatureRecord,
+    subject: String,
+    empty: bool,
+}
+
+impl PatchRecord {
+    pub(crate) fn new<'repo>(
+        stack: &(impl StackAccess<'repo> + StackStateAccess<'repo>),
+        patchname: &PatchName,
+    ) -> Result<Self> {
+        let commit = stack.get_patch_commit(patchname);
+        let commit_ref = commit.decode()?;
+        let top_patchname = stack.applied().last();
+        let state = if stack.is_applied(patchname) {
+            PatchStatus::Applied
+        } else if stack.is_unapplied(patchname) {
+            PatchStatus::Unapplied
+        } else {
+            PatchStatus::Hidden
+        };
+        let subject = commit
+            .message_ex()
+            .decode()?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Ok(Self {
+            name: patchname.to_string(),
+            branch: stack.get_branch_name().to_string(),
+            state,
+            top: Some(patchname) == top_patchname,
+            index: stack.index_of(patchname),
+            offset: stack.distance_from(patchname, top_patchname),
+            id: commit.id.to_string(),
+            author: SignatureRecord::new(commit_ref.author()),
+            committer: SignatureRecord::new(commit_ref.committer()),
+            subject,
+            empty: commit.is_no_change()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Record {
+        name: &'static str,
+        nested: Nested,
+        count: usize,
+        flag: bool,
+        missing: Option<usize>,
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        value: &'static str,
+    }
+
+    const RECORD: Record = Record {
+        name: "p0",
+        nested: Nested { value: "inner" },
+        count: 3,
+        flag: true,
+        missing: None,
+    };
+
+    fn render(template: &str) -> Result<String> {
+        let mut out = Vec::new();
+        OutputFormat::from_str(template)?.write_one(&mut out, &RECORD)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn template_expansion() {
+        assert_eq!(render("{name}").unwrap(), "p0\n");
+        assert_eq!(
+            render("{{{name}}} {nested.value} {count} {flag}{missing}").unwrap(),
+            "{p0} inner 3 true\n"
+        );
+        assert!(render("{nope}").is_err());
+        assert!(render("{name").is_err());
+        assert!(render("name}").is_err());
+        assert!(render("{}").is_err());
+    }
+
+    #[test]
+    fn json_output() {
+        let mut out = Vec::new();
+        OutputFormat::Json.write_list(&mut out, &[RECORD]).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value[0]["nested"]["value"], "inner");
+        assert_eq!(value[0]["missing"], serde_json::Value::Null);
+    }
+}
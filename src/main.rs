@@ -13,8 +13,10 @@ mod branchloc;
 mod cmd;
 mod color;
 mod ext;
+mod format;
 mod hook;
 mod nl_extensions;
+mod operation;
 mod patch;
 mod signal;
 mod stack;
@@ -91,6 +93,19 @@ fn get_base_command(color_choice: Option<termcolor::ColorChoice>) -> clap::Comma
                 .value_name("path")
                 .value_hint(clap::ValueHint::AnyPath),
         )
+        .arg(
+            clap::Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show what the command would do without changing anything")
+                .long_help(
+                    "Perform the command's stack operations and report the resulting \
+                     stack, including any new patch commits and expected merge \
+                     conflicts, without updating the branch, the stack, the index, or \
+                     the working tree. Only commands that modify the stack support \
+                     this option.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(color::get_color_arg().global(true).display_order(998));
 
     // Ensure "stg" and not "stg.exe" shows up in usage on Windows.
@@ -176,6 +191,8 @@ fn main() -> ! {
         } else if matches.get_flag("help-option") {
             full_app_help(argv, None, color_choice)
         } else if let Some((sub_name, sub_matches)) = matches.subcommand() {
+            stack::set_dry_run(matches.get_flag("dry-run"));
+
             // If the name matches any known subcommands, then only the Command for that
             // particular command is constructed and the costs of searching for aliases
             // and constructing all subcommands' Command instances are avoided.
@@ -199,6 +216,13 @@ fn main() -> ! {
                                 .map_or_else(Vec::new, |vals| vals.cloned().collect());
 
                             match alias.kind {
+                                alias::AliasKind::Shell if stack::is_dry_run() => exit_with_result(
+                                    Err(anyhow!(
+                                        "shell alias `{}` does not support `--dry-run`",
+                                        alias.name
+                                    )),
+                                    color_choice,
+                                ),
                                 alias::AliasKind::Shell => execute_shell_alias(
                                     alias,
                                     user_args,
@@ -403,6 +427,15 @@ fn execute_command(
             let (_sub_name, sub_matches) = top_matches
                 .subcommand()
                 .expect("this subcommand is already known to be in argv");
+            if stack::is_dry_run() && !cmd::DRY_RUN_COMMANDS.contains(&command.name) {
+                exit_with_result(
+                    Err(anyhow!(
+                        "`stg {}` does not support `--dry-run`",
+                        command.name
+                    )),
+                    color_choice,
+                )
+            }
             exit_with_result((command.run)(sub_matches), color_choice)
         }
 
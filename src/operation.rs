@@ -0,0 +1,631 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Resumable multi-step operations.
+//!
+//! Commands such as `stg rebase` and `stg goto` push a sequence of patches. When a push
+//! results in merge conflicts, the stack transaction halts with the conflicting patch
+//! applied and the conflicts left in the worktree. An [`Operation`] record is then
+//! persisted so that, once the conflicts are resolved, the command may be resumed with
+//! `--continue`. Alternatively, the conflicting patch may be skipped with `--skip` or
+//! the whole operation rolled back with `--abort`.
+//!
+//! Operations may also consist of a sequence of [`Step`]s, e.g. those of an interactive
+//! rebase. Such an operation is also recorded when it stops without conflicts, either
+//! because a command run by one of its steps failed or because of a `break` step. The
+//! steps that remain are performed when the operation is resumed with `--continue`.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use bstr::ByteSlice;
+use clap::{Arg, ArgGroup, ArgMatches};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    stack::{
+        is_dry_run, InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess,
+        StackTransaction, TransactionError,
+    },
+    stupid::Stupid,
+};
+
+/// Add the `--continue`, `--skip`, and `--abort` options to a command.
+///
+/// The options are mutually exclusive and conflict with any of the command's arguments
+/// named in `conflicts`.
+pub(crate) fn add_args(command: clap::Command, conflicts: &[&'static str]) -> clap::Command {
+    command
+        .arg(
+            Arg::new("continue")
+                .long("continue")
+                .help("Continue after resolving merge conflicts")
+                .long_help(
+                    "Continue the operation after resolving merge conflicts. Changes to \
+                     tracked files are refreshed into the conflicting patch, as with \
+                     `stg add --update` followed by `stg refresh`, and the remaining \
+                     patches are pushed.\n\
+                     \n\
+                     An operation that stopped without conflicts, e.g. at a `break` \
+                     step or after a failed `exec` step, refreshes changes to tracked \
+                     files into the topmost patch instead, reporting which patch was \
+                     refreshed. Use `git stash` or `stg reset --hard` first to keep \
+                     such changes out of the stack.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .help("Skip the conflicting patch and continue")
+                .long_help(
+                    "Discard the conflicts, leave the conflicting patch unapplied with \
+                     its original content, and push the remaining patches.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("abort")
+                .long("abort")
+                .help("Abort the operation and restore the original stack")
+                .long_help(
+                    "Abort the operation, discarding any conflicts, and restore the stack \
+                     to its state from before the operation started.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(
+            ArgGroup::new("resume")
+                .args(["continue", "skip", "abort"])
+                .multiple(false)
+                .conflicts_with_all(conflicts),
+        )
+}
+
+/// How to resume a halted operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ResumeAction {
+    Continue,
+    Skip,
+    Abort,
+}
+
+/// Get the resume action from the `--continue`, `--skip`, or `--abort` options.
+pub(crate) fn get_resume_action(matches: &ArgMatches) -> Option<ResumeAction> {
+    if matches.get_flag("continue") {
+        Some(ResumeAction::Continue)
+    } else if matches.get_flag("skip") {
+        Some(ResumeAction::Skip)
+    } else if matches.get_flag("abort") {
+        Some(ResumeAction::Abort)
+    } else {
+        None
+    }
+}
+
+/// The patch whose push halted an operation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Conflict {
+    /// Name of the conflicting patch.
+    pub(crate) patch: PatchName,
+
+    /// The patch's commit from before the conflicting push.
+    #[serde(with = "hex_oid")]
+    pub(crate) commit: gix::ObjectId,
+}
+
+/// Patch synchronization source for resuming `stg sync`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SyncSource {
+    /// Branch to synchronize with.
+    pub(crate) ref_branch: Option<String>,
+
+    /// Series file to synchronize with.
+    pub(crate) series: Option<PathBuf>,
+
+    /// Patches to be synchronized.
+    pub(crate) patches: Vec<PatchName>,
+
+    /// Patch to be synchronized whose push, prior to being synchronized, resulted in
+    /// conflicts.
+    #[serde(default)]
+    pub(crate) unsynced: Option<PatchName>,
+}
+
+/// A step of a multi-step operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Step {
+    /// Push the patch.
+    Push(PatchName),
+
+    /// Run the shell command from the root of the worktree.
+    Exec(String),
+
+    /// Stop the operation such that it may be resumed with `--continue`.
+    Break,
+}
+
+/// Persistent record of an operation halted by merge conflicts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Operation {
+    /// Name of the command performing the operation, e.g. "rebase".
+    pub(crate) command: String,
+
+    /// Stack state commit from before the operation started.
+    #[serde(with = "hex_oid")]
+    pub(crate) original_state: gix::ObjectId,
+
+    /// The patch whose push resulted in conflicts.
+    pub(crate) conflict: Option<Conflict>,
+
+    /// Patches remaining to be pushed after the conflicting patch, in order.
+    pub(crate) remaining: Vec<PatchName>,
+
+    /// Whether to check for patches merged upstream when pushing.
+    #[serde(default)]
+    pub(crate) check_merged: bool,
+
+    /// Whether to use the author date as the committer date.
+    #[serde(default)]
+    pub(crate) committer_date_is_author_date: bool,
+
+    /// Whether local changes were stashed when the operation started.
+    #[serde(default)]
+    pub(crate) autostash: bool,
+
+    /// Synchronization source when resuming `stg sync`.
+    #[serde(default)]
+    pub(crate) sync: Option<SyncSource>,
+
+    /// Steps remaining to be performed after the remaining patches are pushed.
+    #[serde(default)]
+    pub(crate) steps: Vec<Step>,
+
+    /// Whether the operation stopped without conflicts.
+    #[serde(default)]
+    pub(crate) stopped: bool,
+}
+
+impl Operation {
+    /// Start a new operation for `command` on the given stack.
+    ///
+    /// Starting an operation while another operation is stopped is refused since the
+    /// stopped operation's record would otherwise be lost.
+    pub(crate) fn new(command: &str, stack: &Stack) -> Result<Self> {
+        if let Some(stopped) = Self::load(stack)?.filter(|operation| operation.stopped) {
+            let other = &stopped.command;
+            return Err(anyhow!(
+                "`stg {other}` is stopped; use `stg {other} --continue` or \
+                 `stg {other} --abort` first"
+            ));
+        }
+        let original_state = stack
+            .repo
+            .find_reference(stack.get_stack_refname())?
+            .peel_to_id_in_place()?
+            .detach();
+        Ok(Self {
+            command: command.to_string(),
+            original_state,
+            conflict: None,
+            remaining: Vec::new(),
+            check_merged: false,
+            committer_date_is_author_date: false,
+            autostash: false,
+            sync: None,
+            steps: Vec::new(),
+            stopped: false,
+        })
+    }
+
+    /// Load the halted operation for the stack's branch, if any.
+    ///
+    /// A record whose conflicting patch is no longer the topmost patch is stale, e.g.
+    /// because the operation was undone or resolved manually, and is discarded. Records
+    /// of stopped operations are kept regardless of the topmost patch since the stack
+    /// may be freely modified while stopped.
+    pub(crate) fn load(stack: &Stack) -> Result<Option<Self>> {
+        let path = record_path(stack);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading `{}`", path.display()));
+            }
+        };
+        let operation: Self = serde_json::from_slice(&data)
+            .with_context(|| format!("parsing operation record `{}`", path.display()))?;
+        let is_current = if let Some(conflict) = operation.conflict.as_ref() {
+            stack.applied().last() == Some(&conflict.patch)
+        } else {
+            operation.stopped
+        };
+        if is_current {
+            Ok(Some(operation))
+        } else {
+            Self::remove(stack)?;
+            Ok(None)
+        }
+    }
+
+    fn save(&self, stack: &Stack) -> Result<()> {
+        let path = record_path(stack);
+        std::fs::create_dir_all(path.parent().expect("record path has parent"))?;
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing `{}`", path.display()))
+    }
+
+    /// Remove any operation record for the stack's branch.
+    ///
+    /// Records are kept for dry runs.
+    pub(crate) fn remove(stack: &Stack) -> Result<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
+        let path = record_path(stack);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("removing `{}`", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Finish a step of the operation given the result of its stack transaction.
+    ///
+    /// `targets` are the patches the transaction intended to push, in order. If the
+    /// transaction halted due to merge conflicts, the operation is recorded so that it
+    /// may be resumed and the halt error is amended with instructions. Otherwise, any
+    /// record for the branch is removed, unless it is the record of another command's
+    /// stopped operation.
+    pub(crate) fn finish<'repo>(
+        mut self,
+        repo: &'repo gix::Repository,
+        targets: &[PatchName],
+        result: Result<Stack<'repo>>,
+    ) -> Result<Stack<'repo>> {
+        match result {
+            Ok(stack) => {
+                let stopped_other = Self::load(&stack)?.is_some_and(|operation| {
+                    operation.stopped && operation.command != self.command
+                });
+                if !stopped_other {
+                    Self::remove(&stack)?;
+                }
+                Ok(stack)
+            }
+            Err(e) => {
+                let Some(TransactionError::TransactionHalt {
+                    conflicts: true, ..
+                }) = e.downcast_ref::<TransactionError>()
+                else {
+                    return Err(e);
+                };
+                let stack = Stack::current(repo, InitializationPolicy::RequireInitialized)?;
+                let Some(patchname) = stack.applied().last().cloned() else {
+                    return Err(e);
+                };
+                // The conflicting patch's commit from before the halted transaction is
+                // found in the previous stack state.
+                let state_commit = repo
+                    .find_reference(stack.get_stack_refname())?
+                    .peel_to_commit()?;
+                let prev_state = StackState::from_commit(repo, &state_commit)?
+                    .prev
+                    .map(|prev_commit| StackState::from_commit(repo, &prev_commit))
+                    .transpose()?;
+                let commit = match prev_state {
+                    Some(prev_state) if prev_state.has_patch(&patchname) => {
+                        prev_state.get_patch_commit(&patchname).id
+                    }
+                    _ => stack.get_patch_commit(&patchname).id,
+                };
+                self.remaining = targets
+                    .iter()
+                    .skip_while(|pn| *pn != &patchname)
+                    .skip(1)
+                    .filter(|pn| stack.is_unapplied(pn))
+                    .cloned()
+                    .collect();
+                self.conflict = Some(Conflict {
+                    patch: patchname,
+                    commit,
+                });
+                self.stopped = false;
+                self.save(&stack)?;
+                let command = &self.command;
+                Err(TransactionError::TransactionHalt {
+                    msg: format!(
+                        "merge conflicts; resolve conflicts manually then run \
+                         `stg {command} --continue`, skip the conflicting patch with \
+                         `stg {command} --skip`, or abort with `stg {command} --abort`"
+                    ),
+                    conflicts: true,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Record the operation as stopped without conflicts.
+    ///
+    /// The operation's remaining [`steps`](Self::steps) are performed when the operation
+    /// is resumed with `--continue`.
+    pub(crate) fn stop(mut self, stack: &Stack) -> Result<()> {
+        self.conflict = None;
+        self.remaining.clear();
+        self.stopped = true;
+        self.save(stack)
+    }
+}
+
+/// Push the remaining patches of an operation.
+///
+/// This is the default way to push the remaining patches when resuming an operation.
+pub(crate) fn push_remaining(
+    trans: &mut StackTransaction,
+    operation: &mut Operation,
+    remaining: &[PatchName],
+) -> Result<()> {
+    trans.push_patches(remaining, operation.check_merged)
+}
+
+/// Resume the halted `command` operation for the current branch.
+///
+/// The `push` function pushes the remaining patches, given the operation record and the
+/// remaining patches that are still unapplied. Changes it makes to the operation record
+/// are kept should the operation halt again.
+pub(crate) fn resume<F>(
+    matches: &ArgMatches,
+    command: &str,
+    action: ResumeAction,
+    push: F,
+) -> Result<()>
+where
+    F: FnOnce(&mut StackTransaction, &mut Operation, &[PatchName]) -> Result<()>,
+{
+    resume_with_steps(matches, command, action, push, |_, _| {
+        unreachable!("only operations with steps are resumed with steps")
+    })
+}
+
+/// Resume the halted or stopped `command` operation for the current branch.
+///
+/// Like [`resume()`], but once the remaining patches are pushed, the operation's
+/// remaining [`Step`]s, if any, are performed by `run_steps`, which is then also
+/// responsible for popping any autostash.
+pub(crate) fn resume_with_steps<F, G>(
+    matches: &ArgMatches,
+    command: &str,
+    action: ResumeAction,
+    push: F,
+    run_steps: G,
+) -> Result<()>
+where
+    F: FnOnce(&mut StackTransaction, &mut Operation, &[PatchName]) -> Result<()>,
+    G: for<'repo> FnOnce(Stack<'repo>, Operation) -> Result<()>,
+{
+    if is_dry_run() {
+        let option = match action {
+            ResumeAction::Continue => "--continue",
+            ResumeAction::Skip => "--skip",
+            ResumeAction::Abort => "--abort",
+        };
+        return Err(anyhow!(
+            "`stg {command} {option}` does not support `--dry-run`"
+        ));
+    }
+
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+
+    let mut operation = match Operation::load(&stack)? {
+        Some(operation) if operation.command == command => operation,
+        Some(operation) => {
+            let other = &operation.command;
+            return Err(anyhow!(
+                "`stg {other}` is in progress; use `stg {other} --continue`, \
+                 `stg {other} --skip`, or `stg {other} --abort`"
+            ));
+        }
+        None => return Err(anyhow!("no `stg {command}` operation is in progress")),
+    };
+    let remaining: Vec<PatchName> = operation
+        .remaining
+        .iter()
+        .filter(|pn| stack.is_unapplied(pn))
+        .cloned()
+        .collect();
+
+    repo.check_repository_state()?;
+
+    let stack = match action {
+        ResumeAction::Continue => {
+            stupid
+                .statuses(None)?
+                .check_conflicts()
+                .map_err(|_| anyhow!("mark resolved conflicts with `stg add` first"))?;
+            stack.check_head_top_mismatch()?;
+
+            // Refresh the conflict resolution into the conflicting patch. A stopped
+            // operation's changes are refreshed into the topmost patch.
+            let refresh_patchname = operation
+                .conflict
+                .as_ref()
+                .map(|conflict| &conflict.patch)
+                .or_else(|| stack.applied().last())
+                .cloned();
+            let head_tree_id = stack.get_branch_head().tree_id()?.detach();
+            stupid.update_index_refresh()?;
+            let paths = stupid.diff_index_names(head_tree_id, None)?;
+            let paths: Vec<&OsStr> = paths
+                .split_str(b"\0")
+                .filter(|path| !path.is_empty())
+                .map(|path| path.to_os_str())
+                .collect::<Result<_, _>>()
+                .context("getting modified file list")?;
+            if !paths.is_empty() {
+                stupid.update_index(Some(paths))?;
+            }
+            let tree_id = stupid.write_tree()?;
+
+            let new_commit_id = if let Some(patchname) = refresh_patchname.as_ref() {
+                let patch_commit = stack.get_patch_commit(patchname);
+                if tree_id == patch_commit.tree_id()?.detach() {
+                    None
+                } else {
+                    let author = patch_commit.author_strict()?;
+                    let mut committer = repo.get_committer()?.to_owned();
+                    if operation.committer_date_is_author_date {
+                        committer.time = author.time;
+                    }
+                    Some(repo.commit_ex(
+                        &author,
+                        &committer,
+                        &patch_commit.message_ex(),
+                        tree_id,
+                        patch_commit.parent_ids().map(|id| id.detach()),
+                    )?)
+                }
+            } else if tree_id == head_tree_id {
+                None
+            } else {
+                return Err(anyhow!(
+                    "no applied patch to refresh local changes into; \
+                     use `stg new` or `stg reset --hard` first"
+                ));
+            };
+
+            // The index and worktree already match the refreshed patch.
+            let stack = if let Some(commit_id) = new_commit_id {
+                let patchname = refresh_patchname.expect("new commit is for a patch");
+                if operation.conflict.is_none() {
+                    crate::print_info_message(
+                        matches,
+                        &format!("Refreshed local changes into `{patchname}`"),
+                    );
+                }
+                stack
+                    .setup_transaction()
+                    .use_index_and_worktree(false)
+                    .with_output_stream(get_color_stdout(matches))
+                    .transact(|trans| trans.update_patch(&patchname, commit_id))
+                    .execute(&format!("{command} --continue (refresh)"))?
+            } else {
+                stack
+            };
+
+            if operation.conflict.is_some() {
+                let result = stack
+                    .setup_transaction()
+                    .use_index_and_worktree(true)
+                    .allow_push_conflicts(true)
+                    .committer_date_is_author_date(operation.committer_date_is_author_date)
+                    .with_output_stream(get_color_stdout(matches))
+                    .transact(|trans| push(trans, &mut operation, &remaining))
+                    .execute(&format!("{command} --continue"));
+                Some(operation.clone().finish(&repo, &remaining, result)?)
+            } else {
+                Operation::remove(&stack)?;
+                Some(stack)
+            }
+        }
+
+        ResumeAction::Skip => {
+            let Some(conflict) = operation.conflict.clone() else {
+                return Err(anyhow!(
+                    "`stg {command}` stopped without conflicts; use \
+                     `stg {command} --continue` or `stg {command} --abort`"
+                ));
+            };
+
+            // Discard the conflicts before popping the conflicting patch.
+            stupid.read_tree_checkout_hard(stack.get_branch_head().tree_id()?.detach())?;
+
+            let result = stack
+                .setup_transaction()
+                .use_index_and_worktree(true)
+                .allow_bad_head(true)
+                .allow_push_conflicts(true)
+                .committer_date_is_author_date(operation.committer_date_is_author_date)
+                .with_output_stream(get_color_stdout(matches))
+                .transact(|trans| {
+                    let popped_extra = trans.pop_patches(|pn| pn == &conflict.patch)?;
+                    assert!(popped_extra.is_empty());
+                    trans.update_patch(&conflict.patch, conflict.commit)?;
+                    push(trans, &mut operation, &remaining)
+                })
+                .execute(&format!("{command} --skip"));
+            Some(operation.clone().finish(&repo, &remaining, result)?)
+        }
+
+        ResumeAction::Abort => {
+            let stack = stack
+                .setup_transaction()
+                .use_index_and_worktree(true)
+                .allow_bad_head(true)
+                .discard_changes(true)
+                .with_output_stream(get_color_stdout(matches))
+                .transact(|trans| {
+                    let state_commit = trans.repo().find_commit(operation.original_state)?;
+                    let state = StackState::from_commit(trans.repo(), &state_commit)?;
+                    trans.reset_to_state(state)
+                })
+                .execute(&format!("{command} --abort"))?;
+            Operation::remove(&stack)?;
+            None
+        }
+    };
+
+    if let Some(stack) = stack {
+        if !operation.steps.is_empty() {
+            operation.conflict = None;
+            operation.remaining.clear();
+            operation.stopped = false;
+            return run_steps(stack, operation);
+        }
+    }
+
+    if operation.autostash && !stupid.stash_pop()? {
+        return Err(crate::cmd::Error::CausedConflicts(
+            "stash pop resulted in conflicts".to_string(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Path of the operation record for the stack's branch.
+fn record_path(stack: &Stack) -> PathBuf {
+    Path::new(stack.repo.git_dir())
+        .join("stgit")
+        .join("operations")
+        .join(stack.get_branch_name())
+}
+
+mod hex_oid {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        oid: &gix::ObjectId,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&oid.to_string())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<gix::ObjectId, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        gix::ObjectId::from_hex(hex.as_bytes())
+            .map_err(|_| D::Error::custom(format!("invalid oid `{hex}`")))
+    }
+}
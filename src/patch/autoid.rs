@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Automatically generated patch names.
+//!
+//! Generated names are driven by the `stgit.autoid.*` configuration variables and are
+//! unique amongst the patches of all branches' stacks.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
+use rand::Rng;
+
+use super::PatchName;
+use crate::{
+    ext::RepositoryExtended,
+    stack::{state_refname_from_branch_name, Stack, StackAccess, StackState, StackStateAccess},
+    wrap::Branch,
+};
+
+const DEFAULT_PREFIX: &str = "misc";
+const DEFAULT_LENGTH: usize = 5;
+const DEFAULT_CHARSET: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+const DEFAULT_FORMAT: &str = "{prefix}@{random}";
+
+/// Generator of patch names that do not collide with any existing patch.
+pub(crate) struct AutoId {
+    prefix: String,
+    length: usize,
+    charset: Vec<char>,
+    format: String,
+    date: String,
+    user: String,
+    taken: Vec<PatchName>,
+}
+
+impl AutoId {
+    /// Create generator for new patches in the given stack.
+    ///
+    /// When `prefix` is not provided, the prefix is determined by [`default_prefix()`].
+    pub(crate) fn new(stack: &Stack, prefix: Option<String>) -> Result<Self> {
+        let repo = stack.repo;
+        let config = repo.config_snapshot();
+        let prefix = prefix.unwrap_or_else(|| default_prefix(stack));
+        let length = config
+            .integer("stgit.autoid.length")
+            .map_or(DEFAULT_LENGTH, |n| usize::try_from(n).unwrap_or(0));
+        let charset: Vec<char> = config
+            .string("stgit.autoid.charset")
+            .map_or_else(
+                || DEFAULT_CHARSET.to_string(),
+                |s| s.to_str_lossy().to_string(),
+            )
+            .chars()
+            .collect();
+        if length > 0 && charset.is_empty() {
+            return Err(anyhow!("`stgit.autoid.charset` may not be empty"));
+        }
+        let format = config.string("stgit.autoid.format").map_or_else(
+            || DEFAULT_FORMAT.to_string(),
+            |s| s.to_str_lossy().to_string(),
+        );
+        let user = if format.contains("{user}") {
+            let author = repo.get_author()?;
+            let email = author.email.to_str_lossy();
+            let local_part = email
+                .split_once('@')
+                .map_or(email.as_ref(), |(local, _)| local);
+            PatchName::make(local_part, true, None).to_string()
+        } else {
+            String::new()
+        };
+
+        Ok(Self {
+            prefix,
+            length,
+            charset,
+            format,
+            date: jiff::Zoned::now().strftime("%Y%m%d").to_string(),
+            user,
+            taken: all_stacks_patchnames(stack)?,
+        })
+    }
+
+    /// Generate the next patch name.
+    ///
+    /// The generated name is reserved such that subsequently generated names will not
+    /// collide with it.
+    pub(crate) fn next(&mut self) -> Result<PatchName> {
+        let random: String = if self.length > 0 {
+            let dist =
+                rand::distr::slice::Choose::new(&self.charset).expect("charset is not empty");
+            rand::rng().sample_iter(dist).take(self.length).collect()
+        } else {
+            String::new()
+        };
+
+        let mut seq = 1;
+        let patchname = loop {
+            let name = self
+                .format
+                .replace("{prefix}", &self.prefix)
+                .replace("{random}", &random)
+                .replace("{date}", &self.date)
+                .replace("{user}", &self.user)
+                .replace("{seq}", &seq.to_string());
+            let patchname = PatchName::from_str(&name).map_err(|e| {
+                anyhow!("`stgit.autoid.format` produced an invalid patch name: {e}")
+            })?;
+            if !self.format.contains("{seq}") || self.taken.iter().all(|pn| !patchname.collides(pn))
+            {
+                break patchname;
+            }
+            seq += 1;
+        }
+        .uniquify(&[], &self.taken);
+
+        self.taken.push(patchname.clone());
+        Ok(patchname)
+    }
+}
+
+/// Determine the default prefix for generated patch names.
+///
+/// The prefix is taken from `stgit.autoid.prefix`. Otherwise, the prefix of the
+/// topmost patch's name, i.e. the part before `@`, is used, falling back to "misc".
+pub(crate) fn default_prefix(stack: &Stack) -> String {
+    stack
+        .repo
+        .config_snapshot()
+        .string("stgit.autoid.prefix")
+        .map(|s| s.to_str_lossy().to_string())
+        .or_else(|| {
+            stack.applied().last().and_then(|pn| {
+                AsRef::<str>::as_ref(pn)
+                    .split_once('@')
+                    .map(|(prefix, _)| prefix.to_string())
+            })
+        })
+        .unwrap_or_else(|| DEFAULT_PREFIX.to_string())
+}
+
+/// Get the names of all patches in all branches' stacks.
+fn all_stacks_patchnames(stack: &Stack) -> Result<Vec<PatchName>> {
+    let repo = stack.repo;
+    let mut patchnames: Vec<PatchName> = stack.all_patches().cloned().collect();
+    for local_branch in repo.references()?.local_branches()?.filter_map(Result::ok) {
+        let local_branch = Branch::wrap(local_branch);
+        let Ok(branchname) = local_branch.get_branch_partial_name() else {
+            continue;
+        };
+        if branchname.to_string() == stack.get_branch_name() {
+            continue;
+        }
+        // The other stacks' states are read directly rather than instantiating stacks,
+        // which may write to the repository, e.g. to repair patch refs.
+        let state_refname = state_refname_from_branch_name(branchname.as_ref());
+        let Ok(Some(mut state_ref)) = repo.try_find_reference(state_refname.as_str()) else {
+            continue;
+        };
+        let Ok(state_commit) = state_ref.peel_to_commit() else {
+            continue;
+        };
+        if let Ok(other) = StackState::from_commit(repo, &state_commit) {
+            patchnames.extend(other.all_patches().cloned());
+        }
+    }
+    Ok(patchnames)
+}
@@ -2,6 +2,7 @@
 
 //! Abstractions for specifying patches within a stack.
 
+pub(crate) mod autoid;
 mod constraint;
 pub(crate) mod edit;
 mod identifier;
@@ -34,12 +35,17 @@ use crate::branchloc::BranchLocator;
 /// The last patch in an open-ended range depends on command-specific policy which is
 /// determined by the [`RangeConstraint`] used with [`patchrange::resolve_names()`] or
 /// [`patchrange::resolve_names_contiguous()`].
+///
+/// A range may also be specified as `@label:<label>` to select all patches carrying
+/// the given label, in stack order.
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum PatchRange {
     /// A range consisting of a single patch.
     Single(PatchLocator),
     /// A range bound by optional begin and end patches.
     Range(PatchRangeBounds),
+    /// All patches with the given label.
+    Label(String),
 }
 
 /// Patch locations bounding a range of patches.
@@ -170,7 +176,7 @@ pub(crate) enum RangeConstraint {
 ///
 /// The stack consists of all the applied patches, then unapplied, followed by any
 /// hidden patches.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum LocationGroup {
     Applied,
     Unapplied,
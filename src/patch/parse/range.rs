@@ -3,15 +3,17 @@
 //! Parsing support for [`PatchRange`] and [`PatchRangeBounds`].
 
 use winnow::{
-    combinator::{alt, opt, separated_pair},
+    combinator::{alt, opt, preceded, separated_pair},
+    token::take_while,
     ModalResult, Parser,
 };
 
 use super::patch_locator;
-use crate::patch::{PatchRange, PatchRangeBounds};
+use crate::patch::{patchrange::is_label_char, PatchRange, PatchRangeBounds};
 
 pub(in super::super) fn patch_range(input: &mut &str) -> ModalResult<PatchRange> {
     alt((
+        patch_range_label,
         patch_range_bounds.map(PatchRange::Range),
         patch_locator.map(PatchRange::Single),
     ))
@@ -23,3 +25,9 @@ pub(super) fn patch_range_bounds(input: &mut &str) -> ModalResult<PatchRangeBoun
         .map(|(begin, end)| PatchRangeBounds { begin, end })
         .parse_next(input)
 }
+
+fn patch_range_label(input: &mut &str) -> ModalResult<PatchRange> {
+    preceded("@label:", take_while(1.., is_label_char))
+        .map(|label: &str| PatchRange::Label(label.to_string()))
+        .parse_next(input)
+}
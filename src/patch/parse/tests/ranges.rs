@@ -99,3 +99,18 @@ fn range_parsing() {
         )
     );
 }
+
+#[test]
+fn label_range_parsing() {
+    assert_eq!(
+        patch_range.parse_peek("@label:perf").unwrap(),
+        ("", PatchRange::Label(String::from("perf")))
+    );
+    assert_eq!(
+        patch_range.parse_peek("@label:topic/needs-review").unwrap(),
+        ("", PatchRange::Label(String::from("topic/needs-review")))
+    );
+    assert!(patch_range.parse("@label:").is_err());
+    assert!(patch_range.parse("@label:a:b").is_err());
+    assert!(patch_range.parse("@label:a..b").is_err());
+}
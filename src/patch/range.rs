@@ -24,6 +24,15 @@ pub(crate) enum Error {
     #[error("patch `{patchname}` is used more than once")]
     Duplicate { patchname: PatchName },
 
+    #[error("no patches with label `{0}`")]
+    UnknownLabel(String),
+
+    #[error("no applicable patches with label `{0}`")]
+    NoApplicableLabel(String),
+
+    #[error("patches with label `{0}` are not contiguous")]
+    LabelNotContiguous(String),
+
     #[error("`{range}` not contiguous with preceding range `{prev_range}`")]
     NotContiguous { range: String, prev_range: String },
 
@@ -39,6 +48,7 @@ impl std::fmt::Display for PatchRange {
         match self {
             PatchRange::Single(patch_loc) => patch_loc.fmt(f),
             PatchRange::Range(bounds) => bounds.fmt(f),
+            PatchRange::Label(label) => write!(f, "@label:{label}"),
         }
     }
 }
@@ -215,12 +225,55 @@ pub(crate) fn resolve_names<'a, 'repo>(
                 }
                 patches.push(patchname);
             }
+
+            PatchRange::Label(label) => {
+                for pn in resolve_label(stack, &allowed_patches, label)? {
+                    let patchname = allowed_patches[pn].clone();
+                    if patches.contains(&patchname) {
+                        return Err(Error::Duplicate { patchname });
+                    }
+                    patches.push(patchname);
+                }
+            }
         }
     }
 
     Ok(patches)
 }
 
+/// Test whether the character may be used in a label.
+///
+/// Labels are used to select patches with `@label:<label>` patch ranges.
+pub(crate) fn is_label_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '/')
+}
+
+/// Find positions within `allowed_patches` of patches with the given label.
+///
+/// Labeled patches outside of the allowed patches are ignored, but it is an error if
+/// no patch in the stack has the label or if none of the labeled patches are allowed.
+fn resolve_label<'repo>(
+    stack: &impl StackStateAccess<'repo>,
+    allowed_patches: &[&PatchName],
+    label: &str,
+) -> Result<Vec<usize>, Error> {
+    if !stack
+        .all_patches()
+        .any(|pn| stack.get_patch(pn).meta.has_label(label))
+    {
+        return Err(Error::UnknownLabel(label.to_string()));
+    }
+    let positions: Vec<usize> = allowed_patches
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, pn)| stack.get_patch(pn).meta.has_label(label).then_some(pos))
+        .collect();
+    if positions.is_empty() {
+        return Err(Error::NoApplicableLabel(label.to_string()));
+    }
+    Ok(positions)
+}
+
 /// Resolve user-provided patch ranges into contiguous patch names.
 ///
 /// It is an error if any of the ranges provided in `ranges` are discontiguous.
@@ -324,6 +377,28 @@ pub(crate) fn resolve_names_contiguous<'a>(
                     next_pos = Some(pos + 1);
                 }
             }
+            PatchRange::Label(label) => {
+                let positions = resolve_label(stack, &allowed_patches, label)?;
+                let first_pos = positions[0];
+                let last_pos = positions[positions.len() - 1];
+                if last_pos - first_pos + 1 != positions.len() {
+                    return Err(Error::LabelNotContiguous(label.clone()));
+                }
+                if next_pos.is_some() && Some(first_pos) != next_pos {
+                    return Err(Error::NotContiguous {
+                        range: range.to_string(),
+                        prev_range: prev_range.unwrap().to_string(),
+                    });
+                }
+                for pos in positions {
+                    let patchname = allowed_patches[pos].clone();
+                    if patches.contains(&patchname) {
+                        return Err(Error::Duplicate { patchname });
+                    }
+                    patches.push(patchname);
+                }
+                next_pos = Some(last_pos + 1);
+            }
         }
 
         prev_range = Some(range);
@@ -83,6 +83,7 @@ fn display_range() {
     check_same("~1");
     check_same("patch");
     check_same("patch++~++");
+    check_same("@label:perf");
 }
 
 #[test]
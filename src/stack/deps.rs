@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Patch dependency tracking.
+//!
+//! A patch depends on a patch lower in the stack when the lines the patch modifies
+//! overlap or abut the lines modified by the lower patch. Such a patch cannot be
+//! applied without the lower patch also being applied beneath it.
+//!
+//! Dependencies are determined by walking down the stack from each patch, tracking
+//! the line ranges the patch modifies in terms of each lower patch's post-image, and
+//! mapping those ranges through each lower patch's hunks to its pre-image.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use bstr::BString;
+use indexmap::IndexSet;
+
+use super::StackStateAccess;
+use crate::{
+    ext::CommitExtended,
+    patch::PatchName,
+    stupid::{FileHunks, Hunk, Stupid},
+};
+
+/// Dependency relationships between patches in a stack.
+pub(crate) struct PatchDependencies {
+    /// Patches in stack order, i.e. applied followed by unapplied.
+    order: Vec<PatchName>,
+
+    /// Direct dependencies of each patch, in stack order.
+    dependencies: BTreeMap<PatchName, Vec<PatchName>>,
+}
+
+/// Half-open range of 1-based line numbers.
+///
+/// An empty range marks the position where lines are inserted or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LineRange {
+    start: usize,
+    end: usize,
+}
+
+impl LineRange {
+    /// Range covering an entire file, used for binary files.
+    const WHOLE_FILE: LineRange = LineRange {
+        start: 0,
+        end: usize::MAX,
+    };
+
+    fn old_side(hunk: &Hunk) -> Self {
+        Self::from_start_and_lines(hunk.old_start, hunk.old_lines)
+    }
+
+    fn new_side(hunk: &Hunk) -> Self {
+        Self::from_start_and_lines(hunk.new_start, hunk.new_lines)
+    }
+
+    fn from_start_and_lines(start: usize, lines: usize) -> Self {
+        if lines == 0 {
+            // Zero-line hunk sides are positioned *after* the given line.
+            Self {
+                start: start + 1,
+                end: start + 1,
+            }
+        } else {
+            Self {
+                start,
+                end: start + lines,
+            }
+        }
+    }
+
+    /// Determine whether two ranges overlap or are adjacent.
+    fn touches(&self, other: &LineRange) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    /// Remove the lines of `other` from this range.
+    ///
+    /// Zero, one, or two ranges remain. Empty ranges (insertion points) are retained
+    /// as-is.
+    fn subtract(&self, other: &LineRange) -> Vec<LineRange> {
+        if self.start == self.end {
+            return vec![*self];
+        }
+        let mut remaining = Vec::with_capacity(2);
+        if self.start < other.start {
+            remaining.push(LineRange {
+                start: self.start,
+                end: self.end.min(other.start),
+            });
+        }
+        if self.end > other.end {
+            remaining.push(LineRange {
+                start: self.start.max(other.end),
+                end: self.end,
+            });
+        }
+        remaining
+    }
+}
+
+impl PatchDependencies {
+    /// Compute dependencies for all applied and unapplied patches.
+    ///
+    /// Hidden patches do not participate in dependency tracking.
+    pub(crate) fn compute<'repo>(
+        repo: &gix::Repository,
+        stack: &impl StackStateAccess<'repo>,
+    ) -> Result<Self> {
+        let stupid = repo.stupid();
+        let order: Vec<PatchName> = stack.applied_and_unapplied().cloned().collect();
+
+        let mut patch_hunks: Vec<Vec<FileHunks>> = Vec::with_capacity(order.len());
+        for patchname in &order {
+            let commit = stack.get_patch_commit(patchname);
+            let parent = commit.get_parent_commit()?;
+            patch_hunks.push(
+                stupid.diff_tree_hunks(parent.tree_id()?.detach(), commit.tree_id()?.detach())?,
+            );
+        }
+
+        let mut dependencies = BTreeMap::new();
+        for (i, patchname) in order.iter().enumerate() {
+            let mut deps: Vec<PatchName> = direct_dependencies(&patch_hunks, i)
+                .into_iter()
+                .map(|j| order[j].clone())
+                .collect();
+            deps.reverse();
+            dependencies.insert(patchname.clone(), deps);
+        }
+
+        Ok(Self {
+            order,
+            dependencies,
+        })
+    }
+
+    /// Get the direct dependencies of a patch, in stack order.
+    pub(crate) fn dependencies(&self, patchname: &PatchName) -> &[PatchName] {
+        self.dependencies
+            .get(patchname)
+            .map_or(&[], |deps| deps.as_slice())
+    }
+
+    /// Get the patches that directly depend on a patch, in stack order.
+    pub(crate) fn dependents(&self, patchname: &PatchName) -> Vec<&PatchName> {
+        self.order
+            .iter()
+            .filter(|pn| self.dependencies(pn).contains(patchname))
+            .collect()
+    }
+
+    /// Extend the given patches with all of their transitive dependencies.
+    ///
+    /// The order of the given patches is preserved, with each patch preceded by its
+    /// dependencies (in stack order) that do not precede it already.
+    pub(crate) fn with_dependencies<'a>(
+        &self,
+        patchnames: impl IntoIterator<Item = &'a PatchName>,
+    ) -> Vec<PatchName> {
+        let mut extended: IndexSet<PatchName> = IndexSet::new();
+        for patchname in patchnames {
+            for pn in self.closure([patchname], |pn| self.dependencies(pn).iter().collect()) {
+                extended.insert(pn);
+            }
+        }
+        extended.into_iter().collect()
+    }
+
+    /// Extend the given patches with all patches that transitively depend on them.
+    ///
+    /// The returned patches are in stack order.
+    pub(crate) fn with_dependents<'a>(
+        &self,
+        patchnames: impl IntoIterator<Item = &'a PatchName>,
+    ) -> Vec<PatchName> {
+        self.closure(patchnames, |pn| self.dependents(pn))
+    }
+
+    fn closure<'a, 's, F>(
+        &'s self,
+        patchnames: impl IntoIterator<Item = &'a PatchName>,
+        next: F,
+    ) -> Vec<PatchName>
+    where
+        F: Fn(&PatchName) -> Vec<&'s PatchName>,
+    {
+        let mut found: IndexSet<&PatchName> = IndexSet::new();
+        let mut pending: Vec<&PatchName> = patchnames.into_iter().collect();
+        let mut extra: IndexSet<PatchName> = IndexSet::new();
+        while let Some(patchname) = pending.pop() {
+            if let Some(known) = self.order.iter().find(|pn| *pn == patchname) {
+                if found.insert(known) {
+                    pending.extend(next(known));
+                }
+            } else {
+                // Patches outside of dependency tracking (i.e. hidden) are passed through.
+                extra.insert(patchname.clone());
+            }
+        }
+        self.order
+            .iter()
+            .filter(|pn| found.contains(pn))
+            .cloned()
+            .chain(extra)
+            .collect()
+    }
+
+    /// Check whether the given patches, applied in order to the tree of `base`, apply
+    /// cleanly.
+    ///
+    /// The check is performed using a temporary index; neither the index nor the
+    /// working tree are touched. The first patch that fails to apply is returned.
+    pub(crate) fn check_applies<'repo>(
+        repo: &gix::Repository,
+        stack: &impl StackStateAccess<'repo>,
+        base: &gix::Commit<'repo>,
+        patchnames: &[PatchName],
+    ) -> Result<Option<PatchName>> {
+        let stupid = repo.stupid();
+        let base_tree_id = base.tree_id()?.detach();
+        stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(base_tree_id)?;
+            for patchname in patchnames {
+                let commit = stack.get_patch_commit(patchname);
+                let parent = commit.get_parent_commit()?;
+                if !stupid_temp.apply_treediff_to_index(
+                    parent.tree_id()?.detach(),
+                    commit.tree_id()?.detach(),
+                    false,
+                )? {
+                    return Ok(Some(patchname.clone()));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Find indices of the patches that the patch at `index` directly depends on.
+///
+/// The returned indices are ordered from nearest to furthest.
+fn direct_dependencies(patch_hunks: &[Vec<FileHunks>], index: usize) -> Vec<usize> {
+    let mut tracked: BTreeMap<&BString, Vec<LineRange>> = BTreeMap::new();
+    for file in &patch_hunks[index] {
+        let ranges = if file.binary || file.hunks.is_empty() {
+            vec![LineRange::WHOLE_FILE]
+        } else {
+            file.hunks.iter().map(LineRange::old_side).collect()
+        };
+        tracked.insert(&file.path, ranges);
+    }
+
+    let mut deps = Vec::new();
+    for lower in (0..index).rev() {
+        if tracked.is_empty() {
+            break;
+        }
+        let mut is_dependency = false;
+        for file in &patch_hunks[lower] {
+            let Some(ranges) = tracked.get_mut(&file.path) else {
+                continue;
+            };
+
+            if file.binary || file.hunks.is_empty() {
+                is_dependency = true;
+                continue;
+            }
+
+            if ranges.iter().any(|range| {
+                file.hunks
+                    .iter()
+                    .any(|hunk| range.touches(&LineRange::new_side(hunk)))
+            }) {
+                is_dependency = true;
+            }
+
+            // Lines modified by this lower patch are accounted for by it; patches
+            // further down only matter via this patch's own dependencies.
+            for hunk in &file.hunks {
+                let new = LineRange::new_side(hunk);
+                *ranges = ranges
+                    .iter()
+                    .flat_map(|range| range.subtract(&new))
+                    .collect();
+            }
+
+            let is_creation = matches!(
+                file.hunks.as_slice(),
+                [Hunk {
+                    old_start: 0,
+                    old_lines: 0,
+                    ..
+                }]
+            );
+            if is_creation {
+                // Nothing further down the stack can have touched this file.
+                tracked.remove(&file.path);
+            } else {
+                for range in ranges.iter_mut() {
+                    *range = LineRange {
+                        start: map_to_old(range.start, &file.hunks),
+                        end: map_to_old(range.end, &file.hunks),
+                    };
+                }
+            }
+        }
+        if is_dependency {
+            deps.push(lower);
+        }
+    }
+    deps
+}
+
+/// Map a line position in a patch's post-image to the corresponding position in the
+/// patch's pre-image.
+///
+/// Positions within lines added by the patch map to the start of the hunk's pre-image.
+fn map_to_old(pos: usize, hunks: &[Hunk]) -> usize {
+    if pos == usize::MAX {
+        return pos;
+    }
+    let mut offset: isize = 0;
+    for hunk in hunks {
+        let new = LineRange::new_side(hunk);
+        if pos >= new.end {
+            offset += hunk.old_lines as isize - hunk.new_lines as isize;
+        } else if pos >= new.start {
+            return LineRange::old_side(hunk).start;
+        } else {
+            break;
+        }
+    }
+    pos.saturating_add_signed(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, hunks: &[(usize, usize, usize, usize)]) -> FileHunks {
+        FileHunks {
+            path: path.into(),
+            binary: false,
+            hunks: hunks
+                .iter()
+                .map(|&(old_start, old_lines, new_start, new_lines)| Hunk {
+                    old_start,
+                    old_lines,
+                    new_start,
+                    new_lines,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn overlapping_hunks() {
+        let patch_hunks = vec![
+            // p0 creates a.txt with 10 lines.
+            vec![file("a.txt", &[(0, 0, 1, 10)])],
+            // p1 changes b.txt only.
+            vec![file("b.txt", &[(5, 1, 5, 1)])],
+            // p2 inserts 3 lines after line 2 of a.txt.
+            vec![file("a.txt", &[(2, 0, 3, 3)])],
+            // p3 changes line 12 of a.txt, which was line 9 prior to p2.
+            vec![file("a.txt", &[(12, 1, 12, 1)])],
+            // p4 changes a line added by p2 and also b.txt. Only p2 is a direct
+            // dependency since p2 itself depends on p0.
+            vec![
+                file("a.txt", &[(4, 1, 4, 1)]),
+                file("b.txt", &[(20, 1, 20, 2)]),
+            ],
+        ];
+        assert_eq!(direct_dependencies(&patch_hunks, 0), Vec::<usize>::new());
+        assert_eq!(direct_dependencies(&patch_hunks, 1), Vec::<usize>::new());
+        assert_eq!(direct_dependencies(&patch_hunks, 2), vec![0]);
+        assert_eq!(direct_dependencies(&patch_hunks, 3), vec![0]);
+        assert_eq!(direct_dependencies(&patch_hunks, 4), vec![2]);
+    }
+
+    #[test]
+    fn position_mapping() {
+        let hunks = [
+            Hunk {
+                old_start: 2,
+                old_lines: 0,
+                new_start: 3,
+                new_lines: 3,
+            },
+            Hunk {
+                old_start: 8,
+                old_lines: 2,
+                new_start: 11,
+                new_lines: 0,
+            },
+        ];
+        assert_eq!(map_to_old(1, &hunks), 1);
+        assert_eq!(map_to_old(4, &hunks), 3);
+        assert_eq!(map_to_old(6, &hunks), 3);
+        assert_eq!(map_to_old(12, &hunks), 11);
+    }
+}
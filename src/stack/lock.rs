@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Guard stacks against concurrent modification by multiple StGit processes.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+
+/// Default duration, in milliseconds, to wait for another process's stack lock.
+const DEFAULT_LOCK_TIMEOUT_MS: u64 = 1000;
+
+/// Errors for stacks that are concurrently modified by another process.
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum Error {
+    #[error(
+        "stack for branch `{branch_name}` is locked by another StGit process; \
+         retry once it completes, or increase `stgit.lock.timeout` to wait longer. \
+         If no other StGit process is running, remove `{}`",
+        lock_path.display()
+    )]
+    Locked {
+        branch_name: String,
+        lock_path: PathBuf,
+    },
+
+    #[error(
+        "stack for branch `{branch_name}` was modified by another process; \
+         retry the command"
+    )]
+    Modified { branch_name: String },
+}
+
+/// Exclusive lock on a stack, held until dropped.
+///
+/// The lock file is `stgit/<branch>.lock` in the repository's common git directory,
+/// i.e. it is shared by all worktrees.
+pub(crate) struct StackLock {
+    _marker: gix::lock::Marker,
+}
+
+impl StackLock {
+    /// Acquire the lock for a branch's stack.
+    ///
+    /// If the stack is already locked, retry until the duration configured by
+    /// `stgit.lock.timeout`, in milliseconds, elapses.
+    pub(crate) fn acquire(repo: &gix::Repository, branch_name: &str) -> Result<Self> {
+        use gix::lock::acquire::{Error as AcquireError, Fail};
+
+        let timeout_ms = repo
+            .config_snapshot()
+            .integer("stgit.lock.timeout")
+            .map_or(DEFAULT_LOCK_TIMEOUT_MS, |ms| u64::try_from(ms).unwrap_or(0));
+        let mode = if timeout_ms == 0 {
+            Fail::Immediately
+        } else {
+            Fail::AfterDurationWithBackoff(Duration::from_millis(timeout_ms))
+        };
+        let common_dir = repo.common_dir();
+        let resource_path = common_dir.join("stgit").join(branch_name);
+
+        match gix::lock::Marker::acquire_to_hold_resource(
+            &resource_path,
+            mode,
+            Some(common_dir.to_owned()),
+        ) {
+            Ok(marker) => Ok(Self { _marker: marker }),
+            Err(AcquireError::PermanentlyLocked { .. }) => Err(Error::Locked {
+                branch_name: branch_name.to_string(),
+                lock_path: common_dir.join("stgit").join(format!("{branch_name}.lock")),
+            }
+            .into()),
+            Err(e) => Err(anyhow!("locking stack for branch `{branch_name}`: {e}")),
+        }
+    }
+}
+
+/// Map errors from reference edits whose expected previous values did not match.
+///
+/// Such mismatches indicate that another process modified the stack's references.
+pub(super) fn map_ref_edit_error(
+    branch_name: &str,
+    e: gix::reference::edit::Error,
+) -> anyhow::Error {
+    use gix::refs::file::transaction::prepare::Error as PrepareError;
+    match e {
+        gix::reference::edit::Error::FileTransactionPrepare(
+            PrepareError::ReferenceOutOfDate { .. }
+            | PrepareError::MustExist { .. }
+            | PrepareError::MustNotExist { .. }
+            | PrepareError::DeleteReferenceMustExist { .. }
+            | PrepareError::LockAcquire { .. },
+        ) => Error::Modified {
+            branch_name: branch_name.to_string(),
+        }
+        .into(),
+        e => e.into(),
+    }
+}
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Per-patch metadata recorded in the stack state.
+//!
+//! Patch metadata is an extensible mapping of keys to string values that is stored
+//! alongside each patch's commit id in `stack.json`. Because the metadata is part of
+//! the stack state, it is versioned with the stack's history and is thus restored by
+//! `stg undo`, `stg redo`, and `stg reset`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::patch::patchrange::is_label_char;
+
+/// Free-form notes about the patch.
+pub(crate) const NOTE_KEY: &str = "note";
+
+/// Whitespace-separated list of user labels.
+pub(crate) const LABELS_KEY: &str = "labels";
+
+/// Version number of the patch when it was last sent for review.
+pub(crate) const SENT_VERSION_KEY: &str = "sent-version";
+
+/// Newline-separated list of paths, relative to the worktree root, that the patch is
+/// expected to change.
+pub(crate) const SCOPE_KEY: &str = "scope";
+
+/// Key/value metadata associated with a patch.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub(crate) struct PatchMeta(BTreeMap<String, String>);
+
+impl PatchMeta {
+    /// Test whether there is no metadata.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get value for the given key.
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Set value for the given key, replacing any existing value.
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    /// Remove the given key, returning whether the key was present.
+    pub(crate) fn unset(&mut self, key: &str) -> bool {
+        self.0.remove(key).is_some()
+    }
+
+    /// Iterator over key/value pairs, ordered by key.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Iterator over the patch's labels.
+    pub(crate) fn labels(&self) -> impl Iterator<Item = &str> {
+        self.get(LABELS_KEY).unwrap_or_default().split_whitespace()
+    }
+
+    /// Test whether the patch has the given label.
+    pub(crate) fn has_label(&self, label: &str) -> bool {
+        self.labels().any(|l| l == label)
+    }
+
+    /// Add label, returning whether the label was newly added.
+    pub(crate) fn add_label(&mut self, label: &str) -> bool {
+        if self.has_label(label) {
+            false
+        } else {
+            let labels: Vec<&str> = self.labels().chain([label]).collect();
+            self.set(LABELS_KEY, &labels.join(" "));
+            true
+        }
+    }
+
+    /// Remove label, returning whether the label was present.
+    pub(crate) fn remove_label(&mut self, label: &str) -> bool {
+        if self.has_label(label) {
+            let labels: Vec<&str> = self.labels().filter(|l| *l != label).collect();
+            if labels.is_empty() {
+                self.unset(LABELS_KEY);
+            } else {
+                self.set(LABELS_KEY, &labels.join(" "));
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Iterator over the paths of the patch's file scope, if a scope is recorded.
+    pub(crate) fn scope(&self) -> Option<impl Iterator<Item = &str>> {
+        self.get(SCOPE_KEY)
+            .map(|scope| scope.lines().filter(|path| !path.is_empty()))
+    }
+
+    /// Set the patch's file scope, removing the scope if there are no paths.
+    pub(crate) fn set_scope<'a>(&mut self, paths: impl IntoIterator<Item = &'a str>) {
+        let mut scope: Vec<&str> = paths.into_iter().collect();
+        scope.sort_unstable();
+        scope.dedup();
+        if scope.is_empty() {
+            self.unset(SCOPE_KEY);
+        } else {
+            self.set(SCOPE_KEY, &scope.join("\n"));
+        }
+    }
+
+    /// Test whether the path is within the patch's file scope.
+    ///
+    /// A path is in scope if it is a scope path or is in a directory that is a scope
+    /// path. All paths are in scope for patches without a recorded scope.
+    pub(crate) fn in_scope(&self, path: &str) -> bool {
+        self.scope().map_or(true, |mut scope| {
+            scope.any(|scope_path| {
+                path.strip_prefix(scope_path)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+        })
+    }
+
+    /// Merge metadata from another patch into this metadata.
+    ///
+    /// This is used when multiple patches are combined into one, e.g. by `stg squash`.
+    /// Notes are concatenated and labels and file scopes are combined. For all other
+    /// keys, existing values take precedence over values from `other`.
+    pub(crate) fn merge(&mut self, other: &PatchMeta) {
+        for (key, value) in other.iter() {
+            if key == LABELS_KEY {
+                for label in other.labels() {
+                    self.add_label(label);
+                }
+            } else if key == SCOPE_KEY {
+                let scope: Vec<String> = self
+                    .scope()
+                    .into_iter()
+                    .flatten()
+                    .chain(other.scope().into_iter().flatten())
+                    .map(str::to_string)
+                    .collect();
+                self.set_scope(scope.iter().map(String::as_str));
+            } else if let Some(existing) = self.0.get_mut(key) {
+                if key == NOTE_KEY && existing != value {
+                    existing.push_str("\n\n");
+                    existing.push_str(value);
+                }
+            } else {
+                self.set(key, value);
+            }
+        }
+    }
+}
+
+/// Validate metadata key and value.
+///
+/// Keys must start with an ASCII letter and may only contain ASCII alphanumerics,
+/// `-`, `_`, and `.`. The well-known `labels` key must be a whitespace-separated list
+/// of valid labels and the `sent-version` key must be a positive integer.
+pub(crate) fn validate(key: &str, value: &str) -> Result<()> {
+    parse_key(key)?;
+    if key == LABELS_KEY {
+        for label in value.split_whitespace() {
+            parse_label(label)?;
+        }
+    } else if key == SENT_VERSION_KEY && value.parse::<u32>().map_or(true, |v| v == 0) {
+        return Err(anyhow!("`{SENT_VERSION_KEY}` must be a positive integer"));
+    }
+    Ok(())
+}
+
+/// For use with `clap::Arg::value_parser()` to parse a metadata key.
+pub(crate) fn parse_key(key: &str) -> Result<String> {
+    let mut chars = key.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        || !chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(anyhow!("invalid metadata key `{key}`"));
+    }
+    Ok(key.to_string())
+}
+
+/// For use with `clap::Arg::value_parser()` to parse a label.
+///
+/// Labels may contain alphanumerics, `-`, `_`, and `/`.
+pub(crate) fn parse_label(label: &str) -> Result<String> {
+    if label.is_empty() || !label.chars().all(is_label_char) {
+        return Err(anyhow!("invalid label `{label}`"));
+    }
+    Ok(label.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_meta() {
+        let mut meta = PatchMeta::default();
+        meta.set(NOTE_KEY, "first");
+        meta.set("upstream", "https://example.com/1");
+        meta.set(LABELS_KEY, "perf wip");
+        let mut other = PatchMeta::default();
+        other.set(NOTE_KEY, "second");
+        other.set("upstream", "https://example.com/2");
+        other.set(SENT_VERSION_KEY, "3");
+        other.set(LABELS_KEY, "wip needs-review");
+        meta.merge(&other);
+        assert_eq!(meta.get(NOTE_KEY), Some("first\n\nsecond"));
+        assert_eq!(meta.get("upstream"), Some("https://example.com/1"));
+        assert_eq!(meta.get(SENT_VERSION_KEY), Some("3"));
+        assert_eq!(meta.get(LABELS_KEY), Some("perf wip needs-review"));
+    }
+
+    #[test]
+    fn labels() {
+        let mut meta = PatchMeta::default();
+        assert!(meta.add_label("perf"));
+        assert!(meta.add_label("topic/io"));
+        assert!(!meta.add_label("perf"));
+        assert!(meta.has_label("topic/io"));
+        assert!(!meta.has_label("topic"));
+        assert!(meta.remove_label("perf"));
+        assert!(!meta.remove_label("perf"));
+        assert_eq!(meta.get(LABELS_KEY), Some("topic/io"));
+        assert!(meta.remove_label("topic/io"));
+        assert!(meta.is_empty());
+        assert!(parse_label("needs-review").is_ok());
+        assert!(parse_label("").is_err());
+        assert!(parse_label("a:b").is_err());
+        assert!(validate(LABELS_KEY, "a b").is_ok());
+        assert!(validate(LABELS_KEY, "a b@c").is_err());
+    }
+
+    #[test]
+    fn key_validation() {
+        assert!(parse_key("note").is_ok());
+        assert!(parse_key("x-review.id_2").is_ok());
+        assert!(parse_key("").is_err());
+        assert!(parse_key("2fa").is_err());
+        assert!(parse_key("has space").is_err());
+        assert!(validate(SENT_VERSION_KEY, "2").is_ok());
+        assert!(validate(SENT_VERSION_KEY, "0").is_err());
+        assert!(validate(SENT_VERSION_KEY, "v2").is_err());
+    }
+
+    #[test]
+    fn scope() {
+        let mut meta = PatchMeta::default();
+        assert!(meta.scope().is_none());
+        assert!(meta.in_scope("any/file.txt"));
+        meta.set_scope(["src/lib.rs", "doc", "src/lib.rs"]);
+        assert_eq!(meta.get(SCOPE_KEY), Some("doc\nsrc/lib.rs"));
+        assert!(meta.in_scope("src/lib.rs"));
+        assert!(meta.in_scope("doc/guide/intro.txt"));
+        assert!(!meta.in_scope("src/lib.rs.orig"));
+        assert!(!meta.in_scope("docs/index.txt"));
+        let mut other = PatchMeta::default();
+        other.set_scope(["README.md"]);
+        meta.merge(&other);
+        assert_eq!(meta.get(SCOPE_KEY), Some("README.md\ndoc\nsrc/lib.rs"));
+        meta.set_scope([]);
+        assert!(meta.is_empty());
+    }
+}
@@ -2,15 +2,23 @@
 
 //! The StGit stack data structure.
 mod access;
+mod deps;
 mod iter;
+mod lock;
+pub(crate) mod meta;
 mod serde;
 #[allow(clippy::module_inception)]
 mod stack;
 mod state;
+pub(crate) mod statediff;
 mod transaction;
 mod upgrade;
 
 pub(crate) use access::{StackAccess, StackStateAccess};
+pub(crate) use deps::PatchDependencies;
+pub(crate) use meta::PatchMeta;
 pub(crate) use stack::{state_refname_from_branch_name, InitializationPolicy, Stack};
-pub(crate) use state::{PatchState, StackState};
-pub(crate) use transaction::{Error as TransactionError, StackTransaction};
+pub(crate) use state::{LogRetention, PatchState, StackState};
+pub(crate) use transaction::{
+    is_dry_run, set_dry_run, Error as TransactionError, StackTransaction,
+};
@@ -6,8 +6,18 @@ use std::collections::BTreeMap;
 
 use anyhow::{Context, Result};
 
+use super::meta::PatchMeta;
 use crate::patch::PatchName;
 
+/// Current stack state format version.
+pub(crate) const STACK_FORMAT_VERSION: i64 = 5;
+
+/// Stack state format version for stack states with patch metadata.
+///
+/// Stack states without any patch metadata continue to be written as version 5 such
+/// that they remain readable by StGit versions without patch metadata support.
+pub(crate) const STACK_META_FORMAT_VERSION: i64 = 6;
+
 /// Raw state deserialization representation.
 ///
 /// `PatchNames` and `Oids` are checked, but `Oids` are not converted to `Commits`.
@@ -24,6 +34,9 @@ pub(crate) struct RawStackState {
 pub(crate) struct RawPatchState {
     /// The commit id of the patch.
     pub oid: gix::ObjectId,
+
+    /// User metadata associated with the patch.
+    pub meta: PatchMeta,
 }
 
 impl RawStackState {
@@ -55,14 +68,16 @@ impl<'de> serde::Deserialize<'de> for RawStackState {
         #[derive(serde::Deserialize)]
         struct DeserPatchState {
             pub oid: String,
+            #[serde(default)]
+            pub meta: PatchMeta,
         }
 
         let ds = DeserState::deserialize(deserializer)?;
 
-        if ds.version != 5 {
+        if ds.version != STACK_FORMAT_VERSION && ds.version != STACK_META_FORMAT_VERSION {
             return Err(D::Error::invalid_value(
                 ::serde::de::Unexpected::Signed(ds.version),
-                &"5",
+                &"5 or 6",
             ));
         }
 
@@ -90,7 +105,13 @@ impl<'de> serde::Deserialize<'de> for RawStackState {
                     patchname, &raw_patch.oid
                 ))
             })?;
-            patches.insert(patchname, RawPatchState { oid });
+            patches.insert(
+                patchname,
+                RawPatchState {
+                    oid,
+                    meta: raw_patch.meta,
+                },
+            );
         }
 
         Ok(RawStackState {
@@ -117,28 +138,37 @@ impl serde::Serialize for super::state::StackState<'_> {
             pub applied: &'a Vec<PatchName>,
             pub unapplied: &'a Vec<PatchName>,
             pub hidden: &'a Vec<PatchName>,
-            pub patches: BTreeMap<&'a PatchName, SerializablePatchState>,
+            pub patches: BTreeMap<&'a PatchName, SerializablePatchState<'a>>,
         }
 
         #[derive(serde::Serialize)]
-        struct SerializablePatchState {
+        struct SerializablePatchState<'a> {
             pub oid: String,
+            #[serde(skip_serializing_if = "PatchMeta::is_empty")]
+            pub meta: &'a PatchMeta,
         }
 
         let prev: Option<String> = self.prev.as_ref().map(|commit| commit.id().to_string());
         let head: String = self.head.id().to_string();
-        let mut patches: BTreeMap<&PatchName, SerializablePatchState> = BTreeMap::new();
+        let mut patches: BTreeMap<&PatchName, SerializablePatchState<'_>> = BTreeMap::new();
         for (patchname, patch_state) in &self.patches {
             patches.insert(
                 patchname,
                 SerializablePatchState {
                     oid: patch_state.commit.id().to_string(),
+                    meta: &patch_state.meta,
                 },
             );
         }
 
+        let version = if self.patches.values().all(|patch| patch.meta.is_empty()) {
+            STACK_FORMAT_VERSION
+        } else {
+            STACK_META_FORMAT_VERSION
+        };
+
         let ss = SerializableState {
-            version: 5,
+            version,
             prev,
             head,
             applied: &self.applied,
@@ -8,8 +8,11 @@ use anyhow::{anyhow, Context, Result};
 use bstr::ByteSlice;
 
 use super::{
-    state::StackState, transaction::TransactionBuilder, upgrade::stack_upgrade, PatchState,
-    StackAccess, StackStateAccess,
+    lock::{map_ref_edit_error, Error as LockError, StackLock},
+    state::{prune_log, LogRetention, StackState},
+    transaction::TransactionBuilder,
+    upgrade::stack_upgrade,
+    PatchState, StackAccess, StackStateAccess,
 };
 use crate::{
     branchloc::BranchLocator,
@@ -30,6 +33,7 @@ pub(crate) struct Stack<'repo> {
     stack_refname: String,
     base: Rc<gix::Commit<'repo>>,
     state: StackState<'repo>,
+    state_commit_id: Option<gix::ObjectId>,
     is_initialized: bool,
 }
 
@@ -151,36 +155,41 @@ impl<'repo> Stack<'repo> {
 
         let maybe_state_ref = repo.find_reference(&stack_refname).ok();
 
-        let state_and_base_from_ref =
-            |state_ref: gix::Reference<'repo>| -> Result<(StackState<'repo>, Rc<gix::Commit<'repo>>)> {
-                let stack_tree = state_ref.id().object()?.try_into_commit()?.tree()?;
-                let state = StackState::from_tree(repo, stack_tree)?;
-                let base = if let Some(first_patchname) = state.applied.first() {
-                    Rc::new(
-                        repo.find_object(
-                            state.patches[first_patchname]
-                                .commit
-                                .parent_ids()
-                                .next()
-                                .unwrap(),
-                        )?
-                        .try_into_commit()?,
-                    )
-                } else {
-                    branch_head.clone()
-                };
-                Ok((state, base))
+        let state_and_base_from_ref = |state_ref: gix::Reference<'repo>| -> Result<(
+            StackState<'repo>,
+            Rc<gix::Commit<'repo>>,
+            Option<gix::ObjectId>,
+        )> {
+            let state_commit = state_ref.id().object()?.try_into_commit()?;
+            let state_commit_id = state_commit.id;
+            let stack_tree = state_commit.tree()?;
+            let state = StackState::from_tree(repo, stack_tree)?;
+            let base = if let Some(first_patchname) = state.applied.first() {
+                Rc::new(
+                    repo.find_object(
+                        state.patches[first_patchname]
+                            .commit
+                            .parent_ids()
+                            .next()
+                            .unwrap(),
+                    )?
+                    .try_into_commit()?,
+                )
+            } else {
+                branch_head.clone()
             };
+            Ok((state, base, Some(state_commit_id)))
+        };
 
         let initialize_state_and_base =
-            || -> Result<(StackState<'repo>, Rc<gix::Commit<'repo>>)> {
+            || -> Result<(StackState<'repo>, Rc<gix::Commit<'repo>>, Option<gix::ObjectId>)> {
                 let state = StackState::new(branch_head.clone());
                 let base = branch_head.clone();
-                state.commit(repo, Some(&stack_refname), "initialize")?;
-                Ok((state, base))
+                let state_commit_id = state.commit(repo, Some(&stack_refname), "initialize")?;
+                Ok((state, base, Some(state_commit_id)))
             };
 
-        let (state, base) = match init_policy {
+        let (state, base, state_commit_id) = match init_policy {
             InitializationPolicy::AutoInitialize => {
                 is_initialized = true;
                 if let Some(state_ref) = maybe_state_ref {
@@ -217,7 +226,7 @@ impl<'repo> Stack<'repo> {
                     is_initialized = false;
                     let state = StackState::new(branch_head.clone());
                     let base = branch_head.clone();
-                    (state, base)
+                    (state, base, None)
                 }
             }
         };
@@ -231,6 +240,7 @@ impl<'repo> Stack<'repo> {
             stack_refname,
             base,
             state,
+            state_commit_id,
             is_initialized,
         })
     }
@@ -281,6 +291,94 @@ impl<'repo> Stack<'repo> {
         Ok(())
     }
 
+    /// Get the branch and patch this stack is stacked on, as recorded in the config.
+    ///
+    /// A stacked branch is based on a patch of another branch's stack, as created with
+    /// `stg branch --create <branch> --on <parent-branch>:<patch>`.
+    pub(crate) fn get_parent_patch(
+        &self,
+        config: &gix::config::Snapshot,
+    ) -> Option<(String, PatchName)> {
+        let subsection = format!("{}.stgit", self.branch_name);
+        let parent_branchname = config
+            .string_by("branch", Some(subsection.as_str().into()), "parentbranch")?
+            .to_str()
+            .ok()?
+            .to_string();
+        let parent_patchname = config
+            .string_by("branch", Some(subsection.as_str().into()), "parentpatch")?
+            .to_str()
+            .ok()?
+            .parse::<PatchName>()
+            .ok()?;
+        Some((parent_branchname, parent_patchname))
+    }
+
+    /// Record the patch of the parent branch's stack this stack is stacked on.
+    pub(crate) fn set_parent_patch(&self, patchname: &PatchName) -> Result<()> {
+        let subsection = format!("{}.stgit", self.branch_name);
+        let mut local_config_file = self.repo.local_config_file()?;
+        local_config_file.set_raw_value_by(
+            "branch",
+            Some(subsection.as_str().into()),
+            "parentpatch",
+            patchname.to_string().as_str(),
+        )?;
+        self.repo.write_local_config(local_config_file)?;
+        Ok(())
+    }
+
+    /// Update the parent patch of branches stacked on renamed patches of this stack.
+    ///
+    /// `renames` maps the patches' old names to their new names.
+    pub(crate) fn follow_renamed_parent_patches(
+        &self,
+        renames: &BTreeMap<PatchName, PatchName>,
+    ) -> Result<()> {
+        if renames.is_empty() {
+            return Ok(());
+        }
+        let mut local_config_file = self.repo.local_config_file()?;
+        let mut updates: Vec<(String, &PatchName)> = Vec::new();
+        if let Some(sections) = local_config_file.sections_by_name("branch") {
+            for section in sections {
+                let Some(subsection) = section
+                    .header()
+                    .subsection_name()
+                    .and_then(|name| name.to_str().ok())
+                    .filter(|name| name.ends_with(".stgit"))
+                else {
+                    continue;
+                };
+                if section.value("parentbranch").as_deref()
+                    != Some(self.branch_name.as_bytes().as_bstr())
+                {
+                    continue;
+                }
+                if let Some(new_patchname) = section
+                    .value("parentpatch")
+                    .and_then(|value| value.to_str().ok()?.parse::<PatchName>().ok())
+                    .and_then(|patchname| renames.get(&patchname))
+                {
+                    updates.push((subsection.to_string(), new_patchname));
+                }
+            }
+        }
+        if updates.is_empty() {
+            return Ok(());
+        }
+        for (subsection, new_patchname) in updates {
+            local_config_file.set_raw_value_by(
+                "branch",
+                Some(subsection.as_str().into()),
+                "parentpatch",
+                new_patchname.to_string().as_str(),
+            )?;
+        }
+        self.repo.write_local_config(local_config_file)?;
+        Ok(())
+    }
+
     /// Check whether the stack's recorded head matches the branch's head.
     pub(crate) fn is_head_top(&self) -> bool {
         self.state.head.id() == self.branch_head.id()
@@ -299,6 +397,45 @@ impl<'repo> Stack<'repo> {
         }
     }
 
+    /// Return an error if the stack's branch is checked out in another worktree.
+    ///
+    /// Operations that move the branch head must be run in the worktree where the
+    /// branch is checked out, lest that worktree's index and files get out of sync with
+    /// its HEAD.
+    pub(crate) fn check_other_worktree(&self) -> Result<()> {
+        if let Some(path) = self.repo.find_other_worktree(self.get_branch_refname())? {
+            Err(anyhow!(
+                "branch `{}` is checked out in worktree `{}`; \
+                 run the command there or leave the applied patches unchanged",
+                self.get_branch_name(),
+                path.display(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Lock the stack against modification by other StGit processes.
+    ///
+    /// The stack is locked until the returned [`StackLock`] is dropped. An error is
+    /// returned if the stack's state was modified since this [`Stack`] was loaded.
+    pub(crate) fn lock(&self) -> Result<StackLock> {
+        let lock = StackLock::acquire(self.repo, &self.branch_name)?;
+        let current_state_commit_id = self
+            .repo
+            .find_reference(&self.stack_refname)
+            .ok()
+            .and_then(|reference| reference.target().try_id().map(ToOwned::to_owned));
+        if current_state_commit_id == self.state_commit_id {
+            Ok(lock)
+        } else {
+            Err(LockError::Modified {
+                branch_name: self.branch_name.clone(),
+            }
+            .into())
+        }
+    }
+
     /// Re-commit stack state with updated branch head.
     pub(crate) fn log_external_mods(self, message: Option<&str>) -> Result<Self> {
         assert!(
@@ -306,11 +443,10 @@ impl<'repo> Stack<'repo> {
             "Attempt to log stack state when uninitialized"
         );
 
-        let prev_state_commit = self
-            .repo
-            .find_reference(&self.stack_refname)?
-            .peel_to_commit()?;
-        let prev_state_commit_id = prev_state_commit.id;
+        let prev_state_commit_id = self
+            .state_commit_id
+            .expect("initialized stack has a state commit");
+        let prev_state_commit = self.repo.find_commit(prev_state_commit_id)?;
         let state = self
             .state
             .advance_head(self.branch_head.clone(), Rc::new(prev_state_commit));
@@ -324,23 +460,29 @@ impl<'repo> Stack<'repo> {
 
         let state_commit_id = state.commit(self.repo, None, message)?;
 
-        self.repo.edit_reference(gix::refs::transaction::RefEdit {
-            change: gix::refs::transaction::Change::Update {
-                log: gix::refs::transaction::LogChange {
-                    mode: gix::refs::transaction::RefLog::AndReference,
-                    force_create_reflog: false,
-                    message: reflog_msg.into(),
+        self.repo
+            .edit_reference(gix::refs::transaction::RefEdit {
+                change: gix::refs::transaction::Change::Update {
+                    log: gix::refs::transaction::LogChange {
+                        mode: gix::refs::transaction::RefLog::AndReference,
+                        force_create_reflog: false,
+                        message: reflog_msg.into(),
+                    },
+                    expected: gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                        gix::refs::Target::Object(prev_state_commit_id),
+                    ),
+                    new: gix::refs::Target::Object(state_commit_id),
                 },
-                expected: gix::refs::transaction::PreviousValue::ExistingMustMatch(
-                    gix::refs::Target::Object(prev_state_commit_id),
-                ),
-                new: gix::refs::Target::Object(state_commit_id),
-            },
-            name: gix::refs::FullName::try_from(self.stack_refname.as_str())?,
-            deref: false,
-        })?;
+                name: gix::refs::FullName::try_from(self.stack_refname.as_str())?,
+                deref: false,
+            })
+            .map_err(|e| map_ref_edit_error(&self.branch_name, e))?;
 
-        Ok(Self { state, ..self })
+        Ok(Self {
+            state,
+            state_commit_id: Some(state_commit_id),
+            ..self
+        })
     }
 
     /// Start a transaction to modify the stack.
@@ -354,12 +496,67 @@ impl<'repo> Stack<'repo> {
 
     /// Clear the stack state history.
     pub(crate) fn clear_state_log(&mut self, reflog_msg: &str) -> Result<()> {
+        let _lock = self.lock()?;
         self.state.prev = None;
-        self.state
-            .commit(self.repo, Some(&self.stack_refname), reflog_msg)?;
+        let state_commit_id =
+            self.state
+                .commit(self.repo, Some(&self.stack_refname), reflog_msg)?;
+        self.state_commit_id = Some(state_commit_id);
         Ok(())
     }
 
+    /// Prune the stack state history, retaining only the selected log entries.
+    ///
+    /// Returns `false` if there were no entries to prune.
+    pub(crate) fn prune_state_log(
+        &mut self,
+        retention: LogRetention,
+        reflog_msg: &str,
+    ) -> Result<bool> {
+        let _lock = self.lock()?;
+        let state_commit = Rc::new(
+            self.repo.find_commit(
+                self.state_commit_id
+                    .expect("initialized stack has a state commit"),
+            )?,
+        );
+        let Some(pruned_commit) = prune_log(self.repo, &state_commit, retention)? else {
+            return Ok(false);
+        };
+
+        self.repo
+            .edit_reference(gix::refs::transaction::RefEdit {
+                change: gix::refs::transaction::Change::Update {
+                    log: gix::refs::transaction::LogChange {
+                        mode: gix::refs::transaction::RefLog::AndReference,
+                        force_create_reflog: false,
+                        message: reflog_msg.into(),
+                    },
+                    expected: gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                        gix::refs::Target::Object(state_commit.id),
+                    ),
+                    new: gix::refs::Target::Object(pruned_commit.id),
+                },
+                name: gix::refs::FullName::try_from(self.stack_refname.as_str())?,
+                deref: false,
+            })
+            .map_err(|e| map_ref_edit_error(&self.branch_name, e))?;
+
+        self.state = StackState::from_commit(self.repo, &pruned_commit)?;
+        self.state_commit_id = Some(pruned_commit.id);
+        Ok(true)
+    }
+
+    /// Get the id of the stack state commit this stack was loaded from.
+    pub(super) fn state_commit_id(&self) -> Option<gix::ObjectId> {
+        self.state_commit_id
+    }
+
+    /// Update the stack state commit id after the state reference was updated.
+    pub(super) fn update_state_commit_id(&mut self, state_commit_id: gix::ObjectId) {
+        self.state_commit_id = Some(state_commit_id);
+    }
+
     /// Update the branch and branch head commit.
     pub(super) fn update_head(&mut self, branch: Branch<'repo>, commit: Rc<gix::Commit<'repo>>) {
         self.branch = branch;
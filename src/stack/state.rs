@@ -10,7 +10,7 @@ use std::{collections::BTreeMap, rc::Rc, str};
 use anyhow::{anyhow, Result};
 use bstr::{BString, ByteVec};
 
-use super::{access::StackStateAccess, iter::AllPatches, serde::RawStackState};
+use super::{access::StackStateAccess, iter::AllPatches, meta::PatchMeta, serde::RawStackState};
 use crate::{
     ext::{CommitExtended, CommitOptions, RepositoryExtended},
     patch::PatchName,
@@ -21,6 +21,7 @@ use crate::{
 ///
 /// This is the core state recorded-to and read-from the git repository that
 /// describes the state of a StGit stack.
+#[derive(Clone)]
 pub(crate) struct StackState<'repo> {
     /// Commit of the previous stack state.
     ///
@@ -49,10 +50,11 @@ pub(crate) struct StackState<'repo> {
 
 /// State associated with a patch.
 ///
-/// Currently the only state is a commit object.
+/// Each patch has a commit object and, optionally, user metadata.
 #[derive(Clone, Debug)]
 pub(crate) struct PatchState<'repo> {
     pub(crate) commit: Rc<gix::Commit<'repo>>,
+    pub(crate) meta: PatchMeta,
 }
 
 impl<'repo> StackStateAccess<'repo> for StackState<'repo> {
@@ -143,6 +145,7 @@ impl<'repo> StackState<'repo> {
                 patchname,
                 PatchState {
                     commit: Rc::new(commit),
+                    meta: raw_state.meta,
                 },
             );
         }
@@ -217,6 +220,51 @@ impl<'repo> StackState<'repo> {
         repo: &'repo gix::Repository,
         update_ref: Option<&str>,
         message: &str,
+    ) -> Result<gix::ObjectId> {
+        let committer = repo.get_committer()?;
+        let author = repo.get_author()?;
+        let message = Message::from(message);
+
+        // Apply the `stgit.log.maxEntries` policy. Since pruning rewrites the retained
+        // entries, the log may grow beyond the maximum by a margin before it is pruned
+        // back to the maximum. The new state is itself a log entry, so one less entry
+        // than the maximum is retained from the previous states.
+        let max_entries = repo
+            .config_snapshot()
+            .integer("stgit.log.maxentries")
+            .filter(|max_entries| *max_entries > 0)
+            .map(|max_entries| usize::try_from(max_entries).unwrap_or(usize::MAX));
+        if let (Some(max_entries), Some(prev_commit)) = (max_entries, self.prev.as_ref()) {
+            let margin = (max_entries / 10).max(1);
+            if !log_exceeds(repo, prev_commit, max_entries.saturating_add(margin - 1))? {
+                return self.commit_as(repo, update_ref, &message, author, committer);
+            }
+            let keep = max_entries - 1;
+            let pruned_prev = if keep == 0 {
+                Some(None)
+            } else {
+                prune_log(repo, prev_commit, LogRetention::Count(keep))?.map(Some)
+            };
+            if let Some(prev) = pruned_prev {
+                let state = Self {
+                    prev,
+                    ..self.clone()
+                };
+                return state.commit_as(repo, update_ref, &message, author, committer);
+            }
+        }
+
+        self.commit_as(repo, update_ref, &message, author, committer)
+    }
+
+    /// Commit stack state to repository with the given message and signatures.
+    fn commit_as(
+        &self,
+        repo: &'repo gix::Repository,
+        update_ref: Option<&str>,
+        message: &Message,
+        author: gix::actor::SignatureRef<'_>,
+        committer: gix::actor::SignatureRef<'_>,
     ) -> Result<gix::ObjectId> {
         let (state_tree_id, prev_state) = if let Some(prev_commit) = self.prev.as_ref() {
             let prev_state = Self::from_tree(repo, prev_commit.tree()?)?;
@@ -226,8 +274,6 @@ impl<'repo> StackState<'repo> {
             (self.make_tree(repo, None)?, None)
         };
         let config = repo.config_snapshot();
-        let committer = repo.get_committer()?;
-        let author = repo.get_author()?;
 
         let simplified_parents: Vec<gix::ObjectId> = match &self.prev {
             Some(prev_commit) => {
@@ -240,8 +286,6 @@ impl<'repo> StackState<'repo> {
             None => vec![],
         };
 
-        let message = Message::from(message);
-
         let commit_opts = CommitOptions {
             commit_encoding: None,
             gpgsign: config.boolean("stgit.gpgsign").unwrap_or(false),
@@ -250,7 +294,7 @@ impl<'repo> StackState<'repo> {
         let simplified_parent_id = repo.commit_with_options(
             author,
             committer,
-            &message,
+            message,
             state_tree_id,
             simplified_parents,
             &commit_opts,
@@ -295,7 +339,7 @@ impl<'repo> StackState<'repo> {
         let commit_oid = repo.commit_with_options(
             author,
             committer,
-            &message,
+            message,
             state_tree_id,
             parent_oids,
             &commit_opts,
@@ -451,3 +495,100 @@ impl<'repo> StackState<'repo> {
         Ok(patch_meta_id.detach())
     }
 }
+
+/// Selection of the stack state log entries to retain when pruning the log.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum LogRetention {
+    /// Retain the given number of newest entries.
+    Count(usize),
+
+    /// Retain the entries recorded at or after the given time, in seconds since the epoch.
+    Since(gix::date::SecondsSinceUnixEpoch),
+}
+
+/// Determine whether the stack state log ending with `state_commit` has more than
+/// `limit` entries.
+fn log_exceeds(repo: &gix::Repository, state_commit: &gix::Commit, limit: usize) -> Result<bool> {
+    let mut simplified = state_commit.get_parent_commit()?;
+    let mut num_entries = 1;
+    loop {
+        let Some(parent_id) = simplified.parent_ids().next() else {
+            return Ok(false);
+        };
+        if num_entries >= limit {
+            return Ok(true);
+        }
+        num_entries += 1;
+        simplified = repo.find_commit(parent_id)?;
+    }
+}
+
+/// Rewrite the stack state log ending with `state_commit` to drop older entries.
+///
+/// The `state_commit` entry is always retained. The retained entries are recommitted,
+/// from oldest to newest, with their original messages and signatures, such that the
+/// oldest retained entry has no previous state. Since each recommitted state has the
+/// same patch commits as the original, the patch commits referenced by retained entries
+/// remain reachable while those only referenced by dropped entries become subject to
+/// garbage collection.
+///
+/// Returns the new state commit, or `None` if no entries need to be dropped.
+pub(crate) fn prune_log<'repo>(
+    repo: &'repo gix::Repository,
+    state_commit: &Rc<gix::Commit<'repo>>,
+    retention: LogRetention,
+) -> Result<Option<Rc<gix::Commit<'repo>>>> {
+    // Walk the simplified log, whose commits have only one parent, to find how many
+    // entries to retain without having to read each entry's full state.
+    let mut simplified = state_commit.get_parent_commit()?;
+    let mut num_retained = 1;
+    loop {
+        let Some(parent_id) = simplified.parent_ids().next() else {
+            return Ok(None);
+        };
+        let parent = repo.find_commit(parent_id)?;
+        let retain_parent = match retention {
+            LogRetention::Count(count) => num_retained < count,
+            LogRetention::Since(since) => parent.committer()?.time.seconds >= since,
+        };
+        if !retain_parent {
+            break;
+        }
+        num_retained += 1;
+        simplified = parent;
+    }
+
+    let mut entries = Vec::with_capacity(num_retained);
+    let mut entry = state_commit.clone();
+    loop {
+        let state = StackState::from_commit(repo, &entry)?;
+        entries.push((entry, state));
+        if entries.len() == num_retained {
+            break;
+        }
+        entry = entries
+            .last()
+            .and_then(|(_, state)| state.prev.clone())
+            .ok_or_else(|| anyhow!("stack state log ends unexpectedly"))?;
+    }
+
+    let mut prev: Option<Rc<gix::Commit<'repo>>> = None;
+    for (entry, state) in entries.into_iter().rev() {
+        let entry_ref = entry.decode()?;
+        let message = Message::Raw {
+            bytes: entry_ref.message,
+            encoding: None,
+        };
+        let state = StackState { prev, ..state };
+        let commit_id = state.commit_as(
+            repo,
+            None,
+            &message,
+            entry_ref.author(),
+            entry_ref.committer(),
+        )?;
+        prev = Some(Rc::new(repo.find_commit(commit_id)?));
+    }
+
+    Ok(prev)
+}
@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Structured comparison of two stack states.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use anyhow::Result;
+
+use super::{access::StackStateAccess, state::StackState};
+use crate::{
+    ext::CommitExtended,
+    patch::{LocationGroup, PatchName},
+    stupid::{normalize_for_interdiff, Stupid},
+};
+
+/// A single difference between two stack states.
+pub(crate) enum StateChange<'repo> {
+    /// The stack base commit changed, e.g. due to `stg rebase`.
+    Base {
+        old: gix::ObjectId,
+        new: gix::ObjectId,
+    },
+
+    /// The patch is new in the newer state.
+    Added {
+        patchname: PatchName,
+        group: LocationGroup,
+    },
+
+    /// The patch is absent from the newer state.
+    Deleted {
+        patchname: PatchName,
+        group: LocationGroup,
+    },
+
+    /// The patch has a new name, but the same commit.
+    Renamed { old: PatchName, new: PatchName },
+
+    /// The patch moved between the applied, unapplied, and hidden groups.
+    Moved {
+        patchname: PatchName,
+        from: LocationGroup,
+        to: LocationGroup,
+    },
+
+    /// The relative order of the patches in a group changed. All of the group's
+    /// patches are listed in their new order.
+    Reordered {
+        group: LocationGroup,
+        patchnames: Vec<PatchName>,
+    },
+
+    /// The patch has a new commit.
+    Modified {
+        patchname: PatchName,
+        old_commit: Rc<gix::Commit<'repo>>,
+        new_commit: Rc<gix::Commit<'repo>>,
+        kinds: ModifiedKinds,
+    },
+}
+
+impl StateChange<'_> {
+    /// Determine whether this change concerns the given patch.
+    pub(crate) fn involves(&self, patchname: &PatchName) -> bool {
+        match self {
+            StateChange::Base { .. } => false,
+            StateChange::Added { patchname: pn, .. }
+            | StateChange::Deleted { patchname: pn, .. }
+            | StateChange::Moved { patchname: pn, .. }
+            | StateChange::Modified { patchname: pn, .. } => pn == patchname,
+            StateChange::Renamed { old, new } => old == patchname || new == patchname,
+            StateChange::Reordered { patchnames, .. } => patchnames.contains(patchname),
+        }
+    }
+}
+
+/// The aspects of a patch that differ between its old and new commits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ModifiedKinds {
+    /// The patch's parent commit changed.
+    pub(crate) rebased: bool,
+
+    /// The changes made by the patch changed.
+    pub(crate) content: bool,
+
+    /// The commit message changed.
+    pub(crate) message: bool,
+
+    /// The author changed.
+    pub(crate) author: bool,
+}
+
+impl ModifiedKinds {
+    /// Compare two commits of a patch.
+    pub(crate) fn compare(
+        repo: &gix::Repository,
+        old_commit: &gix::Commit<'_>,
+        new_commit: &gix::Commit<'_>,
+    ) -> Result<Self> {
+        let old_parent = old_commit.get_parent_commit()?;
+        let new_parent = new_commit.get_parent_commit()?;
+        let rebased = old_parent.id != new_parent.id;
+        let content = if rebased {
+            let stupid = repo.stupid();
+            let patch_diff = |parent: &gix::Commit<'_>, commit: &gix::Commit<'_>| {
+                stupid
+                    .diff_tree_patch(
+                        parent.tree_id()?.detach(),
+                        commit.tree_id()?.detach(),
+                        None::<Vec<String>>,
+                        false,
+                        ["--no-ext-diff"],
+                    )
+                    .map(|diff| normalize_for_interdiff(&diff))
+            };
+            patch_diff(&old_parent, old_commit)? != patch_diff(&new_parent, new_commit)?
+        } else {
+            old_commit.tree_id()? != new_commit.tree_id()?
+        };
+        Ok(Self {
+            rebased,
+            content,
+            message: old_commit.message_raw()? != new_commit.message_raw()?,
+            author: old_commit.author()? != new_commit.author()?,
+        })
+    }
+
+    /// Determine whether the patch itself changed, as opposed to only being rebased.
+    pub(crate) fn is_patch_changed(&self) -> bool {
+        self.content || self.message || self.author
+    }
+
+    /// Names of the modified aspects, e.g. `["content", "message"]`.
+    pub(crate) fn names(&self) -> Vec<&'static str> {
+        [
+            (self.content, "content"),
+            (self.message, "message"),
+            (self.author, "author"),
+            (self.rebased, "rebased"),
+        ]
+        .into_iter()
+        .filter_map(|(is_set, name)| is_set.then_some(name))
+        .collect()
+    }
+}
+
+/// Compare two stack states.
+///
+/// When `old` is `None`, `new` is compared with an empty stack. Patches whose commit is
+/// unchanged, but whose name changed, are reported as renamed. The changes are ordered
+/// as: base, deletions, additions, renames, moves, reorders, and modifications.
+pub(crate) fn compare_states<'repo>(
+    repo: &'repo gix::Repository,
+    old: Option<&StackState<'repo>>,
+    new: &StackState<'repo>,
+) -> Result<Vec<StateChange<'repo>>> {
+    let mut changes = Vec::new();
+
+    if let Some(old) = old {
+        let (old_base, new_base) = (stack_base(old)?, stack_base(new)?);
+        if old_base != new_base {
+            changes.push(StateChange::Base {
+                old: old_base,
+                new: new_base,
+            });
+        }
+    }
+
+    let old_patchnames: Vec<&PatchName> = old
+        .map(|old| old.all_patches().collect())
+        .unwrap_or_default();
+
+    // Map each patch name in the new state to its name in the old state.
+    let mut old_names: BTreeMap<&PatchName, &PatchName> = BTreeMap::new();
+    let mut renames: Vec<(&PatchName, &PatchName)> = Vec::new();
+    if let Some(old) = old {
+        for pn in new.all_patches() {
+            if old.has_patch(pn) {
+                old_names.insert(pn, pn);
+            }
+        }
+        for pn in new.all_patches().filter(|pn| !old.has_patch(pn)) {
+            let commit_id = new.get_patch(pn).commit.id;
+            if let Some(old_pn) = old_patchnames.iter().copied().find(|old_pn| {
+                !new.has_patch(old_pn)
+                    && !renames.iter().any(|(renamed, _)| renamed == old_pn)
+                    && old.get_patch(old_pn).commit.id == commit_id
+            }) {
+                old_names.insert(pn, old_pn);
+                renames.push((old_pn, pn));
+            }
+        }
+    }
+
+    if let Some(old) = old {
+        for old_pn in old_patchnames.iter().copied() {
+            if !old_names.values().any(|pn| *pn == old_pn) {
+                changes.push(StateChange::Deleted {
+                    patchname: old_pn.clone(),
+                    group: old.location_group(old_pn),
+                });
+            }
+        }
+    }
+
+    for pn in new.all_patches() {
+        if !old_names.contains_key(pn) {
+            changes.push(StateChange::Added {
+                patchname: pn.clone(),
+                group: new.location_group(pn),
+            });
+        }
+    }
+
+    for (old_pn, pn) in renames {
+        changes.push(StateChange::Renamed {
+            old: old_pn.clone(),
+            new: pn.clone(),
+        });
+    }
+
+    let Some(old) = old else {
+        return Ok(changes);
+    };
+
+    for pn in new.all_patches() {
+        let Some(&old_pn) = old_names.get(pn) else {
+            continue;
+        };
+        let (from, to) = (old.location_group(old_pn), new.location_group(pn));
+        if from != to {
+            changes.push(StateChange::Moved {
+                patchname: pn.clone(),
+                from,
+                to,
+            });
+        }
+    }
+
+    for (group, old_group, new_group) in [
+        (LocationGroup::Applied, old.applied(), new.applied()),
+        (LocationGroup::Unapplied, old.unapplied(), new.unapplied()),
+        (LocationGroup::Hidden, old.hidden(), new.hidden()),
+    ] {
+        // Compare the order of the patches that are in this group in both states.
+        let new_common: Vec<&PatchName> = new_group
+            .iter()
+            .filter(|pn| {
+                old_names
+                    .get(pn)
+                    .is_some_and(|old_pn| old_group.contains(old_pn))
+            })
+            .collect();
+        let old_common: Vec<&PatchName> = old_group
+            .iter()
+            .filter(|old_pn| new_common.iter().any(|pn| old_names[pn] == *old_pn))
+            .collect();
+        if new_common
+            .iter()
+            .zip(old_common.iter())
+            .any(|(pn, old_pn)| old_names[pn] != *old_pn)
+        {
+            changes.push(StateChange::Reordered {
+                group,
+                patchnames: new_group.to_vec(),
+            });
+        }
+    }
+
+    for pn in new.all_patches() {
+        let Some(&old_pn) = old_names.get(pn) else {
+            continue;
+        };
+        let old_commit = &old.get_patch(old_pn).commit;
+        let new_commit = &new.get_patch(pn).commit;
+        if old_commit.id == new_commit.id {
+            continue;
+        }
+        let kinds = ModifiedKinds::compare(repo, old_commit, new_commit)?;
+        changes.push(StateChange::Modified {
+            patchname: pn.clone(),
+            old_commit: old_commit.clone(),
+            new_commit: new_commit.clone(),
+            kinds,
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Get the base commit id of the stack state.
+fn stack_base(state: &StackState<'_>) -> Result<gix::ObjectId> {
+    if let Some(pn) = state.applied().first() {
+        Ok(state.get_patch(pn).commit.get_parent_commit()?.id)
+    } else {
+        Ok(state.head().id)
+    }
+}
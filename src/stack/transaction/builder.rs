@@ -150,9 +150,11 @@ impl<'repo> TransactionBuilder<'repo> {
         let applied = stack.applied().to_vec();
         let unapplied = stack.unapplied().to_vec();
         let hidden = stack.hidden().to_vec();
+        let lock = stack.lock();
 
         let mut transaction = StackTransaction {
             stack,
+            lock: None,
             ui,
             options,
             applied,
@@ -161,11 +163,18 @@ impl<'repo> TransactionBuilder<'repo> {
             updated_patches: BTreeMap::new(),
             updated_head: None,
             updated_base: None,
+            renamed_patches: BTreeMap::new(),
             current_tree_id,
             error: None,
         };
 
-        transaction.error = f(&mut transaction).err();
+        match lock {
+            Ok(lock) => {
+                transaction.lock = Some(lock);
+                transaction.error = f(&mut transaction).err();
+            }
+            Err(e) => transaction.error = Some(e),
+        }
 
         ExecuteContext(transaction)
     }
@@ -30,9 +30,14 @@ mod builder;
 mod options;
 mod ui;
 
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
 use indexmap::IndexSet;
 
 pub(crate) use self::builder::TransactionBuilder;
@@ -40,11 +45,15 @@ use self::{
     options::{ConflictMode, TransactionOptions},
     ui::TransactionUserInterface,
 };
-use super::{state::StackState, StackAccess};
+use super::{
+    lock::{map_ref_edit_error, StackLock},
+    state::StackState,
+    StackAccess,
+};
 use crate::{
     ext::{CommitExtended, RepositoryExtended},
     patch::PatchName,
-    stack::{PatchState, Stack, StackStateAccess},
+    stack::{PatchMeta, PatchState, Stack, StackStateAccess},
     stupid::{Stupid, StupidContext},
     wrap::Branch,
 };
@@ -58,9 +67,26 @@ pub(crate) enum Error {
     TransactionHalt { msg: String, conflicts: bool },
 }
 
+/// Whether stack transactions are executed as dry runs, i.e. `stg --dry-run`.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Set whether stack transactions are executed as dry runs.
+///
+/// Dry run transactions perform their operations and report the outcome, but the
+/// stack's references, the index, and the worktree are left untouched.
+pub(crate) fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::SeqCst);
+}
+
+/// Determine whether stack transactions are executed as dry runs.
+pub(crate) fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
 /// Stack transaction state.
 pub(crate) struct StackTransaction<'repo> {
     stack: Stack<'repo>,
+    lock: Option<StackLock>,
     ui: TransactionUserInterface,
     options: TransactionOptions,
 
@@ -70,6 +96,7 @@ pub(crate) struct StackTransaction<'repo> {
     updated_patches: BTreeMap<PatchName, Option<PatchState<'repo>>>,
     updated_head: Option<Rc<gix::Commit<'repo>>>,
     updated_base: Option<Rc<gix::Commit<'repo>>>,
+    renamed_patches: BTreeMap<PatchName, PatchName>,
 
     current_tree_id: gix::ObjectId,
     error: Option<anyhow::Error>,
@@ -131,15 +158,18 @@ impl<'repo> ExecuteContext<'repo> {
         let trans_head = transaction.head().clone();
         let trans_head_tree_id = trans_head.tree_id()?.detach();
         let trans_top_patchname = transaction.applied().last().cloned();
+        let trans_base = transaction.base().clone();
 
         let StackTransaction {
             stack,
+            lock: _lock,
             ui,
             options,
             applied,
             unapplied,
             hidden,
             updated_patches,
+            renamed_patches,
             current_tree_id,
             error,
             ..
@@ -159,6 +189,41 @@ impl<'repo> ExecuteContext<'repo> {
             false
         };
 
+        if options.set_head && trans_head.id != stack.get_branch_head().id {
+            stack.check_other_worktree()?;
+        }
+
+        // A dry run reports the resulting stack and then discards the transaction,
+        // leaving references, the index, and the worktree untouched.
+        if options.dry_run {
+            if options.set_head && options.use_index_and_worktree && !options.allow_bad_head {
+                stack.check_head_top_mismatch()?;
+            }
+            let new_base = (trans_base.id != stack.base().id).then_some(trans_base.as_ref());
+            ui.print_dry_run(
+                &stack,
+                new_base,
+                &applied,
+                &unapplied,
+                &hidden,
+                &updated_patches,
+            )?;
+            return match error {
+                Some(_) if has_conflicts => Err(Error::TransactionHalt {
+                    msg: format!(
+                        "dry run: merge conflicts expected{}; no changes made",
+                        trans_top_patchname
+                            .as_ref()
+                            .map_or_else(String::new, |pn| format!(" with `{pn}`"))
+                    ),
+                    conflicts: false,
+                }
+                .into()),
+                Some(err) => Err(err),
+                None => Ok(stack),
+            };
+        }
+
         // Log external modifications
         let mut stack = if stack.is_head_top() {
             stack
@@ -214,9 +279,17 @@ impl<'repo> ExecuteContext<'repo> {
                 reflog_msg
             };
             let branch_ref_name = stack.get_branch_refname().to_owned();
-            let prev_state_commit = repo
-                .find_reference(stack.get_stack_refname())?
-                .peel_to_commit()?;
+            let prev_branch_head_id = stack.get_branch_head().id;
+            let prev_state_commit = repo.find_commit(
+                stack
+                    .state_commit_id()
+                    .expect("initialized stack has a state commit"),
+            )?;
+            let prev_patch_ids: BTreeMap<PatchName, gix::ObjectId> = updated_patches
+                .keys()
+                .filter(|pn| stack.has_patch(pn))
+                .map(|pn| (pn.clone(), stack.get_patch(pn).commit.id))
+                .collect();
             let state = stack.state_mut();
             for (patchname, maybe_patch) in &updated_patches {
                 if let Some(patch) = maybe_patch {
@@ -225,6 +298,7 @@ impl<'repo> ExecuteContext<'repo> {
                     state.patches.remove(patchname);
                 }
             }
+            let prev_state_commit_id = prev_state_commit.id;
             state.prev = Some(Rc::new(prev_state_commit));
             state.head = trans_head.clone();
             state.applied = applied;
@@ -242,15 +316,22 @@ impl<'repo> ExecuteContext<'repo> {
                 message: state_reflog_msg.into(),
             };
             for (patchname, maybe_patch) in &updated_patches {
+                let expected = if let Some(prev_patch_id) = prev_patch_ids.get(patchname) {
+                    gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                        gix::refs::Target::Object(*prev_patch_id),
+                    )
+                } else {
+                    gix::refs::transaction::PreviousValue::MustNotExist
+                };
                 let change = if let Some(patch) = maybe_patch {
                     gix::refs::transaction::Change::Update {
                         log: log.clone(),
-                        expected: gix::refs::transaction::PreviousValue::Any, // TODO?
+                        expected,
                         new: gix::refs::Target::Object(patch.commit.id),
                     }
                 } else {
                     gix::refs::transaction::Change::Delete {
-                        expected: gix::refs::transaction::PreviousValue::Any,
+                        expected,
                         log: gix::refs::transaction::RefLog::AndReference,
                     }
                 };
@@ -265,13 +346,9 @@ impl<'repo> ExecuteContext<'repo> {
             ref_edits.push(gix::refs::transaction::RefEdit {
                 change: gix::refs::transaction::Change::Update {
                     log: log.clone(),
-                    expected: if let Some(prev_state_commit) = stack.state_mut().prev.as_ref() {
-                        gix::refs::transaction::PreviousValue::ExistingMustMatch(
-                            gix::refs::Target::Object(prev_state_commit.id),
-                        )
-                    } else {
-                        gix::refs::transaction::PreviousValue::MustNotExist
-                    },
+                    expected: gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                        gix::refs::Target::Object(prev_state_commit_id),
+                    ),
                     new: gix::refs::Target::Object(state_commit_id),
                 },
                 name: gix::refs::FullName::try_from(stack.get_stack_refname())
@@ -283,7 +360,9 @@ impl<'repo> ExecuteContext<'repo> {
                 ref_edits.push(gix::refs::transaction::RefEdit {
                     change: gix::refs::transaction::Change::Update {
                         log,
-                        expected: gix::refs::transaction::PreviousValue::Any,
+                        expected: gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                            gix::refs::Target::Object(prev_branch_head_id),
+                        ),
                         new: gix::refs::Target::Object(trans_head.id),
                     },
                     name: branch_ref_name.clone(),
@@ -291,7 +370,9 @@ impl<'repo> ExecuteContext<'repo> {
                 })
             }
 
-            repo.edit_references(ref_edits)?;
+            repo.edit_references(ref_edits)
+                .map_err(|e| map_ref_edit_error(stack.get_branch_name(), e))?;
+            stack.update_state_commit_id(state_commit_id);
 
             if options.set_head {
                 stack.update_head(
@@ -304,6 +385,8 @@ impl<'repo> ExecuteContext<'repo> {
         })
         .map_err(|e| rollback(trans_head_tree_id, e))?;
 
+        stack.follow_renamed_parent_patches(&renamed_patches)?;
+
         if let Some(err) = error {
             Err(err)
         } else {
@@ -473,22 +556,33 @@ impl<'repo> StackTransaction<'repo> {
     ) -> Result<()> {
         let commit = self.stack.repo.find_commit(commit_id)?;
         let old_commit = self.get_patch_commit(patchname);
-        // Failure to copy is okay. The old commit may not have a note to copy.
-        self.stack
-            .repo
-            .stupid()
-            .notes_copy(old_commit.id, commit_id)
-            .ok();
+        self.copy_notes(old_commit.id, commit_id);
+        let meta = self.get_patch(patchname).meta.clone();
         self.updated_patches.insert(
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta,
             }),
         );
         self.ui.print_updated(patchname, self.applied())?;
         Ok(())
     }
 
+    /// Replace a patch's metadata.
+    ///
+    /// The patch's commit is unchanged.
+    pub(crate) fn update_patch_meta(
+        &mut self,
+        patchname: &PatchName,
+        meta: PatchMeta,
+    ) -> Result<()> {
+        let commit = self.get_patch_commit(patchname).clone();
+        self.updated_patches
+            .insert(patchname.clone(), Some(PatchState { commit, meta }));
+        Ok(())
+    }
+
     /// Add new patch to the top of the stack.
     ///
     /// The commit for the new patch must be parented by the former top commit of the
@@ -501,6 +595,7 @@ impl<'repo> StackTransaction<'repo> {
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta: PatchMeta::default(),
             }),
         );
         self.ui.print_pushed(patchname, PushStatus::New, true)?;
@@ -522,9 +617,10 @@ impl<'repo> StackTransaction<'repo> {
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta: PatchMeta::default(),
             }),
         );
-        self.ui.print_popped(&[patchname.clone()])?;
+        self.ui.print_popped(std::slice::from_ref(patchname))?;
         Ok(())
     }
 
@@ -575,13 +671,13 @@ impl<'repo> StackTransaction<'repo> {
             )?;
 
             let commit = repo.find_commit(new_commit_id)?;
-            repo.stupid()
-                .notes_copy(patch_commit.id, new_commit_id)
-                .ok();
+            self.copy_notes(patch_commit.id, new_commit_id);
+            let meta = self.get_patch(patchname).meta.clone();
             self.updated_patches.insert(
                 patchname.clone(),
                 Some(PatchState {
                     commit: Rc::new(commit),
+                    meta,
                 }),
             );
 
@@ -681,6 +777,15 @@ impl<'repo> StackTransaction<'repo> {
         Ok(())
     }
 
+    /// Move the stack onto a new base commit.
+    ///
+    /// All patches must be unapplied. The unapplied patches may subsequently be pushed
+    /// onto the new base.
+    pub(crate) fn rebase_onto(&mut self, base: Rc<gix::Commit<'repo>>) {
+        assert!(self.applied.is_empty());
+        self.updated_base = Some(base);
+    }
+
     // Finalize patches to be regular Git commits.
     //
     // Committed patches are no longer managed by StGit, but their commit objects remain
@@ -737,6 +842,7 @@ impl<'repo> StackTransaction<'repo> {
                 patchname.clone(),
                 Some(PatchState {
                     commit: Rc::new(commit),
+                    meta: PatchMeta::default(),
                 }),
             );
             new_applied.push(patchname.clone());
@@ -831,7 +937,7 @@ impl<'repo> StackTransaction<'repo> {
         if let Some(Some(patch_state)) = self.updated_patches.remove(old_patchname) {
             // The renamed patch may have been previously updated in this transaction.
             // This can happen, for example, for `stg refresh`.
-            self.updated_patches.insert(old_patchname.clone(), None);
+            self.forget_patch(old_patchname.clone());
             self.updated_patches
                 .insert(new_patchname.clone(), Some(patch_state));
         } else {
@@ -841,9 +947,35 @@ impl<'repo> StackTransaction<'repo> {
                 .insert(new_patchname.clone(), Some(patch_state));
         }
 
+        // Track each patch's original name such that stacked branches may follow
+        // renamed parent patches.
+        let original_patchname = self
+            .renamed_patches
+            .iter()
+            .find_map(|(original, current)| (current == old_patchname).then(|| original.clone()))
+            .unwrap_or_else(|| old_patchname.clone());
+        if &original_patchname == new_patchname {
+            self.renamed_patches.remove(&original_patchname);
+        } else {
+            self.renamed_patches
+                .insert(original_patchname, new_patchname.clone());
+        }
+
         self.ui.print_rename(old_patchname, new_patchname)
     }
 
+    /// Record that a patch no longer exists.
+    ///
+    /// A patch that was created within this transaction is simply forgotten since
+    /// there is no existing patch reference to be deleted.
+    fn forget_patch(&mut self, patchname: PatchName) {
+        if self.stack.has_patch(&patchname) {
+            self.updated_patches.insert(patchname, None);
+        } else {
+            self.updated_patches.remove(&patchname);
+        }
+    }
+
     /// Delete one or more patches from the stack.
     ///
     /// Deleted patches' commits become disconnected from the regular git history and
@@ -876,7 +1008,7 @@ impl<'repo> StackTransaction<'repo> {
         for patchname in all_popped {
             if should_delete(&patchname) {
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.forget_patch(patchname);
             } else if !deleted_group.is_empty() {
                 self.ui.print_deleted(&deleted_group)?;
                 deleted_group.clear();
@@ -886,7 +1018,7 @@ impl<'repo> StackTransaction<'repo> {
         for patchname in unapplied {
             if should_delete(&patchname) {
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.forget_patch(patchname);
             } else {
                 self.ui.print_deleted(&deleted_group)?;
                 deleted_group.clear();
@@ -899,7 +1031,7 @@ impl<'repo> StackTransaction<'repo> {
             if should_delete(&self.hidden[i]) {
                 let patchname = self.hidden.remove(i);
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.forget_patch(patchname);
             } else {
                 i += 1;
                 self.ui.print_deleted(&deleted_group)?;
@@ -953,6 +1085,26 @@ impl<'repo> StackTransaction<'repo> {
         Ok(incidental)
     }
 
+    /// Determine whether checking-out the tree would overwrite untracked files.
+    ///
+    /// Files added relative to the currently checked-out tree that already exist in the
+    /// worktree are untracked.
+    fn overwrites_untracked(&self, tree_id: gix::ObjectId) -> Result<bool> {
+        let repo = self.stack.repo;
+        let Some(work_dir) = repo.workdir() else {
+            return Ok(false);
+        };
+        for (status, path) in repo
+            .stupid()
+            .diff_tree_name_status(self.current_tree_id, tree_id)?
+        {
+            if status == "A" && work_dir.join(path.to_path()?).symlink_metadata().is_ok() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     /// Push unapplied patches to become applied.
     ///
     /// Pushing a patch may result in a merge conflict. When this occurs, a
@@ -1047,8 +1199,30 @@ impl<'repo> StackTransaction<'repo> {
                 None
             };
 
+            // When the patch does not apply, attempt an in-memory merge before falling
+            // back to merging in the worktree, which also reports any conflicts. The
+            // worktree merge is also used when the merged tree would overwrite untracked
+            // files such that they are reported as with any other merge.
+            let maybe_merged_tree_id = if maybe_tree_id.is_none() {
+                match repo.merge_trees_clean(base, ours, theirs)? {
+                    Some(tree_id)
+                        if self.options.use_index_and_worktree
+                            && !self.options.dry_run
+                            && self.overwrites_untracked(tree_id)? =>
+                    {
+                        None
+                    }
+                    maybe_tree_id => maybe_tree_id,
+                }
+            } else {
+                None
+            };
+
             if let Some(tree_id) = maybe_tree_id {
                 tree_id
+            } else if let Some(tree_id) = maybe_merged_tree_id {
+                push_status = PushStatus::Modified;
+                tree_id
             } else if !self.options.use_index_and_worktree {
                 return Err(Error::TransactionHalt {
                     msg: format!("{patchname} does not apply cleanly"),
@@ -1068,6 +1242,11 @@ impl<'repo> StackTransaction<'repo> {
                     conflicts: false,
                 }
                 .into());
+            } else if self.options.dry_run {
+                // Merging in the worktree is not possible for a dry run. Since the
+                // in-memory merge failed, conflicts are expected.
+                push_status = PushStatus::Conflict;
+                ours
             } else {
                 if stupid
                     .read_tree_checkout(self.current_tree_id, ours)
@@ -1125,7 +1304,7 @@ impl<'repo> StackTransaction<'repo> {
                 [new_parent.id],
             )?;
             let commit = Rc::new(repo.find_commit(commit_id)?);
-            stupid.notes_copy(patch_commit.id, commit_id).ok();
+            self.copy_notes(patch_commit.id, commit_id);
             if push_status == PushStatus::Conflict {
                 // In the case of a conflict, update() will be called after the
                 // execute() performs the checkout. Setting the transaction head
@@ -1137,8 +1316,9 @@ impl<'repo> StackTransaction<'repo> {
                 push_status = PushStatus::Empty;
             }
 
+            let meta = self.get_patch(patchname).meta.clone();
             self.updated_patches
-                .insert(patchname.clone(), Some(PatchState { commit }));
+                .insert(patchname.clone(), Some(PatchState { commit, meta }));
         }
 
         if push_status == PushStatus::Conflict {
@@ -1169,6 +1349,42 @@ impl<'repo> StackTransaction<'repo> {
         }
     }
 
+    /// Halt the transaction due to merge conflicts in the index and worktree.
+    ///
+    /// As with conflicts from pushing a patch, the stack state is committed and the
+    /// conflicts are left for the user to resolve. The conflicts must be relative to the
+    /// transaction's current head.
+    pub(crate) fn halt_with_conflicts(&mut self, msg: String) -> Result<()> {
+        self.options.conflict_mode = ConflictMode::Allow;
+        Err(Error::TransactionHalt {
+            msg,
+            conflicts: true,
+        }
+        .into())
+    }
+
+    /// Copy any notes from a patch's previous commit to its new commit.
+    ///
+    /// Failure to copy is okay. The old commit may not have a note to copy. Notes are
+    /// not copied for dry runs since doing so updates the notes reference.
+    fn copy_notes(&self, old_commit_id: gix::ObjectId, new_commit_id: gix::ObjectId) {
+        if !self.options.dry_run {
+            self.stack
+                .repo
+                .stupid()
+                .notes_copy(old_commit_id, new_commit_id)
+                .ok();
+        }
+    }
+
+    /// Determine whether the transaction is a dry run.
+    ///
+    /// Transaction operations that would modify the index or worktree must instead be
+    /// simulated for dry runs.
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.options.dry_run
+    }
+
     /// Find patches that have already been merged into the stack base's tree.
     ///
     /// The diffs for each provided patchname are applied to the stack's base tree (in
@@ -9,6 +9,7 @@ pub(super) struct TransactionOptions {
     pub(super) set_head: bool,
     pub(super) allow_bad_head: bool,
     pub(super) committer_date_is_author_date: bool,
+    pub(super) dry_run: bool,
 }
 
 impl Default for TransactionOptions {
@@ -21,16 +22,19 @@ impl Default for TransactionOptions {
             set_head: true,
             allow_bad_head: false,
             committer_date_is_author_date: false,
+            dry_run: super::is_dry_run(),
         }
     }
 }
 
 /// Policies for whether a transaction may execute when conflicts emerge from the
 /// transactions operations.
+#[derive(Default)]
 pub(crate) enum ConflictMode {
     /// Transaction execution will fail if there are conflicts recorded in the index.
     ///
     /// This is the default.
+    #[default]
     Disallow,
 
     /// Transaction execution will succeed even if there are outstanding conflicts.
@@ -40,9 +44,3 @@ pub(crate) enum ConflictMode {
     /// is unchanged by the transaction.
     AllowIfSameTop,
 }
-
-impl Default for ConflictMode {
-    fn default() -> Self {
-        Self::Disallow
-    }
-}
@@ -1,12 +1,19 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
-use std::{cell::RefCell, io::Write};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    io::Write,
+};
 
 use anyhow::Result;
 use termcolor::WriteColor;
 
 use super::PushStatus;
-use crate::patch::PatchName;
+use crate::{
+    patch::PatchName,
+    stack::{PatchState, Stack, StackStateAccess},
+};
 
 /// User output for stack transactions.
 pub(super) struct TransactionUserInterface {
@@ -243,6 +250,102 @@ impl TransactionUserInterface {
         Ok(())
     }
 
+    /// Print the stack that would result from a dry run transaction.
+    ///
+    /// Patches whose commits would be new are annotated with the new commit's id.
+    /// Patches that would be deleted are listed last.
+    pub(super) fn print_dry_run(
+        &self,
+        stack: &Stack,
+        new_base: Option<&gix::Commit>,
+        applied: &[PatchName],
+        unapplied: &[PatchName],
+        hidden: &[PatchName],
+        updated_patches: &BTreeMap<PatchName, Option<PatchState>>,
+    ) -> Result<()> {
+        let mut output = self.output.borrow_mut();
+        let mut color_spec = termcolor::ColorSpec::new();
+        output.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+        writeln!(output, "Dry run, no changes made; the stack would be:")?;
+        output.reset()?;
+
+        if let Some(base) = new_base {
+            write!(output, "  base ")?;
+            color_spec.clear();
+            output.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+            writeln!(output, "{}", base.id().shorten_or_id())?;
+            output.reset()?;
+        }
+
+        let old_commit_ids: HashSet<gix::ObjectId> = stack
+            .all_patches()
+            .map(|pn| stack.get_patch(pn).commit.id)
+            .collect();
+        let new_commit_ids: HashSet<gix::ObjectId> = updated_patches
+            .values()
+            .filter_map(|maybe_patch| maybe_patch.as_ref().map(|patch| patch.commit.id))
+            .collect();
+
+        let entries = applied
+            .iter()
+            .enumerate()
+            .map(|(i, pn)| {
+                if i + 1 == applied.len() {
+                    ('>', termcolor::Color::Blue, pn)
+                } else {
+                    ('+', termcolor::Color::Green, pn)
+                }
+            })
+            .chain(
+                unapplied
+                    .iter()
+                    .map(|pn| ('-', termcolor::Color::Magenta, pn)),
+            )
+            .chain(hidden.iter().map(|pn| ('!', termcolor::Color::Red, pn)));
+
+        for (sigil, color, patchname) in entries {
+            color_spec.clear();
+            output.set_color(color_spec.set_fg(Some(color)))?;
+            write!(output, "  {sigil} ")?;
+            color_spec.clear();
+            output.set_color(color_spec.set_bold(sigil == '>').set_dimmed(sigil == '!'))?;
+            write!(output, "{patchname}")?;
+            output.reset()?;
+            let new_commit = updated_patches
+                .get(patchname)
+                .and_then(Option::as_ref)
+                .map(|patch| &patch.commit)
+                .filter(|commit| !old_commit_ids.contains(&commit.id));
+            if let Some(commit) = new_commit {
+                color_spec.clear();
+                output.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+                write!(output, " (new commit {})", commit.id().shorten_or_id())?;
+                output.reset()?;
+            }
+            writeln!(output)?;
+        }
+
+        // Patches that were renamed keep their commit and are not deleted. Patches
+        // created and deleted by the transaction were never in the stack.
+        let deleted = updated_patches.iter().filter_map(|(pn, maybe_patch)| {
+            (maybe_patch.is_none()
+                && stack.has_patch(pn)
+                && !new_commit_ids.contains(&stack.get_patch(pn).commit.id))
+            .then_some(pn)
+        });
+        for patchname in deleted {
+            color_spec.clear();
+            output.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+            write!(output, "  # ")?;
+            color_spec.clear();
+            output.set_color(color_spec.set_dimmed(true))?;
+            writeln!(output, "{patchname}")?;
+            output.reset()?;
+        }
+
+        Ok(())
+    }
+
     pub(super) fn print_updated(&self, patchname: &PatchName, applied: &[PatchName]) -> Result<()> {
         let mut output = self.output.borrow_mut();
         let (is_applied, is_top) = if let Some(pos) = applied.iter().position(|pn| pn == patchname)
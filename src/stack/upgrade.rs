@@ -2,7 +2,10 @@
 
 //! Methods for upgrading old stack state representations to the current version.
 //!
-//! The current stack state format is version 5, introduced in StGit `v1.2`.
+//! The current stack state format is version 5, introduced in StGit `v1.2`. Stack
+//! states with per-patch metadata are written as version 6, which only adds the
+//! metadata to version 5. Both versions are stored in the same way and thus do not
+//! require any upgrade.
 //!
 //! This module is capable of upgrading stack state version 4 to version 5.
 //! - Stack state version 5 was introduced in StGit `v1.2`.
@@ -20,14 +23,17 @@ use std::{
 use anyhow::{anyhow, Context, Result};
 use bstr::{ByteSlice, ByteVec};
 
-use super::serde::{RawPatchState, RawStackState};
+use super::{
+    meta::PatchMeta,
+    serde::{RawPatchState, RawStackState},
+};
 use crate::{ext::RepositoryExtended, patch::PatchName, stack::state::StackState};
 
 /// Upgrade stack state metadata to most recent version.
 pub(crate) fn stack_upgrade(repo: &gix::Repository, branch_name: &str) -> Result<()> {
     let version = get_format_version(repo, branch_name)?;
     match version {
-        5 => Ok(()),
+        5 => Ok(()), // or version 6
         4 => stack_upgrade_from_4(repo, branch_name),
         3 => stack_upgrade_from_3(repo, branch_name),
         2 => stack_upgrade_from_2(repo, branch_name),
@@ -137,7 +143,13 @@ fn stack_upgrade_from_4(repo: &gix::Repository, branch_name: &str) -> Result<()>
                                     format!("converting `{oid_str}` for `{patchname}`")
                                 })?;
                             patch_list.push(patchname.clone());
-                            patches.insert(patchname, RawPatchState { oid: commit_id });
+                            patches.insert(
+                                patchname,
+                                RawPatchState {
+                                    oid: commit_id,
+                                    meta: PatchMeta::default(),
+                                },
+                            );
                         }
                     } else {
                         return Err(anyhow!("malformed metadata"));
@@ -253,7 +265,13 @@ fn stack_upgrade_from_3(repo: &gix::Repository, branch_name: &str) -> Result<()>
                     .with_context(|| format!("converting `{}` to patchname", &pn))?;
                 patch_list.push(patchname.clone());
                 cleanup.push(format!("refs/patches/{branch_name}/{pn}.log"));
-                patches.insert(patchname, RawPatchState { oid: commit_id });
+                patches.insert(
+                    patchname,
+                    RawPatchState {
+                        oid: commit_id,
+                        meta: PatchMeta::default(),
+                    },
+                );
             }
         }
     }
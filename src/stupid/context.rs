@@ -17,7 +17,8 @@ use bstr::{BStr, BString, ByteSlice, ByteVec};
 
 use super::{
     command::{git_command_error, StupidCommand, StupidExitStatus, StupidOutput},
-    diff::DiffFiles,
+    diff::{parse_hunks, DiffFiles, FileHunks},
+    native,
     oid::parse_oid,
     status::{StatusOptions, Statuses},
     tempindex::TempIndex,
@@ -27,6 +28,8 @@ use super::{
 /// Context for running stupid commands.
 #[derive(Clone, Debug, Default)]
 pub(crate) struct StupidContext<'repo, 'index> {
+    /// Repository used for native queries, or `None` to run `git` for all queries.
+    pub(super) repo: Option<&'repo gix::Repository>,
     pub(super) git_dir: Option<&'repo Path>,
     pub(super) work_dir: Option<&'repo Path>,
     pub(super) index_filename: Option<&'index Path>,
@@ -46,6 +49,7 @@ impl StupidContext<'_, '_> {
             .expect("git_dir required to use with_temp_index");
         let temp_index = TempIndex::new(git_dir)?;
         let stupid_temp = StupidContext {
+            repo: self.repo,
             git_dir: self.git_dir,
             work_dir: self.work_dir,
             index_filename: Some(temp_index.filename()),
@@ -111,11 +115,6 @@ impl StupidContext<'_, '_> {
 }
 
 impl StupidContext<'_, '_> {
-    /// Create a new git command (low-level).
-    pub(crate) fn git_cmd(&self) -> Command {
-        self.git()
-    }
-
     /// Apply a patch (diff) to the specified index using `git apply --cached`.
     pub(crate) fn apply_to_index(&self, diff: &BStr) -> Result<()> {
         self.git_in_work_root()?
@@ -126,6 +125,19 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Apply a diff without context lines to the specified index.
+    ///
+    /// Uses `git apply --cached --unidiff-zero`, which is required for diffs generated
+    /// with `-U0`.
+    pub(crate) fn apply_zero_context_to_index(&self, diff: &BStr) -> Result<()> {
+        self.git_in_work_root()?
+            .args(["apply", "--cached", "--unidiff-zero"])
+            .stdout(Stdio::null())
+            .in_and_out(diff)?
+            .require_success("apply")?;
+        Ok(())
+    }
+
     /// Apply a patch (diff) to both the index and the working tree.
     ///
     /// Returns `None` if the patch applies cleanly or output of the `git apply`
@@ -322,6 +334,56 @@ impl StupidContext<'_, '_> {
         }
     }
 
+    /// Determine which commit last modified each line of a file.
+    ///
+    /// Lines are blamed on commits in the range `base..head`; lines that are older
+    /// than `base` are blamed on boundary commits. The returned vector is indexed by
+    /// line number minus one.
+    pub(crate) fn blame(
+        &self,
+        base: gix::ObjectId,
+        head: gix::ObjectId,
+        path: &BStr,
+    ) -> Result<Vec<gix::ObjectId>> {
+        let output = self
+            .git_in_work_root()?
+            .args(["blame", "--porcelain"])
+            .arg(format!("{base}..{head}"))
+            .arg("--")
+            .arg(
+                path.to_os_str()
+                    .context("converting path for `git blame`")?,
+            )
+            .output_git()?
+            .require_success("blame")?;
+
+        let mut line_commits = Vec::new();
+        for line in output.stdout.lines() {
+            if line.starts_with(b"\t") {
+                continue;
+            }
+            let mut fields = line.fields();
+            let (Some(hex), Some(_), Some(final_line)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(commit_id) = gix::ObjectId::from_hex(hex) else {
+                continue;
+            };
+            let final_line: usize = final_line
+                .to_str()
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| anyhow!("invalid `git blame` output"))?;
+            if line_commits.len() < final_line {
+                line_commits.resize(final_line, commit_id);
+            }
+            line_commits[final_line - 1] = commit_id;
+        }
+        Ok(line_commits)
+    }
+
     /// Copy branch
     ///
     /// Copies branch ref, reflog, and `branch.<name>` config sections.
@@ -503,6 +565,27 @@ impl StupidContext<'_, '_> {
         Ok(BString::from(output.stdout))
     }
 
+    /// Diff tree with working tree without any context lines.
+    ///
+    /// The diff is suitable for [`StupidContext::apply_zero_context_to_index()`].
+    pub(crate) fn diff_index_zero_context(&self, tree_id: gix::ObjectId) -> Result<BString> {
+        let output = self
+            .git()
+            .args([
+                "diff-index",
+                "-p",
+                "--unified=0",
+                "--full-index",
+                "--no-renames",
+                "--no-ext-diff",
+                "--no-color",
+            ])
+            .arg(tree_id.to_string())
+            .output_git()?
+            .require_success("diff-index")?;
+        Ok(BString::from(output.stdout))
+    }
+
     /// Get file names that differ between tree and index.
     pub(crate) fn diff_index_names(
         &self,
@@ -545,12 +628,52 @@ impl StupidContext<'_, '_> {
         Ok(!no_diff)
     }
 
+    /// Diff two arbitrary blobs of content using `git diff --no-index`.
+    ///
+    /// The content is written to temporary files which appear in the diff output as
+    /// `a/<old_label>` and `b/<new_label>`. An empty result means the contents are
+    /// identical.
+    pub(crate) fn diff_no_index(
+        &self,
+        old: (&str, &[u8]),
+        new: (&str, &[u8]),
+        use_color: bool,
+    ) -> Result<BString> {
+        let dir = tempfile::tempdir()?;
+        let old_path = Path::new("a").join(old.0);
+        let new_path = Path::new("b").join(new.0);
+        for (path, content) in [(&old_path, old.1), (&new_path, new.1)] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().expect("path has a parent"))?;
+            std::fs::write(path, content)?;
+        }
+        let mut command = Command::new("git");
+        command
+            .current_dir(dir.path())
+            .args(["diff", "--no-index", "--no-prefix", "--no-ext-diff"])
+            .arg(if use_color {
+                "--color=always"
+            } else {
+                "--color=never"
+            })
+            .arg("--")
+            .args([old_path, new_path]);
+        // `git diff --no-index` exits with 1 when the files differ.
+        let output = command
+            .output_git()?
+            .require_code_less_than("diff --no-index", 2)?;
+        Ok(BString::from(output.stdout))
+    }
+
     /// Get names of files that differ between two trees.
     pub(crate) fn diff_tree_files(
         &self,
         tree1: gix::ObjectId,
         tree2: gix::ObjectId,
     ) -> Result<DiffFiles> {
+        if let Some(repo) = self.repo {
+            return native::diff_tree_files(repo, tree1, tree2).map(DiffFiles::new);
+        }
         self.git()
             .args(["diff-tree", "-r", "--name-only", "-z"])
             .args([tree1.to_string(), tree2.to_string()])
@@ -559,6 +682,53 @@ impl StupidContext<'_, '_> {
             .map(|output| DiffFiles::new(output.stdout))
     }
 
+    /// Get status letter and path of each file that differs between two trees.
+    ///
+    /// Rename detection is disabled, so each path appears with a single status, e.g.
+    /// 'A', 'M', or 'D'.
+    pub(crate) fn diff_tree_name_status(
+        &self,
+        tree1: gix::ObjectId,
+        tree2: gix::ObjectId,
+    ) -> Result<Vec<(BString, BString)>> {
+        if let Some(repo) = self.repo {
+            return Ok(native::diff_tree_name_status(repo, tree1, tree2)?
+                .into_iter()
+                .map(|(status, path)| (BString::from([status]), path))
+                .collect());
+        }
+        let output = self
+            .git()
+            .args(["diff-tree", "-r", "--name-status", "--no-renames", "-z"])
+            .args([tree1.to_string(), tree2.to_string()])
+            .output_git()?
+            .require_success("diff-tree")?;
+        let mut fields = output.stdout.split_str(b"\0").filter(|f| !f.is_empty());
+        let mut files = Vec::new();
+        while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+            files.push((BString::from(status), BString::from(path)));
+        }
+        Ok(files)
+    }
+
+    /// Get a summary of the differences between two trees.
+    ///
+    /// E.g. "1 file changed, 2 insertions(+), 1 deletion(-)". The summary is empty if
+    /// the trees do not differ.
+    pub(crate) fn diff_tree_shortstat(
+        &self,
+        tree1: gix::ObjectId,
+        tree2: gix::ObjectId,
+    ) -> Result<String> {
+        let output = self
+            .git()
+            .args(["diff-tree", "-r", "--shortstat", "--no-renames"])
+            .args([tree1.to_string(), tree2.to_string()])
+            .output_git()?
+            .require_success("diff-tree --shortstat")?;
+        Ok(output.stdout.to_str_lossy().trim().to_string())
+    }
+
     /// Interactive diff-tree (for 'stg files').
     pub(crate) fn diff_tree_files_status(
         &self,
@@ -568,6 +738,10 @@ impl StupidContext<'_, '_> {
         name_only: bool,
         use_color: bool,
     ) -> Result<BString> {
+        // Only the `--stat` output is colored by git.
+        if let (Some(repo), false) = (self.repo, stat) {
+            return native::diff_tree_files_status(repo, tree1, tree2, name_only);
+        }
         let mut command = self.git();
         command.args(["diff-tree", "-r"]);
         if stat {
@@ -587,6 +761,32 @@ impl StupidContext<'_, '_> {
         Ok(BString::from(output.stdout))
     }
 
+    /// Get zero-context hunks for the differences between two trees.
+    ///
+    /// Rename detection is disabled such that each file's hunks are reported against
+    /// the same path in both trees.
+    pub(crate) fn diff_tree_hunks(
+        &self,
+        tree1: gix::ObjectId,
+        tree2: gix::ObjectId,
+    ) -> Result<Vec<FileHunks>> {
+        let output = self
+            .git()
+            .args([
+                "diff-tree",
+                "-p",
+                "-r",
+                "-U0",
+                "--no-renames",
+                "--no-ext-diff",
+                "--color=never",
+            ])
+            .args([tree1.to_string(), tree2.to_string()])
+            .output_git()?
+            .require_success("diff-tree")?;
+        Ok(parse_hunks(&output.stdout))
+    }
+
     /// Generate diff between two trees using `git diff-tree -p`.
     pub(crate) fn diff_tree_patch<SpecIter, SpecArg, OptIter, OptArg>(
         &self,
@@ -819,12 +1019,16 @@ impl StupidContext<'_, '_> {
         id0: gix::ObjectId,
         id1: gix::ObjectId,
     ) -> Result<Vec<gix::ObjectId>> {
+        if let Some(repo) = self.repo {
+            return native::merge_bases(repo, id0, id1);
+        }
+        // `git merge-base` exits with 1 when there are no merge bases.
         let output = self
             .git()
             .args(["merge-base", "--all"])
             .args([id0.to_string(), id1.to_string()])
             .output_git()?
-            .require_success("merge-base --all")?;
+            .require_code_less_than("merge-base --all", 2)?;
         let mut oids: Vec<gix::ObjectId> = Vec::new();
         for line in output
             .stdout
@@ -884,6 +1088,35 @@ impl StupidContext<'_, '_> {
         }
     }
 
+    /// Perform three-way merge of two commits with `git merge-tree --write-tree`.
+    ///
+    /// Unlike [`merge_recursive()`][Self::merge_recursive], neither the index nor the
+    /// worktree are used. Returns the merged tree id, or `None` if the merge results in
+    /// conflicts or if the git version does not support `git merge-tree --write-tree`.
+    pub(crate) fn merge_tree(
+        &self,
+        our_commit_id: gix::ObjectId,
+        their_commit_id: gix::ObjectId,
+    ) -> Result<Option<gix::ObjectId>> {
+        if !self.at_least_version(&StupidVersion::new(2, 38, 0))? {
+            return Ok(None);
+        }
+        let output = self
+            .git()
+            .args(["merge-tree", "--write-tree", "--no-messages"])
+            .arg(our_commit_id.to_string())
+            .arg(their_commit_id.to_string())
+            .output_git()?;
+
+        if output.status.success() {
+            Ok(Some(parse_oid(&output.stdout)?))
+        } else if output.status.code() == Some(1) {
+            Ok(None)
+        } else {
+            Err(git_command_error("merge-tree", &output.stderr))
+        }
+    }
+
     /// Attempt to resolve outstanding merge conflicts with `git merge-tool`.
     pub(crate) fn mergetool(&self) -> Result<bool> {
         let output = self.git().arg("merge-tool").output_git()?;
@@ -961,6 +1194,38 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Reset the index entries of the given paths to their state in a commit.
+    ///
+    /// Paths must be relative to the repository root. The working tree is not modified.
+    pub(crate) fn reset_index_paths<SpecIter, SpecArg>(
+        &self,
+        commit_id: gix::ObjectId,
+        paths: SpecIter,
+    ) -> Result<()>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<BStr>,
+    {
+        let mut pathspec_input = BString::from(Vec::new());
+        for path in paths {
+            pathspec_input.push_str(path.as_ref());
+            pathspec_input.push(0);
+        }
+        self.git_in_work_root()?
+            .args([
+                "--literal-pathspecs",
+                "reset",
+                "-q",
+                "--pathspec-from-file=-",
+                "--pathspec-file-nul",
+            ])
+            .arg(commit_id.to_string())
+            .stdout(Stdio::null())
+            .in_and_out(pathspec_input.as_ref())?
+            .require_success("reset")?;
+        Ok(())
+    }
+
     /// Get list of revisions using `git rev-list`.
     pub(crate) fn rev_list<SpecIter, SpecArg>(
         &self,
@@ -972,13 +1237,16 @@ impl StupidContext<'_, '_> {
         SpecIter: IntoIterator<Item = SpecArg>,
         SpecArg: AsRef<OsStr>,
     {
+        let mut pathspecs = pathspecs.into_iter().flatten().peekable();
+        if let (Some(repo), None) = (self.repo, pathspecs.peek()) {
+            return native::rev_list(repo, base, top);
+        }
+
         let mut command = self.git();
         command.arg("rev-list").arg(format!("{base}..{top}"));
 
         command.arg("--");
-        if let Some(pathspecs) = pathspecs {
-            command.args(pathspecs);
-        }
+        command.args(pathspecs);
 
         let output = command.output_git()?.require_success("rev-list")?;
         let mut oids: Vec<gix::ObjectId> = Vec::new();
@@ -1366,6 +1634,18 @@ impl StupidContext<'_, '_> {
         Ok(version_line)
     }
 
+    /// Add a linked worktree at `path` with `branch_name` checked out.
+    pub(crate) fn worktree_add(&self, path: &Path, branch_name: &str) -> Result<()> {
+        self.git()
+            .args(["worktree", "add"])
+            .arg(path)
+            .arg(branch_name)
+            .stdout(Stdio::null())
+            .output_git()?
+            .require_success("worktree add")?;
+        Ok(())
+    }
+
     /// Write tree object from content of specified index using `git write-tree`.
     pub(crate) fn write_tree(&self) -> Result<gix::ObjectId> {
         let output = self
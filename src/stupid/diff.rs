@@ -4,7 +4,7 @@
 
 use std::path::Path;
 
-use bstr::ByteSlice;
+use bstr::{BString, ByteSlice};
 
 /// Diff output containing only names of differing files.
 ///
@@ -52,6 +52,262 @@ impl<'a> Iterator for DiffFilesIter<'a> {
     }
 }
 
+/// Line ranges of a single hunk from a unified diff header.
+///
+/// I.e. from a `@@ -<old_start>,<old_lines> +<new_start>,<new_lines> @@` line. Line
+/// numbers are 1-based. When a side of the hunk has zero lines, its start is the line
+/// *after which* the lines of the other side are inserted or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+}
+
+/// Hunks for a single file from a unified diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FileHunks {
+    /// Path of the file. The post-image path is used unless the file is deleted.
+    pub path: BString,
+
+    /// Whether the file's diff is binary, in which case there are no hunks.
+    pub binary: bool,
+
+    /// Hunks in the order they appear in the diff.
+    pub hunks: Vec<Hunk>,
+}
+
+/// Parse hunk headers from `git diff` style unified diff output.
+///
+/// Only the file paths and hunk line ranges are extracted. The hunk content lines are
+/// skipped. The diff is expected to be generated without color and without rename
+/// detection.
+pub(crate) fn parse_hunks(diff: &[u8]) -> Vec<FileHunks> {
+    let mut files: Vec<FileHunks> = Vec::new();
+    // Remaining (old, new) content lines of the current hunk. Content lines are
+    // skipped so that, e.g., a removed line starting with "-- " is not mistaken for a
+    // file header.
+    let mut remaining = (0usize, 0usize);
+
+    for line in diff.lines() {
+        if remaining != (0, 0) {
+            match line.first() {
+                Some(b' ') => {
+                    remaining = (remaining.0.saturating_sub(1), remaining.1.saturating_sub(1))
+                }
+                Some(b'-') => remaining.0 = remaining.0.saturating_sub(1),
+                Some(b'+') => remaining.1 = remaining.1.saturating_sub(1),
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix(b"diff --git ") {
+            // Fallback path in case there are no ---/+++ lines, e.g. for mode changes
+            // or binary files. The path is assumed to be the same on both sides.
+            let rest = rest.strip_prefix(b"a/").unwrap_or(rest);
+            let path_len = rest.len().saturating_sub(3) / 2;
+            files.push(FileHunks {
+                path: unquote_path(&rest[..path_len]),
+                binary: false,
+                hunks: Vec::new(),
+            });
+        } else if let Some(file) = files.last_mut() {
+            if let Some(path) = line.strip_prefix(b"--- ") {
+                if path != b"/dev/null" {
+                    let path = unquote_path(path);
+                    file.path = path.strip_prefix(b"a/").unwrap_or(&path).into();
+                }
+            } else if let Some(path) = line.strip_prefix(b"+++ ") {
+                if path != b"/dev/null" {
+                    let path = unquote_path(path);
+                    file.path = path.strip_prefix(b"b/").unwrap_or(&path).into();
+                }
+            } else if line.starts_with(b"Binary files ") || line == b"GIT binary patch" {
+                file.binary = true;
+            } else if let Some(hunk) = parse_hunk_header(line) {
+                remaining = (hunk.old_lines, hunk.new_lines);
+                file.hunks.push(hunk);
+            }
+        }
+    }
+
+    files
+}
+
+/// Parse a `@@ -a,b +c,d @@` hunk header line.
+fn parse_hunk_header(line: &[u8]) -> Option<Hunk> {
+    let rest = line.strip_prefix(b"@@ -")?;
+    let end = rest.find(b" @@")?;
+    let (old, new) = rest[..end].to_str().ok()?.split_once(" +")?;
+
+    fn parse_range(range: &str) -> Option<(usize, usize)> {
+        if let Some((start, lines)) = range.split_once(',') {
+            Some((start.parse().ok()?, lines.parse().ok()?))
+        } else {
+            Some((range.parse().ok()?, 1))
+        }
+    }
+
+    let (old_start, old_lines) = parse_range(old)?;
+    let (new_start, new_lines) = parse_range(new)?;
+    Some(Hunk {
+        old_start,
+        old_lines,
+        new_start,
+        new_lines,
+    })
+}
+
+/// A single hunk of a unified diff, including its content lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HunkDiff {
+    pub hunk: Hunk,
+
+    /// Text following the closing `@@` of the hunk header, e.g. the function name.
+    pub section: BString,
+
+    /// Content lines with their leading ` `, `-`, `+`, or `\` and line terminators.
+    pub lines: Vec<BString>,
+}
+
+/// A single file's portion of a unified diff, including hunk content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FileDiff {
+    /// Path of the file. The post-image path is used unless the file is deleted.
+    pub path: BString,
+
+    /// The `diff --git` line and extended header lines, including any binary patch
+    /// data, with line terminators.
+    pub header: BString,
+
+    /// Hunks in the order they appear in the diff. Empty for binary files, mode
+    /// changes, and empty files.
+    pub hunks: Vec<HunkDiff>,
+}
+
+/// Split `git diff` style unified diff output into files and hunks.
+///
+/// Unlike [`parse_hunks()`], the content of each hunk is retained so that a subset of
+/// the hunks may be reassembled into a new diff. The diff is expected to be generated
+/// without color and without rename detection.
+pub(crate) fn parse_file_diffs(diff: &[u8]) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut remaining = (0usize, 0usize);
+
+    for line in diff.lines_with_terminator() {
+        if remaining != (0, 0) || line.starts_with(b"\\") {
+            if let Some(hunk) = files.last_mut().and_then(|file| file.hunks.last_mut()) {
+                match line.first() {
+                    Some(b' ') => {
+                        remaining = (remaining.0.saturating_sub(1), remaining.1.saturating_sub(1))
+                    }
+                    Some(b'-') => remaining.0 = remaining.0.saturating_sub(1),
+                    Some(b'+') => remaining.1 = remaining.1.saturating_sub(1),
+                    _ => {}
+                }
+                hunk.lines.push(line.into());
+                continue;
+            }
+        }
+
+        if let Some(rest) = line.strip_prefix(b"diff --git ") {
+            let rest = rest.trim_end_with(|c| c == '\n');
+            let rest = rest.strip_prefix(b"a/").unwrap_or(rest);
+            let path_len = rest.len().saturating_sub(3) / 2;
+            files.push(FileDiff {
+                path: unquote_path(&rest[..path_len]),
+                header: line.into(),
+                hunks: Vec::new(),
+            });
+        } else if let Some(file) = files.last_mut() {
+            let trimmed = line.trim_end_with(|c| c == '\n');
+            if let Some(hunk) = parse_hunk_header(trimmed) {
+                let end = trimmed[4..].find(b" @@").expect("valid hunk header") + 4 + 3;
+                remaining = (hunk.old_lines, hunk.new_lines);
+                file.hunks.push(HunkDiff {
+                    hunk,
+                    section: trimmed[end..].into(),
+                    lines: Vec::new(),
+                });
+                continue;
+            } else if let Some(path) = trimmed.strip_prefix(b"--- ") {
+                if path != b"/dev/null" {
+                    let path = unquote_path(path);
+                    file.path = path.strip_prefix(b"a/").unwrap_or(&path).into();
+                }
+            } else if let Some(path) = trimmed.strip_prefix(b"+++ ") {
+                if path != b"/dev/null" {
+                    let path = unquote_path(path);
+                    file.path = path.strip_prefix(b"b/").unwrap_or(&path).into();
+                }
+            }
+            file.header.extend_from_slice(line);
+        }
+    }
+
+    files
+}
+
+/// Strip volatile details from a unified diff so that two diffs may be compared.
+///
+/// Blob ids on `index` lines and the line numbers in `@@` hunk headers change whenever
+/// unrelated parts of a file change. Removing them leaves only the differences that
+/// matter when comparing two versions of a patch, i.e. in an interdiff.
+pub(crate) fn normalize_for_interdiff(diff: &[u8]) -> BString {
+    let mut normalized = BString::from(Vec::with_capacity(diff.len()));
+    for line in diff.lines_with_terminator() {
+        if line.starts_with(b"index ") {
+            continue;
+        } else if let Some(rest) = line.strip_prefix(b"@@ -") {
+            if let Some(end) = rest.find(b" @@") {
+                normalized.extend_from_slice(b"@@");
+                normalized.extend_from_slice(&rest[end + 3..]);
+                continue;
+            }
+        }
+        normalized.extend_from_slice(line);
+    }
+    normalized
+}
+
+/// Remove C-style quoting that git applies to paths with unusual characters.
+fn unquote_path(path: &[u8]) -> BString {
+    let Some(inner) = path
+        .strip_prefix(b"\"")
+        .and_then(|path| path.strip_suffix(b"\""))
+    else {
+        return path.into();
+    };
+
+    let mut unquoted = BString::from(Vec::with_capacity(inner.len()));
+    let mut iter = inner.iter().copied().peekable();
+    while let Some(b) = iter.next() {
+        if b != b'\\' {
+            unquoted.push(b);
+            continue;
+        }
+        match iter.next() {
+            Some(b'n') => unquoted.push(b'\n'),
+            Some(b't') => unquoted.push(b'\t'),
+            Some(d0 @ b'0'..=b'7') => {
+                let mut value = u32::from(d0 - b'0');
+                for _ in 0..2 {
+                    if let Some(d @ b'0'..=b'7') = iter.peek().copied() {
+                        value = value * 8 + u32::from(d - b'0');
+                        iter.next();
+                    }
+                }
+                unquoted.push(value as u8);
+            }
+            Some(other) => unquoted.push(other),
+            None => unquoted.push(b'\\'),
+        }
+    }
+    unquoted
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,4 +321,127 @@ mod tests {
         assert_eq!(it.next(), Some(Path::new("jkl")));
         assert!(it.next().is_none());
     }
+
+    #[test]
+    fn hunk_parsing() {
+        let diff = b"diff --git a/foo.txt b/foo.txt\n\
+                     index 1111111..2222222 100644\n\
+                     --- a/foo.txt\n\
+                     +++ b/foo.txt\n\
+                     @@ -3 +3 @@ context\n\
+                     -old\n\
+                     +new\n\
+                     @@ -10,0 +11,2 @@\n\
+                     +added\n\
+                     +added\n\
+                     diff --git a/gone.txt b/gone.txt\n\
+                     deleted file mode 100644\n\
+                     --- a/gone.txt\n\
+                     +++ /dev/null\n\
+                     @@ -1,2 +0,0 @@\n\
+                     -a\n\
+                     -b\n\
+                     diff --git a/img.png b/img.png\n\
+                     Binary files a/img.png and b/img.png differ\n";
+        let files = parse_hunks(diff);
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].path, "foo.txt");
+        assert_eq!(
+            files[0].hunks,
+            vec![
+                Hunk {
+                    old_start: 3,
+                    old_lines: 1,
+                    new_start: 3,
+                    new_lines: 1
+                },
+                Hunk {
+                    old_start: 10,
+                    old_lines: 0,
+                    new_start: 11,
+                    new_lines: 2
+                },
+            ]
+        );
+        assert_eq!(files[1].path, "gone.txt");
+        assert_eq!(files[1].hunks[0].new_lines, 0);
+        assert_eq!(files[2].path, "img.png");
+        assert!(files[2].binary);
+        assert!(files[2].hunks.is_empty());
+    }
+
+    #[test]
+    fn file_diff_parsing() {
+        let diff = b"diff --git a/foo.txt b/foo.txt\n\
+                     index 1111111..2222222 100644\n\
+                     --- a/foo.txt\n\
+                     +++ b/foo.txt\n\
+                     @@ -1,2 +1,2 @@ fn main() {\n\
+                     \x20a\n\
+                     -b\n\
+                     +c\n\
+                     @@ -9 +9,2 @@\n\
+                     -x\n\
+                     \\ No newline at end of file\n\
+                     +y\n\
+                     +z\n\
+                     diff --git a/img.png b/img.png\n\
+                     index 3333333..4444444 100644\n\
+                     GIT binary patch\n\
+                     literal 1\n\
+                     Hc$@<O00001\n\
+                     \n";
+        let files = parse_file_diffs(diff);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "foo.txt");
+        assert_eq!(
+            files[0].header,
+            "diff --git a/foo.txt b/foo.txt\n\
+             index 1111111..2222222 100644\n\
+             --- a/foo.txt\n\
+             +++ b/foo.txt\n"
+        );
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(files[0].hunks[0].section, " fn main() {");
+        assert_eq!(files[0].hunks[0].lines, vec![" a\n", "-b\n", "+c\n"]);
+        assert_eq!(files[0].hunks[1].hunk.new_lines, 2);
+        assert_eq!(files[0].hunks[1].lines.len(), 4);
+        assert_eq!(files[1].path, "img.png");
+        assert!(files[1].hunks.is_empty());
+        assert!(files[1].header.ends_with(b"Hc$@<O00001\n\n"));
+    }
+
+    #[test]
+    fn interdiff_normalization() {
+        let diff = b"diff --git a/f b/f\n\
+                     index 1234567..89abcde 100644\n\
+                     --- a/f\n\
+                     +++ b/f\n\
+                     @@ -10,2 +10,3 @@ fn main() {\n\
+                     \x20a\n\
+                     -index 0\n\
+                     +b\n\
+                     @@ -1 +1 @@\n\
+                     -c\n\
+                     +d\n";
+        assert_eq!(
+            normalize_for_interdiff(diff),
+            "diff --git a/f b/f\n\
+             --- a/f\n\
+             +++ b/f\n\
+             @@ fn main() {\n\
+             \x20a\n\
+             -index 0\n\
+             +b\n\
+             @@\n\
+             -c\n\
+             +d\n"
+        );
+    }
+
+    #[test]
+    fn quoted_path() {
+        assert_eq!(unquote_path(b"\"a\\tb\\303\\251\""), "a\tb\u{e9}");
+        assert_eq!(unquote_path(b"plain"), "plain");
+    }
 }
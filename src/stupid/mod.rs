@@ -5,10 +5,14 @@
 //! Each function in this module calls-out to a specific git command that is useful to
 //! StGit. This module originally existed to overcome limitations of `libgit2`, but
 //! remains until `gitoxide` can replace its behaviors.
+//!
+//! Some read-only queries have native gitoxide implementations which are used unless
+//! the `stgit.query-backend` configuration variable is set to `subprocess`.
 
 mod command;
 mod context;
 mod diff;
+mod native;
 mod oid;
 mod status;
 mod tempindex;
@@ -18,6 +22,7 @@ use std::cell::RefCell;
 
 pub(crate) use self::{
     context::StupidContext,
+    diff::{normalize_for_interdiff, parse_file_diffs, FileDiff, FileHunks, Hunk, HunkDiff},
     status::{Status, StatusOptions, Statuses},
 };
 
@@ -28,7 +33,12 @@ pub(crate) trait Stupid<'repo, 'index> {
 
 impl<'repo, 'index> Stupid<'repo, 'index> for gix::Repository {
     fn stupid(&'repo self) -> StupidContext<'repo, 'index> {
+        let use_subprocess = self
+            .config_snapshot()
+            .string("stgit.query-backend")
+            .is_some_and(|backend| backend.as_ref() == "subprocess");
         StupidContext {
+            repo: (!use_subprocess).then_some(self),
             git_dir: Some(self.git_dir()),
             work_dir: self.workdir(),
             index_filename: None,
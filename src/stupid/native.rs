@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Native implementations of read-only stupid queries using gitoxide.
+//!
+//! Each function here produces exactly the output of the `git` command it replaces
+//! such that [`StupidContext`][super::StupidContext] may use either implementation
+//! interchangeably. Queries that depend on the state of the index or worktree are not
+//! implemented here and always run `git`.
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use bstr::{BString, ByteVec};
+
+/// Get status letter and path of each file that differs between two trees.
+///
+/// Equivalent to `git diff-tree -r --name-status --no-renames`. Paths are sorted in
+/// the same order as output by `git`.
+pub(super) fn diff_tree_name_status(
+    repo: &gix::Repository,
+    tree1: gix::ObjectId,
+    tree2: gix::ObjectId,
+) -> Result<Vec<(u8, BString)>> {
+    use gix::diff::tree::recorder::Change;
+
+    let tree1 = repo.find_tree(tree1)?;
+    let tree2 = repo.find_tree(tree2)?;
+    let mut recorder = gix::diff::tree::Recorder::default();
+    gix::diff::tree(
+        gix::objs::TreeRefIter::from_bytes(&tree1.data),
+        gix::objs::TreeRefIter::from_bytes(&tree2.data),
+        &mut gix::diff::tree::State::default(),
+        &repo.objects,
+        &mut recorder,
+    )?;
+
+    let mut files: Vec<(u8, BString)> = recorder
+        .records
+        .into_iter()
+        .filter_map(|change| match change {
+            Change::Addition {
+                entry_mode, path, ..
+            } => (!entry_mode.is_tree()).then_some((b'A', path)),
+            Change::Deletion {
+                entry_mode, path, ..
+            } => (!entry_mode.is_tree()).then_some((b'D', path)),
+            Change::Modification {
+                previous_entry_mode,
+                entry_mode,
+                path,
+                ..
+            } => {
+                if entry_mode.is_tree() {
+                    None
+                } else if previous_entry_mode.kind() == entry_mode.kind()
+                    || (previous_entry_mode.is_blob() && entry_mode.is_blob())
+                {
+                    Some((b'M', path))
+                } else {
+                    Some((b'T', path))
+                }
+            }
+        })
+        .collect();
+
+    // The recorder visits trees breadth-first whereas git visits them depth-first.
+    // Sorting full paths bytewise yields git's order because a subtree's name sorts
+    // as if it ends with '/'.
+    files.sort_by(|(_, a), (_, b)| a.cmp(b));
+    Ok(files)
+}
+
+/// Get names of files that differ between two trees.
+///
+/// Equivalent to the output of `git diff-tree -r --name-only -z`.
+pub(super) fn diff_tree_files(
+    repo: &gix::Repository,
+    tree1: gix::ObjectId,
+    tree2: gix::ObjectId,
+) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    for (_, path) in diff_tree_name_status(repo, tree1, tree2)? {
+        output.push_str(path);
+        output.push(b'\0');
+    }
+    Ok(output)
+}
+
+/// Format the differences between two trees as `git diff-tree -r` would.
+///
+/// Only `--name-only` and `--name-status` formats are supported. Paths are quoted
+/// according to `core.quotePath`.
+pub(super) fn diff_tree_files_status(
+    repo: &gix::Repository,
+    tree1: gix::ObjectId,
+    tree2: gix::ObjectId,
+    name_only: bool,
+) -> Result<BString> {
+    let quote_fully = repo
+        .config_snapshot()
+        .boolean("core.quotePath")
+        .unwrap_or(true);
+    let mut output = BString::default();
+    for (status, path) in diff_tree_name_status(repo, tree1, tree2)? {
+        if !name_only {
+            output.push(status);
+            output.push(b'\t');
+        }
+        quote_c_style(&path, quote_fully, &mut output);
+        output.push(b'\n');
+    }
+    Ok(output)
+}
+
+/// Quote path in the manner of git's `quote_c_style()`.
+///
+/// The path is only quoted if it contains control characters, a double-quote, or a
+/// backslash. When `quote_fully` is true, bytes above 0x7f also cause quoting and are
+/// escaped as octal.
+fn quote_c_style(path: &[u8], quote_fully: bool, output: &mut BString) {
+    let must_quote =
+        |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b == 0x7f || (b >= 0x80 && quote_fully);
+    if !path.iter().copied().any(must_quote) {
+        output.push_str(path);
+        return;
+    }
+    output.push(b'"');
+    for &b in path {
+        if !must_quote(b) {
+            output.push(b);
+            continue;
+        }
+        output.push(b'\\');
+        match b {
+            0x07 => output.push(b'a'),
+            0x08 => output.push(b'b'),
+            b'\t' => output.push(b't'),
+            b'\n' => output.push(b'n'),
+            0x0b => output.push(b'v'),
+            0x0c => output.push(b'f'),
+            b'\r' => output.push(b'r'),
+            b'"' | b'\\' => output.push(b),
+            _ => output.push_str(format!("{b:03o}")),
+        }
+    }
+    output.push(b'"');
+}
+
+/// Get all merge bases of two commits.
+///
+/// Equivalent to `git merge-base --all`, with the bases ordered from newest to oldest
+/// committer date.
+pub(super) fn merge_bases(
+    repo: &gix::Repository,
+    id0: gix::ObjectId,
+    id1: gix::ObjectId,
+) -> Result<Vec<gix::ObjectId>> {
+    let cache = repo.commit_graph_if_enabled()?;
+    let mut graph = repo.revision_graph(cache.as_ref());
+    let mut bases = Vec::new();
+    for id in repo.merge_bases_many_with_graph(id0, &[id1], &mut graph)? {
+        bases.push((commit_date(repo, id.detach())?, id.detach()));
+    }
+    // Like git, order by date with ties remaining in the order found.
+    bases.sort_by(|(date_a, _), (date_b, _)| date_b.cmp(date_a));
+    Ok(bases.into_iter().map(|(_, id)| id).collect())
+}
+
+fn commit_date(repo: &gix::Repository, id: gix::ObjectId) -> Result<i64> {
+    Ok(repo.find_commit(id)?.committer()?.time.seconds)
+}
+
+/// Number of additional commits to walk after the walk appears to be complete.
+///
+/// This allows some tolerance for clock skew. The value matches git's `SLOP`.
+const SLOP: usize = 5;
+
+const SEEN: u8 = 1 << 0;
+const UNINTERESTING: u8 = 1 << 1;
+const ADDED: u8 = 1 << 2;
+
+#[derive(Default)]
+struct WalkCommit {
+    date: i64,
+    parents: Option<Vec<gix::ObjectId>>,
+    flags: u8,
+}
+
+/// Revision walk that is a faithful port of git's `limit_list()`.
+///
+/// Porting git's algorithm, as opposed to using [`gix::revision::Walk`], guarantees
+/// that the same commits are output in the same order as `git rev-list` even when
+/// commits have identical committer dates.
+struct RevWalk<'repo> {
+    repo: &'repo gix::Repository,
+    commits: HashMap<gix::ObjectId, WalkCommit>,
+}
+
+impl RevWalk<'_> {
+    fn flags(&self, id: &gix::ObjectId) -> u8 {
+        self.commits.get(id).map_or(0, |commit| commit.flags)
+    }
+
+    fn set_flags(&mut self, id: gix::ObjectId, flags: u8) {
+        self.commits.entry(id).or_default().flags |= flags;
+    }
+
+    fn date(&self, id: &gix::ObjectId) -> i64 {
+        self.commits.get(id).map_or(0, |commit| commit.date)
+    }
+
+    fn parents(&self, id: &gix::ObjectId) -> Option<Vec<gix::ObjectId>> {
+        self.commits
+            .get(id)
+            .and_then(|commit| commit.parents.clone())
+    }
+
+    fn parse(&mut self, id: gix::ObjectId) -> Result<()> {
+        if self.parents(&id).is_none() {
+            let commit = self.repo.find_commit(id)?;
+            let date = commit.committer()?.time.seconds;
+            let parents = commit.parent_ids().map(|id| id.detach()).collect();
+            let entry = self.commits.entry(id).or_default();
+            entry.date = date;
+            entry.parents = Some(parents);
+        }
+        Ok(())
+    }
+
+    fn insert_by_date(&self, list: &mut VecDeque<gix::ObjectId>, id: gix::ObjectId) {
+        let date = self.date(&id);
+        let pos = list
+            .iter()
+            .position(|other| self.date(other) < date)
+            .unwrap_or(list.len());
+        list.insert(pos, id);
+    }
+
+    fn mark_parents_uninteresting(&mut self, id: &gix::ObjectId) {
+        let mut pending = self.parents(id).unwrap_or_default();
+        pending.reverse();
+        while let Some(id) = pending.pop() {
+            if self.flags(&id) & UNINTERESTING == 0 {
+                self.set_flags(id, UNINTERESTING);
+                if let Some(parents) = self.parents(&id) {
+                    pending.extend(parents.into_iter().rev());
+                }
+            }
+        }
+    }
+
+    fn process_parents(
+        &mut self,
+        id: gix::ObjectId,
+        list: &mut VecDeque<gix::ObjectId>,
+    ) -> Result<()> {
+        if self.flags(&id) & ADDED != 0 {
+            return Ok(());
+        }
+        self.set_flags(id, ADDED);
+        let uninteresting = self.flags(&id) & UNINTERESTING != 0;
+        for parent in self.parents(&id).unwrap_or_default() {
+            if uninteresting {
+                self.set_flags(parent, UNINTERESTING);
+            }
+            self.parse(parent)?;
+            if uninteresting && self.parents(&parent).is_some_and(|p| !p.is_empty()) {
+                self.mark_parents_uninteresting(&parent);
+            }
+            if self.flags(&parent) & SEEN == 0 {
+                self.set_flags(parent, SEEN);
+                self.insert_by_date(list, parent);
+            }
+        }
+        Ok(())
+    }
+
+    fn still_interesting(&self, list: &VecDeque<gix::ObjectId>, date: i64, slop: usize) -> usize {
+        if let Some(first) = list.front() {
+            if date <= self.date(first) {
+                SLOP
+            } else if list.iter().all(|id| self.flags(id) & UNINTERESTING != 0) {
+                0
+            } else {
+                slop - 1
+            }
+        } else {
+            0
+        }
+    }
+}
+
+/// Get commits reachable from `top` but not from `base`.
+///
+/// Equivalent to `git rev-list base..top`.
+pub(super) fn rev_list(
+    repo: &gix::Repository,
+    base: gix::ObjectId,
+    top: gix::ObjectId,
+) -> Result<Vec<gix::ObjectId>> {
+    let mut walk = RevWalk {
+        repo,
+        commits: HashMap::new(),
+    };
+
+    walk.set_flags(base, UNINTERESTING);
+    walk.parse(base)?;
+    walk.mark_parents_uninteresting(&base);
+    walk.parse(top)?;
+
+    let mut starts: Vec<gix::ObjectId> = Vec::new();
+    for id in [base, top] {
+        if walk.flags(&id) & SEEN == 0 {
+            walk.set_flags(id, SEEN);
+            starts.push(id);
+        }
+    }
+    starts.sort_by_key(|id| std::cmp::Reverse(walk.date(id)));
+    let mut list = VecDeque::from(starts);
+
+    let mut output = Vec::new();
+    let mut slop = SLOP;
+    let mut date = i64::MAX;
+    while let Some(id) = list.pop_front() {
+        walk.process_parents(id, &mut list)?;
+        if walk.flags(&id) & UNINTERESTING != 0 {
+            walk.mark_parents_uninteresting(&id);
+            slop = walk.still_interesting(&list, date, slop);
+            if slop > 0 {
+                continue;
+            }
+            break;
+        }
+        date = walk.date(&id);
+        output.push(id);
+    }
+
+    output.retain(|id| walk.flags(id) & UNINTERESTING == 0);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    fn quoted(path: &str, quote_fully: bool) -> String {
+        let mut output = BString::default();
+        quote_c_style(path.as_bytes(), quote_fully, &mut output);
+        output.to_str_lossy().into_owned()
+    }
+
+    #[test]
+    fn quote_plain_path() {
+        assert_eq!(quoted("dir/file.txt", true), "dir/file.txt");
+        assert_eq!(quoted("with space", true), "with space");
+    }
+
+    #[test]
+    fn quote_special_characters() {
+        assert_eq!(quoted("tab\there", true), r#""tab\there""#);
+        assert_eq!(quoted("q\"uote", true), r#""q\"uote""#);
+        assert_eq!(quoted("back\\slash", true), r#""back\\slash""#);
+        assert_eq!(quoted("bell\x07\x1b", true), r#""bell\a\033""#);
+    }
+
+    #[test]
+    fn quote_non_ascii() {
+        assert_eq!(quoted("é", true), r#""\303\251""#);
+        assert_eq!(quoted("é", false), "é");
+        assert_eq!(quoted("é\n", false), "\"é\\n\"");
+    }
+}
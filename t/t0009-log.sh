@@ -77,11 +77,37 @@ test_expect_success 'Verify log for p0' '
     grep -e "uncommit" log.txt
 '
 
-test_expect_success 'Log with diff' '
-    stg log --diff p0 >log.txt &&
+test_expect_success 'Log with raw diff' '
+    stg log --diff --full p0 >log.txt &&
     grep -e "diff --git a/patches/p0 b/patches/p0" log.txt
 '
 
+test_expect_success 'Log with state summary' '
+    stg log --diff -n 3 >log.txt &&
+    cat >expected <<-\EOF &&
+	    modified  p3 (message)
+	    moved     p3 (unapplied -> applied)
+	    modified  p3 (rebased)
+	    deleted   refresh-temp (applied)
+	    modified  p2 (content): 1 file changed, 1 insertion(+), 1 deletion(-)
+	EOF
+    grep -e "^    " log.txt >changes &&
+    test_cmp expected changes &&
+    grep -e "^[0-9a-f]*   .*   edit: p3\$" log.txt
+'
+
+test_expect_success 'Log state summary for a patch' '
+    stg log --diff p2 >log.txt &&
+    grep -e "^    " log.txt >changes &&
+    cat >expected <<-\EOF &&
+	    modified  p2 (content): 1 file changed, 1 insertion(+), 1 deletion(-)
+	    moved     p2 (unapplied -> applied)
+	    modified  p2 (rebased)
+	    added     p2 (applied)
+	EOF
+    test_cmp expected changes
+'
+
 test_expect_success 'Verify log for p1' '
     stg log p1 >log.txt &&
     test_line_count = 3 log.txt &&
@@ -136,6 +162,22 @@ test_expect_success 'Log with number' '
     head -n 3 log.txt | tail -n 1 | grep -e "refresh"
 '
 
+test_expect_success 'Log state summary of rename, reorder, and delete' '
+    stg rename p1 r1 &&
+    stg log --diff -n 1 >log.txt &&
+    grep -e "^    renamed   p1 -> r1\$" log.txt &&
+    stg float r1 &&
+    stg log --diff -n 1 >log.txt &&
+    grep -e "^    reordered applied: p0 p2 p3 r1\$" log.txt &&
+    grep -e "^    modified  p2 (rebased)\$" log.txt &&
+    grep -e "^    modified  r1 (rebased)\$" log.txt &&
+    stg delete --top &&
+    stg log --diff -n 1 >log.txt &&
+    grep -e "^    deleted   r1 (applied)\$" log.txt &&
+    stg undo -n 3 &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3"
+'
+
 test_expect_success 'Clear the log' '
     stg log --clear &&
     test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3" &&
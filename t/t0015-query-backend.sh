@@ -0,0 +1,99 @@
+#!/bin/sh
+
+test_description='Test that native and subprocess query backends agree'
+
+. ./test-lib.sh
+
+compare_backends () {
+    git config stgit.query-backend subprocess &&
+    "$@" >expected &&
+    git config --unset stgit.query-backend &&
+    "$@" >actual &&
+    test_cmp expected actual
+}
+
+test_expect_success 'Create criss-cross history' '
+    test_commit root &&
+    git checkout -q -b other &&
+    test_commit o1 &&
+    git checkout -q master &&
+    test_commit m1 &&
+    git merge -q --no-edit o1 &&
+    git checkout -q other &&
+    git merge -q --no-edit m1 &&
+    test_commit o2 &&
+    git checkout -q master
+'
+
+test_expect_success 'Create patches with unusual paths' '
+    stg init &&
+    mkdir -p a d/e &&
+    echo x >a/c &&
+    echo x >a-b &&
+    echo x >a0 &&
+    echo x >d/e/f &&
+    echo x >"q\"t" &&
+    echo x >"tab	here" &&
+    echo x >"é x" &&
+    echo x >exe &&
+    echo x >link &&
+    git add -A &&
+    stg new -m p0 p0 &&
+    stg refresh &&
+    git rm -q -r a d/e/f &&
+    echo x >a &&
+    echo y >>a0 &&
+    echo y >>"é x" &&
+    rm link &&
+    ln -s a link &&
+    chmod +x exe &&
+    git add -A &&
+    stg new -m p1 p1 &&
+    stg refresh
+'
+
+test_expect_success 'Compare files' '
+    compare_backends stg files p0 &&
+    compare_backends stg files p1 &&
+    compare_backends stg files --bare p1 &&
+    compare_backends stg files --format=json p1 &&
+    test_config core.quotePath false &&
+    compare_backends stg files p1 &&
+    grep -e "^T link" actual &&
+    grep -e "^M exe" actual &&
+    grep -e "^A a\$" actual &&
+    grep -e "^D a/c" actual
+'
+
+test_expect_success 'Native backend does not run git diff-tree' '
+    GIT_TRACE="$(pwd)/trace" stg files p1 &&
+    ! grep -e "diff-tree" trace &&
+    git config stgit.query-backend subprocess &&
+    GIT_TRACE="$(pwd)/trace-subprocess" stg files p1 &&
+    git config --unset stgit.query-backend &&
+    grep -e "diff-tree" trace-subprocess
+'
+
+test_expect_success 'Compare commits since multiple merge bases' '
+    compare_backends stg range-diff -s o2 &&
+    compare_backends stg range-diff -s other~1 &&
+    compare_backends stg range-diff -s o1
+'
+
+test_expect_success 'Compare commits with distinct dates' '
+    git checkout -q -b dated root &&
+    test_tick &&
+    test_commit d1 &&
+    git checkout -q -b dated-side root &&
+    test_tick &&
+    test_commit d2 &&
+    git checkout -q dated &&
+    test_tick &&
+    git merge -q --no-edit dated-side &&
+    test_tick &&
+    test_commit d3 &&
+    git checkout -q master &&
+    compare_backends stg range-diff -s dated
+'
+
+test_done
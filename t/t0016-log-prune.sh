@@ -0,0 +1,127 @@
+#!/bin/sh
+
+test_description='Test pruning the stack log'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack with history' '
+    stg init &&
+    test_tick &&
+    stg new -m p0 p0 &&
+    echo a >a &&
+    stg add a &&
+    stg refresh &&
+    test_tick &&
+    stg new -m p1 p1 &&
+    test_tick &&
+    stg new -m p2 p2 &&
+    stg id p2 >p2-first &&
+    test_tick &&
+    stg edit -m p2-edited p2 &&
+    stg goto p0 &&
+    echo aa >>a &&
+    stg refresh &&
+    stg pop -a &&
+    stg log >log.txt &&
+    test_line_count = 11 log.txt
+'
+
+test_expect_success 'Attempt prune without retention' '
+    general_error stg log --prune 2>err &&
+    grep -e "the following required arguments were not provided" err
+'
+
+test_expect_success 'Attempt prune with conflicting options' '
+    general_error stg log --prune --keep 2 --older-than now 2>err &&
+    grep -e "cannot be used with" err &&
+    general_error stg log --prune --clear 2>err &&
+    grep -e "cannot be used with" err &&
+    general_error stg log --keep 2 2>err &&
+    grep -e "the following required arguments were not provided" err
+'
+
+test_expect_success 'Attempt to keep zero entries' '
+    general_error stg log --prune --keep 0 2>err &&
+    grep -e "at least one entry must be kept" err
+'
+
+test_expect_success 'Prune keeping more entries than exist' '
+    stg log >expected &&
+    stg log --prune --keep 20 &&
+    stg log >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Prune keeping newest entries' '
+    head -n 4 expected | cut -c 8- >expected-tail &&
+    stg log --prune --keep 4 &&
+    stg log >log.txt &&
+    test_line_count = 4 log.txt &&
+    cut -c 8- log.txt >actual-tail &&
+    test_cmp expected-tail actual-tail &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2"
+'
+
+test_expect_success 'Pruned history drops unreferenced patch commits' '
+    git rev-list refs/stacks/master >reachable &&
+    ! grep -e "$(cat p2-first)" reachable &&
+    grep -e "$(stg id p0)" reachable &&
+    grep -e "$(stg id p2)" reachable
+'
+
+test_expect_success 'Undo within pruned history' '
+    stg undo &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    stg undo &&
+    stg undo &&
+    command_error stg undo 2>err &&
+    grep -e "not enough undo information available" err
+'
+
+test_expect_success 'Prune entries older than date' '
+    test_tick &&
+    stg push p1 &&
+    test_tick &&
+    stg push p2 &&
+    stg log --prune --older-than "$GIT_COMMITTER_DATE" &&
+    stg log >log.txt &&
+    test_line_count = 1 log.txt &&
+    grep -e "push$" log.txt
+'
+
+test_expect_success 'Limit log with stgit.log.maxEntries' '
+    test_config stgit.log.maxEntries 3 &&
+    stg pop -a &&
+    stg push -a &&
+    stg log >log.txt &&
+    test_line_count = 3 log.txt &&
+    stg new -m p3 p3 &&
+    stg log >log.txt &&
+    test_line_count = 4 log.txt &&
+    stg new -m p4 p4 &&
+    stg log >log.txt &&
+    test_line_count = 3 log.txt &&
+    head -n 1 log.txt | grep -e "new: p4" &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3 p4"
+'
+
+test_expect_success 'Log grows by a tenth of stgit.log.maxEntries before pruning' '
+    test_config stgit.log.maxEntries 20 &&
+    for i in $(test_seq 20); do
+        stg pop && stg push || return 1
+    done &&
+    stg log >log.txt &&
+    test_line_count = 22 log.txt &&
+    stg pop &&
+    stg log >log.txt &&
+    test_line_count = 20 log.txt &&
+    head -n 1 log.txt | grep -e "pop"
+'
+
+test_expect_success 'Log is not pruned when stgit.log.maxEntries is unset' '
+    stg new -m p5 p5 &&
+    stg log >log.txt &&
+    test_line_count = 21 log.txt
+'
+
+test_done
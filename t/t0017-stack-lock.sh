@@ -0,0 +1,77 @@
+#!/bin/sh
+
+test_description='Test locking of stacks against concurrent modification'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack' '
+    test_commit_bulk --message="base%s" 1 &&
+    stg init &&
+    stg new -m p0 p0 &&
+    stg new -m p1 p1 &&
+    test_path_is_missing .git/stgit/master.lock
+'
+
+test_expect_success 'Attempt to modify locked stack' '
+    mkdir -p .git/stgit &&
+    touch .git/stgit/master.lock &&
+    test_when_finished rm -f .git/stgit/master.lock &&
+    test_config stgit.lock.timeout 0 &&
+    command_error stg pop 2>err &&
+    grep -e "stack for branch \`master\` is locked by another StGit process" err &&
+    grep -e "remove \`.*stgit/master.lock\`" err &&
+    test "$(echo $(stg series))" = "+ p0 > p1"
+'
+
+test_expect_success 'Wait for locked stack until timeout' '
+    touch .git/stgit/master.lock &&
+    test_when_finished rm -f .git/stgit/master.lock &&
+    test_config stgit.lock.timeout 200 &&
+    command_error stg new -m p2 p2 2>err &&
+    grep -e "is locked by another StGit process" err &&
+    test "$(echo $(stg series))" = "+ p0 > p1"
+'
+
+test_expect_success 'Read locked stack' '
+    touch .git/stgit/master.lock &&
+    test_when_finished rm -f .git/stgit/master.lock &&
+    test "$(echo $(stg series))" = "+ p0 > p1" &&
+    stg log -n 1
+'
+
+test_expect_success 'Stack lock is released' '
+    stg pop &&
+    stg push &&
+    stg log --clear &&
+    test_path_is_missing .git/stgit/master.lock
+'
+
+test_expect_success 'Lock is specific to the branch' '
+    stg branch --create other &&
+    touch .git/stgit/master.lock &&
+    test_when_finished rm -f .git/stgit/master.lock &&
+    stg new -m q0 q0 &&
+    test "$(echo $(stg series))" = "> q0" &&
+    stg branch master
+'
+
+test_expect_success 'Detect stack modified by another process' '
+    write_script concurrent-editor <<-\EOF &&
+	stg new -m concurrent concurrent &&
+	echo "edited" >"$1"
+	EOF
+    test_set_editor "$(pwd)/concurrent-editor" &&
+    test_when_finished test_set_editor false &&
+    command_error stg edit p0 2>err &&
+    grep -e "stack for branch \`master\` was modified by another process; retry the command" err &&
+    test "$(echo $(stg series))" = "+ p0 + p1 > concurrent" &&
+    test "$(git log -1 --format=%s $(stg id p0))" = "p0"
+'
+
+test_expect_success 'Retry after concurrent modification' '
+    stg edit -m "p0 edited" p0 &&
+    test "$(git log -1 --format=%s $(stg id p0))" = "p0 edited" &&
+    test "$(echo $(stg series))" = "+ p0 + p1 > concurrent"
+'
+
+test_done
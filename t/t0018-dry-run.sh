@@ -0,0 +1,109 @@
+#!/bin/sh
+
+test_description='Test dry runs of stack transactions'
+
+. ./test-lib.sh
+
+state_snapshot () {
+    git rev-parse refs/heads/master refs/stacks/master &&
+    git ls-files -s &&
+    cat foo.txt
+}
+
+test_expect_success 'Initialize stack' '
+    echo base >foo.txt &&
+    git add foo.txt &&
+    git commit -m base &&
+    git branch upstream &&
+    stg init &&
+    for i in 1 2 3; do
+        stg new -m p$i p$i &&
+        echo $i >>foo.txt &&
+        stg refresh || return 1
+    done &&
+    stg pop p3 &&
+    git checkout upstream &&
+    echo up >bar.txt &&
+    git add bar.txt &&
+    git commit -m up &&
+    git tag up-clean &&
+    echo conflict >foo.txt &&
+    git commit -a -m conflict &&
+    git checkout master &&
+    state_snapshot >expected-state
+'
+
+test_expect_success 'Dry run delete' '
+    stg --dry-run delete p3 >out &&
+    grep -e "^Dry run, no changes made" out &&
+    grep -e "^  # p3$" out &&
+    state_snapshot >state &&
+    test_cmp expected-state state &&
+    test "$(echo $(stg series))" = "+ p1 > p2 - p3"
+'
+
+test_expect_success 'Dry run rebase' '
+    stg --dry-run rebase up-clean >out &&
+    grep -e "^  base $(git rev-parse --short up-clean)$" out &&
+    grep -e "^  + p1 (new commit " out &&
+    grep -e "^  > p2 (new commit " out &&
+    grep -e "^  - p3$" out &&
+    state_snapshot >state &&
+    test_cmp expected-state state &&
+    test "$(stg id {base})" = "$(git rev-parse master~2)"
+'
+
+test_expect_success 'Dry run rebase with expected conflicts' '
+    conflict stg --dry-run rebase upstream >out 2>err &&
+    grep -e "p1 (conflict)" out &&
+    grep -e "dry run: merge conflicts expected with \`p1\`; no changes made" err &&
+    state_snapshot >state &&
+    test_cmp expected-state state &&
+    test -z "$(git diff-files --name-only)" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Dry run push and pop' '
+    stg --dry-run push >out &&
+    grep -e "^  > p3$" out &&
+    stg --dry-run pop -a >out &&
+    grep -e "^  - p1$" out &&
+    state_snapshot >state &&
+    test_cmp expected-state state
+'
+
+test_expect_success 'Dry run sync' '
+    stg branch --clone cloned &&
+    stg edit -m "p1 cloned" p1 &&
+    stg goto p1 &&
+    echo cloned >other.txt &&
+    stg add other.txt &&
+    stg refresh &&
+    stg branch master &&
+    stg --dry-run sync --all --ref-branch cloned >out &&
+    grep -e "^  + p1 (new commit " out &&
+    state_snapshot >state &&
+    test_cmp expected-state state &&
+    test_path_is_missing other.txt
+'
+
+test_expect_success 'Dry run of commands without stack transactions' '
+    command_error stg --dry-run series 2>err &&
+    grep -e "\`stg series\` does not support \`--dry-run\`" err &&
+    command_error stg --dry-run refresh 2>err &&
+    grep -e "\`stg refresh\` does not support \`--dry-run\`" err &&
+    command_error stg --dry-run reset --hard 2>err &&
+    grep -e "does not support \`--dry-run\`" err
+'
+
+test_expect_success 'Dry run with aliases' '
+    test_config stgit.alias.zap "delete p3" &&
+    test_config stgit.alias.shellcmd "!echo hello" &&
+    stg --dry-run zap >out &&
+    grep -e "^  # p3$" out &&
+    command_error stg --dry-run shellcmd 2>err &&
+    grep -e "shell alias \`shellcmd\` does not support \`--dry-run\`" err &&
+    test "$(echo $(stg series))" = "+ p1 > p2 - p3"
+'
+
+test_done
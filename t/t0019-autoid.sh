@@ -0,0 +1,107 @@
+#!/bin/sh
+
+test_description='Test automatically generated patch ids'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack' '
+    test_commit_bulk --message="base%s" 3 &&
+    stg init
+'
+
+test_expect_success 'New patch with default format' '
+    test_config stgit.autoid.prompt false &&
+    stg new --auto -m first &&
+    stg top | grep -E "^misc@[0-9a-z]{5}$"
+'
+
+test_expect_success 'Prefix taken from topmost patch' '
+    test_config stgit.autoid.prompt false &&
+    stg new -m feat feat@x &&
+    stg new --auto -m second &&
+    stg top | grep -E "^feat@[0-9a-z]{5}$"
+'
+
+test_expect_success 'Configured prefix, length and charset' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.prefix fix &&
+    test_config stgit.autoid.length 3 &&
+    test_config stgit.autoid.charset "AB" &&
+    stg new --auto -m third &&
+    stg top | grep -E "^fix@[AB]{3}$"
+'
+
+test_expect_success 'Sequence, date, and user placeholders' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.prefix p &&
+    test_config stgit.autoid.format "{prefix}-{user}-{date}-{seq}" &&
+    stg new --auto -m seq1 &&
+    stg new --auto -m seq2 &&
+    date=$(date +%Y%m%d) &&
+    test "$(echo $(stg series --applied --noprefix | tail -n 2))" = \
+         "p-author-$date-1 p-author-$date-2"
+'
+
+test_expect_success 'Ids are unique across branches' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.prefix q &&
+    test_config stgit.autoid.length 0 &&
+    test_config stgit.autoid.format "{prefix}{seq}" &&
+    stg new --auto -m q1 &&
+    stg branch --create other &&
+    stg new --auto -m q2 &&
+    test "$(stg top)" = "q2" &&
+    stg branch master
+'
+
+test_expect_success 'Colliding format is made unique' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.format "fixed" &&
+    stg new --auto -m fixed1 &&
+    stg new --auto -m fixed2 &&
+    test "$(echo $(stg series --applied --noprefix | tail -n 2))" = "fixed fixed-1"
+'
+
+test_expect_success 'Invalid format' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.format "{prefix}.." &&
+    command_error stg new --auto -m bad 2>err &&
+    grep -e "\`stgit.autoid.format\` produced an invalid patch name" err
+'
+
+test_expect_success 'Empty charset' '
+    test_config stgit.autoid.prompt false &&
+    test_config stgit.autoid.charset "" &&
+    command_error stg new --auto -m bad 2>err &&
+    grep -e "\`stgit.autoid.charset\` may not be empty" err
+'
+
+test_expect_success 'Uncommit with auto ids' '
+    test_config stgit.autoid.prefix u &&
+    test_config stgit.autoid.length 0 &&
+    test_config stgit.autoid.format "{prefix}{seq}" &&
+    stg pop -a &&
+    stg uncommit --auto -n 2 &&
+    test "$(echo $(stg series --applied --noprefix))" = "u1 u2"
+'
+
+test_expect_success 'Pick with auto ids' '
+    test_config stgit.autoid.prefix k &&
+    test_config stgit.autoid.length 0 &&
+    test_config stgit.autoid.format "{prefix}{seq}" &&
+    stg pick --auto --noapply other:q2 fixed fixed-1 &&
+    stg series --unapplied --noprefix >unapplied &&
+    test "$(echo $(head -n 3 unapplied))" = "k1 k2 k3"
+'
+
+test_expect_success 'Import with auto ids' '
+    test_config stgit.autoid.prefix i &&
+    test_config stgit.autoid.length 0 &&
+    test_config stgit.autoid.format "{prefix}{seq}" &&
+    stg export --dir export-dir u1 &&
+    stg pop -a &&
+    stg import --auto export-dir/u1 &&
+    test "$(stg top)" = "i1"
+'
+
+test_done
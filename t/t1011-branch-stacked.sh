@@ -0,0 +1,128 @@
+#!/bin/sh
+
+test_description='Test branches stacked on patches of other branches'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize parent stack' '
+    test_seq 1 10 >a &&
+    git add a &&
+    git commit -m "add a" &&
+    stg init &&
+    stg new -m p1 p1 &&
+    sed -e "s/^2\$/two/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    echo p2 >p2.txt &&
+    stg add p2.txt &&
+    stg refresh
+'
+
+test_expect_success 'Attempt to stack on invalid parent patch' '
+    command_error stg branch --create bad --on master 2>err &&
+    grep -e "expected \`<branch>:<patch>\`" err &&
+    command_error stg branch --create bad --on master:nope 2>err &&
+    grep -e "patch \`nope\` does not exist in branch \`master\`" err &&
+    general_error stg branch --create bad HEAD --on master:p1 2>err &&
+    grep -e "cannot be used with" err &&
+    test_must_fail git rev-parse --verify -q refs/heads/bad
+'
+
+test_expect_success 'Create branch stacked on patch' '
+    stg branch --create B --on master:p1 2>err &&
+    grep -e "Recording \`master:p1\` as parent patch" err &&
+    test "$(stg branch)" = "B" &&
+    test "$(git rev-parse HEAD)" = "$(stg id master:p1)" &&
+    test "$(git config branch.B.stgit.parentbranch)" = "master" &&
+    test "$(git config branch.B.stgit.parentpatch)" = "p1" &&
+    test_path_is_missing p2.txt &&
+    stg new -m b1 b1 &&
+    sed -e "s/^8\$/eight/" a >a.new && mv a.new a &&
+    stg refresh
+'
+
+test_expect_success 'Create branch stacked on stacked branch' '
+    stg branch --create C --on B:b1 &&
+    stg new -m c1 c1 &&
+    echo c1 >c1.txt &&
+    stg add c1.txt &&
+    stg refresh
+'
+
+test_expect_success 'List stacked branches as a tree' '
+    stg branch --list >list.txt &&
+    cat >expected <<-\EOF &&
+	  s 	master  |
+	  s 	  B     | (on p1)
+	> s 	    C   | (on b1)
+	EOF
+    test_cmp expected list.txt &&
+    stg branch --list --format=json >list.json &&
+    grep -e "\"stacked_on\": \"master:p1\"" list.json &&
+    grep -e "\"stacked_on\": \"B:b1\"" list.json
+'
+
+test_expect_success 'Restack up to date branch' '
+    stg restack 2>err &&
+    grep -e "Branch \`C\` is up to date" err
+'
+
+test_expect_success 'Restack branches after refreshing parent patch' '
+    stg branch master &&
+    stg goto p1 &&
+    sed -e "s/^4\$/four/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg restack 2>err &&
+    grep -e "Restacking \`B\` onto \`master:p1\`" err &&
+    grep -e "Restacking \`C\` onto \`B:b1\`" err &&
+    test "$(git rev-parse B~1)" = "$(stg id p1)" &&
+    test "$(git rev-parse C~1)" = "$(stg id B:b1)" &&
+    test "$(echo $(stg series -b C --applied --noprefix))" = "c1" &&
+    stg log -b B -n 1 | grep -e "restack" &&
+    test_write_lines 1 two 3 four 5 6 7 eight 9 10 >expected &&
+    git show C:a >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Restack checked out branch' '
+    stg refresh -m p1-reworded &&
+    stg branch B &&
+    stg restack &&
+    test "$(git rev-parse HEAD~1)" = "$(stg id master:p1)" &&
+    test "$(git rev-parse C~1)" = "$(stg id b1)" &&
+    test_write_lines 1 two 3 four 5 6 7 eight 9 10 >expected &&
+    test_cmp expected a
+'
+
+test_expect_success 'Stacked branches follow renamed parent patch and branch' '
+    stg rename b1 b1-new &&
+    test "$(git config branch.C.stgit.parentpatch)" = "b1-new" &&
+    stg branch --rename B B-new &&
+    test "$(git config branch.C.stgit.parentbranch)" = "B-new" &&
+    test "$(git config branch.B-new.stgit.parentbranch)" = "master" &&
+    stg refresh -m b1-reworded &&
+    stg restack &&
+    test "$(git rev-parse C~1)" = "$(stg id b1-new)" &&
+    stg rename b1-new b1 &&
+    stg branch --rename B-new B &&
+    test "$(git config branch.C.stgit.parentbranch)" = "B" &&
+    test "$(git config branch.C.stgit.parentpatch)" = "b1"
+'
+
+test_expect_success 'Branch without stacked branches' '
+    stg restack -b C 2>err &&
+    grep -e "Branch \`C\` is up to date" err &&
+    stg branch --create D master &&
+    stg restack 2>err &&
+    grep -e "Branch \`D\` has no stacked branches to restack" err
+'
+
+test_expect_success 'Attempt restack when parent patch is gone' '
+    stg delete -b master p2 &&
+    stg branch master &&
+    stg commit p1 &&
+    command_error stg restack 2>err &&
+    grep -e "parent patch \`p1\` of branch \`B\` does not exist in branch \`master\`" err
+'
+
+test_done
@@ -0,0 +1,110 @@
+#!/bin/sh
+
+test_description='Test stacks with branches checked out in linked worktrees'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize branches' '
+    echo wt >>.git/info/exclude &&
+    test_commit_bulk --message="master%s" 1 &&
+    stg init &&
+    stg branch --create other &&
+    stg new -m p1 p1 &&
+    echo p1 >p1.txt &&
+    stg add p1.txt &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    stg pop p2 &&
+    stg branch master
+'
+
+test_expect_success 'Attempt worktree for current branch' '
+    command_error stg branch --worktree wt/master master 2>err &&
+    grep -e "branch \`master\` is checked out in the current worktree" err &&
+    test_path_is_missing wt/master
+'
+
+test_expect_success 'Attempt worktree for branch without stack' '
+    git branch plain &&
+    command_error stg branch --worktree wt/plain plain 2>err &&
+    grep -e "StGit stack not initialized for branch \`plain\`" err &&
+    test_path_is_missing wt/plain
+'
+
+test_expect_success 'Create worktree for stack' '
+    stg branch --worktree wt/other other &&
+    test_path_is_file wt/other/p1.txt &&
+    (cd wt/other && test "$(stg branch)" = "other") &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2"
+'
+
+test_expect_success 'Attempt second worktree for same stack' '
+    command_error stg branch --worktree wt/other2 other 2>err &&
+    grep -e "branch \`other\` is already checked out in worktree" err &&
+    test_path_is_missing wt/other2
+'
+
+test_expect_success 'List branches checked out in other worktrees' '
+    stg branch --list >list &&
+    grep -e "^> s.*master" list &&
+    grep -e "^+ s.*other" list &&
+    stg branch --list --format=json >list.json &&
+    grep -e "\"worktree\": \".*wt/other\"" list.json &&
+    (cd wt/other && stg branch --list) >list &&
+    grep -e "^+ s.*master" list &&
+    grep -e "^> s.*other" list
+'
+
+test_expect_success 'Refuse to delete applied patch of stack in other worktree' '
+    command_error stg delete -b other p1 2>err &&
+    grep -e "branch \`other\` is checked out in worktree" err &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2"
+'
+
+test_expect_success 'Refuse to move applied patch from stack in other worktree' '
+    command_error stg move -b other p1 --to-branch master 2>err &&
+    grep -e "branch \`other\` is checked out in worktree" err &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2" &&
+    test -z "$(stg series)"
+'
+
+test_expect_success 'Allow state-only operations on stack in other worktree' '
+    stg rename -b other p2 p2x &&
+    stg hide -b other p2x &&
+    stg unhide -b other p2x &&
+    stg new -m m1 m1 &&
+    stg move m1 --to-branch other &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2x - m1" &&
+    stg delete -b other m1 &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2x"
+'
+
+test_expect_success 'Operate on stack in its own worktree' '
+    (
+        cd wt/other &&
+        stg push p2x &&
+        stg pop -a &&
+        stg push p1
+    ) &&
+    test "$(echo $(stg series -b other))" = "> p1 - p2x"
+'
+
+test_expect_success 'Refuse to restack stack in other worktree' '
+    stg branch --create stacked --on other:p1 &&
+    stg new -m s1 s1 &&
+    stg branch master &&
+    stg branch --worktree wt/stacked stacked &&
+    (cd wt/other && stg edit -m "p1 edited" p1) &&
+    command_error stg restack -b other 2>err &&
+    grep -e "branch \`stacked\` is checked out in worktree" err &&
+    (cd wt/stacked && stg restack) &&
+    test "$(git log -1 --format=%s stacked~1)" = "p1 edited"
+'
+
+test_expect_success 'Refuse to delete branch checked out in other worktree' '
+    command_error stg branch --delete --force other 2>err &&
+    grep -e "cannot delete branch \`other\` checked out in worktree" err &&
+    git rev-parse --verify -q refs/heads/other
+'
+
+test_done
@@ -0,0 +1,61 @@
+#!/bin/sh
+
+test_description='Test pushing patches that require a three-way merge'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack' '
+    test_seq 1 12 >a &&
+    git add a &&
+    git commit -m "add a" &&
+    git branch upstream &&
+    stg init &&
+    stg new -m p0 p0 &&
+    sed -e "s/^3\$/three/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg new -m p1 p1 &&
+    git mv a b &&
+    sed -e "s/^8\$/eight/" b >b.new && mv b.new b &&
+    git add b &&
+    stg refresh --index
+'
+
+test_expect_success 'Push patch onto a renamed file without a worktree' '
+    sed -e "s/^three\$/THREE/" b >b.new && mv b.new b &&
+    stg absorb 2>err &&
+    grep -e "absorbed 1 hunk into \`p0\`" err &&
+    stg show p0 | grep -e "^+THREE" &&
+    stg show p1 | grep -e "^rename to b" &&
+    stg show p1 | grep -e "^+eight" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Rename file upstream' '
+    git checkout -q upstream &&
+    git mv a c &&
+    git commit -q -m "rename a to c" &&
+    git checkout -q master
+'
+
+test_expect_success 'Rebase patch onto a renamed file' '
+    stg pop p1 &&
+    stg rebase upstream >out &&
+    grep -e "p0 (modified)" out &&
+    stg show p0 | grep -e "^+++ b/c" &&
+    stg show p0 | grep -e "^+THREE" &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Conflicting merge falls back to the worktree' '
+    git checkout -q upstream &&
+    sed -e "s/^3\$/drei/" c >c.new && mv c.new c &&
+    git commit -q -a -m "change line 3" &&
+    git checkout -q master &&
+    conflict stg rebase upstream &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    git diff --name-only --diff-filter=U >conflicts &&
+    test "$(cat conflicts)" = "c"
+'
+
+test_done
@@ -0,0 +1,70 @@
+#!/bin/sh
+
+test_description='Test patch dependency tracking'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack with dependent patches' '
+    test_seq 1 20 >a.txt &&
+    test_seq 1 20 >b.txt &&
+    stg add a.txt b.txt &&
+    git commit -m "base" &&
+    stg new -m p0 &&
+    sed -e "s/^3$/three/" a.txt >a.txt.new && mv a.txt.new a.txt &&
+    stg refresh &&
+    stg new -m p1 &&
+    sed -e "s/^15$/fifteen/" b.txt >b.txt.new && mv b.txt.new b.txt &&
+    stg refresh &&
+    stg new -m p2 &&
+    sed -e "s/^three$/THREE/" a.txt >a.txt.new && mv a.txt.new a.txt &&
+    stg refresh &&
+    stg new -m p3 &&
+    sed -e "s/^fifteen$/FIFTEEN/" b.txt >b.txt.new && mv b.txt.new b.txt &&
+    sed -e "s/^THREE$/Three/" a.txt >a.txt.new && mv a.txt.new a.txt &&
+    stg refresh &&
+    stg new -m p4 &&
+    sed -e "s/^10$/ten/" a.txt >a.txt.new && mv a.txt.new a.txt &&
+    stg refresh &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3 p4"
+'
+
+test_expect_success 'Show direct dependencies' '
+    test "$(echo $(stg deps))" = "" &&
+    test "$(echo $(stg deps p0))" = "" &&
+    test "$(echo $(stg deps p2))" = "p0" &&
+    test "$(echo $(stg deps p3))" = "p1 p2"
+'
+
+test_expect_success 'Show transitive and reverse dependencies' '
+    test "$(echo $(stg deps --transitive p3))" = "p0 p1 p2" &&
+    test "$(echo $(stg deps --reverse p0))" = "p2" &&
+    test "$(echo $(stg deps --reverse --transitive p0))" = "p2 p3"
+'
+
+test_expect_success 'Check patch applies with only its dependencies' '
+    stg deps --check p3 &&
+    stg deps --check p4
+'
+
+test_expect_success 'Pop with dependents' '
+    stg pop --with-deps p2 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p4" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p2 p3"
+'
+
+test_expect_success 'Push with dependencies' '
+    stg push --with-deps p3 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p4 p2 p3"
+'
+
+test_expect_success 'Float with dependencies' '
+    stg float --with-deps p3 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p4 p0 p1 p2 p3"
+'
+
+test_expect_success 'Sink with dependencies' '
+    stg sink --with-deps p2 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2 p4 p1 p3"
+'
+
+test_done
@@ -0,0 +1,148 @@
+#!/bin/sh
+
+test_description='Test moving patches between stacks'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stacks' '
+    test_commit_bulk --message="base %s" 2 &&
+    git branch other &&
+    stg init &&
+    stg new -m p0 p0 &&
+    echo p0 >p0.txt &&
+    stg add p0.txt &&
+    stg refresh &&
+    stg new -m p1 p1 &&
+    echo p1 >p1.txt &&
+    stg add p1.txt &&
+    stg refresh &&
+    stg meta set note "keep me" &&
+    stg new -m p2 p2 &&
+    echo p2 >p2.txt &&
+    stg add p2.txt &&
+    stg refresh &&
+    stg new -m p3 p3 &&
+    echo p3 >p3.txt &&
+    stg add p3.txt &&
+    stg refresh &&
+    stg hide p3 &&
+    stg branch other &&
+    stg init &&
+    stg new -m q0 q0 &&
+    echo q0 >q0.txt &&
+    stg add q0.txt &&
+    stg refresh &&
+    stg new -m q1 q1 &&
+    stg pop &&
+    stg branch master
+'
+
+test_expect_success 'Attempt move without target branch' '
+    general_error stg move p1 2>err &&
+    grep -e "the following required arguments were not provided" err
+'
+
+test_expect_success 'Attempt move within the same branch' '
+    command_error stg move p1 --to-branch master 2>err &&
+    grep -e "cannot move patches within branch \`master\`" err
+'
+
+test_expect_success 'Attempt move next to applied patch of other branch' '
+    command_error stg move p1 --to-branch other --above q0 2>err &&
+    grep -e "cannot move patches next to applied patch \`q0\`" err
+'
+
+test_expect_success 'Attempt move with colliding name' '
+    stg new -m Q1 Q1 &&
+    command_error stg move Q1 --to-branch other 2>err &&
+    grep -e "patch \`Q1\` collides with existing patch \`q1\`" err &&
+    stg delete Q1
+'
+
+test_expect_success 'Move patches to branch that is not checked out' '
+    p1_commit=$(stg id p1) &&
+    stg move p3 p1 --to-branch other &&
+    test "$(echo $(stg series --all --noprefix))" = "p0 p2" &&
+    test "$(echo $(stg series --applied --noprefix -b other))" = "q0" &&
+    test "$(echo $(stg series --unapplied --noprefix -b other))" = "q1 p1" &&
+    test "$(echo $(stg series --hidden --noprefix -b other))" = "p3" &&
+    test "$(stg id other:p1)" = "$p1_commit" &&
+    test_path_is_missing p1.txt &&
+    test_path_is_file p2.txt &&
+    stg log -n 1 | grep -e "move to other" &&
+    stg log -n 1 -b other | grep -e "move from master"
+'
+
+test_expect_success 'Moved patch keeps its metadata' '
+    test "$(stg meta -b other -p p1 get note)" = "keep me"
+'
+
+test_expect_success 'Move patches below unapplied patch' '
+    stg move p2 --to-branch other --below p1 &&
+    test "$(echo $(stg series --all --noprefix))" = "p0" &&
+    test "$(echo $(stg series --unapplied --noprefix -b other))" = "q1 p2 p1"
+'
+
+test_expect_success 'Move patches to checked out branch' '
+    stg branch other &&
+    stg move -b master p0 --to-branch other --above q0 &&
+    test "$(echo $(stg series --applied --noprefix))" = "q0 p0" &&
+    test_path_is_file p0.txt &&
+    test "$(echo $(stg series --all --noprefix -b master))" = ""
+'
+
+test_expect_success 'Move patches from checked out branch' '
+    stg move p0 q0 p1 --to-branch master &&
+    test "$(echo $(stg series --all --noprefix))" = "q1 p2 p3" &&
+    test_path_is_missing q0.txt &&
+    test "$(echo $(stg series --unapplied --noprefix -b master))" = "q0 p0 p1"
+'
+
+test_expect_success 'Undo move in each stack' '
+    stg undo &&
+    test "$(echo $(stg series --applied --noprefix))" = "q0 p0" &&
+    stg branch master &&
+    stg undo &&
+    test "$(echo $(stg series --all --noprefix))" = ""
+'
+
+test_expect_success 'Refuse move when remaining source patches would conflict' '
+    stg new -m r0 r0 &&
+    echo r0 >r.txt &&
+    stg add r.txt &&
+    stg refresh &&
+    stg new -m r1 r1 &&
+    echo r1 >r.txt &&
+    stg refresh &&
+    command_error stg move r0 --to-branch other 2>err &&
+    grep -e "patch \`r1\` does not apply cleanly once the moved patches are deleted from branch \`master\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "r0 r1" &&
+    test_must_fail stg id other:r0
+'
+
+test_lazy_prereq MERGE_TREE_WRITE_TREE '
+    git merge-tree --write-tree HEAD HEAD
+'
+
+test_expect_success MERGE_TREE_WRITE_TREE 'Move when remaining source patches merge like a push' '
+    stg delete r0 r1 &&
+    test_write_lines 1 2 3 4 5 6 7 8 9 10 >m.txt &&
+    stg add m.txt &&
+    stg new -m m0 m0 &&
+    stg refresh &&
+    stg new -m m1 m1 &&
+    git mv m.txt moved.txt &&
+    test_write_lines 1 2 3 4 5 6 7 8 9 ten >moved.txt &&
+    git add moved.txt &&
+    stg refresh --index &&
+    stg new -m m2 m2 &&
+    test_write_lines 1 2 3 4 5 6 7 eight 9 ten >moved.txt &&
+    stg refresh &&
+    stg move m1 --to-branch other &&
+    test "$(echo $(stg series --applied --noprefix))" = "m0 m2" &&
+    test_path_is_missing moved.txt &&
+    test "$(echo $(cat m.txt))" = "1 2 3 4 5 6 7 eight 9 10" &&
+    test "$(echo $(stg series --branch other --noprefix m1))" = "m1"
+'
+
+test_done
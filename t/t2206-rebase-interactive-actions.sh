@@ -0,0 +1,174 @@
+#!/bin/sh
+
+test_description='Test rebase --interactive reword, rename, unapply, exec, and break'
+
+. ./test-lib.sh
+
+# The fake editor writes the instructions from the "instructions" file and otherwise
+# runs the "message-editor" script, if any.
+test_expect_success 'Setup fake editor' '
+    write_script fake-editor <<-\EOF &&
+	case "$1" in
+	*.stgit-rebase-interactive.txt)
+	    cp instructions "$1"
+	    ;;
+	*)
+	    if grep -q "^diff --git" "$1"
+	    then
+	        echo diff >>editor-saw-diff
+	    fi
+	    test ! -x message-editor || ./message-editor "$1"
+	    ;;
+	esac
+	EOF
+    test_set_editor "$(pwd)/fake-editor"
+'
+
+test_expect_success 'Initialize StGit stack' '
+    for i in 0 1 2 3; do
+        stg new -m "p$i" p$i &&
+        echo "p$i" >f$i.txt &&
+        stg add f$i.txt &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'Reword a patch without editing its diff' '
+    printf "keep p0\nreword p1\nkeep p2\nkeep p3\n" >instructions &&
+    write_script message-editor <<-\EOF &&
+	sed "s/^p1$/p1 reworded/" "$1" >"$1".tmp && mv "$1".tmp "$1"
+	EOF
+    test_when_finished rm -f message-editor &&
+    tree=$(git rev-parse $(stg id p1)^{tree}) &&
+    stg rebase --interactive &&
+    test_path_is_missing editor-saw-diff &&
+    test "$(git log -1 --format=%s $(stg id p1))" = "p1 reworded" &&
+    test "$(git rev-parse $(stg id p1)^{tree})" = "$tree" &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3"
+'
+
+test_expect_success 'Rename a patch inline' '
+    printf "keep p0\nkeep p1\nrename p2 p2-new\nkeep p3\n" >instructions &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2-new p3"
+'
+
+test_expect_success 'Rename to an existing patch name is refused' '
+    printf "keep p0\nrename p1 p3\n" >instructions &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "cannot rename \`p1\` to \`p3\`: patch name already in use" err
+'
+
+test_expect_success 'Unapply patches and reorder across the APPLY_LINE' '
+    stg push -a &&
+    printf "keep p0\nunapply p3\nkeep p1\n# --- APPLY_LINE ---\nkeep p2-new\n" >instructions &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p3 p2-new" &&
+    printf "keep p0\nkeep p1\n# --- APPLY_LINE ---\nkeep p2-new\nkeep p3\n" >instructions &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p2-new p3"
+'
+
+test_expect_success 'Exec runs commands after pushing preceding patches' '
+    cat >instructions <<-\EOF &&
+	keep p0
+	exec stg top >exec-out
+	keep p1
+	x echo "$(stg top) # not a comment" >>exec-out
+	keep p2-new
+	EOF
+    stg rebase --interactive &&
+    printf "p0\np1 # not a comment\n" >expected &&
+    test_cmp expected exec-out &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2-new" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Failed exec stops the rebase' '
+    printf "keep p0\nexec false\nkeep p1\nkeep p2-new\nkeep p3\n" >instructions &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "\`false\` failed; fix the problem then run \`stg rebase --continue\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    command_error stg rebase --skip 2>err &&
+    grep -e "\`stg rebase\` stopped without conflicts" err &&
+    stg rebase --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2-new p3" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Break stops the rebase and continue refreshes changes' '
+    printf "keep p0\nbreak\nkeep p1\nkeep p2-new\nkeep p3\n" >instructions &&
+    stg rebase --interactive 2>err &&
+    grep -e "Stopped at \`p0\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    echo changed >f0.txt &&
+    stg rebase --continue 2>err &&
+    grep -e "Refreshed local changes into \`p0\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2-new p3" &&
+    test "$(git show $(stg id p0):f0.txt)" = "changed" &&
+    git diff-index --quiet HEAD
+'
+
+test_expect_success 'Push while stopped at break, then --continue' '
+    printf "keep p0\nbreak\nkeep p1\nkeep p2-new\nkeep p3\n" >instructions &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    command_error stg push p1 2>err &&
+    grep -e "\`stg rebase\` is stopped; use \`stg rebase --continue\` or \`stg rebase --abort\` first" err &&
+    command_error stg goto p3 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    stg rebase --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2-new p3" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Abort a stopped rebase' '
+    printf "b\nkeep p0\nkeep p1\n" >instructions &&
+    stg rebase --interactive &&
+    test -z "$(stg series --applied --noprefix)" &&
+    stg new -m extra extra &&
+    stg rebase --abort &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2-new p3" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Exec and break are not allowed below the APPLY_LINE' '
+    printf "keep p0\n# --- APPLY_LINE ---\nexec true\n" >instructions &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "\`exec\` instructions must be above the APPLY_LINE" err &&
+    stg push -a &&
+    printf "keep p0\nbreak now\n" >instructions &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "bad instruction line: \`break now\`" err &&
+    stg push -a
+'
+
+test_expect_success 'Remaining steps are performed after resolving conflicts' '
+    stg delete $(stg series --all --noprefix --no-description) &&
+    echo a >f.txt &&
+    git add f.txt &&
+    git commit -m base &&
+    stg new -m q1 q1 &&
+    echo b >f.txt &&
+    stg refresh &&
+    stg new -m q2 q2 &&
+    echo c >f.txt &&
+    stg refresh &&
+    stg new -m q3 q3 &&
+    echo q3 >g.txt &&
+    stg add g.txt &&
+    stg refresh &&
+    printf "keep q2\nexec echo ran >ran\nkeep q3\n# --- APPLY_LINE ---\nkeep q1\n" >instructions &&
+    conflict stg rebase --interactive &&
+    test_path_is_missing ran &&
+    echo c >f.txt &&
+    stg add f.txt &&
+    stg rebase --continue &&
+    test_path_is_file ran &&
+    test "$(echo $(stg series --applied --noprefix))" = "q2 q3" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "q1" &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_done
@@ -0,0 +1,157 @@
+#!/bin/sh
+
+test_description='Test autosquash of fixup!, squash!, and amend! patches'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    test_commit_bulk --message="base%s" 1 &&
+    git branch upstream &&
+    for i in 0 1 2; do
+        stg new -m "p$i subject" p$i &&
+        echo "p$i" >f$i.txt &&
+        stg add f$i.txt &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'New fixup patch' '
+    stg new --fixup p1 &&
+    test "$(git log -1 --format=%s)" = "fixup! p1 subject" &&
+    test "$(stg top)" != "p2" &&
+    echo fix >>f1.txt &&
+    stg refresh &&
+    test "$(echo $(stg series --noprefix --no-description))" = "p0 p1 p2 $(stg top)"
+'
+
+test_expect_success 'New fixup patch with explicit name' '
+    stg new --fixup p0 p0-fix &&
+    test "$(git log -1 --format=%s)" = "fixup! p0 subject" &&
+    echo fix >>f0.txt &&
+    stg refresh
+'
+
+test_expect_success 'Fixup target must exist' '
+    command_error stg new --fixup not-a-patch 2>err &&
+    grep -e "patch \`not-a-patch\` does not exist" err
+'
+
+test_expect_success 'Fixup conflicts with message' '
+    general_error stg new --fixup p0 -m foo 2>err &&
+    grep -e "the argument .--fixup <patch>. cannot be used with .--message <message>." err
+'
+
+test_expect_success 'Autosquash fixup patches' '
+    stg autosquash &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2" &&
+    test "$(git log -1 --format=%s $(stg id p0))" = "p0 subject" &&
+    test "$(git log -1 --format=%s $(stg id p1))" = "p1 subject" &&
+    printf "p0\nfix\n" >expected &&
+    git show $(stg id p0):f0.txt >actual &&
+    test_cmp expected actual &&
+    printf "p1\nfix\n" >expected &&
+    git show $(stg id p1):f1.txt >actual &&
+    test_cmp expected actual &&
+    git diff-index --quiet HEAD
+'
+
+test_expect_success 'Autosquash without patches to squash' '
+    stg autosquash 2>out &&
+    grep -e "No patches to autosquash" out &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2"
+'
+
+test_expect_success 'Autosquash amend patch by patch name' '
+    stg new -m "amend! p2
+
+p2 amended subject
+
+p2 amended body" amend-p2 &&
+    echo amend >>f2.txt &&
+    stg refresh &&
+    stg autosquash &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2" &&
+    test "$(git log -1 --format=%s $(stg id p2))" = "p2 amended subject" &&
+    test "$(git log -1 --format=%b $(stg id p2))" = "p2 amended body" &&
+    printf "p2\namend\n" >expected &&
+    test_cmp expected f2.txt
+'
+
+test_expect_success 'Autosquash squash patch' '
+    stg new -m "squash! p0 subject" sq-p0 &&
+    echo squash >>f0.txt &&
+    stg refresh &&
+    write_script fake-editor <<-\EOF &&
+	grep -e "^squash! p0 subject" "$1" >editor-saw-squash &&
+	printf "p0 subject\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg autosquash &&
+    test_path_is_file editor-saw-squash &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2" &&
+    test "$(git log -1 --format=%s $(stg id p0))" = "p0 subject" &&
+    printf "p0\nfix\nsquash\n" >expected &&
+    git show $(stg id p0):f0.txt >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Interactive autosquash presents rearranged instructions' '
+    stg new --fixup p1 fix-p1 &&
+    echo fix2 >>f1.txt &&
+    stg refresh &&
+    write_script fake-editor <<-\EOF &&
+	grep -v -e "^#" -e "^$" "$1" | sed "s/ *#.*//" >instructions &&
+	printf "keep p0\nkeep p1\nkeep fix-p1\nkeep p2\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg autosquash -i &&
+    printf "keep p0\nkeep p1\nfixup fix-p1\nkeep p2\n" >expected &&
+    test_cmp expected instructions &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 fix-p1 p2"
+'
+
+test_expect_success 'Rebase with autosquash' '
+    git checkout upstream &&
+    echo up >up.txt &&
+    git add up.txt &&
+    git commit -m up &&
+    git checkout master &&
+    stg rebase --autosquash upstream &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2" &&
+    test "$(stg id {base})" = "$(git rev-parse upstream)" &&
+    printf "p1\nfix\nfix2\n" >expected &&
+    test_cmp expected f1.txt
+'
+
+test_expect_success 'Stopped interactive autosquash can be continued' '
+    stg new --fixup p0 fix-p0 &&
+    echo fix3 >>f0.txt &&
+    stg refresh &&
+    write_script fake-editor <<-\EOF &&
+	sed "/^keep p1/i break" "$1" >"$1".tmp && mv "$1".tmp "$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg autosquash -i 2>err &&
+    grep -e "Stopped at \`p0\`; run \`stg autosquash --continue\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    command_error stg rebase --continue 2>err &&
+    grep -e "\`stg autosquash\` is in progress" err &&
+    stg autosquash --continue &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2" &&
+    printf "p0\nfix\nsquash\nfix3\n" >expected &&
+    git show $(stg id p0):f0.txt >actual &&
+    test_cmp expected actual &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Autosquash leaves patches without target in place' '
+    stg new -m "fixup! no such subject" orphan &&
+    stg autosquash 2>out &&
+    grep -e "No patches to autosquash" out &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 orphan"
+'
+
+test_done
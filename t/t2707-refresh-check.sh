@@ -0,0 +1,103 @@
+#!/bin/sh
+
+test_description='Test checking of changes incorporated by refresh'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack' '
+    printf "a\nb\nc\n" >foo.txt &&
+    echo other >other.txt &&
+    git add foo.txt other.txt &&
+    git commit -m base &&
+    stg init &&
+    stg new -m p1 p1 &&
+    echo p1 >>foo.txt &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    echo p2 >p2.txt &&
+    stg add p2.txt &&
+    stg refresh
+'
+
+test_expect_success 'Pure addition is accepted' '
+    echo more >>p2.txt &&
+    stg refresh --checked=deny >out &&
+    ! grep -e "^:: Checking" out &&
+    test -z "$(git diff HEAD)"
+'
+
+test_expect_success 'Change outside patch is denied' '
+    echo changed >>other.txt &&
+    command_error stg refresh --checked=deny >out 2>err &&
+    grep -e "refresh of \`p2\` denied by refresh check: outside-patch" err &&
+    grep -e "^:> Classification: outside-patch$" out &&
+    grep -e "outside +1 -0 other.txt" out &&
+    grep -e "^	++changed$" out &&
+    test "$(git diff --name-only HEAD)" = "other.txt" &&
+    test "$(stg files p2)" = "A p2.txt"
+'
+
+test_expect_success 'Configured policy and --no-checked' '
+    test_config stgit.refresh.check deny &&
+    command_error stg refresh 2>err &&
+    grep -e "denied by refresh check" err &&
+    stg refresh --no-checked &&
+    test "$(echo $(stg files p2))" = "M other.txt A p2.txt"
+'
+
+test_expect_success 'Warn policy refreshes anyway' '
+    echo new >new.txt &&
+    stg add new.txt &&
+    stg refresh --checked=warn >out &&
+    grep -e "^:> Classification: new-files$" out &&
+    grep -e "new     +1 -0 new.txt" out &&
+    test "$(stg files p2 | grep new.txt)" = "A new.txt"
+'
+
+test_expect_success 'Removing patch lines with JSON report' '
+    printf "a\nb\nc\n" >foo.txt &&
+    command_error stg refresh -p p1 --checked=deny --check-report=report.json >out &&
+    grep -e "\"patch\": \"p1\"" report.json &&
+    grep -e "\"removes-patch-lines\"" report.json &&
+    grep -e "\"accepted\": false" report.json &&
+    grep -e "\"removed_patch_lines\": 1" report.json &&
+    grep -e "^	-+p1$" out &&
+    test "$(git diff --name-only HEAD)" = "foo.txt" &&
+    git checkout HEAD foo.txt
+'
+
+test_expect_success 'JSON report to stdout' '
+    echo even-more >>p2.txt &&
+    stg refresh --check-report=- >out &&
+    grep -e "\"classification\": \[" out &&
+    grep -e "\"pure-addition\"" out &&
+    grep -e "\"accepted\": true" out &&
+    grep -e "\"policy\": \"prompt\"" out
+'
+
+test_expect_success 'Allowed classifications' '
+    test_config stgit.refresh.checkallow "pure-addition, outside-patch" &&
+    echo again >>foo.txt &&
+    stg refresh --checked=deny >out &&
+    ! grep -e "^:: Checking" out &&
+    test "$(echo $(stg files p2))" = "M foo.txt A new.txt M other.txt A p2.txt"
+'
+
+test_expect_success 'Prompt without terminal' '
+    echo prompt >>other.txt &&
+    command_error stg refresh -p p1 --checked </dev/null 2>err &&
+    grep -e "cannot prompt to confirm refresh without a terminal" err &&
+    git checkout HEAD other.txt
+'
+
+test_expect_success 'Invalid configuration' '
+    test_config stgit.refresh.check bogus &&
+    command_error stg refresh 2>err &&
+    grep -e "unsupported refresh check policy \`bogus\`" err &&
+    test_config stgit.refresh.check warn &&
+    test_config stgit.refresh.checkallow "pure-addition bogus" &&
+    command_error stg refresh 2>err &&
+    grep -e "unknown refresh check classification \`bogus\`" err
+'
+
+test_done
@@ -0,0 +1,113 @@
+#!/bin/sh
+
+test_description='Test refresh guarded by patch file scopes'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack' '
+    mkdir dir &&
+    echo a >a.txt &&
+    echo b >b.txt &&
+    echo c >dir/c.txt &&
+    git add a.txt b.txt dir &&
+    git commit -m base &&
+    stg init &&
+    stg new -m p1 p1 &&
+    echo p1 >>a.txt &&
+    stg refresh
+'
+
+test_expect_success 'Patch without scope' '
+    test -z "$(stg scope list)" &&
+    command_error stg scope clear 2>err &&
+    grep -e "patch \`p1\` has no scope" err
+'
+
+test_expect_success 'Record scope' '
+    stg scope record &&
+    test "$(stg scope list)" = "a.txt" &&
+    test "$(stg meta get scope)" = "a.txt"
+'
+
+test_expect_success 'Refresh within scope' '
+    echo more >>a.txt &&
+    stg refresh --scope=refuse &&
+    test -z "$(git diff HEAD)"
+'
+
+test_expect_success 'Refuse changes outside scope' '
+    echo more >>a.txt &&
+    echo more >>b.txt &&
+    command_error stg refresh --scope=refuse 2>err &&
+    grep -e "refusing to refresh files outside the patch.s scope: \`b.txt\`" err &&
+    test "$(stg files p1)" = "M a.txt" &&
+    test "$(echo $(git diff --name-only))" = "a.txt b.txt"
+'
+
+test_expect_success 'Refuse changes outside scope from config' '
+    test_config stgit.refresh.scope refuse &&
+    command_error stg refresh 2>err &&
+    grep -e "refusing to refresh files outside" err &&
+    git add b.txt &&
+    command_error stg refresh --index 2>err &&
+    grep -e "refusing to refresh files outside" err &&
+    git reset -q b.txt &&
+    test "$(stg files p1)" = "M a.txt"
+'
+
+test_expect_success 'Update is limited to scope' '
+    stg refresh --update &&
+    test "$(stg files p1)" = "M a.txt" &&
+    test "$(git diff --name-only)" = "b.txt"
+'
+
+test_expect_success 'Add directory to scope' '
+    (cd dir && stg scope add . ../b.txt) &&
+    test "$(echo $(stg scope list))" = "a.txt b.txt dir" &&
+    echo more >>dir/c.txt &&
+    stg refresh --scope=refuse &&
+    test "$(echo $(stg files p1))" = "M a.txt M b.txt M dir/c.txt" &&
+    stg scope remove b.txt dir &&
+    test "$(stg scope list)" = "a.txt" &&
+    command_error stg scope remove b.txt 2>err &&
+    grep -e "\`b.txt\` is not in the scope of \`p1\`" err
+'
+
+test_expect_success 'Split changes outside scope' '
+    echo split >>a.txt &&
+    echo split >>dir/c.txt &&
+    stg refresh --scope=split &&
+    test "$(echo $(stg series --noprefix))" = "p1 p1-unscoped" &&
+    test "$(echo $(stg files p1))" = "M a.txt M b.txt M dir/c.txt" &&
+    test "$(stg files p1-unscoped)" = "M dir/c.txt" &&
+    test "$(git show -s --format=%s $(stg id p1-unscoped))" = "Changes outside the scope of p1" &&
+    test "$(git show $(stg id p1) | grep -c "^+split")" = "1" &&
+    test -z "$(git diff HEAD)"
+'
+
+test_expect_success 'Split with --index is not supported' '
+    echo index >>b.txt &&
+    git add b.txt &&
+    command_error stg refresh -p p1 --index --scope=split 2>err &&
+    grep -e "\`--scope=split\` cannot be used with \`--index\`" err &&
+    git reset -q --hard
+'
+
+test_expect_success 'Ignore scope' '
+    test_config stgit.refresh.scope refuse &&
+    stg pop &&
+    echo ignored >>b.txt &&
+    stg refresh --no-scope &&
+    test "$(echo $(stg files p1))" = "M a.txt M b.txt M dir/c.txt"
+'
+
+test_expect_success 'Scopes are combined by squash' '
+    stg push &&
+    stg scope record &&
+    stg squash -m squashed -n squashed p1 p1-unscoped &&
+    test "$(echo $(stg scope list))" = "a.txt dir/c.txt" &&
+    stg scope clear &&
+    test_must_fail stg meta get scope
+'
+
+test_done
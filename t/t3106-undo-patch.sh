@@ -0,0 +1,102 @@
+#!/bin/sh
+
+test_description='Test undo of individual patches'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize stack with three patches' '
+    echo a >a &&
+    echo b >b &&
+    echo c >c &&
+    stg add a b c &&
+    git commit -m initial &&
+    stg init &&
+    stg new -m p1 p1 &&
+    echo a1 >>a &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    echo b1 >>b &&
+    stg refresh &&
+    stg new -m p3 p3 &&
+    echo c1 >>c &&
+    stg refresh &&
+    stg log -n 1 | cut -d " " -f 1 >initial-entry
+'
+
+test_expect_success 'Modify patches' '
+    stg goto p1 &&
+    echo a2 >>a &&
+    stg refresh &&
+    stg goto p2 &&
+    echo b2 >>b &&
+    stg refresh &&
+    stg goto p3 &&
+    stg edit -m "p3 reworded" &&
+    test "$(echo $(stg series))" = "+ p1 + p2 > p3"
+'
+
+test_expect_success 'Attempt to combine --patch and --number' '
+    general_error stg undo --patch p1 -n 2 2>err &&
+    grep -e "cannot be used with" err
+'
+
+test_expect_success 'Attempt --to without --patch' '
+    general_error stg undo --to "$(cat initial-entry)" 2>err &&
+    grep -e "the following required arguments were not provided" err
+'
+
+test_expect_success 'Undo one patch' '
+    stg undo --patch p1 &&
+    test "$(echo $(stg series))" = "+ p1 + p2 > p3" &&
+    stg show p1 | grep -e "^+a1" &&
+    ! stg show p1 | grep -e "^+a2" &&
+    stg show p2 | grep -e "^+b2" &&
+    test "$(stg id p3 | git log -1 --format=%s --stdin)" = "p3 reworded" &&
+    test_write_lines a a1 >expected &&
+    test_cmp expected a &&
+    stg log -n 1 | grep -e "undo --patch p1"
+'
+
+test_expect_success 'Selective undo is undoable' '
+    stg undo &&
+    stg show p1 | grep -e "^+a2" &&
+    test "$(echo $(stg series))" = "+ p1 + p2 > p3"
+'
+
+test_expect_success 'Undo multiple patches' '
+    stg undo --patch p2 --patch p3 &&
+    stg show p1 | grep -e "^+a2" &&
+    ! stg show p2 | grep -e "^+b2" &&
+    test "$(stg id p3 | git log -1 --format=%s --stdin)" = "p3" &&
+    stg undo
+'
+
+test_expect_success 'Undo patch to log entry' '
+    stg undo --patch p1 --patch p2 --to "$(cat initial-entry)" &&
+    ! stg show p1 | grep -e "^+a2" &&
+    ! stg show p2 | grep -e "^+b2" &&
+    test "$(stg id p3 | git log -1 --format=%s --stdin)" = "p3 reworded" &&
+    test_write_lines b b1 >expected &&
+    test_cmp expected b &&
+    stg undo
+'
+
+test_expect_success 'Undo unapplied patch' '
+    stg pop p2 &&
+    stg undo --patch p2 &&
+    test "$(echo $(stg series))" = "+ p1 > p3 - p2" &&
+    ! stg show p2 | grep -e "^+b2"
+'
+
+test_expect_success 'Attempt to undo patch without earlier commit' '
+    stg new -m p4 p4 &&
+    command_error stg undo --patch p4 2>err &&
+    grep -e "patch \`p4\` has no earlier commit in the stack log" err
+'
+
+test_expect_success 'Attempt to undo patch to log entry without the patch' '
+    command_error stg undo --patch p4 --to "$(cat initial-entry)" 2>err &&
+    grep -e "patch \`p4\` does not exist in log entry" err
+'
+
+test_done
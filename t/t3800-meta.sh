@@ -0,0 +1,101 @@
+#!/bin/sh
+
+test_description='Test patch metadata'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    stg new -m p0 &&
+    stg new -m p1 &&
+    stg new -m p2
+'
+
+test_expect_success 'No metadata initially' '
+    git show refs/stacks/master:stack.json >stack.json &&
+    grep -e "\"version\": 5" stack.json &&
+    test -z "$(stg meta list)" &&
+    command_error stg meta get note 2>err &&
+    grep -e "patch \`p2\` has no \`note\` metadata" err
+'
+
+test_expect_success 'Set and get metadata' '
+    stg meta set note "needs more tests" &&
+    stg meta -p p0 set upstream https://example.com/review/1 &&
+    stg meta -p p0 set sent-version 2 &&
+    test "$(stg meta get note)" = "needs more tests" &&
+    test "$(stg meta -p p0 get upstream)" = "https://example.com/review/1" &&
+    cat >expected <<-\EOF &&
+	sent-version=2
+	upstream=https://example.com/review/1
+	EOF
+    stg meta -p p0 list >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Invalid keys and values' '
+    general_error stg meta set 1note foo 2>err &&
+    grep -e "invalid metadata key" err &&
+    command_error stg meta set sent-version v3 2>err &&
+    grep -e "must be a positive integer" err
+'
+
+test_expect_success 'Unset metadata' '
+    stg meta -p p0 unset sent-version &&
+    test "$(stg meta -p p0 list)" = "upstream=https://example.com/review/1" &&
+    command_error stg meta -p p0 unset sent-version 2>err &&
+    grep -e "patch \`p0\` has no \`sent-version\` metadata" err
+'
+
+test_expect_success 'Metadata is recorded in the stack state' '
+    git show refs/stacks/master:stack.json >stack.json &&
+    grep -e "\"version\": 6" stack.json &&
+    grep -e "\"note\": \"needs more tests\"" stack.json
+'
+
+test_expect_success 'Stack state without metadata is version 5' '
+    test_when_finished "stg undo -n 2" &&
+    stg meta -p p0 unset upstream &&
+    stg meta unset note &&
+    git show refs/stacks/master:stack.json >stack.json &&
+    grep -e "\"version\": 5" stack.json
+'
+
+test_expect_success 'Metadata survives refresh and push/pop' '
+    echo content >file &&
+    stg add file &&
+    stg refresh &&
+    stg pop -a &&
+    stg push -a &&
+    test "$(stg meta get note)" = "needs more tests"
+'
+
+test_expect_success 'Metadata follows rename' '
+    stg rename p2 p2-renamed &&
+    test "$(stg meta get note)" = "needs more tests"
+'
+
+test_expect_success 'Undo and redo metadata changes' '
+    stg meta set note "updated" &&
+    test "$(stg meta get note)" = "updated" &&
+    stg undo &&
+    test "$(stg meta get note)" = "needs more tests" &&
+    stg redo &&
+    test "$(stg meta get note)" = "updated"
+'
+
+test_expect_success 'Metadata follows pick' '
+    stg pick --noapply --name p0-copy p0 &&
+    test "$(stg meta -p p0-copy get upstream)" = "https://example.com/review/1"
+'
+
+test_expect_success 'Metadata is combined by squash' '
+    stg delete p0-copy &&
+    stg meta -p p1 set note "p1 note" &&
+    stg meta -p p1 set upstream https://example.com/review/2 &&
+    stg squash -n squashed -m squashed p0 p1 &&
+    test "$(stg meta -p squashed get upstream)" = "https://example.com/review/1" &&
+    test "$(stg meta -p squashed get note)" = "p1 note"
+'
+
+test_done
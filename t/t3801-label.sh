@@ -0,0 +1,92 @@
+#!/bin/sh
+
+test_description='Test patch labels and label patch ranges'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    for i in 0 1 2 3 4; do
+        stg new -m p$i &&
+        echo "p$i" >>file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'Add labels' '
+    stg label add perf p1 p3 &&
+    stg label add needs-review &&
+    stg label add topic/io p1..p2 &&
+    test "$(stg meta -p p1 get labels)" = "perf topic/io" &&
+    test "$(stg meta get labels)" = "needs-review" &&
+    test "$(echo $(stg label list))" = "perf topic/io needs-review" &&
+    test "$(echo $(stg label list p1))" = "perf topic/io"
+'
+
+test_expect_success 'Adding an existing label is a no-op' '
+    stg label add perf p1 &&
+    test "$(stg meta -p p1 get labels)" = "perf topic/io"
+'
+
+test_expect_success 'Invalid label' '
+    general_error stg label add "bad:label" 2>err &&
+    grep -e "invalid label" err
+'
+
+test_expect_success 'Select patches by label' '
+    test "$(echo $(stg series --noprefix @label:perf))" = "p1 p3" &&
+    test "$(echo $(stg series --noprefix @label:topic/io))" = "p1 p2"
+'
+
+test_expect_success 'Unknown label' '
+    command_error stg series @label:nope 2>err &&
+    grep -e "no patches with label \`nope\`" err
+'
+
+test_expect_success 'Pop and push by label' '
+    stg pop @label:perf &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2 p4" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p1 p3" &&
+    stg push @label:perf &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2 p4 p1 p3"
+'
+
+test_expect_success 'Sink and float by label' '
+    stg sink @label:perf &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p3 p0 p2 p4" &&
+    stg float @label:topic/io &&
+    test "$(echo $(stg series --applied --noprefix))" = "p3 p0 p4 p1 p2"
+'
+
+test_expect_success 'Push and pop by label without applicable patches' '
+    command_error stg push @label:perf 2>err &&
+    grep -e "no applicable patches with label \`perf\`" err &&
+    stg pop @label:perf &&
+    command_error stg pop @label:perf 2>err &&
+    grep -e "no applicable patches with label \`perf\`" err &&
+    stg push @label:perf &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p4 p2 p3 p1"
+'
+
+test_expect_success 'Hide and unhide by label' '
+    stg hide @label:perf &&
+    test "$(echo $(stg series --hidden --noprefix))" = "p3 p1" &&
+    stg unhide @label:perf &&
+    test "$(echo $(stg series --hidden --noprefix))" = ""
+'
+
+test_expect_success 'Remove labels' '
+    stg label remove perf .. &&
+    command_error stg series @label:perf &&
+    command_error stg label remove perf p0 2>err &&
+    grep -e "no patches with label \`perf\`" err &&
+    test "$(stg meta -p p1 get labels)" = "topic/io"
+'
+
+test_expect_success 'Undo label change' '
+    stg undo &&
+    test "$(stg meta -p p1 get labels)" = "perf topic/io"
+'
+
+test_done
@@ -0,0 +1,70 @@
+#!/bin/sh
+
+test_description='Test patch revision history'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    stg new -m p0 &&
+    test_seq 1 10 >file &&
+    stg add file &&
+    stg refresh &&
+    test_seq 1 11 >file &&
+    stg refresh &&
+    stg new -m p1
+'
+
+test_expect_success 'List revisions' '
+    stg history p0 >out &&
+    test_line_count = 3 out &&
+    grep -e "^1 .*new: p0\$" out &&
+    grep -e "^2 .*refresh p0\$" out &&
+    grep -e "^3 .*refresh p0\$" out &&
+    stg history p1 >out &&
+    test_line_count = 1 out
+'
+
+test_expect_success 'Revision ids' '
+    test "$(stg history --color=never p0 | tail -n 1 | awk "{print \$2}")" = \
+         "$(git rev-parse --short "$(stg id p0)")"
+'
+
+test_expect_success 'History follows renames' '
+    stg rename p0 renamed &&
+    stg goto renamed &&
+    sed -e "s/5/five/" file >file.new && mv file.new file &&
+    stg refresh &&
+    stg history renamed >out &&
+    test_line_count = 4 out &&
+    grep -e "^4 .*refresh renamed\$" out
+'
+
+test_expect_success 'Undo does not duplicate revisions' '
+    stg undo &&
+    stg history renamed >out &&
+    test_line_count = 4 out
+'
+
+test_expect_success 'Interdiff between revisions' '
+    stg redo &&
+    stg history --interdiff 3..4 renamed >out &&
+    cat >expected <<-\EOF &&
+	-+5
+	++five
+	EOF
+    grep -e "^[-+][-+][0-9f]" out >actual &&
+    test_cmp expected actual &&
+    grep -e "^--- a/renamed.v3" out &&
+    grep -e "^+++ b/renamed.v4" out &&
+    test -z "$(stg history --interdiff 3..3 renamed)"
+'
+
+test_expect_success 'Invalid interdiff revisions' '
+    command_error stg history --interdiff 1..9 renamed 2>err &&
+    grep -e "patch \`renamed\` has 4 revisions, not 9" err &&
+    general_error stg history --interdiff 0..1 renamed 2>err &&
+    grep -e "not a valid revision number" err
+'
+
+test_done
@@ -0,0 +1,85 @@
+#!/bin/sh
+
+test_description='Test comparing versions of a patch series'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    for i in 0 1 2 3 4; do
+        stg new -m p$i &&
+        test_seq 1 10 | sed -e "s/^/p$i-/" >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done &&
+    git rev-parse refs/stacks/master >old-state
+'
+
+test_expect_success 'Identical series' '
+    stg range-diff "$(cat old-state)" >out &&
+    test_line_count = 5 out &&
+    test "$(grep -c " = " out)" = 5
+'
+
+test_expect_success 'Rework the series' '
+    stg rename p1 first &&
+    stg goto first &&
+    sed -e "s/^p1-5\$/p1-five/" file1 >file1.new && mv file1.new file1 &&
+    stg refresh &&
+    stg delete p2 &&
+    stg float p0 &&
+    stg new -m extra &&
+    echo extra >extra &&
+    stg add extra &&
+    stg refresh
+'
+
+test_expect_success 'Compare with old stack state' '
+    stg range-diff --color=never -s "$(cat old-state)" >out &&
+    cat >expected <<-\EOF &&
+	2:  ! 1:  p1 -> first
+	1:  = 2:  p0 (reordered)
+	-:  > 3:  extra
+	3:  < -:  p2
+	4:  = 4:  p3
+	5:  = 5:  p4
+	EOF
+    sed -e "s/ [0-9a-f-]\{7,\} / /g" out >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Interdiff of changed patch' '
+    stg range-diff --color=never "$(cat old-state)" >out &&
+    cat >expected <<-\EOF &&
+	    -+p1-5
+	    ++p1-five
+	EOF
+    grep -e "^    [-+][-+]p" out >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Compare with stack log revision' '
+    stg range-diff -s "$(stg log | sed -n 8p | cut -d " " -f 1)" >out &&
+    grep -e "! 1: .* first\$" out
+'
+
+test_expect_success 'Compare with revision specifications' '
+    stg range-diff -s {base} >out &&
+    test "$(grep -c " > " out)" = 5 &&
+    stg range-diff -s first..p3 p0..p4 >out &&
+    cat >expected <<-\EOF &&
+	1:  < -:  first
+	2:  = 1:  p0
+	3:  = 2:  extra
+	4:  = 3:  p3
+	-:  > 4:  p4
+	EOF
+    sed -e "s/ [0-9a-f-]\{7,\} / /g" out >actual &&
+    test_cmp expected actual
+'
+
+test_expect_success 'Invalid revision' '
+    command_error stg range-diff no-such-rev
+'
+
+test_done
@@ -0,0 +1,109 @@
+#!/bin/sh
+
+test_description='Test machine-readable --format output'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    for i in 0 1 2; do
+        stg new -m "p$i subject" p$i &&
+        echo "p$i" >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done &&
+    stg pop &&
+    stg new -m empty empty &&
+    stg new -m hidden hidden &&
+    stg pop &&
+    stg hide hidden
+'
+
+test_expect_success 'Series with template' '
+    stg series -a --format="{name} {state} {top} {index} {offset} {empty}" >actual &&
+    cat >expected <<-\EOF &&
+	p0 applied false 0 -2 false
+	p1 applied false 1 -1 false
+	empty applied true 2 0 true
+	p2 unapplied false 3 1 false
+	hidden hidden false 4 2 true
+	EOF
+    test_cmp expected actual
+'
+
+test_expect_success 'Series with template fields' '
+    stg series --reverse --applied \
+        --format="{{{name}}} {id} {author.email} {subject}" >actual &&
+    cat >expected <<-EOF &&
+	{empty} $(stg id empty) author@example.com empty
+	{p1} $(stg id p1) author@example.com p1 subject
+	{p0} $(stg id p0) author@example.com p0 subject
+	EOF
+    test_cmp expected actual
+'
+
+test_expect_success 'Series as JSON' '
+    stg series --format=json >out &&
+    grep -e "\"name\": \"p0\"" out &&
+    grep -e "\"state\": \"unapplied\"" out &&
+    grep -e "\"empty\": true" out &&
+    test "$(grep -c "\"branch\":" out)" = 4
+'
+
+test_expect_success 'Invalid templates' '
+    general_error stg series --format="{name" 2>err &&
+    grep -e "invalid placeholder" err &&
+    command_error stg series --format="{nope}" 2>err &&
+    grep -e "unknown format field \`nope\`" err &&
+    general_error stg series --count --format=json
+'
+
+test_expect_success 'Top and id' '
+    test "$(stg top --format="{name}:{subject}")" = "empty:empty" &&
+    stg top --format=json >out &&
+    grep -e "\"top\": true" out &&
+    test "$(stg id --format="{id} {kind}" p1)" = "$(stg id p1) commit" &&
+    test "$(stg id --format="{kind}" "p1^{tree}")" = "tree"
+'
+
+test_expect_success 'Files' '
+    echo change >>file0 &&
+    stg refresh -p p1 &&
+    stg files --format="{status} {path}" p1 >actual &&
+    cat >expected <<-\EOF &&
+	M file0
+	A file1
+	EOF
+    test_cmp expected actual &&
+    stg files --format=json p0 >out &&
+    grep -e "\"path\": \"file0\"" out &&
+    general_error stg files --format=json --stat
+'
+
+test_expect_success 'Log' '
+    stg log --format="{subject}" -n 2 >actual &&
+    cat >expected <<-\EOF &&
+	refresh p1
+	refresh refresh-temp (create temporary patch)
+	EOF
+    test_cmp expected actual &&
+    stg log --format="{subject}" p2 >actual &&
+    cat >expected <<-\EOF &&
+	refresh p2
+	new: p2
+	EOF
+    test_cmp expected actual &&
+    test "$(stg log --format="{id}" -n 1)" = "$(git rev-parse refs/stacks/master^)"
+'
+
+test_expect_success 'Branch list' '
+    git branch other &&
+    stg branch --list --format="{name} {current} {stack} {protected}" >actual &&
+    cat >expected <<-\EOF &&
+	master true true false
+	other false false false
+	EOF
+    test_cmp expected actual
+'
+
+test_done
@@ -0,0 +1,21 @@
+#!/bin/sh
+
+test_description='Test the terminal user interface'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    stg init &&
+    stg new -m p0
+'
+
+test_expect_success 'Requires a terminal' '
+    command_error stg tui </dev/null >out 2>err &&
+    grep -e "must be run in a terminal" err
+'
+
+test_expect_success 'Reject extra arguments' '
+    general_error stg tui p0
+'
+
+test_done
@@ -0,0 +1,98 @@
+#!/bin/sh
+
+test_description='Test splitting patches'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    test_seq 1 20 >a &&
+    git add a &&
+    git commit -m "add a" &&
+    stg init &&
+    stg new -m "p0 message" p0 &&
+    sed -e "s/^2\$/two/" -e "s/^18\$/eighteen/" a >a.new &&
+    mv a.new a &&
+    echo b >b &&
+    stg add a b &&
+    stg refresh &&
+    stg new -m p1 &&
+    echo c >c &&
+    stg add c &&
+    stg refresh
+'
+
+test_expect_success 'List hunks' '
+    stg split --list p0 >out &&
+    test_line_count = 3 out &&
+    grep -e "^  1  a @@ -1,5 +1,5 @@" out &&
+    grep -e "^  3  b @@ -0,0 +1,1 @@" out
+'
+
+test_expect_success 'Require a selection when not in a terminal' '
+    command_error stg split p0 2>err &&
+    grep -e "select changes with" err
+'
+
+test_expect_success 'Split a hunk from a non-top patch' '
+    head_tree=$(git rev-parse HEAD^{tree}) &&
+    stg split --hunks 1 -m "first part" p0 &&
+    test "$(echo $(stg series --noprefix))" = "first-part p0 p1" &&
+    test "$(git rev-parse HEAD^{tree})" = "$head_tree" &&
+    stg files first-part >out &&
+    echo "M a" >expected &&
+    test_cmp expected out &&
+    stg show first-part | grep -e "^+two\$" &&
+    ! grep -e "eighteen" out &&
+    stg show p0 | grep -e "^+eighteen\$" &&
+    test "$(git log -1 --format=%s $(stg id p0))" = "p0 message" &&
+    git diff --quiet &&
+    git diff --cached --quiet
+'
+
+test_expect_success 'Undo the split' '
+    stg undo &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1"
+'
+
+test_expect_success 'Split by path with a name' '
+    stg split --path b --name add-b -m "Add b" p0 &&
+    test "$(echo $(stg series --noprefix))" = "add-b p0 p1" &&
+    stg files add-b >out &&
+    echo "A b" >expected &&
+    test_cmp expected out &&
+    stg undo
+'
+
+test_expect_success 'Split by line range' '
+    stg split --lines a:18 -m "line 18" p0 &&
+    stg show line-18 >out &&
+    grep -e "^-18\$" out &&
+    grep -e "^+eighteen\$" out &&
+    ! grep -e "two" out &&
+    stg undo
+'
+
+test_expect_success 'Split an unapplied patch' '
+    stg pop -a &&
+    stg split --hunks 2-3 -m "second part" p0 &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "second-part p0 p1" &&
+    stg push -a &&
+    test "$(echo $(stg series --applied --noprefix))" = "second-part p0 p1" &&
+    stg files p0 >out &&
+    echo "M a" >expected &&
+    test_cmp expected out &&
+    stg undo &&
+    stg undo
+'
+
+test_expect_success 'Selecting all changes is an error' '
+    command_error stg split --hunks 1-3 -m all p0 2>err &&
+    grep -e "nothing would remain" err &&
+    command_error stg split --hunks 4 -m none p0 2>err &&
+    grep -e "hunk 4 does not exist" err &&
+    command_error stg split --path nope -m none p0 2>err &&
+    grep -e "path \`nope\` is not changed" err &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1"
+'
+
+test_done
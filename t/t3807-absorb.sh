@@ -0,0 +1,116 @@
+#!/bin/sh
+
+test_description='Test absorbing worktree changes into patches'
+
+. ./test-lib.sh
+
+test_expect_success 'Attempt absorb without applied patches' '
+    test_seq 1 20 >a &&
+    test_seq 1 10 >b &&
+    git add a b &&
+    git commit -m "add a and b" &&
+    stg init &&
+    command_error stg absorb 2>err &&
+    grep -e "no patches applied" err
+'
+
+test_expect_success 'Initialize patches' '
+    stg new -m p0 p0 &&
+    sed -e "s/^3\$/three/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg new -m p1 p1 &&
+    sed -e "s/^15\$/fifteen/" a >a.new && mv a.new a &&
+    echo b11 >>b &&
+    stg add a b &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    sed -e "s/^7\$/seven/" b >b.new && mv b.new b &&
+    stg refresh
+'
+
+test_expect_success 'Absorb with no local changes' '
+    stg absorb 2>err &&
+    grep -e "no local changes to absorb" err
+'
+
+test_expect_success 'Absorb hunks into the patches that modified them' '
+    sed -e "s/^three\$/THREE/" -e "s/^fifteen\$/FIFTEEN/" -e "s/^10\$/ten/" a >a.new &&
+    mv a.new a &&
+    sed -e "s/^seven\$/SEVEN/" b >b.new && mv b.new b &&
+    echo b12 >>b &&
+    stg absorb 2>err &&
+    grep -e "line 10 not modified by any applied patch" err &&
+    grep -e "absorbed 1 hunk into \`p0\`" err &&
+    grep -e "absorbed 2 hunks into \`p1\`" err &&
+    grep -e "absorbed 1 hunk into \`p2\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2" &&
+    stg show p0 | grep -e "^+THREE" &&
+    stg show p1 | grep -e "^+FIFTEEN" &&
+    stg show p1 | grep -e "^+b12" &&
+    stg show p2 | grep -e "^+SEVEN" &&
+    git diff --cached --quiet &&
+    git diff >diff &&
+    grep -e "^+ten" diff &&
+    git diff --numstat >numstat &&
+    test_line_count = 1 numstat
+'
+
+test_expect_success 'Absorb is a single undoable operation' '
+    test "$(stg log --number=1 | grep -c absorb)" = "1" &&
+    stg undo --hard &&
+    stg show p0 | grep -e "^+three" &&
+    stg show p1 | grep -e "^+fifteen" &&
+    stg show p2 | grep -e "^+seven" &&
+    git diff --quiet
+'
+
+test_expect_success 'Leave ambiguous hunks in the worktree' '
+    sed -e "s/^10\$/ten/" -e "s/^b11\$/B11/" b >b.new &&
+    mv b.new b &&
+    sed -e "s/^seven\$/7/" -e "s/^8\$/eight/" b >b.new &&
+    mv b.new b &&
+    git diff -U0 b | grep -e "^@@" >hunks &&
+    test_line_count = 2 hunks &&
+    stg absorb 2>err &&
+    grep -e "lines 7-8 is ambiguous" err &&
+    grep -e "lines 10-11 is ambiguous" err &&
+    grep -e "no changes could be absorbed" err &&
+    test "$(stg log --number=1 | grep -c absorb)" = "0" &&
+    git reset -q --hard
+'
+
+test_expect_success 'Leave additions bordering unmodified lines in the worktree' '
+    sed -e "/^three\$/i\\
+2.5" a >a.new &&
+    mv a.new a &&
+    stg absorb 2>err &&
+    grep -e "addition after line 2 is ambiguous" err &&
+    grep -e "no changes could be absorbed" err &&
+    git reset -q --hard
+'
+
+test_expect_success 'Leave new files in the worktree' '
+    echo c >c &&
+    git add c &&
+    stg absorb 2>err &&
+    grep -e "\`c\`: cannot absorb new, deleted, or binary file" err &&
+    git diff --cached --name-only >names &&
+    test "$(cat names)" = "c" &&
+    git rm -q -f c
+'
+
+test_expect_success 'Abort when a patch cannot be updated' '
+    stg new -m p3 p3 &&
+    sed -e "s/^4\$/four/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg series >series.before &&
+    sed -e "s/^three\$/THREE/" a >a.new && mv a.new a &&
+    command_error stg absorb 2>err &&
+    grep -e "absorbing into \`p0\`" err &&
+    stg series >series.after &&
+    test_cmp series.before series.after &&
+    stg show p0 | grep -e "^+three" &&
+    git diff | grep -e "^+THREE"
+'
+
+test_done
@@ -0,0 +1,212 @@
+#!/bin/sh
+
+test_description='Test resuming operations halted by merge conflicts'
+
+. ./test-lib.sh
+
+reset_stack () {
+    stg reset --hard "$(cat initial-state)" &&
+    git reset -q --hard &&
+    test "$(echo $(stg series --applied --noprefix))" = ""
+}
+
+test_expect_success 'Initialize patches' '
+    test_seq 1 10 >a &&
+    echo b >b &&
+    git add a b &&
+    git commit -m "add a and b" &&
+    stg init &&
+    stg new -m p0 p0 &&
+    sed -e "s/^3\$/three/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg new -m p1 p1 &&
+    sed -e "s/^three\$/THREE/" a >a.new && mv a.new a &&
+    stg refresh &&
+    stg new -m p2 p2 &&
+    echo 11 >>a &&
+    stg refresh &&
+    stg pop -a &&
+    git rev-parse refs/stacks/master >initial-state &&
+    test_seq 1 10 | sed -e "s/^3\$/THREE/" >resolved
+'
+
+test_expect_success 'Attempt continue without an operation in progress' '
+    command_error stg push --continue 2>err &&
+    grep -e "no \`stg push\` operation is in progress" err
+'
+
+test_expect_success 'Attempt continue with other arguments' '
+    general_error stg push --continue p1 2>err &&
+    grep -e "cannot be used with" err
+'
+
+test_expect_success 'Push with conflicts records the operation' '
+    conflict stg push p1 p2 2>err &&
+    grep -e "stg push --continue" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1" &&
+    test_path_is_file .git/stgit/operations/master
+'
+
+test_expect_success 'Attempt continue with unresolved conflicts' '
+    command_error stg push --continue 2>err &&
+    grep -e "mark resolved conflicts with \`stg add\` first" err
+'
+
+test_expect_success 'Attempt to resume with a different command' '
+    command_error stg goto --continue 2>err &&
+    grep -e "\`stg push\` is in progress" err
+'
+
+test_expect_success 'Continue push after resolving conflicts' '
+    cp resolved a &&
+    stg add a &&
+    stg push --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2" &&
+    stg show p1 | grep -e "^+THREE" &&
+    test_path_is_missing .git/stgit/operations/master &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Skip the conflicting patch' '
+    reset_stack &&
+    conflict stg push p1 p2 &&
+    stg push --skip &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2" &&
+    stg show p1 | grep -e "^-three" &&
+    stg show p1 | grep -e "^+THREE" &&
+    test_path_is_missing .git/stgit/operations/master &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Abort push' '
+    reset_stack &&
+    stg series >series.before &&
+    conflict stg push p1 p2 &&
+    stg push --abort &&
+    stg series >series.after &&
+    test_cmp series.before series.after &&
+    stg show p1 | grep -e "^-three" &&
+    test_path_is_missing .git/stgit/operations/master &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Undo discards the operation record' '
+    conflict stg push p1 p2 &&
+    stg undo --hard &&
+    command_error stg push --continue 2>err &&
+    grep -e "no \`stg push\` operation is in progress" err &&
+    test_path_is_missing .git/stgit/operations/master
+'
+
+test_expect_success 'Abort goto after repeated conflicts' '
+    reset_stack &&
+    stg push --noapply p1 &&
+    stg series >series.before &&
+    conflict stg goto p2 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1" &&
+    cp resolved a &&
+    stg add a &&
+    conflict stg goto --continue 2>err &&
+    grep -e "stg goto --continue" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p0" &&
+    stg goto --abort &&
+    stg series >series.after &&
+    test_cmp series.before series.after &&
+    stg show p1 | grep -e "^-three" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Continue pick' '
+    reset_stack &&
+    stg branch --clone pickref &&
+    stg rename p1 q1 &&
+    stg rename p2 q2 &&
+    stg branch master &&
+    conflict stg pick -B pickref q1 q2 &&
+    test "$(echo $(stg series --applied --noprefix))" = "q1" &&
+    cp resolved a &&
+    stg add a &&
+    stg pick --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "q1 q2" &&
+    stg show q1 | grep -e "^+THREE" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Setup upstream branch' '
+    reset_stack &&
+    git branch upstream &&
+    git checkout -q upstream &&
+    sed -e "s/^3\$/drei/" a >a.new && mv a.new a &&
+    git commit -q -a -m drei &&
+    git checkout -q master
+'
+
+test_expect_success 'Continue rebase with autostash' '
+    stg push p0 p2 &&
+    echo dirty >>b &&
+    conflict stg rebase --autostash upstream &&
+    git stash list >stashes &&
+    test_line_count = 1 stashes &&
+    test_seq 1 10 | sed -e "s/^3\$/three/" >a &&
+    stg add a &&
+    stg rebase --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2" &&
+    test "$(stg id "{base}")" = "$(git rev-parse upstream)" &&
+    git stash list >stashes &&
+    test_line_count = 0 stashes &&
+    grep -e dirty b &&
+    git checkout b
+'
+
+test_expect_success 'Abort rebase' '
+    stg reset --hard "$(cat initial-state)" &&
+    stg push p0 p2 &&
+    base=$(stg id "{base}") &&
+    conflict stg rebase upstream &&
+    stg rebase --abort &&
+    test "$(stg id "{base}")" = "$base" &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Continue sync' '
+    reset_stack &&
+    stg branch --clone syncref &&
+    stg goto p0 &&
+    test_seq 1 10 | sed -e "s/^3\$/tres/" >a &&
+    stg refresh &&
+    stg branch master &&
+    stg push p0 p2 &&
+    conflict stg sync -B syncref -a &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    test_seq 1 10 | sed -e "s/^3\$/tres/" >a &&
+    stg add a &&
+    stg sync --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p2" &&
+    stg show p0 | grep -e "^+tres" &&
+    git diff --quiet HEAD
+'
+
+test_expect_success 'Continue sync after conflicts pushing the patch to synchronize' '
+    reset_stack &&
+    stg branch --clone syncref2 &&
+    stg goto p1 &&
+    echo b-sync >b &&
+    stg refresh &&
+    stg branch master &&
+    stg push p0 &&
+    test_seq 1 10 | sed -e "s/^3\$/drei/" >a &&
+    stg refresh &&
+    conflict stg sync -B syncref2 p1 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1" &&
+    test "$(cat b)" = "b" &&
+    cp resolved a &&
+    stg add a &&
+    stg sync --continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1" &&
+    stg show p1 | grep -e "^+THREE" &&
+    stg show p1 | grep -e "^+b-sync" &&
+    git diff --quiet HEAD
+'
+
+test_done